@@ -92,24 +92,12 @@ export default function TruthTableGenerator() {
   // This is only created because a null reference throws an error
   const editor_ref = useRef(monaco.editor.create(document.createElement("editor")));
 
-  type option_bool = boolean | null;
-
   function handleEditorMount(editor: any) {
     editor_ref.current = editor;
     editor_ref.current.updateOptions({minimap: {enabled: false}});
   }
 
-  function ob_str(v: option_bool) {
-    if (v === true) {
-      return 1;
-    } else if (v === false) {
-      return 0;
-    } else if (v === null) {
-      return "?";
-    }
-  }
-
-  let initialState: [Array<string>, Array<Array<Array<boolean | null>>>] = [
+  let initialState: [Array<string>, Array<Array<string>>] = [
     [],
     [],
   ];
@@ -120,8 +108,8 @@ export default function TruthTableGenerator() {
 
   useEffect(() => {
     init().then(() => {
-      let table_json = full_table(chips["And"]);
-      let table: [Array<string>, Array<Array<Array<option_bool>>>] =
+      let table_json = full_table(chips["And"], "[]", "{}");
+      let table: [Array<string>, Array<Array<string>>] =
         JSON.parse(table_json);
       console.log(table);
       setAns(table);
@@ -130,8 +118,8 @@ export default function TruthTableGenerator() {
 
   function changeCode(s: any) {
     try {
-      let table_json = full_table(s);
-      let table: [Array<string>, Array<Array<Array<option_bool>>>] =
+      let table_json = full_table(s, "[]", "{}");
+      let table: [Array<string>, Array<Array<string>>] =
         JSON.parse(table_json);
       setAns(table);
       setStatus("OK");
@@ -175,7 +163,7 @@ export default function TruthTableGenerator() {
                   <TableRow key={index}>
                     {row.map((column, index) => {
                       return (
-                        <TableCell key={index}>{column.map(ob_str)}</TableCell>
+                        <TableCell key={index}>{column}</TableCell>
                       );
                     })}
                   </TableRow>
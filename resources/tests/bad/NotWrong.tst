@@ -0,0 +1,12 @@
+load Not.hdl,
+output-file NotWrong.out,
+compare-to NotWrong.cmp,
+output-list in%B3.1.3 out%B3.1.3;
+
+set in 0,
+eval,
+output;
+
+set in 1,
+eval,
+output;
@@ -0,0 +1,8 @@
+load Undriven.hdl,
+output-file Undriven.out,
+compare-to Undriven.cmp,
+output-list a%B1.4.1;
+
+set a 0,
+eval,
+output;
@@ -0,0 +1,10 @@
+// A `*` can't be part of a decimal value.
+load NotPair.hdl,
+output-file BadStar.out,
+compare-to NotPair.cmp,
+output-list a%B3.1.3 b%B3.2.3 x%B3.1.3 y%B3.2.3;
+
+set a 1*,
+set b 0,
+eval,
+output;
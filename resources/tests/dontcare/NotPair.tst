@@ -0,0 +1,15 @@
+// b is a don't-care, so y is unknown and is not compared.
+load NotPair.hdl,
+output-file NotPair.out,
+compare-to NotPair.cmp,
+output-list a%B3.1.3 b%B3.2.3 x%B3.1.3 y%B3.2.3;
+
+set a 0,
+set b *,
+eval,
+output;
+
+set a 1,
+set b %B*1,
+eval,
+output;
@@ -0,0 +1,30 @@
+library ieee;
+use ieee.std_logic_1164.all;
+
+entity Bit is
+port (
+in_n2v : in std_logic_vector(0 downto 0);
+load : in std_logic_vector(0 downto 0);
+out_n2v : out std_logic_vector(0 downto 0);
+clk : in std_logic_vector(0 downto 0)
+);
+end entity Bit;
+
+architecture arch of Bit is
+signal net_n2v : std_logic_vector(8 downto 0);
+begin
+net_n2v(0 downto 0) <= load(0 downto 0) nand load(0 downto 0);
+net_n2v(2 downto 2) <= net_n2v(1 downto 1) nand net_n2v(0 downto 0);
+net_n2v(3 downto 3) <= net_n2v(2 downto 2) nand net_n2v(2 downto 2);
+net_n2v(4 downto 4) <= in_n2v(0 downto 0) nand load(0 downto 0);
+net_n2v(5 downto 5) <= net_n2v(4 downto 4) nand net_n2v(4 downto 4);
+net_n2v(6 downto 6) <= net_n2v(3 downto 3) nand net_n2v(3 downto 3);
+net_n2v(7 downto 7) <= net_n2v(5 downto 5) nand net_n2v(5 downto 5);
+net_n2v(8 downto 8) <= net_n2v(6 downto 6) nand net_n2v(7 downto 7);
+cn2v8: process (clk) begin
+if rising_edge(clk(0)) then
+net_n2v(1 downto 1) <= net_n2v(8 downto 8);
+end if;
+end process;
+out_n2v(0 downto 0) <= net_n2v(1 downto 1);
+end arch;
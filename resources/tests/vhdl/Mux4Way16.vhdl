@@ -0,0 +1,34 @@
+library ieee;
+use ieee.std_logic_1164.all;
+
+entity Mux4Way16 is
+port (
+a : in std_logic_vector(15 downto 0);
+b : in std_logic_vector(15 downto 0);
+c : in std_logic_vector(15 downto 0);
+d : in std_logic_vector(15 downto 0);
+sel : in std_logic_vector(1 downto 0);
+out_n2v : out std_logic_vector(15 downto 0)
+);
+end entity Mux4Way16;
+
+architecture arch of Mux4Way16 is
+component Mux16 is
+port (
+a : in std_logic_vector(15 downto 0);
+b : in std_logic_vector(15 downto 0);
+sel : in std_logic_vector(0 downto 0);
+out_n2v : out std_logic_vector(15 downto 0)
+);
+end component Mux16;
+
+signal outab : std_logic_vector(15 downto 0);
+signal outcd : std_logic_vector(15 downto 0);
+begin
+cn2v0: Mux16 port map(a => a, b => b, sel => sel(0 downto 0), out_n2v => outab);
+
+cn2v1: Mux16 port map(a => c, b => d, sel => sel(0 downto 0), out_n2v => outcd);
+
+cn2v2: Mux16 port map(a => outab, b => outcd, sel => sel(1 downto 1), out_n2v => out_n2v);
+
+end arch;
@@ -0,0 +1,27 @@
+library ieee;
+use ieee.std_logic_1164.all;
+use ieee.numeric_std.all;
+
+entity RAM8 is
+port (
+in_n2v : in std_logic_vector(15 downto 0);
+load : in std_logic_vector(0 downto 0);
+address : in std_logic_vector(2 downto 0);
+out_n2v : out std_logic_vector(15 downto 0);
+clk : in std_logic_vector(0 downto 0)
+);
+end entity RAM8;
+
+architecture arch of RAM8 is
+type memory_t is array (0 to 7) of std_logic_vector(15 downto 0);
+signal memory : memory_t;
+begin
+cn2v0: process (clk) begin
+if rising_edge(clk(0)) then
+if load(0) = '1' then
+memory(to_integer(unsigned(address))) <= in_n2v;
+end if;
+end if;
+end process;
+out_n2v <= memory(to_integer(unsigned(address)));
+end arch;
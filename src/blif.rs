@@ -0,0 +1,183 @@
+//! BLIF netlists, for logic synthesis tools such as Berkeley ABC.
+//!
+//! The chip is flattened to NAND gates and DFFs. Each NAND gate is written
+//! as a two-input `.names` table and each DFF as a `.latch` that starts at
+//! 0. Port buses are split into single-bit nets named `signal[i]`, and the
+//! other nets are numbered `n0`, `n1` and so on, skipping port bit names.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Write;
+
+use crate::parser::PortDirection;
+use crate::simulator::Chip;
+use crate::vhdl::Netlist;
+
+/// Writes a chip and every chip inside it as a single BLIF model.
+pub fn to_blif(chip: &mut Chip) -> Result<String, Box<dyn Error>> {
+    let (mut netlist, port_nets) = Netlist::from_chip(chip)?;
+
+    // Ports in the order they are declared, with the name of each bit.
+    let port_bits = |direction: PortDirection| -> Vec<(usize, String)> {
+        let Some(hdl) = &chip.hdl else {
+            return Vec::new();
+        };
+        let mut bits = Vec::new();
+        for port in hdl.ports.iter().filter(|p| p.direction == direction) {
+            let nets = &port_nets[&port.name.value];
+            for (i, &net) in nets.iter().enumerate() {
+                let bit = match nets.len() {
+                    1 => port.name.value.clone(),
+                    _ => format!("{}[{}]", port.name.value, i),
+                };
+                bits.push((net, bit));
+            }
+        }
+        bits
+    };
+    let inputs = port_bits(PortDirection::In);
+    let outputs = port_bits(PortDirection::Out);
+
+    // Nets joined to an input port are named after the port bit, and the
+    // first output port bit on any other net names it. An output port bit
+    // on a net that already has a name is copied from it by a buffer.
+    let mut names: HashMap<usize, String> = HashMap::new();
+    for (net, bit) in &inputs {
+        let root = netlist.find(*net);
+        names.entry(root).or_insert_with(|| bit.clone());
+    }
+    let mut buffers = Vec::new();
+    for (net, bit) in &outputs {
+        let root = netlist.find(*net);
+        match names.get(&root) {
+            Some(name) => buffers.push((name.clone(), bit.clone())),
+            None => {
+                names.insert(root, bit.clone());
+            }
+        }
+    }
+    // Numbered names skip port bits, so a port named `n0` isn't joined to
+    // another net.
+    let used: HashSet<String> = inputs
+        .iter()
+        .chain(&outputs)
+        .map(|(_, b)| b.clone())
+        .collect();
+    let mut net_count = 0;
+    let mut name = |netlist: &mut Netlist, net: usize| {
+        let root = netlist.find(net);
+        names
+            .entry(root)
+            .or_insert_with(|| loop {
+                let name = format!("n{}", net_count);
+                net_count += 1;
+                if !used.contains(&name) {
+                    break name;
+                }
+            })
+            .clone()
+    };
+
+    let mut blif = String::new();
+    writeln!(blif, ".model {}", chip.name)?;
+    let bit_names = |bits: &[(usize, String)]| -> String {
+        bits.iter().map(|(_, bit)| format!(" {}", bit)).collect()
+    };
+    writeln!(blif, ".inputs{}", bit_names(&inputs))?;
+    writeln!(blif, ".outputs{}", bit_names(&outputs))?;
+
+    // A `.names` table with no rows is the constant 0. Nets driven by the
+    // none literal are left without a driver.
+    for (net, value) in netlist.literals.clone() {
+        match value {
+            Some(true) => writeln!(blif, ".names {}\n1", name(&mut netlist, net))?,
+            Some(false) => writeln!(blif, ".names {}", name(&mut netlist, net))?,
+            None => {}
+        }
+    }
+    for (a, b, out) in netlist.nands.clone() {
+        let (a, b, out) = (
+            name(&mut netlist, a),
+            name(&mut netlist, b),
+            name(&mut netlist, out),
+        );
+        writeln!(blif, ".names {} {} {}\n0- 1\n-0 1", a, b, out)?;
+    }
+    for (d, q) in netlist.dffs.clone() {
+        let (d, q) = (name(&mut netlist, d), name(&mut netlist, q));
+        writeln!(blif, ".latch {} {} 0", d, q)?;
+    }
+    for (source, bit) in buffers {
+        writeln!(blif, ".names {} {}\n1 1", source, bit)?;
+    }
+    writeln!(blif, ".end")?;
+    Ok(blif)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{parse_hdl_path, HdlProvider};
+    use std::env;
+    use std::path::Path;
+    use std::ptr;
+    use std::rc::Rc;
+
+    fn blif(path: &str, generics: &[usize]) -> String {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let (hdl, provider) =
+            parse_hdl_path(&manifest_dir.join("resources/tests").join(path)).expect("Parse error");
+        let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+        let mut chip =
+            Chip::new(&hdl, ptr::null_mut(), &provider, false, generics).expect("Chip error");
+        to_blif(&mut chip).expect("BLIF error")
+    }
+
+    #[test]
+    fn test_and_blif() {
+        assert_eq!(
+            blif("nand2tetris/solutions/And.hdl", &[]),
+            ".model And\n\
+             .inputs a b\n\
+             .outputs out\n\
+             .names a b n0\n0- 1\n-0 1\n\
+             .names n0 n0 out\n0- 1\n-0 1\n\
+             .end\n"
+        );
+    }
+
+    #[test]
+    fn test_sequential_blif() {
+        let bit = blif("nand2tetris/solutions/Bit.hdl", &[]);
+        assert!(bit.starts_with(".model Bit\n.inputs in load\n.outputs out\n"));
+        assert_eq!(bit.matches(".latch ").count(), 1);
+        assert!(bit.contains(" out 0\n"));
+    }
+
+    // Numbered nets don't take the names of ports.
+    #[test]
+    fn test_net_names_blif() {
+        assert_eq!(
+            blif("keyword/NetNames.hdl", &[]),
+            ".model NetNames\n\
+             .inputs n0 b\n\
+             .outputs out\n\
+             .names n0 b n1\n0- 1\n-0 1\n\
+             .names n1 n1 out\n0- 1\n-0 1\n\
+             .end\n"
+        );
+    }
+
+    // Output bits wired straight to input bits are copied by buffers.
+    #[test]
+    fn test_generic_bus_blif() {
+        let join = blif("multiply/JoinGen.hdl", &[2]);
+        assert!(join.starts_with(
+            ".model JoinGen\n\
+             .inputs high[0] high[1] low[0] low[1]\n\
+             .outputs out[0] out[1] out[2] out[3]\n"
+        ));
+        assert!(join.contains(".names low[0] out[0]\n1 1\n"));
+        assert!(join.contains(".names high[1] out[3]\n1 1\n"));
+    }
+}
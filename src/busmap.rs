@@ -4,6 +4,7 @@ use std::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fmt::Write;
+use std::ops::Range;
 
 // Convenience for creating a bus with width 1
 impl From<String> for Bus {
@@ -214,6 +215,29 @@ impl BusMap {
         res
     }
 
+    /// Bits `range` of the bus `name`, most significant first, like
+    /// `get_bus`. Bits past the width of the bus, or of a bus that doesn't
+    /// exist, are None.
+    pub fn get_range(&self, name: &str, range: Range<usize>) -> Vec<Option<bool>> {
+        let current: &[Option<bool>] = self.buses.get(name).map_or(&[], |b| b);
+        range
+            .rev()
+            .map(|i| match current.len().checked_sub(i + 1) {
+                Some(j) => current[j],
+                None => None,
+            })
+            .collect()
+    }
+
+    /// A bus spanning every bit of `name`, or None if there is no such bus.
+    pub fn full_bus(&self, name: &str) -> Option<Bus> {
+        let width = self.get_width(name)?;
+        Some(Bus {
+            name: String::from(name),
+            range: Some(0..width),
+        })
+    }
+
     pub fn get_name(&self, name: &str) -> Vec<Option<bool>> {
         self.buses.get(name).unwrap().to_vec()
     }
@@ -229,6 +253,28 @@ impl BusMap {
         self.insert_option(&bus, x);
     }
 
+    /// Builds a BusMap from `(name, value, width)` triples, decomposing each
+    /// value into `width` bits with bit 0 the least significant. Returns an
+    /// error if a value does not fit in its width.
+    pub fn from_integers(values: &[(&str, u64, usize)]) -> Result<BusMap, String> {
+        let mut r = BusMap::new();
+        for &(name, value, width) in values {
+            if width < 64 && value >> width != 0 {
+                return Err(format!(
+                    "Value {} does not fit in {} bits for bus {}.",
+                    value, width, name
+                ));
+            }
+            r.create_bus(name, width)?;
+            let bits: Vec<bool> = (0..width)
+                .rev()
+                .map(|i| i < 64 && (value >> i) & 1 == 1)
+                .collect();
+            r.insert(Bus::from(name), bits);
+        }
+        Ok(r)
+    }
+
     /// A bus must be created before it can be used.
     pub fn create_bus(&mut self, name: &str, width: usize) -> Result<(), String> {
         if !self.buses.contains_key(name) {
@@ -285,10 +331,149 @@ impl BusMap {
         self.buses.get(name).map(|x| x.len())
     }
 
-    ///! Returns a list of signal names in this busmap.
+    /// Returns a list of signal names in this busmap.
     pub fn keys(&self) -> Vec<String> {
         self.buses.keys().cloned().collect()
     }
+
+    /// Returns the bits of a bus, most significant first, if every bit is
+    /// driven.
+    fn defined_bits(&self, name: &str) -> Option<Vec<bool>> {
+        self.buses.get(name)?.iter().copied().collect()
+    }
+
+    /// Interprets a bus as an unsigned integer. Returns None if the bus
+    /// does not exist, has undriven bits, or is wider than 64 bits.
+    pub fn as_unsigned(&self, name: &str) -> Option<u64> {
+        let bits = self.defined_bits(name)?;
+        if bits.len() > 64 {
+            return None;
+        }
+        Some(bits.iter().fold(0, |acc, b| (acc << 1) | *b as u64))
+    }
+
+    /// Interprets a bus as a two's complement signed integer. Returns None
+    /// if the bus does not exist, is empty, has undriven bits, or is wider
+    /// than 64 bits.
+    pub fn as_signed(&self, name: &str) -> Option<i64> {
+        let width = self.get_width(name)?;
+        let value = self.as_unsigned(name)?;
+        if width == 0 {
+            return None;
+        }
+
+        let negative = value >> (width - 1) & 1 == 1;
+        if negative && width < 64 {
+            Some(value as i64 - (1i64 << width))
+        } else {
+            Some(value as i64)
+        }
+    }
+
+    /// Sets every bit of an existing bus from an integer. Negative values
+    /// are stored in two's complement. Returns an error if the bus does not
+    /// exist or the value does not fit in the bus width.
+    pub fn set_integer(&mut self, name: &str, value: i64) -> Result<(), String> {
+        let width = match self.get_width(name) {
+            Some(w) => w,
+            None => return Err(format!("No bus named {}.", name)),
+        };
+
+        let fits = if width >= 64 {
+            true
+        } else if value < 0 {
+            width > 0 && value >= -(1i64 << (width - 1))
+        } else {
+            value < (1i64 << width)
+        };
+        if !fits {
+            return Err(format!(
+                "Value {} does not fit in {} bits for bus {}.",
+                value, width, name
+            ));
+        }
+
+        // Most significant bit first, matching the bus layout.
+        let bits: Vec<bool> = (0..width)
+            .rev()
+            .map(|i| i < 64 && (value >> i) & 1 == 1)
+            .collect();
+        self.insert(Bus::from(name), bits);
+        Ok(())
+    }
+
+    /// Formats a bus with `format_bits`. Returns None if the bus does not
+    /// exist.
+    pub fn format(&self, name: &str, radix: Radix) -> Option<String> {
+        self.buses.get(name).map(|bits| format_bits(bits, radix))
+    }
+
+    /// Formats a bus as a binary string, most significant bit first, with
+    /// `?` for undriven bits.
+    pub fn as_binary(&self, name: &str) -> Option<String> {
+        self.format(name, Radix::Binary)
+    }
+}
+
+/// How `format_bits` writes a value, like the `%B`, `%D` and `%X` formats of
+/// a test script's output-list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Radix {
+    /// One digit per bit.
+    #[default]
+    Binary,
+    /// Unsigned decimal.
+    Decimal,
+    /// Two's complement decimal.
+    Signed,
+    /// Hexadecimal, with a digit for every 4 bits.
+    Hex,
+}
+
+/// Formats a value, most significant bit first. Unknown bits are `?` in
+/// binary, and make the whole value `?` in the other radixes. Values wider
+/// than 128 bits are always binary.
+pub fn format_bits(bits: &[Option<bool>], radix: Radix) -> String {
+    let binary = || {
+        bits.iter()
+            .map(|b| match b {
+                Some(true) => '1',
+                Some(false) => '0',
+                None => '?',
+            })
+            .collect()
+    };
+    if radix == Radix::Binary || bits.len() > 128 {
+        return binary();
+    }
+    let Some(known) = bits.iter().copied().collect::<Option<Vec<bool>>>() else {
+        return String::from("?");
+    };
+    let unsigned = known.iter().fold(0u128, |acc, b| (acc << 1) | *b as u128);
+    match radix {
+        Radix::Binary => binary(),
+        Radix::Decimal => unsigned.to_string(),
+        // Shift the sign bit to the top and back to extend it.
+        Radix::Signed if known.is_empty() => String::from("0"),
+        Radix::Signed => {
+            let shift = 128 - known.len();
+            (((unsigned << shift) as i128) >> shift).to_string()
+        }
+        Radix::Hex => format!("{:0w$X}", unsigned, w = known.len().div_ceil(4)),
+    }
+}
+
+/// Reads a string of 0s and 1s, most significant bit first, as the bits of a
+/// bus `width` bits wide, padded with leading zeros. Returns None if the
+/// string has other characters or more than `width` of them.
+pub fn parse_binary(bits: &str, width: usize) -> Option<Vec<bool>> {
+    if bits.len() > width || !bits.chars().all(|c| c == '0' || c == '1') {
+        return None;
+    }
+    let mut values = vec![false; width - bits.len()];
+    values.extend(bits.chars().map(|c| c == '1'));
+    Some(values)
 }
 
 #[cfg(test)]
@@ -300,4 +485,134 @@ mod test {
         let b = BusMap::try_from([("a", false)]).expect("Error creating bus.");
         assert_eq!(b.get_bus(&Bus::from("a")), vec![Some(false)]);
     }
+
+    #[test]
+    fn test_busmap_value_formatting() {
+        let mut bits = vec![true; 16];
+        bits[15] = false;
+        let b = BusMap::try_from([("neg", bits), ("one", vec![false, false, false, true])])
+            .expect("Error creating bus.");
+
+        assert_eq!(b.as_signed("neg"), Some(-2));
+        assert_eq!(b.as_unsigned("neg"), Some(0xFFFE));
+        assert_eq!(b.format("neg", Radix::Hex), Some(String::from("FFFE")));
+        assert_eq!(b.as_binary("neg"), Some(String::from("1111111111111110")));
+
+        assert_eq!(b.as_signed("one"), Some(1));
+        assert_eq!(b.format("one", Radix::Hex), Some(String::from("1")));
+        assert_eq!(b.as_binary("one"), Some(String::from("0001")));
+
+        assert_eq!(b.as_signed("missing"), None);
+    }
+
+    // A range past the width of the bus reads the missing bits as None.
+    #[test]
+    fn test_busmap_get_range() {
+        let mut b = BusMap::new();
+        b.create_bus("x", 4).expect("Error creating bus.");
+        b.set_integer("x", 0b0110).expect("Error setting value.");
+
+        assert_eq!(b.get_range("x", 0..4), b.get_bus(&b.full_bus("x").unwrap()));
+        assert_eq!(b.get_range("x", 1..3), vec![Some(true), Some(true)]);
+        assert_eq!(b.get_range("x", 0..1), vec![Some(false)]);
+        assert_eq!(
+            b.get_range("x", 2..6),
+            vec![None, None, Some(false), Some(true)]
+        );
+        assert_eq!(b.get_range("x", 4..6), vec![None, None]);
+        assert_eq!(b.get_range("x", 2..2), vec![]);
+        assert_eq!(b.get_range("y", 0..2), vec![None, None]);
+
+        let bus = b.full_bus("x").unwrap();
+        assert_eq!((bus.name.as_str(), bus.range), ("x", Some(0..4)));
+        assert!(b.full_bus("y").is_none());
+    }
+
+    #[test]
+    fn test_busmap_set_integer() {
+        let mut b = BusMap::new();
+        b.create_bus("x", 4).expect("Error creating bus.");
+
+        b.set_integer("x", 5).expect("Error setting value.");
+        assert_eq!(b.as_binary("x"), Some(String::from("0101")));
+
+        b.set_integer("x", -1).expect("Error setting value.");
+        assert_eq!(b.as_binary("x"), Some(String::from("1111")));
+        assert_eq!(b.as_signed("x"), Some(-1));
+
+        assert!(b.set_integer("x", 16).is_err());
+        assert!(b.set_integer("x", -9).is_err());
+        assert!(b.set_integer("y", 0).is_err());
+    }
+
+    #[test]
+    fn test_busmap_value_formatting_undriven() {
+        let mut b = BusMap::new();
+        b.create_bus("x", 4).expect("Error creating bus.");
+        b.insert(
+            Bus {
+                name: String::from("x"),
+                range: Some(0..2),
+            },
+            vec![true, true],
+        );
+
+        assert_eq!(b.as_signed("x"), None);
+        assert_eq!(b.as_unsigned("x"), None);
+        assert_eq!(b.as_binary("x"), Some(String::from("??11")));
+        assert_eq!(b.format("x", Radix::Hex), Some(String::from("?")));
+        assert_eq!(b.format("x", Radix::Signed), Some(String::from("?")));
+        assert_eq!(b.as_binary("y"), None);
+    }
+
+    #[test]
+    fn test_format_bits() {
+        let bits = |s: &str| -> Vec<Option<bool>> {
+            s.chars()
+                .map(|c| match c {
+                    '1' => Some(true),
+                    '0' => Some(false),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(format_bits(&bits("0110"), Radix::Binary), "0110");
+        assert_eq!(format_bits(&bits("1?0"), Radix::Binary), "1?0");
+        assert_eq!(format_bits(&bits("1?0"), Radix::Decimal), "?");
+        assert_eq!(format_bits(&bits("1110"), Radix::Decimal), "14");
+        assert_eq!(format_bits(&bits("1110"), Radix::Signed), "-2");
+        assert_eq!(format_bits(&bits("0110"), Radix::Signed), "6");
+        assert_eq!(format_bits(&bits("101011110"), Radix::Hex), "15E");
+        assert_eq!(format_bits(&bits(&"1".repeat(16)), Radix::Signed), "-1");
+        assert_eq!(format_bits(&bits(&"1".repeat(128)), Radix::Signed), "-1");
+        assert_eq!(format_bits(&[], Radix::Signed), "0");
+    }
+
+    #[test]
+    fn test_parse_binary() {
+        assert_eq!(parse_binary("101", 3), Some(vec![true, false, true]));
+        assert_eq!(parse_binary("1", 3), Some(vec![false, false, true]));
+        assert_eq!(parse_binary("", 2), Some(vec![false, false]));
+        assert_eq!(parse_binary("1010", 3), None);
+        assert_eq!(parse_binary("102", 3), None);
+    }
+
+    #[test]
+    fn test_busmap_from_integers() {
+        let b = BusMap::from_integers(&[("addr", 5, 3), ("load", 1, 1), ("wide", u64::MAX, 64)])
+            .expect("Error creating bus.");
+        assert_eq!(b.as_binary("addr"), Some(String::from("101")));
+        assert_eq!(
+            b.get_bus(&Bus {
+                name: String::from("addr"),
+                range: Some(0..1),
+            }),
+            vec![Some(true)]
+        );
+        assert_eq!(b.as_unsigned("load"), Some(1));
+        assert_eq!(b.as_unsigned("wide"), Some(u64::MAX));
+
+        let err = BusMap::from_integers(&[("addr", 8, 3)]).expect_err("Expected width error");
+        assert_eq!(err, "Value 8 does not fit in 3 bits for bus addr.");
+    }
 }
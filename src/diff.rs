@@ -0,0 +1,285 @@
+//! Structural differences between two versions of a chip.
+//!
+//! Both versions are flattened to NAND gates and DFFs. A gate is identified
+//! by its path through the parts that contain it, such as
+//! `Mux.1/Not.0/nand.0` for the NAND gate of the first Not in the second
+//! Mux. Each net is named after the outermost port bit it is connected to,
+//! such as `a[0]` or `nand.2/out`. Gates that are in only one version were
+//! added or removed, and gates in both versions whose nets differ were
+//! rewired.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use crate::parser::ChipHDL;
+use crate::vhdl::Netlist;
+
+/// A NAND gate or DFF of a flattened chip.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gate {
+    /// NAND or DFF.
+    pub kind: &'static str,
+    /// Name of the net connected to each pin of the gate, with the output
+    /// pin last.
+    pub pins: Vec<(&'static str, String)>,
+}
+
+impl Gate {
+    fn output(&self) -> &str {
+        &self.pins[self.pins.len() - 1].1
+    }
+
+    fn inputs(&self) -> impl Iterator<Item = &str> {
+        self.pins[..self.pins.len() - 1]
+            .iter()
+            .map(|(_, net)| net.as_str())
+    }
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pins: Vec<String> = self
+            .pins
+            .iter()
+            .map(|(pin, net)| format!("{}={}", pin, net))
+            .collect();
+        write!(f, "{}({})", self.kind, pins.join(", "))
+    }
+}
+
+/// Gates of a flattened chip by path.
+pub type Gates = BTreeMap<String, Gate>;
+
+/// Flattens a chip and names its gates and nets.
+pub fn gates(hdl: &ChipHDL) -> Result<Gates, Box<dyn Error>> {
+    let (mut netlist, _) = Netlist::new(hdl)?;
+
+    // Ports with fewer parts in their path are further out. Among ports
+    // equally far out, a net is named after the gate that drives it, so
+    // that it keeps its name when the gates reading it change. Literals
+    // only name nets that are not connected to any port.
+    let outputs: HashSet<String> = netlist
+        .nand_paths
+        .iter()
+        .chain(&netlist.dff_paths)
+        .map(|path| format!("{}/out", path))
+        .collect();
+    let mut names: HashMap<usize, ((usize, bool), String)> = HashMap::new();
+    let mut name_net = |netlist: &mut Netlist, net: usize, rank: (usize, bool), name: &str| {
+        let root = netlist.find(net);
+        let candidate = (rank, String::from(name));
+        match names.get(&root) {
+            Some(current) if *current <= candidate => {}
+            _ => {
+                names.insert(root, candidate);
+            }
+        }
+    };
+    for (net, name) in netlist.port_bits.clone() {
+        let rank = (name.matches('/').count(), !outputs.contains(&name));
+        name_net(&mut netlist, net, rank, &name);
+    }
+    for (net, value) in netlist.literals.clone() {
+        let name = match value {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "none",
+        };
+        name_net(&mut netlist, net, (usize::MAX, true), name);
+    }
+    let name = |netlist: &mut Netlist, net: usize| {
+        let root = netlist.find(net);
+        match names.get(&root) {
+            Some((_, name)) => name.clone(),
+            None => format!("net{}", root),
+        }
+    };
+
+    let mut res = Gates::new();
+    for ((a, b, out), path) in netlist
+        .nands
+        .clone()
+        .into_iter()
+        .zip(netlist.nand_paths.clone())
+    {
+        let pins = vec![
+            ("a", name(&mut netlist, a)),
+            ("b", name(&mut netlist, b)),
+            ("out", name(&mut netlist, out)),
+        ];
+        res.insert(path, Gate { kind: "NAND", pins });
+    }
+    for ((d, q), path) in netlist
+        .dffs
+        .clone()
+        .into_iter()
+        .zip(netlist.dff_paths.clone())
+    {
+        let pins = vec![
+            ("in", name(&mut netlist, d)),
+            ("out", name(&mut netlist, q)),
+        ];
+        res.insert(path, Gate { kind: "DFF", pins });
+    }
+    Ok(res)
+}
+
+/// Gates that differ between two versions of a chip.
+#[derive(Debug, Default)]
+pub struct ChipDiff {
+    pub added: Gates,
+    pub removed: Gates,
+    /// Gates in both versions whose nets differ, with the old and new gate.
+    pub rewired: BTreeMap<String, (Gate, Gate)>,
+    pub unchanged: Gates,
+}
+
+/// Compares the flattened gates of two versions of a chip.
+pub fn diff(old: &ChipHDL, new: &ChipHDL) -> Result<ChipDiff, Box<dyn Error>> {
+    Ok(diff_gates(gates(old)?, gates(new)?))
+}
+
+fn diff_gates(mut old: Gates, new: Gates) -> ChipDiff {
+    let mut res = ChipDiff::default();
+    for (path, new_gate) in new {
+        match old.remove(&path) {
+            None => {
+                res.added.insert(path, new_gate);
+            }
+            Some(old_gate) if old_gate == new_gate => {
+                res.unchanged.insert(path, new_gate);
+            }
+            Some(old_gate) => {
+                res.rewired.insert(path, (old_gate, new_gate));
+            }
+        }
+    }
+    res.removed = old;
+    res
+}
+
+impl ChipDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.rewired.is_empty()
+    }
+
+    /// Writes a Graphviz graph of the new version of the chip, with removed
+    /// gates added back. Added gates are green, removed gates are red, and
+    /// rewired gates are orange. Edges go from the gate that drives a net to
+    /// the gates that read it. Nets with no driver, such as input port
+    /// bits, are drawn as plain text.
+    pub fn to_dot(&self) -> String {
+        let mut colored: Vec<(&String, &Gate, &str)> = Vec::new();
+        colored.extend(self.unchanged.iter().map(|(p, g)| (p, g, "black")));
+        colored.extend(self.added.iter().map(|(p, g)| (p, g, "green")));
+        colored.extend(self.removed.iter().map(|(p, g)| (p, g, "red")));
+        colored.extend(self.rewired.iter().map(|(p, (_, g))| (p, g, "orange")));
+        colored.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut drivers: HashMap<&str, &str> = HashMap::new();
+        for (path, gate, _) in &colored {
+            drivers.insert(gate.output(), path.as_str());
+        }
+
+        let mut dot = String::from("digraph {\n");
+        let mut inputs = BTreeSet::new();
+        for (path, gate, color) in &colored {
+            dot.push_str(&format!(
+                "  \"{}\" [label=\"{}\\n{}\", color={}];\n",
+                path, gate.kind, path, color
+            ));
+            for net in gate.inputs() {
+                let source = match drivers.get(net) {
+                    Some(driver) => driver,
+                    None => {
+                        inputs.insert(net);
+                        net
+                    }
+                };
+                dot.push_str(&format!(
+                    "  \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                    source, path, net
+                ));
+            }
+        }
+        for net in inputs {
+            dot.push_str(&format!("  \"{}\" [shape=plaintext];\n", net));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl fmt::Display for ChipDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No structural differences.");
+        }
+        writeln!(
+            f,
+            "{} gates added, {} removed, {} rewired.",
+            self.added.len(),
+            self.removed.len(),
+            self.rewired.len()
+        )?;
+        for (path, gate) in &self.removed {
+            writeln!(f, "- {} {}", path, gate)?;
+        }
+        for (path, gate) in &self.added {
+            writeln!(f, "+ {} {}", path, gate)?;
+        }
+        for (path, (old, new)) in &self.rewired {
+            writeln!(f, "~ {} {} -> {}", path, old, new)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_hdl_path;
+    use std::env;
+    use std::path::Path;
+
+    fn parse(path: &str) -> ChipHDL {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let (hdl, _) =
+            parse_hdl_path(&manifest_dir.join("resources/tests").join(path)).expect("Parse error");
+        hdl
+    }
+
+    #[test]
+    fn test_diff_same_chip() {
+        let and = parse("nand2tetris/solutions/And.hdl");
+        let res = diff(&and, &and).expect("Diff error");
+        assert!(res.is_empty());
+        assert_eq!(res.unchanged.len(), 2);
+        assert_eq!(
+            res.unchanged["Not.0/nand.0"].to_string(),
+            "NAND(a=nand.0/out, b=nand.0/out, out=out)"
+        );
+        assert_eq!(res.to_string(), "No structural differences.\n");
+    }
+
+    #[test]
+    fn test_diff_changed_chip() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let dir = manifest_dir.join("resources/tests/diff");
+        let (old, _) = parse_hdl_path(&dir.join("old/Xor.hdl")).expect("Parse error");
+        let (new, _) = parse_hdl_path(&dir.join("new/Xor.hdl")).expect("Parse error");
+        let res = diff(&old, &new).expect("Diff error");
+
+        assert_eq!(res.removed.keys().collect::<Vec<_>>(), vec!["nand.3"]);
+        assert_eq!(res.added.keys().collect::<Vec<_>>(), vec!["Not.0/nand.0"]);
+        assert_eq!(res.rewired.keys().collect::<Vec<_>>(), vec!["nand.2"]);
+        assert!(res
+            .to_string()
+            .starts_with("1 gates added, 1 removed, 1 rewired.\n"));
+
+        let dot = res.to_dot();
+        assert!(dot.contains("\"nand.3\" [label=\"NAND\\nnand.3\", color=red];"));
+        assert!(dot.contains("\"a\" [shape=plaintext];"));
+    }
+}
@@ -10,13 +10,44 @@ use std::rc::Rc;
 pub enum ErrorKind {
     ParseError(crate::scanner::Token),
     ParseIdentError(Rc<dyn HdlProvider>, crate::parser::Identifier),
+    // The token and path say where the error is, for callers that want it.
+    #[allow(dead_code)]
     TestParseError(crate::test_scanner::Token),
+    #[allow(dead_code)]
     SimulationError(Option<PathBuf>),
     IOError,
     Other,
     NonNumeric,
 }
 
+impl ErrorKind {
+    /// Exit code of a command that fails with this kind of error: 1 for
+    /// errors in HDL or test script source, 2 for simulation errors, 3 for
+    /// IO errors, and 4 for anything else.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ErrorKind::ParseError(_) | ErrorKind::ParseIdentError(..) => 1,
+            ErrorKind::TestParseError(_) => 1,
+            ErrorKind::SimulationError(_) | ErrorKind::NonNumeric => 2,
+            ErrorKind::IOError => 3,
+            ErrorKind::Other => 4,
+        }
+    }
+}
+
+/// Exit code of a command that fails with `e`. See `ErrorKind::exit_code`.
+pub fn exit_code(e: &(dyn Error + 'static)) -> u8 {
+    if let Some(e) = e.downcast_ref::<N2VError>() {
+        e.kind.exit_code()
+    } else if let Some(e) = e.downcast_ref::<TransformedError>() {
+        e.kind.exit_code()
+    } else if e.is::<io::Error>() {
+        ErrorKind::IOError.exit_code()
+    } else {
+        ErrorKind::Other.exit_code()
+    }
+}
+
 /// N2VError should be used when generating an error that has no other
 /// source error object. This is the start of the error propagation chain.
 pub struct N2VError {
@@ -48,9 +79,8 @@ impl std::fmt::Debug for TransformedError {
 /// the entire error chain.
 impl std::fmt::Display for TransformedError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.source.is_some() {
+        if let Some(error_source) = &self.source {
             write!(f, "{}", N2VError::from(self))?;
-            let error_source = &**(self.source.as_ref().unwrap());
             write!(f, "{}", error_source)
         } else {
             write!(f, "{}", N2VError::from(self))
@@ -74,6 +104,11 @@ impl std::fmt::Display for N2VError {
         #[allow(unused_must_use)]
         match &self.kind {
             ErrorKind::ParseError(t) => {
+                // Tokens that were not read from a file, such as those made
+                // for generated HDL, have no path and no source to show.
+                if t.path.as_os_str().is_empty() {
+                    return writeln!(f, "{}", self.msg);
+                }
                 let file = match File::open(t.path.clone()) {
                     Ok(x) => x,
                     Err(_) => {
@@ -82,27 +117,20 @@ impl std::fmt::Display for N2VError {
                     }
                 };
 
-                let n2 = t.line;
-                let line_num: usize = n2.try_into().unwrap();
-                let l = io::BufReader::new(file).lines().nth(line_num - 1);
-                if l.is_none() {
-                    writeln!(f, "-- PARSE ERROR ----------- {}", t.path.clone().display());
+                let line_num: usize = t.line.try_into().unwrap();
+                let mut lines = io::BufReader::new(file).lines();
+                let l = line_num
+                    .checked_sub(1)
+                    .and_then(|i| lines.nth(i))
+                    .and_then(Result::ok);
+                writeln!(f, "-- PARSE ERROR ----------- {}", t.path.clone().display());
+                let Some(l) = l else {
                     writeln!(f, "{}|", t.line);
                     return writeln!(f, "\n\n{}", self.msg);
-                }
-
-                let l = l.unwrap().unwrap();
-                let col = t.start;
-                let digits = line_num.to_string();
+                };
 
-                writeln!(f, "-- PARSE ERROR ----------- {}", t.path.clone().display());
                 writeln!(f, "{}| {}", t.line, l);
-                for _ in 0..(col + digits.len() + 2 - t.lexeme.len()) {
-                    write!(f, " ");
-                }
-                for _ in 0..t.lexeme.len() {
-                    write!(f, "^");
-                }
+                write!(f, "{}", underline(t, line_num));
                 writeln!(f, "\n\n{}", self.msg)
             }
             ErrorKind::ParseIdentError(provider, ident) => {
@@ -165,6 +193,16 @@ impl std::fmt::Display for N2VError {
     }
 }
 
+// Carets under the columns of `token`, lined up with its line printed after
+// the prefix `{line_num}| `. An empty token, such as the end of the file,
+// gets a single caret.
+fn underline(token: &crate::scanner::Token, line_num: usize) -> String {
+    let prefix = line_num.to_string().len() + 2;
+    let width = token.end.saturating_sub(token.start).max(1);
+    let column = token.start.saturating_sub(1);
+    format!("{}{}", " ".repeat(prefix + column), "^".repeat(width))
+}
+
 impl From<String> for N2VError {
     fn from(e: String) -> Self {
         N2VError {
@@ -194,3 +232,76 @@ impl Error for TransformedError {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let error = |kind: ErrorKind| -> Box<dyn Error> {
+            Box::new(N2VError {
+                msg: String::new(),
+                kind,
+            })
+        };
+        assert_eq!(
+            exit_code(error(ErrorKind::SimulationError(None)).as_ref()),
+            2
+        );
+        assert_eq!(exit_code(error(ErrorKind::Other).as_ref()), 4);
+
+        let not_found = || io::Error::new(io::ErrorKind::NotFound, "missing");
+        let transformed: Box<dyn Error> = Box::new(TransformedError {
+            msg: String::from("Unable to read file."),
+            kind: ErrorKind::IOError,
+            source: Some(Box::new(not_found())),
+        });
+        assert_eq!(exit_code(transformed.as_ref()), 3);
+        let io_error: Box<dyn Error> = Box::new(not_found());
+        assert_eq!(exit_code(io_error.as_ref()), 3);
+    }
+
+    // The carets are under the unexpected token, past the line number.
+    #[test]
+    fn test_parse_error_underline() {
+        let manifest_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = manifest_dir.join("resources/tests/bad/BitwiseAnd.hdl");
+        let error = crate::parser::parse_hdl_path(&path).err().unwrap();
+        let lines: Vec<String> = error.to_string().lines().map(String::from).collect();
+        assert_eq!(lines[1], "5|     FOR i in 0 to W-1 {");
+        assert_eq!(lines[2], "       ^^^");
+    }
+
+    // Without a readable source line, only the message and where it came
+    // from are printed.
+    #[test]
+    fn test_parse_error_without_source() {
+        let manifest_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let error = |path: PathBuf, line: u32| N2VError {
+            msg: String::from("Expected a semicolon."),
+            kind: ErrorKind::ParseError(crate::scanner::Token {
+                token_type: crate::scanner::TokenType::Identifier,
+                lexeme: String::from("x"),
+                line,
+                start: 0,
+                end: 0,
+                path,
+                radix: 10,
+            }),
+        };
+        assert_eq!(
+            error(PathBuf::new(), 0).to_string(),
+            "Expected a semicolon.\n"
+        );
+
+        let path = manifest_dir.join("resources/tests/bad/BitwiseAnd.hdl");
+        let past_end = error(path.clone(), 1000).to_string();
+        assert!(past_end.contains("\n1000|\n"));
+        assert!(past_end.ends_with("Expected a semicolon.\n"));
+
+        let first = error(path, 1).to_string();
+        let lines: Vec<&str> = first.lines().collect();
+        assert_eq!(lines[2], "   ^");
+    }
+}
@@ -1,6 +1,8 @@
 //! AST for expressions in HDL programs.
-//! HDL Expressions are limited to addition and subtraction operators.
-//! The `Max` operator is for supporting "MAXIMUM" in synthesized VHDL expressions.
+//! HDL Expressions are limited to addition, subtraction, multiplication, division and
+//! remainder operators.
+//! The `Max` operator is for supporting "MAXIMUM" in synthesized VHDL expressions,
+//! which is written as `maximum` for VHDL 2008.
 //! `Max` cannot be used in HDL. Quartus Lite does not support VHDL 2008... ugh.
 
 use std::cmp::Ordering;
@@ -26,6 +28,15 @@ impl GenericWidth {
     pub fn is_numeric(&self) -> bool {
         matches!(self, GenericWidth::Terminal(Terminal::Num(_)))
     }
+
+    /// Returns true if the variable `name` appears anywhere in the expression.
+    pub fn uses_variable(&self, name: &str) -> bool {
+        match self {
+            GenericWidth::Terminal(Terminal::Var(v)) => v.value == name,
+            GenericWidth::Terminal(Terminal::Num(_)) => false,
+            GenericWidth::Expr(_, a, b) => a.uses_variable(name) || b.uses_variable(name),
+        }
+    }
 }
 
 impl From<&Range<usize>> for GenericWidth {
@@ -75,11 +86,39 @@ impl std::ops::Sub<&GenericWidth> for &GenericWidth {
                 return GenericWidth::Terminal(Terminal::Num(x - y));
             }
         }
+        // Such as the last index of a loop over W-1 TO 0 subtracted from W-1.
+        if same_width(self, rhs) {
+            return GenericWidth::Terminal(Terminal::Num(0));
+        }
 
         GenericWidth::Expr(Op::Sub, Box::new(self.clone()), Box::new(rhs.clone()))
     }
 }
 
+impl std::ops::Mul<GenericWidth> for GenericWidth {
+    type Output = GenericWidth;
+
+    fn mul(self, rhs: GenericWidth) -> GenericWidth {
+        (&self) * (&rhs)
+    }
+}
+
+impl std::ops::Mul<&GenericWidth> for &GenericWidth {
+    type Output = GenericWidth;
+
+    fn mul(self, rhs: &GenericWidth) -> GenericWidth {
+        use GenericWidth::Terminal as T;
+        match (self, rhs) {
+            (T(Terminal::Num(x)), T(Terminal::Num(y))) => T(Terminal::Num(x * y)),
+            // 0 * N = 0
+            (zero @ T(Terminal::Num(0)), _) | (_, zero @ T(Terminal::Num(0))) => zero.clone(),
+            // 1 * N = N
+            (T(Terminal::Num(1)), n) | (n, T(Terminal::Num(1))) => n.clone(),
+            _ => GenericWidth::Expr(Op::Mul, Box::new(self.clone()), Box::new(rhs.clone())),
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
 pub enum Terminal {
     Var(Identifier),
@@ -90,26 +129,48 @@ pub enum Terminal {
 pub enum Op {
     Add,
     Sub,
+    Mul,
     Max,
+    Div,
+    Mod,
 }
 
 impl std::fmt::Display for GenericWidth {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.write_with_maximum(f, "MAXIMUM")
+    }
+}
+
+impl GenericWidth {
+    /// Writes the expression as Display does, but calls the maximum function
+    /// `maximum`.
+    pub fn write_with_maximum(
+        &self,
+        w: &mut dyn std::fmt::Write,
+        maximum: &str,
+    ) -> std::fmt::Result {
         match self {
             GenericWidth::Terminal(i) => {
-                write!(f, "{}", i)
+                write!(w, "{}", i)
             }
-            GenericWidth::Expr(op, a, b) => match op {
-                Op::Add => {
-                    write!(f, "({} + {})", a, b)
-                }
-                Op::Sub => {
-                    write!(f, "({} - {})", a, b)
+            GenericWidth::Expr(op, a, b) => {
+                let separator = match op {
+                    Op::Add => " + ",
+                    Op::Sub => " - ",
+                    Op::Mul => " * ",
+                    Op::Max => ", ",
+                    Op::Div => " / ",
+                    Op::Mod => " mod ",
+                };
+                if *op == Op::Max {
+                    write!(w, "{}", maximum)?;
                 }
-                Op::Max => {
-                    write!(f, "MAXIMUM({}, {})", a, b)
-                }
-            },
+                write!(w, "(")?;
+                a.write_with_maximum(w, maximum)?;
+                write!(w, "{}", separator)?;
+                b.write_with_maximum(w, maximum)?;
+                write!(w, ")")
+            }
         }
     }
 }
@@ -137,10 +198,15 @@ pub fn eval_expr_numeric(
         .map(|(k, v)| (k.clone(), GenericWidth::Terminal(Terminal::Num(*v))))
         .collect();
 
-    let res = eval_expr(expr, &general_state);
+    let res = eval_expr(expr, &general_state)?;
 
     if let GenericWidth::Terminal(Terminal::Num(x)) = res {
         Ok(x)
+    } else if let Some(var) = undefined_variable(expr, state) {
+        Err(N2VError {
+            msg: format!("Undefined generic '{}' in expression {}", var.value, expr),
+            kind: ErrorKind::NonNumeric,
+        })
     } else {
         Err(N2VError {
             msg: format!("Expression {} is non-numeric", expr),
@@ -149,13 +215,37 @@ pub fn eval_expr_numeric(
     }
 }
 
+/// Returns the first variable in an expression that has no value in `state`.
+pub fn undefined_variable<'a>(
+    expr: &'a GenericWidth,
+    state: &HashMap<String, usize>,
+) -> Option<&'a Identifier> {
+    match expr {
+        GenericWidth::Terminal(Terminal::Var(v)) if !state.contains_key(&v.value) => Some(v),
+        GenericWidth::Terminal(_) => None,
+        GenericWidth::Expr(_, e1, e2) => {
+            undefined_variable(e1, state).or_else(|| undefined_variable(e2, state))
+        }
+    }
+}
+
 /// Evaluates a width expression based on the current state of variables.
-pub fn eval_expr(expr: &GenericWidth, state: &HashMap<String, GenericWidth>) -> GenericWidth {
+/// Fails if it divides by zero.
+pub fn eval_expr(
+    expr: &GenericWidth,
+    state: &HashMap<String, GenericWidth>,
+) -> Result<GenericWidth, N2VError> {
     let res = match expr {
         GenericWidth::Terminal(t) => eval_terminal(t, state),
-        GenericWidth::Expr(Op::Add, t1, t2) => eval_expr(t1, state) + eval_expr(t2, state),
-        GenericWidth::Expr(Op::Sub, t1, t2) => eval_expr(t1, state) - eval_expr(t2, state),
-        GenericWidth::Expr(Op::Max, t1, t2) => eval_max(eval_expr(t1, state), eval_expr(t2, state)),
+        GenericWidth::Expr(Op::Add, t1, t2) => eval_expr(t1, state)? + eval_expr(t2, state)?,
+        GenericWidth::Expr(Op::Sub, t1, t2) => eval_expr(t1, state)? - eval_expr(t2, state)?,
+        GenericWidth::Expr(Op::Mul, t1, t2) => eval_expr(t1, state)? * eval_expr(t2, state)?,
+        GenericWidth::Expr(Op::Max, t1, t2) => {
+            eval_max(eval_expr(t1, state)?, eval_expr(t2, state)?)?
+        }
+        GenericWidth::Expr(op @ (Op::Div | Op::Mod), t1, t2) => {
+            eval_div(*op, eval_expr(t1, state)?, eval_expr(t2, state)?)?
+        }
     };
 
     // normalize (constant + var) to (var + constant)
@@ -163,7 +253,7 @@ pub fn eval_expr(expr: &GenericWidth, state: &HashMap<String, GenericWidth>) ->
     if let GenericWidth::Expr(Op::Add, lhs, rhs) = &res {
         if let GenericWidth::Terminal(Terminal::Num(_)) = &**lhs {
             if let GenericWidth::Terminal(Terminal::Var(_)) = &**rhs {
-                return GenericWidth::Expr(Op::Add, rhs.clone(), lhs.clone());
+                return Ok(GenericWidth::Expr(Op::Add, rhs.clone(), lhs.clone()));
             }
         }
     };
@@ -174,7 +264,7 @@ pub fn eval_expr(expr: &GenericWidth, state: &HashMap<String, GenericWidth>) ->
     // (N - C) + D) = N - (C - D)   if C > D
     // (N - C) + D) = N             if C = D
     if let GenericWidth::Expr(Op::Add, lhs, rhs) = &res {
-        if let GenericWidth::Expr(op, lhs_lhs, lhs_rhs) = &**lhs {
+        if let GenericWidth::Expr(op @ (Op::Add | Op::Sub), lhs_lhs, lhs_rhs) = &**lhs {
             if let n @ GenericWidth::Terminal(Terminal::Var(x)) = &**lhs_lhs {
                 if let c @ GenericWidth::Terminal(Terminal::Num(c_num)) = &**lhs_rhs {
                     if let d @ GenericWidth::Terminal(Terminal::Num(d_num)) = &**rhs {
@@ -191,7 +281,7 @@ pub fn eval_expr(expr: &GenericWidth, state: &HashMap<String, GenericWidth>) ->
                                     Box::new(d.clone()),
                                 ),
                                 Ordering::Equal => {
-                                    return GenericWidth::Terminal(Terminal::Var(x.clone()));
+                                    return Ok(GenericWidth::Terminal(Terminal::Var(x.clone())));
                                 }
                             },
                             Op::Add => GenericWidth::Expr(
@@ -199,9 +289,9 @@ pub fn eval_expr(expr: &GenericWidth, state: &HashMap<String, GenericWidth>) ->
                                 Box::new(c.clone()),
                                 Box::new(d.clone()),
                             ),
-                            Op::Max => panic!(), // MAX should already have been evaluated or produced an error.
+                            Op::Mul | Op::Max | Op::Div | Op::Mod => unreachable!(),
                         };
-                        let collapsed_expr = eval_expr(&collapse_expr, state);
+                        let collapsed_expr = eval_expr(&collapse_expr, state)?;
 
                         let outer_op = match op {
                             Op::Sub => match d_num.cmp(c_num) {
@@ -210,14 +300,14 @@ pub fn eval_expr(expr: &GenericWidth, state: &HashMap<String, GenericWidth>) ->
                                 Ordering::Equal => panic!(),
                             },
                             Op::Add => Op::Add,
-                            Op::Max => panic!(),
+                            Op::Mul | Op::Max | Op::Div | Op::Mod => unreachable!(),
                         };
                         let finished = GenericWidth::Expr(
                             outer_op,
                             Box::new(n.clone()),
                             Box::new(collapsed_expr),
                         );
-                        return finished;
+                        return Ok(finished);
                     }
                 }
             }
@@ -229,7 +319,7 @@ pub fn eval_expr(expr: &GenericWidth, state: &HashMap<String, GenericWidth>) ->
     // (N + C) - D) = N - (D - C)   if C < D
     // (N + C) - D) = N             if C = D
     if let GenericWidth::Expr(Op::Sub, lhs, rhs) = &res {
-        if let GenericWidth::Expr(op, lhs_lhs, lhs_rhs) = &**lhs {
+        if let GenericWidth::Expr(op @ (Op::Add | Op::Sub), lhs_lhs, lhs_rhs) = &**lhs {
             if let n @ GenericWidth::Terminal(Terminal::Var(x)) = &**lhs_lhs {
                 if let c @ GenericWidth::Terminal(Terminal::Num(c_num)) = &**lhs_rhs {
                     if let d @ GenericWidth::Terminal(Terminal::Num(d_num)) = &**rhs {
@@ -246,7 +336,7 @@ pub fn eval_expr(expr: &GenericWidth, state: &HashMap<String, GenericWidth>) ->
                                     Box::new(c.clone()),
                                 ),
                                 Ordering::Equal => {
-                                    return GenericWidth::Terminal(Terminal::Var(x.clone()));
+                                    return Ok(GenericWidth::Terminal(Terminal::Var(x.clone())));
                                 }
                             },
                             Op::Sub => GenericWidth::Expr(
@@ -254,9 +344,9 @@ pub fn eval_expr(expr: &GenericWidth, state: &HashMap<String, GenericWidth>) ->
                                 Box::new(c.clone()),
                                 Box::new(d.clone()),
                             ),
-                            Op::Max => panic!(), // MAX should already have been evaluated or produced an error.
+                            Op::Mul | Op::Max | Op::Div | Op::Mod => unreachable!(),
                         };
-                        let collapsed_expr = eval_expr(&collapse_expr, state);
+                        let collapsed_expr = eval_expr(&collapse_expr, state)?;
                         let outer_op = match op {
                             Op::Add => match c_num.cmp(d_num) {
                                 Ordering::Greater => Op::Add,
@@ -264,21 +354,75 @@ pub fn eval_expr(expr: &GenericWidth, state: &HashMap<String, GenericWidth>) ->
                                 Ordering::Equal => panic!(),
                             },
                             Op::Sub => Op::Sub,
-                            Op::Max => panic!(),
+                            Op::Mul | Op::Max | Op::Div | Op::Mod => unreachable!(),
                         };
                         let finished = GenericWidth::Expr(
                             outer_op,
                             Box::new(n.clone()),
                             Box::new(collapsed_expr),
                         );
-                        return finished;
+                        return Ok(finished);
                     }
                 }
             }
         }
     };
 
-    res
+    // Chains the rules above miss, such as C + (N + D), are flattened into
+    // a single N + C, N - C or N.
+    Ok(canonical_chain(&res).unwrap_or(res))
+}
+
+/// Flattens a chain of additions and subtractions of constants and a
+/// single variable into `N + C`, `N - C` or `N`.
+/// Returns None if the chain has other variables, products, quotients, MAX,
+/// or subtracts the variable.
+fn canonical_chain(expr: &GenericWidth) -> Option<GenericWidth> {
+    let mut var = None;
+    let mut constant: i64 = 0;
+    if !flatten_chain(expr, true, &mut var, &mut constant) {
+        return None;
+    }
+    let n = GenericWidth::Terminal(Terminal::Var(var?));
+    let c = Box::new(GenericWidth::Terminal(Terminal::Num(
+        constant.unsigned_abs() as usize,
+    )));
+    Some(match constant.cmp(&0) {
+        Ordering::Greater => GenericWidth::Expr(Op::Add, Box::new(n), c),
+        Ordering::Less => GenericWidth::Expr(Op::Sub, Box::new(n), c),
+        Ordering::Equal => n,
+    })
+}
+
+// Adds the terms of expr to var and constant, negated if positive is false.
+// Returns false if expr cannot be written as N + C.
+fn flatten_chain(
+    expr: &GenericWidth,
+    positive: bool,
+    var: &mut Option<Identifier>,
+    constant: &mut i64,
+) -> bool {
+    match expr {
+        GenericWidth::Terminal(Terminal::Num(x)) => {
+            let x = *x as i64;
+            *constant += if positive { x } else { -x };
+            true
+        }
+        GenericWidth::Terminal(Terminal::Var(x)) => {
+            if !positive || var.is_some() {
+                return false;
+            }
+            *var = Some(x.clone());
+            true
+        }
+        GenericWidth::Expr(Op::Add, a, b) => {
+            flatten_chain(a, positive, var, constant) && flatten_chain(b, positive, var, constant)
+        }
+        GenericWidth::Expr(Op::Sub, a, b) => {
+            flatten_chain(a, positive, var, constant) && flatten_chain(b, !positive, var, constant)
+        }
+        GenericWidth::Expr(Op::Mul | Op::Max | Op::Div | Op::Mod, _, _) => false,
+    }
 }
 
 // Returns true if a and b have the same variable name, ignoring
@@ -287,19 +431,111 @@ fn same_variable_name(a: &Identifier, b: &Identifier) -> bool {
     a.value == b.value
 }
 
-fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
+/// Returns true if two widths are the same expression. Variables are
+/// compared by name only, so `W` from a port declaration equals `W` passed
+/// to a part.
+pub fn same_width(a: &GenericWidth, b: &GenericWidth) -> bool {
+    match (a, b) {
+        (GenericWidth::Terminal(Terminal::Var(x)), GenericWidth::Terminal(Terminal::Var(y))) => {
+            same_variable_name(x, y)
+        }
+        (GenericWidth::Terminal(Terminal::Num(x)), GenericWidth::Terminal(Terminal::Num(y))) => {
+            x == y
+        }
+        (GenericWidth::Expr(op1, l1, r1), GenericWidth::Expr(op2, l2, r2)) => {
+            op1 == op2 && same_width(l1, l2) && same_width(r1, r2)
+        }
+        _ => false,
+    }
+}
+
+// Folds a quotient or remainder of constants, N / 1 to N, and N % 1 to 0.
+// Others stay symbolic, such as N / 2.
+fn eval_div(op: Op, t1: GenericWidth, t2: GenericWidth) -> Result<GenericWidth, N2VError> {
+    use GenericWidth::Terminal as T;
+    let div = op == Op::Div;
+    match (&t1, &t2) {
+        (_, T(Terminal::Num(0))) => Err(N2VError {
+            msg: format!(
+                "Division by zero in {}",
+                GenericWidth::Expr(op, Box::new(t1.clone()), Box::new(t2.clone()))
+            ),
+            kind: ErrorKind::NonNumeric,
+        }),
+        (T(Terminal::Num(x)), T(Terminal::Num(y))) => {
+            Ok(T(Terminal::Num(if div { x / y } else { x % y })))
+        }
+        (_, T(Terminal::Num(1))) => Ok(if div { t1 } else { T(Terminal::Num(0)) }),
+        _ => Ok(GenericWidth::Expr(op, Box::new(t1), Box::new(t2))),
+    }
+}
+
+// Simplifies MAX(t1, t2). Nested maxes are flattened into one list of
+// operands and duplicates are dropped. Then any operand known to be no
+// larger than another is dropped until one is left. Fails if some operands
+// can't be compared, such as N and M, whose larger one depends on the
+// generic arguments.
+fn eval_max(t1: GenericWidth, t2: GenericWidth) -> Result<GenericWidth, N2VError> {
+    let mut operands = Vec::new();
+    push_max_operands(t1, &mut operands);
+    push_max_operands(t2, &mut operands);
+    while operands.len() > 1 {
+        let n = operands.len();
+        let mut pairs = (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j)));
+        let Some((i, j, max)) =
+            pairs.find_map(|(i, j)| Some((i, j, max_pair(&operands[i], &operands[j])?)))
+        else {
+            let expr = operands
+                .into_iter()
+                .reduce(|a, b| GenericWidth::Expr(Op::Max, Box::new(a), Box::new(b)))
+                .unwrap();
+            return Err(N2VError {
+                msg: format!(
+                    "Unable to simplify {}, because which operand is larger depends on the \
+                     generic arguments.",
+                    expr
+                ),
+                kind: ErrorKind::NonNumeric,
+            });
+        };
+        operands[i] = max;
+        operands.remove(j);
+    }
+    Ok(operands.remove(0))
+}
+
+// Adds the operands of a MAX expression, or the expression itself if it is
+// not one, to `operands` unless they are already there.
+fn push_max_operands(expr: GenericWidth, operands: &mut Vec<GenericWidth>) {
+    match expr {
+        GenericWidth::Expr(Op::Max, a, b) => {
+            push_max_operands(*a, operands);
+            push_max_operands(*b, operands);
+        }
+        _ => {
+            if !operands.iter().any(|o| same_width(o, &expr)) {
+                operands.push(expr);
+            }
+        }
+    }
+}
+
+// MAX(t1, t2) if one of them is known to be at least as large as the other.
+fn max_pair(t1: &GenericWidth, t2: &GenericWidth) -> Option<GenericWidth> {
     // Constant compared with constant
     if let GenericWidth::Terminal(Terminal::Num(n1)) = t1 {
         if let GenericWidth::Terminal(Terminal::Num(n2)) = t2 {
-            return GenericWidth::Terminal(Terminal::Num(std::cmp::max(n1, n2)));
+            return Some(GenericWidth::Terminal(Terminal::Num(*std::cmp::max(
+                n1, n2,
+            ))));
         }
     }
 
     // N, N -> LHS (EQ)
-    if let GenericWidth::Terminal(Terminal::Var(n1)) = &t1 {
-        if let GenericWidth::Terminal(Terminal::Var(n2)) = &t2 {
+    if let GenericWidth::Terminal(Terminal::Var(n1)) = t1 {
+        if let GenericWidth::Terminal(Terminal::Var(n2)) = t2 {
             if same_variable_name(n1, n2) {
-                return t1;
+                return Some(t1.clone());
             }
         }
     }
@@ -307,34 +543,34 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
     // N, D -> LHS
     if let GenericWidth::Terminal(Terminal::Var(_)) = t1 {
         if let GenericWidth::Terminal(Terminal::Num(_)) = t2 {
-            return t1;
+            return Some(t1.clone());
         }
     }
     // D, N -> RHS
     if let GenericWidth::Terminal(Terminal::Num(_)) = t1 {
         if let GenericWidth::Terminal(Terminal::Var(_)) = t2 {
-            return t2;
+            return Some(t2.clone());
         }
     }
     // N op C, D -> LHS
-    if let GenericWidth::Expr(op, lhs_lhs, lhs_rhs) = &t1 {
+    if let GenericWidth::Expr(op, lhs_lhs, lhs_rhs) = t1 {
         if let GenericWidth::Terminal(Terminal::Var(_)) = &**lhs_lhs {
             if let GenericWidth::Terminal(Terminal::Num(_)) = &**lhs_rhs {
-                if let GenericWidth::Terminal(Terminal::Num(_)) = &t2 {
+                if let GenericWidth::Terminal(Terminal::Num(_)) = t2 {
                     if op == &Op::Add || op == &Op::Sub {
-                        return t1;
+                        return Some(t1.clone());
                     }
                 }
             }
         }
     }
     // D, N op C -> RHS
-    if let GenericWidth::Expr(op, rhs_lhs, rhs_rhs) = &t2 {
+    if let GenericWidth::Expr(op, rhs_lhs, rhs_rhs) = t2 {
         if let GenericWidth::Terminal(Terminal::Var(_)) = &**rhs_lhs {
             if let GenericWidth::Terminal(Terminal::Num(_)) = &**rhs_rhs {
-                if let GenericWidth::Terminal(Terminal::Num(_)) = &t1 {
+                if let GenericWidth::Terminal(Terminal::Num(_)) = t1 {
                     if op == &Op::Add || op == &Op::Sub {
-                        return t2;
+                        return Some(t2.clone());
                     }
                 }
             }
@@ -342,12 +578,12 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
     }
 
     // N, N - C     -> LHS
-    if let GenericWidth::Expr(Op::Sub, rhs_lhs, rhs_rhs) = &t2 {
+    if let GenericWidth::Expr(Op::Sub, rhs_lhs, rhs_rhs) = t2 {
         if let GenericWidth::Terminal(Terminal::Var(n1)) = &**rhs_lhs {
             if let GenericWidth::Terminal(Terminal::Num(_)) = &**rhs_rhs {
-                if let GenericWidth::Terminal(Terminal::Var(n2)) = &t1 {
+                if let GenericWidth::Terminal(Terminal::Var(n2)) = t1 {
                     if same_variable_name(n1, n2) {
-                        return t1;
+                        return Some(t1.clone());
                     }
                 }
             }
@@ -355,12 +591,12 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
     }
 
     // N - C, N      -> RHS
-    if let GenericWidth::Expr(Op::Sub, lhs_lhs, lhs_rhs) = &t1 {
+    if let GenericWidth::Expr(Op::Sub, lhs_lhs, lhs_rhs) = t1 {
         if let GenericWidth::Terminal(Terminal::Var(n1)) = &**lhs_lhs {
             if let GenericWidth::Terminal(Terminal::Num(_)) = &**lhs_rhs {
-                if let GenericWidth::Terminal(Terminal::Var(n2)) = &t2 {
+                if let GenericWidth::Terminal(Terminal::Var(n2)) = t2 {
                     if same_variable_name(n1, n2) {
-                        return t2;
+                        return Some(t2.clone());
                     }
                 }
             }
@@ -368,12 +604,12 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
     }
 
     // N, N + C     -> RHS
-    if let GenericWidth::Expr(Op::Add, rhs_lhs, rhs_rhs) = &t2 {
+    if let GenericWidth::Expr(Op::Add, rhs_lhs, rhs_rhs) = t2 {
         if let GenericWidth::Terminal(Terminal::Var(n1)) = &**rhs_lhs {
             if let GenericWidth::Terminal(Terminal::Num(_)) = &**rhs_rhs {
-                if let GenericWidth::Terminal(Terminal::Var(n2)) = &t1 {
+                if let GenericWidth::Terminal(Terminal::Var(n2)) = t1 {
                     if same_variable_name(n1, n2) {
-                        return t2;
+                        return Some(t2.clone());
                     }
                 }
             }
@@ -381,20 +617,20 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
     }
 
     // N + C, N     -> LHS
-    if let GenericWidth::Expr(Op::Add, lhs_lhs, lhs_rhs) = &t1 {
+    if let GenericWidth::Expr(Op::Add, lhs_lhs, lhs_rhs) = t1 {
         if let GenericWidth::Terminal(Terminal::Var(n1)) = &**lhs_lhs {
             if let GenericWidth::Terminal(Terminal::Num(_)) = &**lhs_rhs {
-                if let GenericWidth::Terminal(Terminal::Var(n2)) = &t2 {
+                if let GenericWidth::Terminal(Terminal::Var(n2)) = t2 {
                     if same_variable_name(n1, n2) {
-                        return t1;
+                        return Some(t1.clone());
                     }
                 }
             }
         }
     }
 
-    if let GenericWidth::Expr(Op::Add, lhs_lhs, lhs_rhs) = &t1 {
-        if let GenericWidth::Expr(Op::Add, rhs_lhs, rhs_rhs) = &t2 {
+    if let GenericWidth::Expr(Op::Add, lhs_lhs, lhs_rhs) = t1 {
+        if let GenericWidth::Expr(Op::Add, rhs_lhs, rhs_rhs) = t2 {
             if let GenericWidth::Terminal(Terminal::Var(n1)) = &**lhs_lhs {
                 if let GenericWidth::Terminal(Terminal::Num(c)) = &**lhs_rhs {
                     if let GenericWidth::Terminal(Terminal::Var(n2)) = &**rhs_lhs {
@@ -402,21 +638,21 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
                             if same_variable_name(n1, n2) {
                                 // N + C, N + D -> LHS if C = D and C == 0
                                 if c == &0 && d == &0 {
-                                    return GenericWidth::Terminal(Terminal::Var(n1.clone()));
+                                    return Some(GenericWidth::Terminal(Terminal::Var(n1.clone())));
                                 }
 
                                 match c.cmp(d) {
                                     // N + C, N + D -> LHS if C > D
                                     Ordering::Greater => {
-                                        return t1;
+                                        return Some(t1.clone());
                                     }
                                     // N + C, N + D -> RHS if D > C
                                     Ordering::Less => {
-                                        return t2;
+                                        return Some(t2.clone());
                                     }
                                     // N + C, N + D -> EQ if D = C
                                     Ordering::Equal => {
-                                        return t1;
+                                        return Some(t1.clone());
                                     }
                                 }
                             }
@@ -427,8 +663,8 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
         }
     }
 
-    if let GenericWidth::Expr(Op::Sub, lhs_lhs, lhs_rhs) = &t1 {
-        if let GenericWidth::Expr(Op::Add, rhs_lhs, rhs_rhs) = &t2 {
+    if let GenericWidth::Expr(Op::Sub, lhs_lhs, lhs_rhs) = t1 {
+        if let GenericWidth::Expr(Op::Add, rhs_lhs, rhs_rhs) = t2 {
             if let GenericWidth::Terminal(Terminal::Var(n1)) = &**lhs_lhs {
                 if let GenericWidth::Terminal(Terminal::Num(c)) = &**lhs_rhs {
                     if let GenericWidth::Terminal(Terminal::Var(n2)) = &**rhs_lhs {
@@ -436,12 +672,12 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
                             if same_variable_name(n1, n2) {
                                 // N - C, N + D -> EQ if C = D  and C = 0
                                 if c == &0 && d == &0 {
-                                    return GenericWidth::Terminal(Terminal::Var(n1.clone()));
+                                    return Some(GenericWidth::Terminal(Terminal::Var(n1.clone())));
                                 }
                                 // N - C, N + D -> RHS if C > D
                                 // N - C, N + D -> RHS if C < D
                                 // N - C, N + D -> RHS if C = D and C != 0
-                                return t2;
+                                return Some(t2.clone());
                             }
                         }
                     }
@@ -450,8 +686,8 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
         }
     }
 
-    if let GenericWidth::Expr(Op::Add, lhs_lhs, lhs_rhs) = &t1 {
-        if let GenericWidth::Expr(Op::Sub, rhs_lhs, rhs_rhs) = &t2 {
+    if let GenericWidth::Expr(Op::Add, lhs_lhs, lhs_rhs) = t1 {
+        if let GenericWidth::Expr(Op::Sub, rhs_lhs, rhs_rhs) = t2 {
             if let GenericWidth::Terminal(Terminal::Var(n1)) = &**lhs_lhs {
                 if let GenericWidth::Terminal(Terminal::Num(c)) = &**lhs_rhs {
                     if let GenericWidth::Terminal(Terminal::Var(n2)) = &**rhs_lhs {
@@ -459,12 +695,12 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
                             if same_variable_name(n1, n2) {
                                 // N + C, N - D -> LHS if C = D and C == 0
                                 if c == &0 && d == &0 {
-                                    return GenericWidth::Terminal(Terminal::Var(n1.clone()));
+                                    return Some(GenericWidth::Terminal(Terminal::Var(n1.clone())));
                                 }
                                 // N + C, N - D -> LHS if C > C h
                                 // N + C, N - D -> LHS if D > C
                                 // N + C, N - D -> LHS if C = D and C != 0
-                                return t1;
+                                return Some(t1.clone());
                             }
                         }
                     }
@@ -473,8 +709,8 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
         }
     }
 
-    if let GenericWidth::Expr(Op::Sub, lhs_lhs, lhs_rhs) = &t1 {
-        if let GenericWidth::Expr(Op::Sub, rhs_lhs, rhs_rhs) = &t2 {
+    if let GenericWidth::Expr(Op::Sub, lhs_lhs, lhs_rhs) = t1 {
+        if let GenericWidth::Expr(Op::Sub, rhs_lhs, rhs_rhs) = t2 {
             if let GenericWidth::Terminal(Terminal::Var(n1)) = &**lhs_lhs {
                 if let GenericWidth::Terminal(Terminal::Num(c)) = &**lhs_rhs {
                     if let GenericWidth::Terminal(Terminal::Var(n2)) = &**rhs_lhs {
@@ -482,21 +718,21 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
                             if same_variable_name(n1, n2) {
                                 // N - C, N - D -> LHS if C = D and C == 0
                                 if c == &0 && d == &0 {
-                                    return GenericWidth::Terminal(Terminal::Var(n1.clone()));
+                                    return Some(GenericWidth::Terminal(Terminal::Var(n1.clone())));
                                 }
 
                                 match c.cmp(d) {
                                     // N - C, N - D -> RHS if C > D
                                     Ordering::Greater => {
-                                        return t2;
+                                        return Some(t2.clone());
                                     }
                                     // N - C, N - D -> LHS if D > C
                                     Ordering::Less => {
-                                        return t1;
+                                        return Some(t1.clone());
                                     }
                                     // N - C, N - D -> EQ if C = D
                                     Ordering::Equal => {
-                                        return t1;
+                                        return Some(t1.clone());
                                     }
                                 }
                             }
@@ -507,9 +743,7 @@ fn eval_max(t1: GenericWidth, t2: GenericWidth) -> GenericWidth {
         }
     }
 
-    // We don't know what to do. For example MAX(X, Y) is impossible.
-    // Ideally this would be disallowed in the HDL before we hit this panic.
-    panic!("I don't know how to simplify MAX({}, {}).", t1, t2);
+    None
 }
 
 fn eval_terminal(terminal: &Terminal, state: &HashMap<String, GenericWidth>) -> GenericWidth {
@@ -561,7 +795,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Num(2))),
         );
         let expected = GenericWidth::Terminal(Terminal::Num(4));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -574,7 +808,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Num(2))),
         );
         let expected = GenericWidth::Terminal(Terminal::Num(0));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -591,7 +825,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -608,7 +842,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -629,7 +863,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(2))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -650,10 +884,81 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(2))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_expr_simplify_1_plus_n_plus_2_chain() {
+        let state = HashMap::new();
+        let n = || Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N"))));
+        let num = |x| Box::new(GenericWidth::Terminal(Terminal::Num(x)));
+
+        // 1 + (N + 2)
+        let input = GenericWidth::Expr(
+            Op::Add,
+            num(1),
+            Box::new(GenericWidth::Expr(Op::Add, n(), num(2))),
+        );
+        let expected = GenericWidth::Expr(Op::Add, n(), num(3));
+        assert_eq!(eval_expr(&input, &state).unwrap(), expected);
+
+        // 1 + (N - 1)
+        let input = GenericWidth::Expr(
+            Op::Add,
+            num(1),
+            Box::new(GenericWidth::Expr(Op::Sub, n(), num(1))),
+        );
+        assert_eq!(eval_expr(&input, &state).unwrap(), *n());
+
+        // 3 - (N + 1) subtracts the variable, so it is left alone.
+        let input = GenericWidth::Expr(
+            Op::Sub,
+            num(3),
+            Box::new(GenericWidth::Expr(Op::Add, n(), num(1))),
+        );
+        assert_eq!(eval_expr(&input, &state).unwrap(), input);
+    }
+
+    #[test]
+    fn test_expr_simplify_four_term_chain() {
+        let state = HashMap::new();
+        let n = || Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N"))));
+        let num = |x| Box::new(GenericWidth::Terminal(Terminal::Num(x)));
+
+        // 1 + (2 + (N - 5))
+        let input = GenericWidth::Expr(
+            Op::Add,
+            num(1),
+            Box::new(GenericWidth::Expr(
+                Op::Add,
+                num(2),
+                Box::new(GenericWidth::Expr(Op::Sub, n(), num(5))),
+            )),
+        );
+        let expected = GenericWidth::Expr(Op::Sub, n(), num(2));
+        assert_eq!(eval_expr(&input, &state).unwrap(), expected);
+        assert_eq!(eval_expr(&input, &state).unwrap().to_string(), "(N - 2)");
+
+        // 2 + ((N - 1) + 3), with N bound to M + 1
+        let input = GenericWidth::Expr(
+            Op::Add,
+            num(2),
+            Box::new(GenericWidth::Expr(
+                Op::Add,
+                Box::new(GenericWidth::Expr(Op::Sub, n(), num(1))),
+                num(3),
+            )),
+        );
+        let m = Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("M"))));
+        let state = HashMap::from([(
+            String::from("N"),
+            GenericWidth::Expr(Op::Add, num(1), m.clone()),
+        )]);
+        let expected = GenericWidth::Expr(Op::Add, m, num(5));
+        assert_eq!(eval_expr(&input, &state).unwrap(), expected);
+    }
+
     #[test]
     fn test_expr_simplify_n_minus_2_plus_1() {
         let state = HashMap::new();
@@ -671,7 +976,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -692,7 +997,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -713,7 +1018,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(3))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -730,7 +1035,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -747,7 +1052,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -761,7 +1066,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -775,7 +1080,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
         );
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -797,7 +1102,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -811,7 +1116,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
         );
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -833,7 +1138,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -851,7 +1156,7 @@ mod test {
             )),
         );
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -869,7 +1174,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
         );
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -891,7 +1196,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -913,7 +1218,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -939,7 +1244,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(2))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -965,7 +1270,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(55))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -991,7 +1296,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(4))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1012,7 +1317,7 @@ mod test {
                 Box::new(GenericWidth::Terminal(Terminal::Num(0))),
             )),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
         assert_eq!(actual, expected);
     }
@@ -1039,7 +1344,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(1))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1065,7 +1370,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(99))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1091,7 +1396,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(3))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1113,7 +1418,7 @@ mod test {
             )),
         );
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1135,7 +1440,7 @@ mod test {
             )),
         );
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1161,7 +1466,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(5))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1187,7 +1492,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(5))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1213,7 +1518,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(5))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1235,7 +1540,7 @@ mod test {
             )),
         );
         let expected = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1261,7 +1566,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(3))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1287,7 +1592,7 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(3))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -1313,7 +1618,129 @@ mod test {
             Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
             Box::new(GenericWidth::Terminal(Terminal::Num(3))),
         );
-        let actual = eval_expr(&input, &state);
+        let actual = eval_expr(&input, &state).unwrap();
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_expr_simplify_mul() {
+        let state = HashMap::new();
+        let num = |x| GenericWidth::Terminal(Terminal::Num(x));
+        let n = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
+        let mul = |a: &GenericWidth, b: &GenericWidth| {
+            GenericWidth::Expr(Op::Mul, Box::new(a.clone()), Box::new(b.clone()))
+        };
+
+        assert_eq!(eval_expr(&mul(&num(2), &num(3)), &state).unwrap(), num(6));
+        assert_eq!(eval_expr(&mul(&n, &num(1)), &state).unwrap(), n);
+        assert_eq!(eval_expr(&mul(&num(1), &n), &state).unwrap(), n);
+        assert_eq!(eval_expr(&mul(&num(0), &n), &state).unwrap(), num(0));
+        assert_eq!(
+            eval_expr(&mul(&n, &num(2)), &state).unwrap(),
+            mul(&n, &num(2))
+        );
+        assert_eq!(mul(&n, &num(2)).to_string(), "(N * 2)");
+
+        // (N * 2) - 1 is left alone when N is unknown.
+        let end = GenericWidth::Expr(Op::Sub, Box::new(mul(&n, &num(2))), Box::new(num(1)));
+        assert_eq!(eval_expr(&end, &state).unwrap(), end);
+        let known = HashMap::from([(String::from("N"), 4)]);
+        assert_eq!(eval_expr_numeric(&end, &known).unwrap(), 7);
+        assert!(eval_expr_numeric(&end, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_expr_simplify_div_mod() {
+        let state = HashMap::new();
+        let num = |x| GenericWidth::Terminal(Terminal::Num(x));
+        let n = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
+        let div = |a: &GenericWidth, b: &GenericWidth| {
+            GenericWidth::Expr(Op::Div, Box::new(a.clone()), Box::new(b.clone()))
+        };
+        let rem = |a: &GenericWidth, b: &GenericWidth| {
+            GenericWidth::Expr(Op::Mod, Box::new(a.clone()), Box::new(b.clone()))
+        };
+
+        assert_eq!(eval_expr(&div(&num(7), &num(2)), &state).unwrap(), num(3));
+        assert_eq!(eval_expr(&rem(&num(7), &num(2)), &state).unwrap(), num(1));
+        assert_eq!(eval_expr(&div(&n, &num(1)), &state).unwrap(), n);
+        assert_eq!(eval_expr(&rem(&n, &num(1)), &state).unwrap(), num(0));
+        assert_eq!(
+            eval_expr(&div(&n, &num(2)), &state).unwrap(),
+            div(&n, &num(2))
+        );
+        assert_eq!(
+            eval_expr(&rem(&num(2), &n), &state).unwrap(),
+            rem(&num(2), &n)
+        );
+        assert_eq!(div(&n, &num(2)).to_string(), "(N / 2)");
+        assert_eq!(rem(&n, &num(2)).to_string(), "(N mod 2)");
+
+        // N / 2 - 1 folds once N is known, and is not mistaken for a chain
+        // of additions.
+        let end = GenericWidth::Expr(Op::Sub, Box::new(div(&n, &num(2))), Box::new(num(1)));
+        assert_eq!(eval_expr(&end, &state).unwrap(), end);
+        let known = HashMap::from([(String::from("N"), 16)]);
+        assert_eq!(eval_expr_numeric(&end, &known).unwrap(), 7);
+
+        let err = eval_expr(&div(&n, &num(0)), &state).unwrap_err();
+        assert_eq!(err.msg, "Division by zero in (N / 0)");
+        let zero = HashMap::from([(String::from("N"), 0)]);
+        let err = eval_expr_numeric(&rem(&num(4), &n), &zero).unwrap_err();
+        assert_eq!(err.msg, "Division by zero in (4 mod 0)");
+    }
+
+    // Nested maxes are flattened and duplicate operands dropped, and maxes
+    // that still can't be decided are errors instead of panics.
+    #[test]
+    fn test_expr_simplify_nested_max() {
+        let state = HashMap::new();
+        let num = |x: usize| GenericWidth::Terminal(Terminal::Num(x));
+        let var = |name: &str| GenericWidth::Terminal(Terminal::Var(Identifier::from(name)));
+        let n = var("N");
+        let max = |a: &GenericWidth, b: &GenericWidth| {
+            GenericWidth::Expr(Op::Max, Box::new(a.clone()), Box::new(b.clone()))
+        };
+        let add = |a: &GenericWidth, b: &GenericWidth| {
+            GenericWidth::Expr(Op::Add, Box::new(a.clone()), Box::new(b.clone()))
+        };
+        let mul = |a: &GenericWidth, b: &GenericWidth| {
+            GenericWidth::Expr(Op::Mul, Box::new(a.clone()), Box::new(b.clone()))
+        };
+        let n_plus = |c: usize| add(&n, &num(c));
+
+        let nested = max(&max(&n, &n_plus(1)), &n);
+        assert_eq!(eval_expr(&nested, &state).unwrap(), n_plus(1));
+        assert_eq!(eval_max(max(&n, &n_plus(1)), n.clone()).unwrap(), n_plus(1));
+        let deep = max(
+            &max(&num(3), &max(&n_plus(2), &n)),
+            &max(&n_plus(1), &num(5)),
+        );
+        assert_eq!(eval_max(deep, n.clone()).unwrap(), n_plus(2));
+
+        // A generic whose value is itself a max.
+        let w = HashMap::from([(String::from("W"), max(&n, &n_plus(2)))]);
+        assert_eq!(
+            eval_expr(&max(&var("W"), &n_plus(1)), &w).unwrap(),
+            n_plus(2)
+        );
+
+        // Identical operands that no rule compares.
+        let twice = mul(&n, &num(2));
+        assert_eq!(
+            eval_expr(&max(&twice, &max(&twice, &twice)), &state).unwrap(),
+            twice
+        );
+
+        let err = eval_expr(&max(&n, &var("M")), &state).unwrap_err();
+        assert_eq!(
+            err.msg,
+            "Unable to simplify MAXIMUM(N, M), because which operand is larger depends on the \
+             generic arguments."
+        );
+        let err = eval_max(max(&n, &var("M")), n_plus(1)).unwrap_err();
+        assert!(err
+            .msg
+            .starts_with("Unable to simplify MAXIMUM((N + 1), M),"));
+    }
 }
@@ -0,0 +1,372 @@
+//! Chips built from truth tables.
+//!
+//! Each output is written as a sum of products: the OR of one AND term for
+//! every row in which the output is true. Both levels are built from NAND
+//! gates, so the generated chip uses no other parts. Input combinations that
+//! the table leaves out give false on every output.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{ErrorKind, N2VError, TransformedError};
+
+/// Values of the inputs and the outputs for one row of a truth table.
+pub type TableRow = (Vec<bool>, Vec<bool>);
+
+/// A truth table read from CSV.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TruthTable {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub rows: Vec<TableRow>,
+}
+
+/// Returns HDL for a chip named `FromTable` that implements the truth table
+/// using only NAND gates. Each row gives a value for every input and every
+/// output, in the order the ports are listed.
+///
+/// ```ignore
+/// let rows = vec![
+///     (vec![false, false], vec![false]),
+///     (vec![false, true], vec![true]),
+///     (vec![true, false], vec![true]),
+///     (vec![true, true], vec![false]),
+/// ];
+/// let hdl = synthesize_from_table(&["a", "b"], &["out"], &rows)?;
+/// ```
+#[allow(dead_code)] // Used by the web interface in lib.rs.
+pub fn synthesize_from_table(
+    inputs: &[&str],
+    outputs: &[&str],
+    rows: &[TableRow],
+) -> Result<String, Box<dyn Error>> {
+    synthesize_chip("FromTable", inputs, outputs, rows)
+}
+
+/// Like `synthesize_from_table`, but names the chip `name`.
+pub fn synthesize_chip(
+    name: &str,
+    inputs: &[&str],
+    outputs: &[&str],
+    rows: &[TableRow],
+) -> Result<String, Box<dyn Error>> {
+    check_table(name, inputs, outputs, rows)?;
+
+    let ports: Vec<&str> = inputs.iter().chain(outputs).copied().collect();
+    let mut gates = Gates::new(&ports);
+
+    // Rows with the same inputs share their AND term between outputs.
+    let mut terms: HashMap<&Vec<bool>, String> = HashMap::new();
+    let mut assignments = Vec::new();
+    for (k, output) in outputs.iter().enumerate() {
+        let mut output_terms = Vec::new();
+        for (row_inputs, row_outputs) in rows {
+            if !row_outputs[k] {
+                continue;
+            }
+            if let Some(term) = terms.get(row_inputs) {
+                output_terms.push(term.clone());
+                continue;
+            }
+            let literals: Vec<String> = inputs
+                .iter()
+                .zip(row_inputs)
+                .map(|(input, &value)| match value {
+                    true => String::from(*input),
+                    false => gates.not(input),
+                })
+                .collect();
+            // NAND of the literals, so the output NAND turns it into AND.
+            let term = gates.nand_all(&literals, None);
+            terms.insert(row_inputs, term.clone());
+            output_terms.push(term);
+        }
+
+        if output_terms.is_empty() {
+            assignments.push(format!("{} <= false;", output));
+        } else {
+            gates.nand_all(&output_terms, Some(output));
+        }
+    }
+
+    let mut hdl = String::from("// Generated from a truth table.\n");
+    hdl.push_str(&format!("CHIP {} {{\n", name));
+    hdl.push_str(&format!("    IN {};\n", inputs.join(", ")));
+    hdl.push_str(&format!("    OUT {};\n\n", outputs.join(", ")));
+    hdl.push_str("    PARTS:\n");
+    for line in gates.lines.iter().chain(&assignments) {
+        hdl.push_str(&format!("    {}\n", line));
+    }
+    hdl.push_str("}\n");
+    Ok(hdl)
+}
+
+// Checks that names are identifiers, that no port is listed twice, that
+// rows have a value for every port, and that no two rows disagree.
+fn check_table(
+    name: &str,
+    inputs: &[&str],
+    outputs: &[&str],
+    rows: &[TableRow],
+) -> Result<(), Box<dyn Error>> {
+    let table_error = |msg: String| -> Box<dyn Error> {
+        Box::new(N2VError {
+            msg,
+            kind: ErrorKind::Other,
+        })
+    };
+
+    if inputs.is_empty() || outputs.is_empty() {
+        return Err(table_error(String::from(
+            "A truth table needs at least one input and one output.",
+        )));
+    }
+    for port in [name].iter().chain(inputs).chain(outputs) {
+        let mut chars = port.chars();
+        let valid = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(table_error(format!("'{}' is not a valid HDL name.", port)));
+        }
+    }
+    for (i, port) in inputs.iter().chain(outputs).enumerate() {
+        if inputs.iter().chain(outputs).take(i).any(|p| p == port) {
+            return Err(table_error(format!("Port {} is listed twice.", port)));
+        }
+    }
+
+    let mut seen: HashMap<&Vec<bool>, (usize, &Vec<bool>)> = HashMap::new();
+    for (i, (row_inputs, row_outputs)) in rows.iter().enumerate() {
+        if row_inputs.len() != inputs.len() || row_outputs.len() != outputs.len() {
+            return Err(table_error(format!(
+                "Row {} has {} inputs and {} outputs, expected {} and {}.",
+                i + 1,
+                row_inputs.len(),
+                row_outputs.len(),
+                inputs.len(),
+                outputs.len()
+            )));
+        }
+        match seen.get(row_inputs) {
+            Some((j, other)) if *other != row_outputs => {
+                return Err(table_error(format!(
+                    "Rows {} and {} have the same inputs but different outputs.",
+                    j + 1,
+                    i + 1
+                )));
+            }
+            Some(_) => {}
+            None => {
+                seen.insert(row_inputs, (i, row_outputs));
+            }
+        }
+    }
+    Ok(())
+}
+
+// NAND gates of the generated chip.
+struct Gates {
+    lines: Vec<String>,
+    // Prefix of internal wire names, chosen so they don't clash with ports.
+    prefix: String,
+    wires: usize,
+    negated: HashMap<String, String>,
+}
+
+impl Gates {
+    fn new(ports: &[&str]) -> Gates {
+        let mut prefix = String::from("w");
+        let clashes = |prefix: &str| {
+            ports.iter().any(|p| match p.strip_prefix(prefix) {
+                Some(rest) => !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()),
+                None => false,
+            })
+        };
+        while clashes(&prefix) {
+            prefix.push('_');
+        }
+        Gates {
+            lines: Vec::new(),
+            prefix,
+            wires: 0,
+            negated: HashMap::new(),
+        }
+    }
+
+    // Adds a NAND gate driving `out`, or a new wire if `out` is None.
+    fn nand(&mut self, a: &str, b: &str, out: Option<&str>) -> String {
+        let out = match out {
+            Some(out) => String::from(out),
+            None => {
+                self.wires += 1;
+                format!("{}{}", self.prefix, self.wires - 1)
+            }
+        };
+        self.lines
+            .push(format!("Nand(a={}, b={}, out={});", a, b, out));
+        out
+    }
+
+    // Each input is negated at most once.
+    fn not(&mut self, x: &str) -> String {
+        if let Some(negated) = self.negated.get(x) {
+            return negated.clone();
+        }
+        let negated = self.nand(x, x, None);
+        self.negated.insert(String::from(x), negated.clone());
+        negated
+    }
+
+    // NAND of any number of signals, as a chain of ANDs ending in a NAND.
+    fn nand_all(&mut self, xs: &[String], out: Option<&str>) -> String {
+        let (last, rest) = xs.split_last().unwrap();
+        if rest.is_empty() {
+            return self.nand(last, last, out);
+        }
+        let mut and = rest[0].clone();
+        for x in &rest[1..] {
+            let nand = self.nand(&and, x, None);
+            and = self.nand(&nand, &nand, None);
+        }
+        self.nand(&and, last, out)
+    }
+}
+
+/// Reads a truth table from a CSV file. The header names the ports, and
+/// each row gives 0 or 1 for every port. The columns named in `outputs` are
+/// outputs, or the last column if `outputs` is empty. The other columns are
+/// inputs.
+pub fn read_table_csv(path: &Path, outputs: &[String]) -> Result<TruthTable, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| TransformedError {
+        msg: format!("Unable to read truth table {}.", path.display()),
+        kind: ErrorKind::IOError,
+        source: Some(Box::new(e)),
+    })?;
+    parse_table_csv(&text, outputs).map_err(|(line, msg)| {
+        Box::new(N2VError {
+            msg: format!("{}:{}: {}", path.display(), line, msg),
+            kind: ErrorKind::Other,
+        }) as Box<dyn Error>
+    })
+}
+
+// Returns the line number and a description of the first malformed line.
+fn parse_table_csv(text: &str, outputs: &[String]) -> Result<TruthTable, (usize, String)> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty());
+    let (header_line, header) = lines
+        .next()
+        .ok_or((1, String::from("The truth table is empty.")))?;
+    let columns: Vec<String> = header.split(',').map(|c| String::from(c.trim())).collect();
+
+    let is_output: Vec<bool> = match outputs {
+        [] => (0..columns.len()).map(|i| i + 1 == columns.len()).collect(),
+        _ => {
+            if let Some(missing) = outputs.iter().find(|o| !columns.contains(o)) {
+                return Err((
+                    header_line,
+                    format!("There is no column named {}.", missing),
+                ));
+            }
+            columns.iter().map(|c| outputs.contains(c)).collect()
+        }
+    };
+
+    let mut table = TruthTable {
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        rows: Vec::new(),
+    };
+    for (column, &output) in columns.iter().zip(&is_output) {
+        match output {
+            true => table.outputs.push(column.clone()),
+            false => table.inputs.push(column.clone()),
+        }
+    }
+
+    for (line_number, line) in lines {
+        let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+        if cells.len() != columns.len() {
+            return Err((
+                line_number,
+                format!("Expected {} values, found {}.", columns.len(), cells.len()),
+            ));
+        }
+        let mut row: TableRow = (Vec::new(), Vec::new());
+        for (cell, &output) in cells.iter().zip(&is_output) {
+            let value = match *cell {
+                "0" => false,
+                "1" => true,
+                _ => return Err((line_number, format!("'{}' is not 0 or 1.", cell))),
+            };
+            match output {
+                true => row.1.push(value),
+                false => row.0.push(value),
+            }
+        }
+        table.rows.push(row);
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_synthesize_from_table() {
+        let rows = vec![(vec![true, false], vec![true, false])];
+        let hdl = synthesize_from_table(&["a", "b"], &["x", "y"], &rows).expect("Synthesis error");
+        assert_eq!(
+            hdl,
+            "// Generated from a truth table.\n\
+             CHIP FromTable {\n    IN a, b;\n    OUT x, y;\n\n    PARTS:\n    \
+             Nand(a=b, b=b, out=w0);\n    \
+             Nand(a=a, b=w0, out=w1);\n    \
+             Nand(a=w1, b=w1, out=x);\n    \
+             y <= false;\n}\n"
+        );
+
+        // Wires are renamed to stay clear of the ports.
+        let hdl = synthesize_from_table(&["w0"], &["w1"], &[(vec![false], vec![true])])
+            .expect("Synthesis error");
+        assert!(hdl.contains("Nand(a=w0, b=w0, out=w_0);"));
+
+        let err = synthesize_from_table(&["a"], &["a"], &[]).expect_err("Expected an error");
+        assert!(err.to_string().contains("Port a is listed twice."));
+        let rows = vec![(vec![true], vec![true]), (vec![true], vec![false])];
+        let err = synthesize_from_table(&["a"], &["b"], &rows).expect_err("Expected an error");
+        assert!(err
+            .to_string()
+            .contains("Rows 1 and 2 have the same inputs"));
+    }
+
+    #[test]
+    fn test_parse_table_csv() {
+        let table = parse_table_csv("a, b, sum, carry\n0,1,1,0\n\n1,1,0,1\n", &[]).unwrap();
+        assert_eq!(table.inputs, vec!["a", "b", "sum"]);
+        assert_eq!(table.outputs, vec!["carry"]);
+        assert_eq!(table.rows[1], (vec![true, true, false], vec![true]));
+
+        let outputs = vec![String::from("sum"), String::from("carry")];
+        let table = parse_table_csv("a,b,sum,carry\n0,1,1,0\n", &outputs).unwrap();
+        assert_eq!(table.inputs, vec!["a", "b"]);
+        assert_eq!(table.rows[0], (vec![false, true], vec![true, false]));
+
+        assert_eq!(
+            parse_table_csv("a,out\n0,1\n1,2\n", &[]),
+            Err((3, String::from("'2' is not 0 or 1.")))
+        );
+        assert_eq!(
+            parse_table_csv("a,out\n0\n", &[]),
+            Err((2, String::from("Expected 2 values, found 1.")))
+        );
+    }
+}
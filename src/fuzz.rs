@@ -0,0 +1,225 @@
+//! Random testing of a chip against a reference implementation.
+//!
+//! Exhaustive truth tables are infeasible for wide chips. The tests' `fuzz`
+//! instead simulates a fixed number of pseudo-random inputs and compares the
+//! outputs with a Rust function. Inputs come from a seeded generator so a
+//! failure can be reproduced. `check_inputs` uses the same inputs to look
+//! for ones that make the simulator fail.
+
+use crate::busmap::BusMap;
+use crate::error::{ErrorKind, N2VError, TransformedError};
+use crate::simulator::Simulator;
+use std::collections::HashMap;
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Simulates the all-ones input followed by `random` seeded random inputs,
+/// stopping at the first input that makes the simulator return an error or
+/// panic. The error names that input.
+pub fn check_inputs(
+    simulator: &mut Simulator,
+    random: usize,
+    seed: u64,
+) -> Result<(), Box<dyn Error>> {
+    let input_ports = simulator.input_ports();
+    let rng = fastrand::Rng::with_seed(seed);
+
+    let mut vectors = vec![port_values(&input_ports, || true)?];
+    for _ in 0..random {
+        vectors.push(port_values(&input_ports, || rng.bool())?);
+    }
+
+    for inputs in vectors {
+        simulator.reset();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| simulator.simulate(&inputs)));
+        let source: Box<dyn Error> = match result {
+            Ok(Ok(_)) => continue,
+            Ok(Err(e)) => e,
+            Err(_) => Box::new(N2VError {
+                msg: String::from("The simulator panicked."),
+                kind: ErrorKind::Other,
+            }),
+        };
+        return Err(Box::new(TransformedError {
+            msg: format!("Simulation failed for input:\n{}", inputs),
+            kind: ErrorKind::Other,
+            source: Some(source),
+        }));
+    }
+
+    Ok(())
+}
+
+/// Builds an input for every port, taking each bit from `bit`.
+fn port_values<F: FnMut() -> bool>(
+    ports: &[(String, usize)],
+    mut bit: F,
+) -> Result<BusMap, Box<dyn Error>> {
+    let values: HashMap<String, Vec<bool>> = ports
+        .iter()
+        .map(|(name, width)| (name.clone(), (0..*width).map(|_| bit()).collect()))
+        .collect();
+    Ok(BusMap::try_from(values)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{get_hdl, parse_hdl_path, FileReader, HdlProvider};
+    use crate::simulator::Chip;
+
+    use std::env;
+    use std::path::Path;
+    use std::ptr;
+    use std::rc::Rc;
+
+    /// Simulates the chip in the HDL file `chip` with `cases` random inputs and
+    /// compares every output port with the value that `reference` returns.
+    ///
+    /// Returns the first input where the outputs differ, or None if every case
+    /// matches. It is an error for `reference` to leave out an output port or to
+    /// return a port that is not an output.
+    pub fn fuzz<F: Fn(&BusMap) -> BusMap>(
+        chip: &str,
+        reference: F,
+        cases: usize,
+        seed: u64,
+    ) -> Result<Option<BusMap>, Box<dyn Error>> {
+        let (hdl, provider) = parse_hdl_path(Path::new(chip))?;
+        let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+        let chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())?;
+        let mut simulator = Simulator::new(chip);
+        let input_ports = simulator.input_ports();
+        let output_ports = simulator.output_ports();
+        let rng = fastrand::Rng::with_seed(seed);
+
+        for _ in 0..cases {
+            let inputs = port_values(&input_ports, || rng.bool())?;
+
+            // Sequential chips would carry state from one case to the next.
+            simulator.reset();
+            let outputs = simulator.simulate(&inputs)?;
+            let expected = reference(&inputs);
+            let extra = expected
+                .keys()
+                .into_iter()
+                .find(|n| outputs.get_width(n).is_none());
+            if let Some(name) = extra {
+                return Err(Box::new(N2VError {
+                    msg: format!(
+                        "The reference returned {}, which is not an output port.",
+                        name
+                    ),
+                    kind: ErrorKind::Other,
+                }));
+            }
+            for (name, _) in &output_ports {
+                if expected.get_width(name).is_none() {
+                    return Err(Box::new(N2VError {
+                        msg: format!("The reference returned no value for output port {}.", name),
+                        kind: ErrorKind::Other,
+                    }));
+                }
+                if outputs.get_name(name) != expected.get_name(name) {
+                    return Ok(Some(inputs));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn add16(inputs: &BusMap, carry: u64) -> BusMap {
+        let sum = inputs.as_unsigned("a").unwrap() + inputs.as_unsigned("b").unwrap() + carry;
+        let mut outputs = BusMap::new();
+        outputs.create_bus("out", 16).unwrap();
+        outputs.set_integer("out", (sum & 0xffff) as i64).unwrap();
+        outputs
+    }
+
+    #[test]
+    fn test_fuzz_add16() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = manifest_dir.join("resources/tests/nand2tetris/solutions/Add16.hdl");
+        let path = path.to_str().unwrap();
+
+        let failure = fuzz(path, |i| add16(i, 0), 50, 1).expect("Fuzz error");
+        assert!(failure.is_none());
+
+        // An off by one reference fails on the first case, and the same seed
+        // gives the same failing input.
+        let failure = fuzz(path, |i| add16(i, 1), 50, 7)
+            .expect("Fuzz error")
+            .expect("Expected a failing input");
+        let again = fuzz(path, |i| add16(i, 1), 50, 7)
+            .expect("Fuzz error")
+            .expect("Expected a failing input");
+        assert_eq!(failure.get_width("a"), Some(16));
+        assert_eq!(failure.as_unsigned("a"), again.as_unsigned("a"));
+        assert_eq!(failure.as_unsigned("b"), again.as_unsigned("b"));
+
+        // The reference has to give every output, and only outputs.
+        let err = fuzz(path, |_| BusMap::new(), 1, 1).expect_err("Expected an error");
+        assert!(err.to_string().contains("no value for output port out."));
+        let extra = |i: &BusMap| {
+            let mut outputs = add16(i, 0);
+            outputs.create_bus("carry", 1).unwrap();
+            outputs
+        };
+        let err = fuzz(path, extra, 1, 1).expect_err("Expected an error");
+        assert!(err
+            .to_string()
+            .contains("returned carry, which is not an output port."));
+    }
+
+    // Adds a and b, except that it returns no output at all when a is 255.
+    fn add8_broken_at_max(inputs: &BusMap) -> BusMap {
+        let mut outputs = BusMap::new();
+        let a = inputs.as_unsigned("a").unwrap();
+        if a == 255 {
+            return outputs;
+        }
+        outputs.create_bus("out", 8).unwrap();
+        let sum = a + inputs.as_unsigned("b").unwrap();
+        outputs.set_integer("out", (sum % 256) as i64).unwrap();
+        outputs
+    }
+
+    #[test]
+    fn test_check_inputs() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources/tests/builtin");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("Sum3", &provider).expect("Parse error");
+        let chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())
+            .expect("Chip creation error");
+        let mut simulator = Simulator::new(chip);
+        simulator.register_builtin("Add8", Box::new(add8_broken_at_max));
+
+        // All zeros works, so only the extra inputs find the problem.
+        let zeros = port_values(&simulator.input_ports(), || false).unwrap();
+        simulator.simulate(&zeros).expect("simulation failure");
+
+        let err = check_inputs(&mut simulator, 5, 0).expect_err("Expected a failing input");
+        let msg = err.to_string();
+        assert!(msg.contains("Simulation failed for input:"));
+        assert!(msg.contains("a: 11111111"));
+        assert!(msg.contains("did not return 8 bits for output out"));
+
+        // With a working adder every input passes.
+        let mut simulator = Simulator::new(
+            Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new()).unwrap(),
+        );
+        simulator.register_builtin(
+            "Add8",
+            Box::new(|i: &BusMap| {
+                let sum = i.as_unsigned("a").unwrap() + i.as_unsigned("b").unwrap();
+                let mut outputs = BusMap::new();
+                outputs.create_bus("out", 8).unwrap();
+                outputs.set_integer("out", (sum % 256) as i64).unwrap();
+                outputs
+            }),
+        );
+        check_inputs(&mut simulator, 20, 3).expect("Check error");
+    }
+}
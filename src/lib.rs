@@ -5,15 +5,20 @@
 mod busmap;
 mod error;
 mod expr;
+mod fromtable;
+mod memory;
+mod parser;
+mod profile;
 mod scanner;
 mod simulator;
-mod parser;
+mod table;
 mod test_scanner;
+mod vcd;
 
-use crate::busmap::BusMap;
+use crate::busmap::{BusMap, Radix};
 use crate::error::{ErrorKind, N2VError};
 use crate::parser::*;
-use crate::simulator::{Chip, Simulator};
+use crate::simulator::{Chip, CircuitView, Simulator};
 use expr::*;
 use rust_embed::RustEmbed;
 use scanner::Scanner;
@@ -22,6 +27,7 @@ use std::error::Error;
 use std::path::PathBuf;
 use std::ptr;
 use std::rc::Rc;
+use table::{format_table, FormattedTable, TableOptions};
 
 use wasm_bindgen::prelude::*;
 
@@ -48,16 +54,21 @@ impl HdlProvider for EmbedReader {
     fn get_path(&self, file_name: &str) -> PathBuf {
         PathBuf::from(file_name)
     }
+
+    fn find_case_insensitive(&self, file_name: &str) -> Vec<String> {
+        HdlAsset::iter()
+            .filter(|name| name.eq_ignore_ascii_case(file_name))
+            .map(|name| name.into_owned())
+            .collect()
+    }
 }
 
 #[wasm_bindgen]
 pub fn simulate(s: &str, inputs: &str) -> Result<String, JsValue> {
     console_error_panic_hook::set_once();
-    let mut scanner = Scanner::new(s, PathBuf::from(""));
     let provider: Rc<dyn HdlProvider> = Rc::new(EmbedReader);
-    let mut parser = Parser::new(&mut scanner, provider.clone());
 
-    let hdl = match parser.parse() {
+    let hdl = match parse_hdl_str(s, provider.clone(), PathBuf::from("")) {
         Ok(x) => x,
         Err(e) => return Err(JsValue::from(e.to_string())),
     };
@@ -67,142 +78,164 @@ pub fn simulate(s: &str, inputs: &str) -> Result<String, JsValue> {
         Err(e) => return Err(JsValue::from(e.to_string())),
     };
     let mut simulator = Simulator::new(chip);
-    let chip_inputs: HashMap<String, Vec<bool>> = serde_json::from_str(inputs)
-        .unwrap_or_else(|_| panic!("Unable to parse inputs: {}", inputs));
-
-    let outputs = simulator.simulate(&BusMap::try_from(chip_inputs)
-                                     .unwrap_or_else(|_| panic!("A simulation error occured")));
-    Ok(format!("{:?}", outputs))
-}
-
-#[wasm_bindgen]
-pub fn full_table(s: &str) -> Result<String, JsValue> {
-    console_error_panic_hook::set_once();
-    let table = match full_table_internal(s, Rc::new(EmbedReader)) {
+    let chip_inputs = match inputs_from_json(&hdl, inputs) {
         Ok(x) => x,
-        Err(e) => {
-            return Err(JsValue::from(e.to_string()));
-        }
+        Err(e) => return Err(JsValue::from(e.to_string())),
     };
-    Ok(serde_json::to_string(&table).unwrap())
-}
-
-type Table = Vec<Vec<Vec<Option<bool>>>>;
 
-// Returns (column list, row values)
-pub fn full_table_internal(
-    s: &str,
-    provider: Rc<dyn HdlProvider>,
-) -> Result<(Vec<String>, Table), Box<dyn Error>> {
-    let mut scanner = Scanner::new(s, PathBuf::from(""));
-    let mut parser = Parser::new(&mut scanner, provider.clone());
+    let outputs = simulator.simulate(&chip_inputs);
+    Ok(format!("{:?}", outputs))
+}
 
-    let hdl = match parser.parse() {
-        Ok(x) => x,
-        Err(e) => return Err(e),
+/// Converts JSON simulation inputs to a BusMap. Each input port maps to
+/// either an integer, which is decomposed to the port width, or an array of
+/// bits with the most significant bit first.
+pub fn inputs_from_json(hdl: &ChipHDL, inputs: &str) -> Result<BusMap, Box<dyn Error>> {
+    let input_error = |msg: String| -> Box<dyn Error> {
+        Box::new(N2VError {
+            msg,
+            kind: ErrorKind::Other,
+        })
     };
 
-    let chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())?;
-    let mut simulator = Simulator::new(chip);
+    let values: HashMap<String, serde_json::Value> = serde_json::from_str(inputs)
+        .map_err(|e| input_error(format!("Unable to parse inputs {}: {}", inputs, e)))?;
 
-    // get total width of input ports
-    let total_width = &hdl
-        .ports
-        .iter()
-        .filter(|p| p.direction == PortDirection::In)
-        .fold(0, |acc, p| {
-            if let GenericWidth::Terminal(Terminal::Num(w)) = p.width {
-                return acc + w;
+    let mut chip_inputs = BusMap::new();
+    for port in &hdl.ports {
+        if !port.direction.is_input() {
+            continue;
+        }
+        let name = &port.name.value;
+        let width = eval_expr_numeric(&port.width, &hdl.constant_values())?;
+        chip_inputs.create_bus(name, width).map_err(input_error)?;
+
+        let value = values
+            .get(name)
+            .ok_or_else(|| input_error(format!("No input value for port {}.", name)))?;
+        match value {
+            serde_json::Value::Number(n) => {
+                let n = n.as_i64().ok_or_else(|| {
+                    input_error(format!("Input for port {} is not an integer.", name))
+                })?;
+                chip_inputs.set_integer(name, n).map_err(input_error)?;
             }
-            panic!("Generic widths not supported");
-        });
-
-    let column_names: Vec<String> = hdl.ports.iter().map(|p| p.name.value.clone()).collect();
-
-    let mut column_values: Vec<Vec<Vec<Option<bool>>>> = vec![];
-
-    let base: u32 = 2;
-    let total_rows = base.pow(*total_width as u32);
-
-    if total_rows > 1024 {
-        return Err(Box::new(N2VError {
-            msg: String::from("Too many rows in truth table to display (max 32)."),
-            kind: ErrorKind::Other,
-        }));
-    }
-
-    for i in 0..total_rows {
-        let binary_string = format!("{:0total_width$b}", i);
-        let mut bools: Vec<bool> = binary_string
-            .chars()
-            .map(|c| match c {
-                '0' => false,
-                '1' => true,
-                _ => {
-                    panic!("expected 0 or 1");
+            // Unknown inputs, such as don't-cares, are null or "*".
+            v if v.is_null() || v.as_str() == Some("*") => {
+                let bus = simulator::Bus::from(name.as_str());
+                chip_inputs.insert_option(&bus, vec![None; width]);
+            }
+            serde_json::Value::Array(bits) => {
+                let bits = bits
+                    .iter()
+                    .map(|b| match b {
+                        serde_json::Value::Bool(b) => Some(Some(*b)),
+                        serde_json::Value::Null => Some(None),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<Option<bool>>>>()
+                    .ok_or_else(|| {
+                        input_error(format!(
+                            "Input bits for port {} must be booleans or null.",
+                            name
+                        ))
+                    })?;
+                if bits.len() != width {
+                    return Err(input_error(format!(
+                        "Port {} has width {} but {} bits were given.",
+                        name,
+                        width,
+                        bits.len()
+                    )));
                 }
-            })
-            .collect();
-
-        let mut m: HashMap<String, Vec<bool>> = HashMap::new();
-        let mut remaining_width = *total_width;
-        for p in &hdl.ports {
-            if p.direction == PortDirection::Out {
-                continue;
+                chip_inputs.insert_option(&simulator::Bus::from(name.as_str()), bits);
             }
-            if let GenericWidth::Terminal(Terminal::Num(w)) = p.width {
-                let port_bools = &bools[(bools.len() - w)..];
-                m.insert(p.name.value.clone(), port_bools.to_vec());
-                remaining_width -= w;
-            } else {
-                panic!("Generic widths not supported.");
+            _ => {
+                return Err(input_error(format!(
+                    "Input for port {} must be an integer, an array of bits, or null.",
+                    name
+                )))
             }
-            bools.truncate(remaining_width);
         }
-
-        let inputs = match BusMap::try_from(m) {
-            Ok(x) => x,
-            Err(s) => {
-                return Err(Box::new(N2VError {
-                    msg: s,
-                    kind: ErrorKind::Other,
-                }));
-            }
-        };
-        let outputs = simulator.simulate(&inputs)?;
-
-        let mut row: Vec<Vec<Option<bool>>> = vec![];
-        for s in &column_names {
-            row.push(outputs.get_name(s));
-        }
-        column_values.push(row);
     }
 
-    Ok((column_names, column_values))
+    Ok(chip_inputs)
 }
 
+/// Truth table of a chip as JSON. `generics` is a JSON array of the generic
+/// arguments of a generic chip, such as `[4]`.
 #[wasm_bindgen]
-pub fn component_graphs(s: &str) -> Result<String, JsValue> {
+pub fn full_table(s: &str, generics: &str, formats: &str) -> Result<String, JsValue> {
     console_error_panic_hook::set_once();
-    let mut scanner = Scanner::new(s, PathBuf::from(""));
-    let provider: Rc<dyn HdlProvider> = Rc::new(EmbedReader);
-    let mut parser = Parser::new(&mut scanner, provider.clone());
-
-    let hdl = match parser.parse() {
+    let generics: Vec<usize> = match serde_json::from_str(generics) {
         Ok(x) => x,
         Err(e) => {
-            return Err(JsValue::from(&e.to_string()));
+            return Err(JsValue::from(format!(
+                "Unable to parse generics {}: {}",
+                generics, e
+            )));
         }
     };
-
-    let chip = match Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new()) {
+    let formats: HashMap<String, Radix> = match serde_json::from_str(formats) {
+        Ok(x) => x,
+        Err(e) => {
+            return Err(JsValue::from(format!(
+                "Unable to parse formats {}: {}",
+                formats, e
+            )));
+        }
+    };
+    let table = match full_table_internal(s, &generics, &formats, Rc::new(EmbedReader)) {
         Ok(x) => x,
         Err(e) => {
-            return Err(JsValue::from(&e.to_string()));
+            return Err(JsValue::from(e.to_string()));
         }
     };
+    Ok(serde_json::to_string(&table).unwrap())
+}
+
+// Returns (column list, row values), with the values of each column in the
+// radix `formats` gives it, such as {"out": "hex"}, or binary.
+pub fn full_table_internal(
+    s: &str,
+    generics: &[usize],
+    formats: &HashMap<String, Radix>,
+    provider: Rc<dyn HdlProvider>,
+) -> Result<(Vec<String>, FormattedTable), Box<dyn Error>> {
+    let hdl = parse_hdl_str(s, provider.clone(), PathBuf::from(""))?;
+
+    let (columns, table) = table::full_table(&hdl, &provider, generics, &TableOptions::default())?;
+    let rows = format_table(&columns, &table, formats);
+    Ok((columns, rows))
+}
 
-    Ok(serde_json::to_string(&chip.circuit).unwrap())
+#[wasm_bindgen]
+pub fn component_graphs(s: &str) -> Result<String, JsValue> {
+    console_error_panic_hook::set_once();
+    let provider: Rc<dyn HdlProvider> = Rc::new(EmbedReader);
+    component_graphs_internal(s, provider, true).map_err(|e| JsValue::from(&e.to_string()))
+}
+
+/// Like `component_graphs`, but without merging parallel wires, so there is
+/// one edge for every connection made by a part.
+#[wasm_bindgen]
+pub fn raw_component_graphs(s: &str) -> Result<String, JsValue> {
+    console_error_panic_hook::set_once();
+    let provider: Rc<dyn HdlProvider> = Rc::new(EmbedReader);
+    component_graphs_internal(s, provider, false).map_err(|e| JsValue::from(&e.to_string()))
+}
+
+fn component_graphs_internal(
+    s: &str,
+    provider: Rc<dyn HdlProvider>,
+    optimize: bool,
+) -> Result<String, Box<dyn Error>> {
+    let hdl = parse_hdl_str(s, provider.clone(), PathBuf::from(""))?;
+    let mut chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())?;
+    chip.optimize = optimize;
+    chip.elaborate()?;
+
+    Ok(serde_json::to_string(&CircuitView::from(&chip))?)
 }
 
 #[cfg(test)]
@@ -217,14 +250,124 @@ mod libtest {
     fn test_nand2tetris_solution_and() {
         let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
         let base_path = manifest_dir
-                .join("resources")
-                .join("tests")
-                .join("nand2tetris")
-                .join("solutions");
+            .join("resources")
+            .join("tests")
+            .join("nand2tetris")
+            .join("solutions");
         let provider = Rc::new(FileReader::new(&base_path));
         let contents = provider.get_hdl("And.hdl").unwrap();
-        let (_, table) =
-            full_table_internal(&contents, provider).unwrap();
+        let (_, table) = full_table_internal(&contents, &[], &HashMap::new(), provider).unwrap();
         assert_eq!(table.len(), 4);
+        assert_eq!(table[3], vec!["1", "1", "1"]);
+    }
+
+    #[test]
+    fn test_formatted_table() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let provider = Rc::new(FileReader::new(
+            &manifest_dir.join("resources/tests/multiply"),
+        ));
+        let contents = provider.get_hdl("Join4.hdl").unwrap();
+        let formats: HashMap<String, Radix> = serde_json::from_str(
+            r#"{"high": "decimal", "low": "signed", "out": "hex", "missing": "binary"}"#,
+        )
+        .unwrap();
+        let (columns, table) = full_table_internal(&contents, &[], &formats, provider).unwrap();
+        assert_eq!(columns, vec!["high", "low", "out"]);
+        assert_eq!(table.len(), 256);
+        let row = table.iter().find(|r| r[0] == "15" && r[1] == "-2").unwrap();
+        assert_eq!(row[2], "FE");
+    }
+
+    #[test]
+    fn test_raw_component_graphs() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir
+            .join("resources")
+            .join("tests")
+            .join("nand2tetris")
+            .join("solutions");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let contents = provider.get_hdl("Inc16.hdl").unwrap();
+        let edges = |optimize| {
+            let json = component_graphs_internal(&contents, provider.clone(), optimize).unwrap();
+            let view: serde_json::Value = serde_json::from_str(&json).unwrap();
+            view["edges"].as_array().unwrap().len()
+        };
+
+        // The raw graph has a wire for each bit of in, out and the 16 bits
+        // of b. Optimizing merges them into one wire per pair of nodes.
+        assert_eq!(edges(false), 48);
+        assert_eq!(edges(true), 4);
+    }
+
+    #[test]
+    fn test_inputs_from_json() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir
+            .join("resources")
+            .join("tests")
+            .join("nand2tetris")
+            .join("solutions");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let contents = provider.get_hdl("Mux4Way16.hdl").unwrap();
+        let hdl = parse_hdl_str(&contents, provider.clone(), PathBuf::from("")).unwrap();
+
+        let inputs = inputs_from_json(
+            &hdl,
+            r#"{"a": 5, "b": -1, "c": 0, "d": 65535, "sel": [true, false]}"#,
+        )
+        .unwrap();
+        assert_eq!(inputs.as_unsigned("a"), Some(5));
+        assert_eq!(inputs.as_unsigned("b"), Some(0xFFFF));
+        assert_eq!(inputs.as_unsigned("sel"), Some(2));
+
+        // Out of range, wrong width, wrong type, and missing ports are errors.
+        let bad = [
+            r#"{"a": 65536, "b": 0, "c": 0, "d": 0, "sel": 0}"#,
+            r#"{"a": 0, "b": 0, "c": 0, "d": 0, "sel": [true]}"#,
+            r#"{"a": "x", "b": 0, "c": 0, "d": 0, "sel": 0}"#,
+            r#"{"a": 0, "b": 0, "c": 0, "d": 0}"#,
+            r#"not json"#,
+        ];
+        for b in bad {
+            assert!(inputs_from_json(&hdl, b).is_err());
+        }
+
+        // Unknown inputs are null or "*", and single bits can be null.
+        let inputs = inputs_from_json(
+            &hdl,
+            r#"{"a": null, "b": "*", "c": 0, "d": 0, "sel": [null, false]}"#,
+        )
+        .unwrap();
+        assert_eq!(inputs.get_name("a"), vec![None; 16]);
+        assert_eq!(inputs.get_name("b"), vec![None; 16]);
+        assert_eq!(inputs.get_name("sel"), vec![None, Some(false)]);
+    }
+
+    #[test]
+    fn test_synthesized_table_roundtrip() {
+        // Full adder.
+        let mut rows = Vec::new();
+        for i in 0..8 {
+            let inputs = vec![i & 4 != 0, i & 2 != 0, i & 1 != 0];
+            let count = inputs.iter().filter(|&&x| x).count();
+            rows.push((inputs, vec![count % 2 == 1, count >= 2]));
+        }
+        let hdl =
+            fromtable::synthesize_from_table(&["a", "b", "c"], &["sum", "carry"], &rows).unwrap();
+
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+        let (columns, table) = full_table_internal(&hdl, &[], &HashMap::new(), provider).unwrap();
+        assert_eq!(columns, vec!["a", "b", "c", "sum", "carry"]);
+        assert_eq!(table.len(), 8);
+        for row in table {
+            let bits: Vec<bool> = row.iter().map(|bit| bit == "1").collect();
+            let expected = rows
+                .iter()
+                .find(|(inputs, _)| inputs[..] == bits[..3])
+                .unwrap();
+            assert_eq!(expected.1[..], bits[3..]);
+        }
     }
 }
@@ -1,42 +1,80 @@
 //! This is the main command-line utility.
 
+mod blif;
 mod busmap;
+mod diff;
 mod error;
 mod expr;
+mod fromtable;
+mod fuzz;
+mod manifest;
+mod memory;
 mod modelsim;
+mod opt;
 mod parser;
+mod profile;
+mod repl;
+mod run;
 mod scanner;
+mod selftest;
 mod simulator;
+mod symbolic;
+mod table;
 mod test_parser;
 mod test_scanner;
 mod test_script;
+mod vcd;
+mod verilog;
 mod vhdl;
-mod opt;
 
+use blif::to_blif;
+use busmap::{format_bits, BusMap, Radix};
+use diff::diff;
 use error::*;
+use fromtable::{read_table_csv, synthesize_chip};
+use fuzz::check_inputs;
+use manifest::{Manifest, MANIFEST_NAME};
 use modelsim::synth_vhdl_test;
 use parser::*;
-use simulator::{Bus, Chip, Simulator};
-use test_script::run_test;
-use vhdl::VhdlEntity;
+use profile::Profile;
+use repl::Repl;
+use run::{run_computer, run_program};
+use selftest::selftest;
+use simulator::{check_strict_widths, Bus, Chip, Simulator};
+use symbolic::symbolic;
+use table::{full_table, TableOptions};
+use test_script::{run_test_with_options, TestOptions};
+use verilog::write_verilog_project;
+use vhdl::{VhdlEntity, VhdlTarget};
 
 use clap::Parser as ArgParser;
 use clap::Subcommand;
 use parser::Parser;
 use scanner::Scanner;
+use std::cell::RefCell;
+use std::env;
 use std::error::Error;
 use std::fs;
+use std::io::{self, BufWriter, IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::ptr;
 use std::rc::Rc;
 
-use crate::vhdl::write_quartus_project;
+use crate::opt::optimization::OptimizationPass;
+use crate::opt::structural_hash::StructuralHash;
+use crate::vhdl::{flatten_entity, write_quartus_project, write_single_file, QuartusProject};
 
 #[derive(ArgParser)]
 #[clap(version)]
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+
+    /// Print nothing when a command succeeds, except output it was asked
+    /// for. Errors are still printed.
+    #[clap(short, long, global = true, action)]
+    quiet: bool,
 }
 
 #[derive(Subcommand)]
@@ -54,49 +92,684 @@ enum Commands {
 
         /// The synth-vhdl command creates a Quartus Prime project in
         /// a new folder. This is the folder to create for the project.
+        #[clap(index = 2, required_unless_present = "single_file")]
+        output_dir: Option<PathBuf>,
+
+        /// Write every entity of the design to this one VHDL file instead
+        /// of creating a Quartus Prime project.
+        #[clap(long, action, conflicts_with = "output_dir")]
+        single_file: Option<PathBuf>,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Merge components that have the same chip, generics, and inputs.
+        #[clap(long, action)]
+        structural_hash: bool,
+
+        /// Print each VHDL file as it is written.
+        #[clap(short, long, action)]
+        verbose: bool,
+
+        /// Write the whole design as a single entity of NAND gates and
+        /// DFFs, with no sub-entities.
+        #[clap(long, action)]
+        flatten: bool,
+
+        /// VHDL standard to write. vhdl2008 reads output ports directly
+        /// instead of through mirror signals.
+        #[clap(long, value_enum, default_value_t = VhdlTarget::Vhdl93)]
+        target: VhdlTarget,
+
+        /// Print the time spent building each chip type.
+        #[clap(long, action)]
+        profile: bool,
+    },
+
+    /// Converts a chip to Verilog, writing one module per chip.
+    SynthVerilog {
+        /// Path to the top-level HDL file.
+        #[clap(index = 1)]
+        path: PathBuf,
+
+        /// Folder to create for the Verilog files.
         #[clap(index = 2)]
         output_dir: PathBuf,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Print each Verilog file as it is written.
+        #[clap(short, long, action)]
+        verbose: bool,
     },
 
     /// Parses chip and simulates a single input, for catching errors.
+    /// Without --top-level-file, the project is read from whidl.toml.
     Check {
         #[clap(short, long, action)]
-        top_level_file: String,
+        top_level_file: Option<String>,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Print the value of each output port after simulation.
+        #[clap(long, action)]
+        show_values: bool,
+
+        /// Print the value of an internal signal after simulation, given as
+        /// parts from the top-level chip down and a signal name joined by
+        /// slashes, such as `FullAdder.1/carry`. May be repeated.
+        #[clap(long, action)]
+        probe: Vec<String>,
+
+        /// Print the time spent building each chip type.
+        #[clap(long, action)]
+        profile: bool,
+
+        /// Warn instead of failing when output port bits of the chip or of
+        /// its parts have no source, even with --strict.
+        #[clap(long, action)]
+        allow_undriven: bool,
+
+        /// Fail when mappings imply different widths for a signal instead
+        /// of widening it to the largest, when the chip's own output port
+        /// bits have no source instead of warning, and when output bits
+        /// computed from none are unknown.
+        #[clap(long, action)]
+        strict: bool,
+
+        /// Also simulate with every input set to true and then this many
+        /// random inputs.
+        #[clap(long, action)]
+        random: Option<usize>,
+
+        /// Seed for the random inputs.
+        #[clap(long, action, default_value_t = 0)]
+        seed: u64,
+
+        /// Warn about generic parameters that the chip or the chips it uses
+        /// declare but never use.
+        #[clap(long, action)]
+        lint: bool,
+
+        /// Print how often chip outputs were found in the simulation cache.
+        #[clap(long, action)]
+        cache_stats: bool,
     },
 
     /// Runs a nand2tetris test
     Test {
         #[clap(short, long, action)]
         test_file: String,
+
+        /// Print each input vector and its outputs, marking which rows
+        /// match the compare file.
+        #[clap(short, long, action)]
+        verbose: bool,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Print the time spent building each chip type.
+        #[clap(long, action)]
+        profile: bool,
+
+        /// Warn instead of failing when output port bits have no source.
+        #[clap(long, action)]
+        allow_undriven: bool,
+
+        /// Fail when mappings imply different widths for a signal instead
+        /// of widening it to the largest.
+        #[clap(long, action)]
+        strict: bool,
+
+        /// Fail the test if the clock ticks more than this many times.
+        #[clap(long, action)]
+        max_cycles: Option<u64>,
+
+        /// Write a waveform of the chip's ports to this VCD file, with one
+        /// time unit per clock tick.
+        #[clap(long, action)]
+        vcd: Option<PathBuf>,
+
+        /// Internal signals of the chip to add to the waveform, for example
+        /// `--vcd-signals muxOut,dffOut`.
+        #[clap(long, action, value_delimiter = ',', requires = "vcd")]
+        vcd_signals: Vec<String>,
+
+        /// Compare each output row with the compare file as text, using the
+        /// formats in the output-list, and stop at the first line that
+        /// differs.
+        #[clap(long, action)]
+        cmp: bool,
+    },
+
+    /// Prints the truth table of a chip by simulating every combination of
+    /// inputs.
+    Table {
+        #[clap(short, long, action)]
+        top_level_file: String,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Generic arguments for a generic top-level chip, in the order they
+        /// are declared, for example `--generics 16,4`.
+        #[clap(long, action, value_delimiter = ',')]
+        generics: Vec<usize>,
+
+        /// Fail instead of simulating tables with more rows than this.
+        #[clap(long, action, default_value_t = 1024)]
+        max_rows: u64,
+
+        /// Print the number of rows simulated so far to stderr.
+        #[clap(long, action)]
+        progress: bool,
+
+        /// Print how often chip outputs were found in the simulation cache
+        /// to stderr.
+        #[clap(long, action)]
+        cache_stats: bool,
+
+        /// Write to this file instead of standard output. Missing parent
+        /// directories are created.
+        #[clap(short, long, action)]
+        output: Option<PathBuf>,
+    },
+
+    /// Prints the number of NAND gates in the fully elaborated chip, and the
+    /// number of DFFs if it has any. Every instance of every part is counted.
+    /// Without --top-level-file, the project is read from whidl.toml.
+    #[clap(name = "nandcount")]
+    NandCount {
+        #[clap(short, long, action)]
+        top_level_file: Option<String>,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Generic arguments for a generic top-level chip, in the order they
+        /// are declared, for example `--generics 16,4`.
+        #[clap(long, action, value_delimiter = ',')]
+        generics: Vec<usize>,
+    },
+
+    /// Prints the circuit of a chip, one level deep, as a Graphviz graph
+    /// with a node for each part and port and an edge for each wire.
+    Dot {
+        #[clap(index = 1)]
+        top_level_file: PathBuf,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Generic arguments for a generic top-level chip, in the order they
+        /// are declared, for example `--generics 16,4`.
+        #[clap(long, action, value_delimiter = ',')]
+        generics: Vec<usize>,
+
+        /// Draw an edge for every connection made by a part instead of
+        /// merging parallel wires.
+        #[clap(long, action)]
+        raw: bool,
+
+        /// Write to this file instead of standard output. Missing parent
+        /// directories are created.
+        #[clap(short, long, action)]
+        output: Option<PathBuf>,
+    },
+
+    /// Writes a chip as a BLIF model of NAND gates and latches, for logic
+    /// synthesis tools such as Berkeley ABC.
+    Blif {
+        #[clap(index = 1)]
+        top_level_file: PathBuf,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Generic arguments for a generic top-level chip, in the order they
+        /// are declared, for example `--generics 16,4`.
+        #[clap(long, action, value_delimiter = ',')]
+        generics: Vec<usize>,
+
+        /// Write to this file instead of standard output. Missing parent
+        /// directories are created.
+        #[clap(short, long, action)]
+        output: Option<PathBuf>,
+    },
+
+    /// Prints a boolean expression over the input bits for each output bit
+    /// of a combinational chip, built from its NAND gates. The expressions
+    /// are simplified to NOT, AND and OR where possible.
+    Symbolic {
+        #[clap(index = 1)]
+        top_level_file: PathBuf,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Print the NAND gates without simplifying them.
+        #[clap(long, action)]
+        raw: bool,
+
+        /// Write to this file instead of standard output. Missing parent
+        /// directories are created.
+        #[clap(short, long, action)]
+        output: Option<PathBuf>,
+    },
+
+    /// Loads a chip once and reads commands from standard input to set its
+    /// inputs, evaluate it, tick the clock, and show signals.
+    Repl {
+        #[clap(index = 1)]
+        top_level_file: PathBuf,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Generic arguments for a generic top-level chip, in the order they
+        /// are declared, for example `--generics 16,4`.
+        #[clap(long, action, value_delimiter = ',')]
+        generics: Vec<usize>,
+    },
+
+    /// Compares the NAND gates and DFFs of two versions of a chip and
+    /// prints the gates that were added, removed, or rewired.
+    Diff {
+        /// HDL file of the old version of the chip.
+        #[clap(index = 1)]
+        old: PathBuf,
+
+        /// HDL file of the new version of the chip.
+        #[clap(index = 2)]
+        new: PathBuf,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Also write a Graphviz graph of the new version to this file,
+        /// with the differences highlighted.
+        #[clap(long, action)]
+        dot: Option<PathBuf>,
+    },
+
+    /// Writes a chip built only from NAND gates that implements a truth
+    /// table given as CSV.
+    #[clap(name = "fromtable")]
+    FromTable {
+        /// CSV file whose header names the ports. Each row gives 0 or 1 for
+        /// every port.
+        #[clap(index = 1)]
+        table: PathBuf,
+
+        /// Columns that are outputs, for example `--outputs sum,carry`.
+        /// Defaults to the last column.
+        #[clap(long, action, value_delimiter = ',')]
+        outputs: Vec<String>,
+
+        /// Name of the chip. Defaults to the name of the CSV file.
+        #[clap(long, action)]
+        name: Option<String>,
+
+        /// Write to this file instead of standard output. Missing parent
+        /// directories are created.
+        #[clap(short, long, action)]
+        output: Option<PathBuf>,
+    },
+
+    /// Runs a Hack program on a CPU chip until it halts, and prints the
+    /// number of cycles and the memory words that are not zero.
+    Run {
+        /// HDL file of a CPU with the ports of the nand2tetris CPU, or of
+        /// a whole computer with `--rom`.
+        #[clap(short, long, action)]
+        top_level_file: String,
+
+        /// Program with one binary instruction per line, such as a .hack
+        /// file.
+        #[clap(index = 1)]
+        program: PathBuf,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Fail if the program has not halted after this many cycles.
+        #[clap(long, action, default_value_t = 100000)]
+        max_cycles: usize,
+
+        /// Path of the ROM part of a computer chip, such as `ROM32K.0`.
+        /// The program is loaded into its DFFs and the computer runs for
+        /// `--cycles` cycles.
+        #[clap(long, action, requires = "cycles")]
+        rom: Option<String>,
+
+        /// Path of the memory part of a computer chip to print after the
+        /// run, such as `Memory.0/RAM16K.0`, or `Memory.0/Screen.0` for the
+        /// words of the screen.
+        #[clap(long, action, requires = "rom")]
+        ram: Option<String>,
+
+        /// Number of cycles to run a computer chip for.
+        #[clap(long, action, requires = "rom")]
+        cycles: Option<usize>,
+    },
+
+    /// Runs the `//! ASSERT a=1 b=1 => out=1` comments in an HDL file as
+    /// test cases.
+    Selftest {
+        #[clap(short, long, action)]
+        top_level_file: String,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
     },
+
+    /// Prints the interface of a chip (generics and ports) as HDL with an
+    /// empty PARTS section.
+    Stub {
+        #[clap(short, long, action)]
+        top_level_file: String,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Write to this file instead of standard output. Missing parent
+        /// directories are created.
+        #[clap(short, long, action)]
+        output: Option<PathBuf>,
+    },
+
+    /// Prints the chip as canonical HDL for diffing, with ports and port
+    /// mappings sorted and comments and spacing dropped.
+    Normalize {
+        #[clap(short, long, action)]
+        top_level_file: String,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Write to this file instead of standard output. Missing parent
+        /// directories are created.
+        #[clap(short, long, action)]
+        output: Option<PathBuf>,
+    },
+
+    /// Prints Markdown documentation for the interface of a chip, taken
+    /// from the comments above the chip and its ports.
+    Docs {
+        #[clap(short, long, action)]
+        top_level_file: String,
+
+        /// Additional directory to search for component HDL files.
+        /// May be repeated.
+        #[clap(short = 'I', long = "include", action)]
+        include: Vec<PathBuf>,
+
+        /// Write to this file instead of standard output. Missing parent
+        /// directories are created.
+        #[clap(short, long, action)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Opens the file given with `--output`, or standard output if there is
+/// none.
+fn open_output(output: &Option<PathBuf>) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    let path = match output {
+        None => return Ok(Box::new(io::stdout().lock())),
+        Some(path) => path,
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::File::create(path) {
+        Ok(file) => Ok(Box::new(BufWriter::new(file))),
+        Err(e) => Err(Box::new(TransformedError {
+            msg: format!("Unable to create output file {}.", path.display()),
+            kind: ErrorKind::IOError,
+            source: Some(Box::new(e)),
+        })),
+    }
+}
+
+/// Returns the `-I` include directories followed by the directories listed
+/// in the `WHIDL_PATH` environment variable, which uses the same separator
+/// as `PATH`.
+fn search_path(include: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths = include.to_vec();
+    if let Some(whidl_path) = env::var_os("WHIDL_PATH") {
+        paths.extend(env::split_paths(&whidl_path).filter(|p| !p.as_os_str().is_empty()));
+    }
+    paths
+}
+
+/// Returns the top-level file, full search path, and generic arguments for
+/// a command. Without a top-level file they come from the manifest in the
+/// current directory, and the manifest's include directories are searched
+/// after the `-I` directories.
+fn project_args(
+    top_level_file: &Option<String>,
+    include: &[PathBuf],
+) -> Result<Manifest, Box<dyn Error>> {
+    if let Some(top_level_file) = top_level_file {
+        return Ok(Manifest {
+            top: PathBuf::from(top_level_file),
+            include: search_path(include),
+            generics: Vec::new(),
+        });
+    }
+    match Manifest::read(Path::new("."))? {
+        Some(mut manifest) => {
+            let mut dirs = include.to_vec();
+            dirs.append(&mut manifest.include);
+            manifest.include = search_path(&dirs);
+            Ok(manifest)
+        }
+        None => Err(Box::new(N2VError {
+            msg: format!(
+                "No --top-level-file given and no {} in the current directory.",
+                MANIFEST_NAME
+            ),
+            kind: ErrorKind::Other,
+        })),
+    }
+}
+
+/// Prints a warning for each generic parameter that is declared but never
+/// used, in the chip and every chip it depends on.
+fn lint_generics(hdl: &ChipHDL) -> Result<(), Box<dyn Error>> {
+    let mut chips = vec![hdl.clone()];
+    for name in vhdl::dependency_order(hdl)? {
+        chips.push(get_hdl(&name, &hdl.provider)?);
+    }
+    for chip in chips {
+        for generic in chip.unused_generics() {
+            let location = match (&generic.path, generic.line) {
+                (Some(path), Some(line)) => format!(" ({}:{})", path.display(), line),
+                _ => String::new(),
+            };
+            eprintln!(
+                "Warning: Chip {} declares generic {} but never uses it{}.",
+                chip.name, generic.value, location
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Fails if any output bit is unknown after a simulation with known inputs,
+/// which means it is computed from `none`. Bits with no source are left to
+/// the undriven output check.
+fn check_known_outputs(simulator: &Simulator, outputs: &BusMap) -> Result<(), Box<dyn Error>> {
+    let undriven = simulator.chip.undriven_outputs();
+    let mut names = outputs.keys();
+    names.sort();
+    let mut unknown = Vec::new();
+    for name in names {
+        if !simulator.chip.ports[&name].direction.is_output() || undriven.contains(&name) {
+            continue;
+        }
+        let width = outputs.get_width(&name).unwrap();
+        let bits: Vec<String> = (0..width)
+            .filter(|i| {
+                let bit = Bus {
+                    name: name.clone(),
+                    range: Some(*i..*i + 1),
+                };
+                outputs.get_bus(&bit)[0].is_none()
+            })
+            .map(|i| format!("{}[{}]", name, i))
+            .filter(|bit| !undriven.contains(bit))
+            .collect();
+        if bits.len() == width {
+            unknown.push(name);
+        } else {
+            unknown.extend(bits);
+        }
+    }
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(Box::new(N2VError {
+        msg: format!(
+            "Chip {} has unknown output bits, which are computed from none: {}.",
+            simulator.chip.name,
+            unknown.join(", ")
+        ),
+        kind: ErrorKind::SimulationError(None),
+    }))
 }
 
 // TODO: Remove duplication from this function.
-fn synth_vhdl_chip(output_dir: &PathBuf, hdl_path: &PathBuf) -> Result<(), Box<dyn Error>> {
+fn synth_vhdl_chip(
+    output_dir: &Path,
+    hdl_path: &PathBuf,
+    include_paths: &[PathBuf],
+    structural_hash: bool,
+    flatten: bool,
+    target: VhdlTarget,
+    profile: Option<Rc<RefCell<Profile>>>,
+) -> Result<QuartusProject, Box<dyn Error>> {
     // Standard HDL parsing pipeline.
     let source_code = fs::read_to_string(hdl_path)?;
     let mut scanner = Scanner::new(&source_code, hdl_path.clone());
     let base_path = hdl_path.parent().unwrap();
-    let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(base_path));
+    let provider: Rc<dyn HdlProvider> =
+        Rc::new(FileReader::with_includes(base_path, include_paths));
     let mut parser = Parser::new(&mut scanner, provider.clone());
-    let hdl = parser.parse()?;
+    let mut hdl = parser.parse()?;
+    if structural_hash {
+        (hdl, _) = StructuralHash::new().apply(&hdl, &provider)?;
+    }
 
     // Convert HDL to VHDL (VHDl synthesis).
-    let chip_vhdl: VhdlEntity = VhdlEntity::try_from(&hdl)?;
+    let chip_vhdl: VhdlEntity = match flatten {
+        true => flatten_entity(&hdl, profile.clone())?,
+        false => VhdlEntity::synthesize_with_profile(&hdl, target, profile.clone())?,
+    };
 
     // Create a Quartus Prime project.
     let quartus_dir = Path::new(&output_dir);
-    let project = crate::vhdl::QuartusProject::new(hdl, chip_vhdl, quartus_dir.to_path_buf());
-    write_quartus_project(&project)?;
-
-    Ok(())
+    let mut project = QuartusProject::new(hdl, chip_vhdl, quartus_dir.to_path_buf());
+    project.structural_hash = structural_hash;
+    project.flatten = flatten;
+    project.target = target;
+    project.profile = profile;
+    Ok(project)
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let cli = Cli::parse();
+/// Exits with 0 on success, or the code for the kind of error. See
+/// `ErrorKind::exit_code`.
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        // Help and version requests exit with 0.
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            let _ = e.print();
+            return ExitCode::from(ErrorKind::Other.exit_code());
+        }
+    };
+
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit_code(e.as_ref()))
+        }
+    }
+}
 
+fn run(cli: &Cli) -> Result<(), Box<dyn Error>> {
     match &cli.command {
-        Commands::SynthVHDL { output_dir, path } => {
+        Commands::SynthVHDL {
+            output_dir,
+            path,
+            include,
+            structural_hash,
+            verbose,
+            flatten,
+            target,
+            single_file,
+            profile,
+        } => {
+            let include = &search_path(include);
+            let profile = profile.then(|| Rc::new(RefCell::new(Profile::new())));
+            if let Some(single_file) = single_file {
+                let mut project = synth_vhdl_chip(
+                    Path::new(""),
+                    path,
+                    include,
+                    *structural_hash,
+                    *flatten,
+                    *target,
+                    profile.clone(),
+                )?;
+                project.verbose = *verbose;
+                let mut out = open_output(&Some(single_file.clone()))?;
+                write_single_file(&project, &mut out)?;
+                out.flush()?;
+                if let Some(profile) = profile {
+                    println!("Profile:");
+                    println!("{}", profile.borrow());
+                }
+                return Ok(());
+            }
+
+            // Clap requires the output directory without --single-file.
+            let output_dir = output_dir.as_ref().unwrap();
             // Try synthesizing a Chip. If that fails, try synthesizing a test.
             match fs::create_dir(output_dir) {
                 Ok(_) => (),
@@ -109,13 +782,23 @@ fn main() -> Result<(), Box<dyn Error>> {
                 }
             }
 
-            let vhdl_result = synth_vhdl_chip(output_dir, path);
-            if vhdl_result.is_err() {
-                let synth_result = synth_vhdl_test(output_dir, path);
-
-                if synth_result.is_err() {
-                    println!("Parsing as chip:\n{}", vhdl_result.unwrap_err());
-                    println!("Parsing as test script:\n{}", synth_result.unwrap_err());
+            let project = synth_vhdl_chip(
+                output_dir,
+                path,
+                include,
+                *structural_hash,
+                *flatten,
+                *target,
+                profile.clone(),
+            );
+            let project = project.map(|mut p| {
+                p.verbose = *verbose;
+                p
+            });
+            if let Err(vhdl_err) = project.and_then(|p| write_quartus_project(&p)) {
+                if let Err(synth_err) = synth_vhdl_test(output_dir, path, include) {
+                    println!("Parsing as chip:\n{}", vhdl_err);
+                    println!("Parsing as test script:\n{}", synth_err);
                     return Err(Box::new(N2VError {
                         msg: format!(
                             "Unable to parse {} as either an HDL file or a test script.",
@@ -125,61 +808,448 @@ fn main() -> Result<(), Box<dyn Error>> {
                     }));
                 }
             }
+            if let Some(profile) = profile {
+                println!("Profile:");
+                println!("{}", profile.borrow());
+            }
         }
-        Commands::Check { top_level_file } => {
-            let source_code = fs::read_to_string(top_level_file)?;
-            let mut scanner = Scanner::new(&source_code, PathBuf::from(&top_level_file));
+        Commands::SynthVerilog {
+            path,
+            output_dir,
+            include,
+            verbose,
+        } => {
+            let (hdl, _) = parse_hdl_path_with_includes(path, &search_path(include))?;
+            if let Err(e) = fs::create_dir(output_dir) {
+                return Err(Box::new(TransformedError {
+                    msg: String::from("Unable to create output directory."),
+                    kind: ErrorKind::IOError,
+                    source: Some(Box::new(e)),
+                }));
+            }
+            write_verilog_project(&hdl, output_dir, *verbose)?;
+        }
+        Commands::Check {
+            top_level_file,
+            include,
+            show_values,
+            probe,
+            profile,
+            allow_undriven,
+            strict,
+            random,
+            seed,
+            lint,
+            cache_stats,
+        } => {
+            let project = project_args(top_level_file, include)?;
+            let (include, generics) = (&project.include, &project.generics);
+            let source_code = fs::read_to_string(&project.top)?;
+            let mut scanner = Scanner::new(&source_code, project.top.clone());
             let base_path = scanner.path.parent().unwrap();
-            let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(base_path));
+            let provider: Rc<dyn HdlProvider> =
+                Rc::new(FileReader::with_includes(base_path, include));
             let mut parser = Parser::new(&mut scanner, provider.clone());
 
             let hdl = parser.parse()?;
 
-            let chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())?;
+            if *lint {
+                lint_generics(&hdl)?;
+            }
+
+            if *strict {
+                check_strict_widths(&hdl, &provider, generics)?;
+            }
+
+            let profile = profile.then(|| Rc::new(RefCell::new(Profile::new())));
+            let mut chip = Chip::new_with_profile(
+                &hdl,
+                ptr::null_mut(),
+                &provider,
+                false,
+                generics,
+                profile.clone(),
+            )?;
+            chip.allow_undriven_outputs = *allow_undriven;
+            chip.allow_undriven_ports = !*strict;
+            chip.strict_widths = *strict;
             let mut simulator = Simulator::new(chip);
 
+            // Probed parts are elaborated up front so that they are not
+            // skipped by the simulation cache.
+            let probe_paths: Vec<Vec<&str>> =
+                probe.iter().map(|p| p.split('/').collect()).collect();
+            for path in &probe_paths {
+                simulator.elaborate_path(&path[..path.len() - 1])?;
+            }
+
             // Get all input ports.
             // Set all input ports to false and simulate.
             let mut inputs = simulator
                 .chip
                 .get_port_values_for_direction(PortDirection::In);
 
-            // TODO: make it easier to get full bus out of busmap
-            for sn in inputs.keys() {
-                let sig_width = inputs.get_width(&sn);
-                let usig_width = sig_width.as_ref().unwrap_or(&0);
-                let b = Bus {
-                    name: sn,
-                    range: sig_width.map(|x| 0..x),
-                };
-                inputs.insert_option(&b, vec![Some(false); *usig_width]);
+            for name in inputs.keys() {
+                let bus = inputs.full_bus(&name).unwrap();
+                let width = inputs.get_width(&name).unwrap();
+                inputs.insert_option(&bus, vec![Some(false); width]);
             }
 
             // We don't care what the outputs are, just want to simulate
             // and trigger any dynamic errors.
-            simulator.simulate(&inputs)?;
+            let outputs = simulator.simulate(&inputs)?;
+            if *strict {
+                check_known_outputs(&simulator, &outputs)?;
+            }
+            if let Some(random) = random {
+                check_inputs(&mut simulator, *random, *seed)?;
+            }
+            for bit in simulator.chip.undriven_outputs() {
+                eprintln!("⚠ output '{}' has no source", bit);
+            }
 
-            println!("✔️️️    Check Passed");
-            println!("---------------------");
-            println!("Name: {}", &simulator.chip.name);
-            println!("Ports:");
-            for (port_name, port) in &simulator.chip.ports {
+            if !cli.quiet {
+                println!("✔️️️    Check Passed");
+                println!("---------------------");
+                println!("Name: {}", &simulator.chip.name);
+                println!("Ports:");
+                for (port_name, port) in &simulator.chip.ports {
+                    println!(
+                        "\t{}: Direction={:?} Width={}",
+                        &port_name, port.direction, port.width
+                    );
+                }
+                println!("Signals:");
+                for signal_name in &simulator.chip.signal_names() {
+                    let sig_width = match &simulator.chip.signal_width(signal_name) {
+                        Some(w) => w.to_string(),
+                        None => String::from("?"),
+                    };
+                    println!("\t{}: Width={}", &signal_name, &sig_width);
+                }
+            }
+            let print_value = |name: &str, values: &BusMap| {
+                let format = |radix| {
+                    values
+                        .format(name, radix)
+                        .unwrap_or_else(|| String::from("?"))
+                };
                 println!(
-                    "\t{}: Direction={:?} Width={}",
-                    &port_name, port.direction, port.width
+                    "\t{}: bin={} hex={} signed={}",
+                    name,
+                    format(Radix::Binary),
+                    format(Radix::Hex),
+                    format(Radix::Signed),
                 );
+            };
+            if *show_values {
+                println!("Values:");
+                for port_name in outputs.keys() {
+                    if !simulator.chip.ports[&port_name].direction.is_output() {
+                        continue;
+                    }
+                    print_value(&port_name, &outputs);
+                }
             }
-            println!("Signals:");
-            for signal_name in &simulator.chip.signals.keys() {
-                let sig_width = match &simulator.chip.signals.get_width(signal_name) {
-                    Some(w) => w.to_string(),
-                    None => String::from("?"),
-                };
-                println!("\t{}: Width={}", &signal_name, &sig_width);
+            if !probe.is_empty() {
+                let mut values = BusMap::new();
+                for (name, path) in probe.iter().zip(&probe_paths) {
+                    let bits = simulator.probe(path).ok_or_else(|| N2VError {
+                        msg: format!("There is no signal {} to probe.", name),
+                        kind: ErrorKind::Other,
+                    })?;
+                    values.create_bus(name, bits.len())?;
+                    values.insert_option(&Bus::from(name.as_str()), bits);
+                }
+                println!("Probes:");
+                for name in probe {
+                    print_value(name, &values);
+                }
+            }
+            if let Some(profile) = profile {
+                println!("Profile:");
+                println!("{}", profile.borrow());
+            }
+            if *cache_stats {
+                println!("{}", simulator.cache_stats());
+            }
+        }
+        Commands::Test {
+            test_file,
+            verbose,
+            include,
+            profile,
+            allow_undriven,
+            strict,
+            max_cycles,
+            vcd,
+            vcd_signals,
+            cmp,
+        } => {
+            let include = &search_path(include);
+            let options = TestOptions {
+                include_paths: include.clone(),
+                verbose: *verbose,
+                profile: profile.then(|| Rc::new(RefCell::new(Profile::new()))),
+                allow_undriven_outputs: *allow_undriven,
+                strict_widths: *strict,
+                max_cycles: *max_cycles,
+                quiet: cli.quiet,
+                vcd: vcd.clone(),
+                vcd_signals: vcd_signals.clone(),
+                cmp: *cmp,
+            };
+            let result = run_test_with_options(&PathBuf::from(test_file), &options);
+            if let Some(profile) = &options.profile {
+                println!();
+                println!("{}", profile.borrow());
+            }
+            for bit in result? {
+                eprintln!("⚠ output '{}' has no source", bit);
+            }
+        }
+        Commands::Table {
+            top_level_file,
+            include,
+            generics,
+            max_rows,
+            progress,
+            cache_stats,
+            output,
+        } => {
+            let include = &search_path(include);
+            let (hdl, provider) = parse_hdl_path_with_includes(Path::new(top_level_file), include)?;
+            let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+            let options = TableOptions {
+                max_rows: *max_rows,
+                progress: *progress,
+                cache_stats: *cache_stats,
+            };
+            let (columns, rows) = full_table(&hdl, &provider, generics, &options)?;
+
+            let mut out = open_output(output)?;
+            writeln!(out, "{}", columns.join("\t"))?;
+            for row in rows {
+                let cells: Vec<String> = row
+                    .iter()
+                    .map(|bits| format_bits(bits, Radix::Binary))
+                    .collect();
+                writeln!(out, "{}", cells.join("\t"))?;
+            }
+            out.flush()?;
+        }
+        Commands::NandCount {
+            top_level_file,
+            include,
+            generics,
+        } => {
+            let project = project_args(top_level_file, include)?;
+            let generics = match generics.is_empty() {
+                true => &project.generics,
+                false => generics,
+            };
+            let (hdl, provider) = parse_hdl_path_with_includes(&project.top, &project.include)?;
+            let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+            let mut chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, generics)?;
+            let count = chip.gate_count()?;
+            println!("NAND gates: {}", count.nand);
+            if count.dff > 0 {
+                println!("DFFs: {}", count.dff);
+            }
+        }
+        Commands::Dot {
+            top_level_file,
+            include,
+            generics,
+            raw,
+            output,
+        } => {
+            let include = &search_path(include);
+            let (hdl, provider) = parse_hdl_path_with_includes(top_level_file, include)?;
+            let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+            let mut chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, generics)?;
+            chip.optimize = !raw;
+            chip.elaborate()?;
+            let mut out = open_output(output)?;
+            write!(out, "{}", chip.to_dot())?;
+            out.flush()?;
+        }
+        Commands::Blif {
+            top_level_file,
+            include,
+            generics,
+            output,
+        } => {
+            let include = &search_path(include);
+            let (hdl, provider) = parse_hdl_path_with_includes(top_level_file, include)?;
+            let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+            let mut chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, generics)?;
+            let mut out = open_output(output)?;
+            write!(out, "{}", to_blif(&mut chip)?)?;
+            out.flush()?;
+        }
+        Commands::Symbolic {
+            top_level_file,
+            include,
+            raw,
+            output,
+        } => {
+            let include = &search_path(include);
+            let (hdl, provider) = parse_hdl_path_with_includes(top_level_file, include)?;
+            let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+            let outputs = symbolic(&hdl.name, &provider)?;
+            let mut out = open_output(output)?;
+            for port in hdl
+                .ports
+                .iter()
+                .filter(|p| p.direction == PortDirection::Out)
+            {
+                let bits = &outputs[&port.name.value];
+                for (i, bit) in bits.iter().enumerate() {
+                    let bit = if *raw { bit.clone() } else { bit.simplify() };
+                    match bits.len() {
+                        1 => writeln!(out, "{} = {}", port.name.value, bit)?,
+                        _ => writeln!(out, "{}[{}] = {}", port.name.value, i, bit)?,
+                    }
+                }
+            }
+            out.flush()?;
+        }
+        Commands::Repl {
+            top_level_file,
+            include,
+            generics,
+        } => {
+            let include = &search_path(include);
+            let (hdl, provider) = parse_hdl_path_with_includes(top_level_file, include)?;
+            let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+            let mut chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, generics)?;
+            chip.elaborate()?;
+            let mut repl = Repl::new(Simulator::new(chip));
+            let stdin = io::stdin();
+            let prompt = stdin.is_terminal();
+            repl.run(stdin.lock(), &mut io::stdout(), prompt)?;
+        }
+        Commands::Diff {
+            old,
+            new,
+            include,
+            dot,
+        } => {
+            let include = &search_path(include);
+            let (old_hdl, _) = parse_hdl_path_with_includes(old, include)?;
+            let (new_hdl, _) = parse_hdl_path_with_includes(new, include)?;
+            let res = diff(&old_hdl, &new_hdl)?;
+            print!("{}", res);
+            if dot.is_some() {
+                let mut out = open_output(dot)?;
+                write!(out, "{}", res.to_dot())?;
+                out.flush()?;
+            }
+        }
+        Commands::FromTable {
+            table,
+            outputs,
+            name,
+            output,
+        } => {
+            let truth_table = read_table_csv(table, outputs)?;
+            let name = match name {
+                Some(name) => name.clone(),
+                None => table
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            };
+            let inputs: Vec<&str> = truth_table.inputs.iter().map(String::as_str).collect();
+            let outputs: Vec<&str> = truth_table.outputs.iter().map(String::as_str).collect();
+            let hdl = synthesize_chip(&name, &inputs, &outputs, &truth_table.rows)?;
+
+            let mut out = open_output(output)?;
+            write!(out, "{}", hdl)?;
+            out.flush()?;
+        }
+        Commands::Run {
+            top_level_file,
+            program,
+            include,
+            max_cycles,
+            rom,
+            ram,
+            cycles,
+        } => {
+            let include = &search_path(include);
+            let top_level_file = Path::new(top_level_file);
+            if let (Some(rom), Some(cycles)) = (rom, cycles) {
+                let ram = ram.as_deref();
+                let res = run_computer(top_level_file, program, include, rom, ram, *cycles)?;
+                print!("{}", res);
+            } else {
+                let res = run_program(top_level_file, program, include, *max_cycles)?;
+                print!("{}", res);
+            }
+        }
+        Commands::Selftest {
+            top_level_file,
+            include,
+        } => {
+            let include = &search_path(include);
+            let res = selftest(Path::new(top_level_file), include)?;
+            for failure in &res.failures {
+                println!("{}", failure);
             }
+            if !res.failures.is_empty() {
+                return Err(Box::new(N2VError {
+                    msg: format!(
+                        "{} of {} assertions failed.",
+                        res.failures.len(),
+                        res.failures.len() + res.passed
+                    ),
+                    kind: ErrorKind::Other,
+                }));
+            }
+            if !cli.quiet {
+                println!("{} assertions passed.", res.passed);
+            }
+        }
+        Commands::Stub {
+            top_level_file,
+            include,
+            output,
+        } => {
+            let include = &search_path(include);
+            let (hdl, _) = parse_hdl_path_with_includes(Path::new(top_level_file), include)?;
+            let mut out = open_output(output)?;
+            write!(out, "{}", hdl.stub())?;
+            out.flush()?;
+        }
+        Commands::Normalize {
+            top_level_file,
+            include,
+            output,
+        } => {
+            let include = &search_path(include);
+            let (hdl, _) = parse_hdl_path_with_includes(Path::new(top_level_file), include)?;
+            let mut out = open_output(output)?;
+            write!(out, "{}", hdl.to_normalized())?;
+            out.flush()?;
         }
-        Commands::Test { test_file } => {
-            run_test(&PathBuf::from(test_file))?;
+        Commands::Docs {
+            top_level_file,
+            include,
+            output,
+        } => {
+            let source_code = fs::read_to_string(top_level_file)?;
+            let mut scanner = Scanner::new(&source_code, PathBuf::from(&top_level_file));
+            scanner.keep_comments = true;
+            let base_path = scanner.path.parent().unwrap();
+            let provider: Rc<dyn HdlProvider> =
+                Rc::new(FileReader::with_includes(base_path, &search_path(include)));
+            let mut parser = Parser::new(&mut scanner, provider);
+            let docs = parser.parse()?.docs();
+            let mut out = open_output(output)?;
+            write!(out, "{}", docs)?;
+            out.flush()?;
         }
     }
     Ok(())
@@ -0,0 +1,108 @@
+//! Project manifests.
+//!
+//! A `whidl.toml` file names the top-level chip of a project, the directories
+//! that hold its other chips, and generic arguments for the top-level chip,
+//! so that commands can run without flags. Paths are relative to the
+//! directory of the manifest.
+//!
+//! ```toml
+//! top = "hdl/Computer.hdl"
+//! include = ["lib", "../shared"]
+//! generics = [16]
+//! ```
+
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::error::{ErrorKind, N2VError, TransformedError};
+
+pub const MANIFEST_NAME: &str = "whidl.toml";
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// HDL file of the top-level chip.
+    pub top: PathBuf,
+    /// Directories to search for component HDL files, after the directory
+    /// of the top-level chip.
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
+    /// Generic arguments for a generic top-level chip, in the order they are
+    /// declared.
+    #[serde(default)]
+    pub generics: Vec<usize>,
+}
+
+impl Manifest {
+    /// Reads the manifest in `dir`, or returns None if `dir` has no
+    /// manifest. The paths of the returned manifest include `dir`.
+    pub fn read(dir: &Path) -> Result<Option<Manifest>, Box<dyn Error>> {
+        let path = dir.join(MANIFEST_NAME);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Box::new(TransformedError {
+                    msg: format!("Unable to read {}.", path.display()),
+                    kind: ErrorKind::IOError,
+                    source: Some(Box::new(e)),
+                }))
+            }
+        };
+
+        let mut manifest: Manifest = toml::from_str(&text).map_err(|e| N2VError {
+            msg: format!("Invalid manifest {}: {}", path.display(), e.message()),
+            kind: ErrorKind::Other,
+        })?;
+        manifest.top = dir.join(&manifest.top);
+        manifest.include = manifest.include.iter().map(|p| dir.join(p)).collect();
+        Ok(Some(manifest))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_manifest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        assert_eq!(Manifest::read(dir).expect("Read error"), None);
+
+        let text = "top = \"hdl/Mux.hdl\"\ninclude = [\"lib\"]\ngenerics = [16, 4]\n";
+        fs::write(dir.join(MANIFEST_NAME), text).unwrap();
+        let manifest = Manifest::read(dir)
+            .expect("Read error")
+            .expect("No manifest");
+        assert_eq!(
+            manifest,
+            Manifest {
+                top: dir.join("hdl/Mux.hdl"),
+                include: vec![dir.join("lib")],
+                generics: vec![16, 4],
+            }
+        );
+
+        // Only `top` is required.
+        fs::write(dir.join(MANIFEST_NAME), "top = \"Mux.hdl\"\n").unwrap();
+        let manifest = Manifest::read(dir)
+            .expect("Read error")
+            .expect("No manifest");
+        assert!(manifest.include.is_empty() && manifest.generics.is_empty());
+
+        fs::write(
+            dir.join(MANIFEST_NAME),
+            "top = \"Mux.hdl\"\nincludes = []\n",
+        )
+        .unwrap();
+        match Manifest::read(dir) {
+            Ok(_) => panic!("Expected an error for an unknown key"),
+            Err(e) => assert!(e.to_string().contains("Invalid manifest")),
+        }
+    }
+}
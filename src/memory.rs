@@ -0,0 +1,100 @@
+//! Initial memory contents.
+//!
+//! A memory image is a text file with one binary word per line, most
+//! significant bit first, such as the `.hack` files produced by the Hack
+//! assembler. Blank lines and `//` comments are skipped. Every word must
+//! have the same width.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{ErrorKind, N2VError, TransformedError};
+
+/// Reads the memory image at `path`. Each word is returned as a vector of
+/// bits with the most significant bit first, the order used by `BusMap`.
+pub fn load_memory_image(path: &Path) -> Result<Vec<Vec<bool>>, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| TransformedError {
+        msg: format!("Unable to read memory image {}.", path.display()),
+        kind: ErrorKind::IOError,
+        source: Some(Box::new(e)),
+    })?;
+    parse_memory_image(&text).map_err(|(line, msg)| {
+        Box::new(N2VError {
+            msg: format!("{}:{}: {}", path.display(), line, msg),
+            kind: ErrorKind::Other,
+        }) as Box<dyn Error>
+    })
+}
+
+// Returns the line number and a description of the first malformed line.
+fn parse_memory_image(text: &str) -> Result<Vec<Vec<bool>>, (usize, String)> {
+    let mut words: Vec<Vec<bool>> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let word = match line.split_once("//") {
+            Some((word, _)) => word.trim(),
+            None => line.trim(),
+        };
+        if word.is_empty() {
+            continue;
+        }
+
+        let bits = word
+            .chars()
+            .map(|c| match c {
+                '0' => Ok(false),
+                '1' => Ok(true),
+                _ => Err((line_number, format!("'{}' is not a binary digit.", c))),
+            })
+            .collect::<Result<Vec<bool>, _>>()?;
+        if let Some(first) = words.first() {
+            if bits.len() != first.len() {
+                return Err((
+                    line_number,
+                    format!(
+                        "Expected a {}-bit word, found {} bits.",
+                        first.len(),
+                        bits.len()
+                    ),
+                ));
+            }
+        }
+        words.push(bits);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_load_memory_image() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = manifest_dir.join("resources/tests/memory/Add.hack");
+        let words = load_memory_image(&path).expect("Load error");
+        assert_eq!(words.len(), 6);
+        // @2
+        let mut two = vec![false; 16];
+        two[14] = true;
+        assert_eq!(words[0], two);
+        assert!(words.iter().all(|w| w.len() == 16));
+
+        assert!(load_memory_image(&manifest_dir.join("Missing.hack")).is_err());
+    }
+
+    #[test]
+    fn test_malformed_memory_image() {
+        assert_eq!(parse_memory_image("").unwrap().len(), 0);
+        assert_eq!(
+            parse_memory_image("0101\n\n// comment\n0102\n"),
+            Err((4, String::from("'2' is not a binary digit.")))
+        );
+        assert_eq!(
+            parse_memory_image("0101 // first\n010\n"),
+            Err((2, String::from("Expected a 4-bit word, found 3 bits.")))
+        );
+    }
+}
@@ -3,9 +3,11 @@
 
 use crate::error::{ErrorKind, N2VError, TransformedError};
 use crate::expr::{GenericWidth, Terminal};
-use crate::opt::optimization::{OptimizationPass, OptimizationInfo};
+use crate::opt::optimization::{OptimizationInfo, OptimizationPass};
 use crate::opt::sequential::SequentialPass;
-use crate::parser::{parse_hdl_path, FileReader, HdlProvider, Parser, Part, Component, Identifier};
+use crate::parser::{
+    parse_hdl_path, Component, Extension, FileReader, HdlProvider, Identifier, Parser, Part,
+};
 use crate::scanner::Scanner;
 use crate::simulator::Chip;
 use crate::test_parser::{OutputFormat, TestScript};
@@ -19,8 +21,7 @@ use std::cell::RefCell;
 use std::error::Error;
 use std::fs;
 use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ptr;
 use std::rc::Rc;
 
@@ -51,7 +52,7 @@ impl From<&OutputFormat> for Signal {
 
 impl From<&Vec<OutputFormat>> for TestbenchSignals {
     fn from(output_list: &Vec<OutputFormat>) -> Self {
-        let mut value: Vec<Signal>  = output_list.iter().map(Signal::from).collect();
+        let mut value: Vec<Signal> = output_list.iter().map(Signal::from).collect();
         value.push(Signal {
             name: "clk".to_string(),
             width: GenericWidth::Terminal(Terminal::Num(1)),
@@ -104,32 +105,33 @@ impl TryFrom<&TestScript> for TestBench {
                                 end: None,
                             },
                             right: SignalRhs::Literal(LiteralVHDL { values: fixme }),
+                            extension: Extension::None,
                         }));
                     }
                     crate::test_parser::Instruction::Tick => {
-                        instructions.push(crate::vhdl::Statement::Assignment(
-                            AssignmentVHDL {
-                                left: SliceVHDL {
-                                    name: "clk".to_string(),
-                                    start: None,
-                                    end: None,
-                                },
-                                right: SignalRhs::Literal(LiteralVHDL { values: vec![false] }),
+                        instructions.push(crate::vhdl::Statement::Assignment(AssignmentVHDL {
+                            left: SliceVHDL {
+                                name: "clk".to_string(),
+                                start: None,
+                                end: None,
                             },
-                        ));
+                            right: SignalRhs::Literal(LiteralVHDL {
+                                values: vec![false],
+                            }),
+                            extension: Extension::None,
+                        }));
                         instructions.push(crate::vhdl::Statement::Wait(WaitVHDL {}));
                     }
                     crate::test_parser::Instruction::Tock => {
-                        instructions.push(crate::vhdl::Statement::Assignment(
-                            AssignmentVHDL {
-                                left: SliceVHDL {
-                                    name: "clk".to_string(),
-                                    start: None,
-                                    end: None,
-                                },
-                                right: SignalRhs::Literal(LiteralVHDL { values: vec![true] }),
+                        instructions.push(crate::vhdl::Statement::Assignment(AssignmentVHDL {
+                            left: SliceVHDL {
+                                name: "clk".to_string(),
+                                start: None,
+                                end: None,
                             },
-                        ));
+                            right: SignalRhs::Literal(LiteralVHDL { values: vec![true] }),
+                            extension: Extension::None,
+                        }));
                         instructions.push(crate::vhdl::Statement::Wait(WaitVHDL {}));
                     }
                     crate::test_parser::Instruction::Output => {
@@ -196,7 +198,6 @@ impl TryFrom<&TestBench> for VhdlEntity {
         let mut port_mappings = Vec::new();
         for port in &test_bench.chip.ports {
             port_mappings.push(PortMappingVHDL {
-                wire_name: port.name.value.clone(),
                 port: SliceVHDL {
                     name: port.name.value.clone(),
                     start: None,
@@ -212,8 +213,9 @@ impl TryFrom<&TestBench> for VhdlEntity {
 
         // Only component is the chip being tested.
         let mut statements = vec![Statement::Component(VhdlComponent {
-            unit: keyw(&test_bench.chip.name),
+            unit: test_bench.chip.name.clone(),
             generic_params: Vec::new(),
+            generic_decls: Vec::new(),
             port_mappings,
         })];
 
@@ -227,10 +229,10 @@ impl TryFrom<&TestBench> for VhdlEntity {
             statements: process_statements,
         }));
 
-
         // Create a clock port if this is a sequential chip.
         let mut sequential_pass = SequentialPass::new();
-        let (_, sequential_pass_info_raw) = sequential_pass.apply(chip.hdl.as_ref().unwrap(), &chip.hdl_provider)?;
+        let (_, sequential_pass_info_raw) =
+            sequential_pass.apply(chip.hdl.as_ref().unwrap(), &chip.hdl_provider)?;
         let sequential_pass_info = Rc::new(RefCell::new(sequential_pass_info_raw));
 
         if let OptimizationInfo::SequentialFlagMap(sequential_flag_map) =
@@ -238,7 +240,6 @@ impl TryFrom<&TestBench> for VhdlEntity {
         {
             if sequential_flag_map.get(&keyw(&test_bench.chip.name)) == Some(&true) {
                 let clock_port_mapping = PortMappingVHDL {
-                    wire_name: "clk".to_string(),
                     port: SliceVHDL {
                         name: "clk".to_string(),
                         start: None,
@@ -266,7 +267,8 @@ impl TryFrom<&TestBench> for VhdlEntity {
             statements,
             signals,
             optimization_info: Some(Rc::clone(&sequential_pass_info)),
-            chip,
+            hdl: chip.hdl.unwrap(),
+            target: VhdlTarget::default(),
         })
     }
 }
@@ -276,10 +278,15 @@ impl TryFrom<&TestBench> for VhdlEntity {
 /// HDL for the chip that is being tested.
 ///
 /// - `output_dir`: The directory to create that will house the generated
-///     VHDL files. This directory must exist at the time of calling the
-///     function.
+///   VHDL files. This directory must exist at the time of calling the
+///   function.
 /// - `test_script_path`: Path to the test script to convert.
-pub fn synth_vhdl_test(output_dir: &Path, test_script_path: &Path) -> Result<(), Box<dyn Error>> {
+/// - `include_paths`: Additional directories to search for component HDL.
+pub fn synth_vhdl_test(
+    output_dir: &Path,
+    test_script_path: &Path,
+    include_paths: &[PathBuf],
+) -> Result<(), Box<dyn Error>> {
     let test_script = match parse_test(test_script_path) {
         Err(e) => {
             return Err(Box::new(TransformedError {
@@ -306,7 +313,7 @@ pub fn synth_vhdl_test(output_dir: &Path, test_script_path: &Path) -> Result<(),
     let test_bench_path = output_dir
         .join(test_script_filename)
         .with_extension("tst.vhdl");
-    let mut testbench_file = match File::create(&test_bench_path) {
+    let testbench_file = match File::create(&test_bench_path) {
         Err(e) => {
             return Err(Box::new(TransformedError {
                 msg: format!(
@@ -320,12 +327,13 @@ pub fn synth_vhdl_test(output_dir: &Path, test_script_path: &Path) -> Result<(),
         Ok(f) => f,
     };
     let vhdl_entity = VhdlEntity::try_from(&test_bench)?;
-    testbench_file.write_all(vhdl_entity.to_string().as_bytes())?;
+    vhdl_entity.write_io(testbench_file)?;
 
     let source_code = fs::read_to_string(&test_script.hdl_path)?;
     let mut scanner = Scanner::new(&source_code, test_script.hdl_path);
     let base_path = scanner.path.parent().unwrap();
-    let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(base_path));
+    let provider: Rc<dyn HdlProvider> =
+        Rc::new(FileReader::with_includes(base_path, include_paths));
     let mut parser = Parser::new(&mut scanner, provider.clone());
     let hdl = parser.parse()?;
     let chip_vhdl = VhdlEntity::try_from(&hdl)?;
@@ -348,8 +356,8 @@ pub fn synth_vhdl_test(output_dir: &Path, test_script_path: &Path) -> Result<(),
 }
 
 // Only run these tests if the modelsim_tests feature is enabled.
-// We need to disable these tests sometimes (GitHub actions) because 
-// they depend on Quartus Prime, which is huge. 
+// We need to disable these tests sometimes (GitHub actions) because
+// they depend on Quartus Prime, which is huge.
 #[cfg(all(test, feature = "modelsim_tests"))]
 mod test {
     use super::*;
@@ -361,9 +369,8 @@ mod test {
         let temp_dir = tempdir().unwrap();
         println!("Temp dir: {}", temp_dir.path().display());
 
-        let synth_result = synth_vhdl_test(temp_dir.path(), &tst_path);
-        if synth_result.is_err() {
-            println!("{}", synth_result.unwrap_err());
+        if let Err(e) = synth_vhdl_test(temp_dir.path(), &tst_path, &[]) {
+            println!("{}", e);
             panic!();
         }
 
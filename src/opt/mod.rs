@@ -1,3 +1,5 @@
 pub mod optimization;
+pub mod output_mirror;
 pub mod portmap_dedupe;
 pub mod sequential;
+pub mod structural_hash;
@@ -1,4 +1,4 @@
-use crate::parser::{ChipHDL, HdlProvider};
+use crate::parser::{ChipHDL, Component, HdlProvider, Part};
 use std::{collections::HashMap, error::Error, rc::Rc};
 
 pub type SequentialFlagMap = HashMap<String, bool>;
@@ -13,8 +13,45 @@ pub enum OptimizationInfo {
 /// All HDL passes must implement this trait.
 pub trait OptimizationPass {
     fn apply(
-        &mut self, 
-        chip: &ChipHDL, 
-        provider: &Rc<dyn HdlProvider>
+        &mut self,
+        chip: &ChipHDL,
+        provider: &Rc<dyn HdlProvider>,
     ) -> Result<(ChipHDL, OptimizationInfo), Box<dyn Error>>;
 }
+
+/// Renames signals used anywhere in parts. Keys of `renames` are replaced
+/// by their values.
+pub fn rename_signals(parts: &mut [Part], renames: &HashMap<String, String>) {
+    if renames.is_empty() {
+        return;
+    }
+
+    let rename_component = |comp: &mut Component| {
+        for m in &mut comp.mappings {
+            if let Some(new_name) = renames.get(&m.wire.name) {
+                m.wire.name = new_name.clone();
+                m.wire_ident.value = new_name.clone();
+            }
+        }
+    };
+
+    for part in parts {
+        match part {
+            Part::Component(comp) => rename_component(comp),
+            Part::Loop(loop_part) => loop_part.body.iter_mut().for_each(rename_component),
+            Part::AssignmentHDL(assignment) => {
+                let left = std::iter::once(&mut assignment.left);
+                for bus in left.chain(assignment.right.iter_mut()) {
+                    if let Some(new_name) = renames.get(&bus.name) {
+                        bus.name = new_name.clone();
+                    }
+                }
+            }
+            Part::Assert(assert) => {
+                if let Some(new_name) = renames.get(&assert.signal.value) {
+                    assert.signal.value = new_name.clone();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! # Output Mirror Pass
+//!
+//! HDL allows a chip to read its own output ports, but VHDL-93 does not allow
+//! reading an `out` port. `output_mirror` finds every output port that is
+//! read inside the chip and replaces it with an internal mirror signal. The
+//! mirror is driven wherever the port was driven and read wherever the port
+//! was read, and a final assignment drives the real port from the mirror.
+//! VHDL-93 synthesis depends on this pass.
+
+use crate::opt::optimization::{rename_signals, OptimizationInfo, OptimizationPass};
+use crate::parser::{
+    get_hdl, AssignmentHDL, BusHDL, ChipHDL, Extension, HdlProvider, Part, PortDirection,
+};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::rc::Rc;
+
+pub struct OutputMirror {}
+
+impl OptimizationPass for OutputMirror {
+    fn apply(
+        &mut self,
+        chip: &ChipHDL,
+        provider: &Rc<dyn HdlProvider>,
+    ) -> Result<(ChipHDL, OptimizationInfo), Box<dyn Error>> {
+        let outputs: HashSet<&String> = chip
+            .ports
+            .iter()
+            .filter(|p| p.direction == PortDirection::Out)
+            .map(|p| &p.name.value)
+            .collect();
+
+        // Output ports that are read by a component, an assignment, or an
+        // assertion.
+        let mut read = Vec::new();
+        for part in &chip.parts {
+            let components = match part {
+                Part::Component(comp) => std::slice::from_ref(comp),
+                Part::Loop(loop_part) => loop_part.body.as_slice(),
+                Part::AssignmentHDL(assignment) => {
+                    for segment in &assignment.right {
+                        if outputs.contains(&segment.name) {
+                            read.push(segment.name.clone());
+                        }
+                    }
+                    continue;
+                }
+                Part::Assert(assert) => {
+                    if outputs.contains(&assert.signal.value) {
+                        read.push(assert.signal.value.clone());
+                    }
+                    continue;
+                }
+            };
+            for comp in components {
+                let comp_hdl = get_hdl(&comp.name.value, provider)?;
+                for m in &comp.mappings {
+                    if outputs.contains(&m.wire.name)
+                        && comp_hdl.get_port(&m.port.name)?.direction.is_input()
+                    {
+                        read.push(m.wire.name.clone());
+                    }
+                }
+            }
+        }
+        read.sort();
+        read.dedup();
+
+        let mut used = signal_names(chip);
+        let renames: HashMap<String, String> = read
+            .iter()
+            .map(|p| (p.clone(), mirror_name(p, &mut used)))
+            .collect();
+        let mut new_chip = chip.clone();
+        rename_signals(&mut new_chip.parts, &renames);
+        for port_name in read {
+            let mirror = renames[&port_name].clone();
+            new_chip.parts.push(Part::AssignmentHDL(AssignmentHDL {
+                left: BusHDL {
+                    name: port_name,
+                    start: None,
+                    end: None,
+                },
+                right: vec![BusHDL {
+                    name: mirror,
+                    start: None,
+                    end: None,
+                }],
+                extension: Extension::None,
+                port: None,
+            }));
+        }
+
+        Ok((new_chip, OptimizationInfo::None))
+    }
+}
+
+impl OutputMirror {
+    pub fn new() -> OutputMirror {
+        OutputMirror {}
+    }
+}
+
+/// Name of the internal signal that mirrors an output port, numbered if the
+/// chip already uses the name. The name is added to `used`.
+fn mirror_name(port_name: &str, used: &mut HashSet<String>) -> String {
+    let mut name = format!("{}_mirror", port_name);
+    let mut n = 1;
+    while used.contains(&name) {
+        name = format!("{}_mirror{}", port_name, n);
+        n += 1;
+    }
+    used.insert(name.clone());
+    name
+}
+
+// Names of the ports of a chip and of every signal used by its parts.
+fn signal_names(chip: &ChipHDL) -> HashSet<String> {
+    let mut names: HashSet<String> = chip.ports.iter().map(|p| p.name.value.clone()).collect();
+    for part in &chip.parts {
+        let components = match part {
+            Part::Component(comp) => std::slice::from_ref(comp),
+            Part::Loop(loop_part) => loop_part.body.as_slice(),
+            Part::AssignmentHDL(assignment) => {
+                names.insert(assignment.left.name.clone());
+                names.extend(assignment.right.iter().map(|b| b.name.clone()));
+                continue;
+            }
+            Part::Assert(assert) => {
+                names.insert(assert.signal.value.clone());
+                continue;
+            }
+        };
+        for comp in components {
+            names.extend(comp.mappings.iter().map(|m| m.wire.name.clone()));
+        }
+    }
+    names
+}
@@ -7,11 +7,11 @@
 
 use crate::opt::optimization::OptimizationPass;
 use crate::parser::{
-    get_hdl, AssignmentHDL, BusHDL, ChipHDL, Component, HdlProvider, Identifier, Loop, Part,
-    PortDirection, PortMappingHDL,
+    get_hdl, AssignmentHDL, BusHDL, ChipHDL, Component, Extension, HdlProvider, Identifier, Loop,
+    Part, PortMappingHDL,
 };
-use std::error::Error;
 use std::collections::HashMap;
+use std::error::Error;
 use std::rc::Rc;
 
 use super::optimization::OptimizationInfo;
@@ -24,7 +24,7 @@ impl OptimizationPass for PortMapDedupe {
         &mut self,
         chip: &ChipHDL,
         provider: &Rc<dyn HdlProvider>,
-    ) -> Result<(ChipHDL, OptimizationInfo), Box<(dyn Error)>> {
+    ) -> Result<(ChipHDL, OptimizationInfo), Box<dyn Error>> {
         let mut new_chip = ChipHDL {
             parts: vec![],
             ..chip.clone()
@@ -50,7 +50,9 @@ impl OptimizationPass for PortMapDedupe {
                         ..loop_part.clone()
                     }));
                 }
-                Part::AssignmentHDL(_) => {}
+                Part::AssignmentHDL(_) | Part::Assert(_) => {
+                    new_chip.parts.push(part.clone());
+                }
             }
         }
 
@@ -105,7 +107,7 @@ impl PortMapDedupe {
             let port_name = &mapping.port.name;
             let port = comp_chip.get_port(port_name)?;
 
-            if port.direction == PortDirection::In {
+            if port.direction.is_input() {
                 new_comp.mappings.push(mapping.clone());
                 continue;
             }
@@ -116,22 +118,26 @@ impl PortMapDedupe {
                     *count -= 1;
                     new_assignments.push(AssignmentHDL {
                         left: mapping.wire.clone(),
-                        right: BusHDL {
+                        right: vec![BusHDL {
                             name: new_signal_name.clone(),
                             start: None,
                             end: None,
-                        },
+                        }],
+                        extension: Extension::None,
+                        port: None,
                     });
                 } else {
                     *count -= 1;
 
                     new_assignments.push(AssignmentHDL {
                         left: mapping.wire.clone(),
-                        right: BusHDL {
+                        right: vec![BusHDL {
                             name: new_signal_name.clone(),
                             start: None,
                             end: None,
-                        },
+                        }],
+                        extension: Extension::None,
+                        port: None,
                     });
 
                     new_comp.mappings.push(PortMappingHDL {
@@ -146,6 +152,7 @@ impl PortMapDedupe {
                             end: None,
                         },
                         port: mapping.port.clone(),
+                        concatenation: false,
                     })
                 }
             } else {
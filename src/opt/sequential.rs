@@ -6,6 +6,7 @@ use std::error::Error;
 use std::{collections::HashMap, rc::Rc};
 
 use crate::parser::{get_hdl, Component, Part};
+use crate::vhdl::RAM_BUILTIN;
 use crate::{
     opt::optimization::OptimizationPass,
     parser::{ChipHDL, HdlProvider},
@@ -24,12 +25,13 @@ impl OptimizationPass for SequentialPass {
         &mut self,
         chip: &ChipHDL,
         provider: &Rc<dyn HdlProvider>,
-    ) -> Result<(ChipHDL, OptimizationInfo), Box<(dyn Error)>> {
+    ) -> Result<(ChipHDL, OptimizationInfo), Box<dyn Error>> {
         // Traverse the chip to identify sequential components.
         let mut chip_sequential = false;
         for part in &chip.parts {
             chip_sequential |= self.traverse(part, provider)?;
         }
+        chip_sequential |= self.is_sequential(chip);
 
         // If any part of the chip is sequential, the chip is sequential.
         // But avoid overwriting existing flags.
@@ -69,7 +71,7 @@ impl SequentialPass {
                 let is_sequential = self.process_component(component, provider)?;
                 Ok(is_sequential)
             }
-            Part::AssignmentHDL(_) => Ok(false),
+            Part::AssignmentHDL(_) | Part::Assert(_) => Ok(false),
         }
     }
 
@@ -103,9 +105,9 @@ impl SequentialPass {
     }
 
     /// Function to determine if a chip is sequential.
-    /// Currently, it only checks if the chip is a DFF.
+    /// Currently, it only checks if the chip is a DFF or a builtin RAM.
     fn is_sequential(&self, chip: &ChipHDL) -> bool {
-        chip.name == "DFF"
+        chip.name == "DFF" || chip.builtin.as_deref() == Some(RAM_BUILTIN)
     }
 
     fn get_all_dependencies(&self, chip: &ChipHDL) -> Result<Vec<Part>, Box<dyn Error>> {
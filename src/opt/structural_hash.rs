@@ -0,0 +1,187 @@
+//! # Structural Hashing Optimization Pass
+//!
+//! `structural_hash` merges component instances that are structurally
+//! identical: the same chip with the same generics and the same signals
+//! mapped to every input port. Such instances always produce the same
+//! outputs, so only the first one is kept. The outputs of each duplicate are
+//! taken from the kept instance instead, either by renaming the duplicate's
+//! output signal everywhere it is used or by moving the output mapping onto
+//! the kept instance. Renaming can make further instances identical, so the
+//! pass runs until nothing changes.
+//!
+//! Components inside for-generate loops are left alone because their
+//! mappings depend on the loop iterator.
+
+use crate::opt::optimization::{rename_signals, OptimizationInfo, OptimizationPass};
+use crate::parser::{get_hdl, ChipHDL, Component, HdlProvider, Part, PortDirection};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::rc::Rc;
+
+pub struct StructuralHash {
+    /// Number of component instances removed by the last application.
+    pub merged: usize,
+}
+
+impl OptimizationPass for StructuralHash {
+    fn apply(
+        &mut self,
+        chip: &ChipHDL,
+        provider: &Rc<dyn HdlProvider>,
+    ) -> Result<(ChipHDL, OptimizationInfo), Box<dyn Error>> {
+        let mut new_chip = chip.clone();
+        self.merged = 0;
+        while self.merge_once(&mut new_chip, provider)? {}
+        Ok((new_chip, OptimizationInfo::None))
+    }
+}
+
+impl StructuralHash {
+    pub fn new() -> StructuralHash {
+        StructuralHash { merged: 0 }
+    }
+
+    // Merges the first duplicate component found. Returns false if there
+    // are no duplicates left.
+    fn merge_once(
+        &mut self,
+        chip: &mut ChipHDL,
+        provider: &Rc<dyn HdlProvider>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let chip_ports: HashSet<&String> = chip.ports.iter().map(|p| &p.name.value).collect();
+
+        // Index of the first component with each structural hash.
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut duplicate = None;
+        for (part_idx, part) in chip.parts.iter().enumerate() {
+            if let Part::Component(comp) = part {
+                let key = structural_key(comp, provider)?;
+                match seen.get(&key) {
+                    Some(&canonical_idx) => {
+                        duplicate = Some((canonical_idx, part_idx));
+                        break;
+                    }
+                    None => {
+                        seen.insert(key, part_idx);
+                    }
+                }
+            }
+        }
+
+        let (canonical_idx, duplicate_idx) = match duplicate {
+            None => return Ok(false),
+            Some(x) => x,
+        };
+        let (canonical, removed) = match (&chip.parts[canonical_idx], &chip.parts[duplicate_idx]) {
+            (Part::Component(c), Part::Component(d)) => (c.clone(), d.clone()),
+            _ => unreachable!("Structural hashes are only computed for components"),
+        };
+        let removed_hdl = get_hdl(&removed.name.value, provider)?;
+
+        // Signal renames from the removed instance to the canonical instance.
+        let mut renames: HashMap<String, String> = HashMap::new();
+        let mut moved = Vec::new();
+        for m in &removed.mappings {
+            let port = removed_hdl.get_port(&m.port.name)?;
+            if port.direction != PortDirection::Out {
+                continue;
+            }
+
+            // A whole internal signal can be renamed to a whole internal
+            // signal driven by the same port. Anything else, such as a
+            // chip output port or a slice, is driven by the canonical
+            // instance through an extra mapping.
+            let same_port = canonical.mappings.iter().find(|c| {
+                c.port == m.port && c.wire.start.is_none() && !chip_ports.contains(&c.wire.name)
+            });
+            match same_port {
+                Some(c) if m.wire.start.is_none() && !chip_ports.contains(&m.wire.name) => {
+                    renames.insert(m.wire.name.clone(), c.wire.name.clone());
+                }
+                _ => moved.push(m.clone()),
+            }
+        }
+
+        if let Part::Component(c) = &mut chip.parts[canonical_idx] {
+            c.mappings.extend(moved);
+        }
+        chip.parts.remove(duplicate_idx);
+        rename_signals(&mut chip.parts, &renames);
+        self.merged += 1;
+
+        Ok(true)
+    }
+}
+
+// Components with equal keys compute the same outputs.
+fn structural_key(
+    comp: &Component,
+    provider: &Rc<dyn HdlProvider>,
+) -> Result<String, Box<dyn Error>> {
+    let comp_hdl = get_hdl(&comp.name.value, provider)?;
+
+    let mut inputs = Vec::new();
+    for m in &comp.mappings {
+        if comp_hdl.get_port(&m.port.name)?.direction.is_input() {
+            inputs.push(format!("{}={}", m.port, m.wire));
+        }
+    }
+    inputs.sort();
+
+    let generics: Vec<String> = comp.generic_params.iter().map(|g| g.to_string()).collect();
+    Ok(format!(
+        "{}<{}>({})",
+        comp.name.value,
+        generics.join(","),
+        inputs.join(",")
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::busmap::BusMap;
+    use crate::parser::parse_hdl_path_with_includes;
+    use crate::simulator::{Chip, Simulator};
+
+    use std::env;
+    use std::path::Path;
+    use std::ptr;
+
+    fn simulator(hdl: &ChipHDL, provider: &Rc<dyn HdlProvider>) -> Simulator {
+        let chip = Chip::new(hdl, ptr::null_mut(), provider, false, &Vec::new())
+            .expect("Chip creation error");
+        Simulator::new(chip)
+    }
+
+    #[test]
+    fn test_structural_hash_redundant() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let (hdl, provider) = parse_hdl_path_with_includes(
+            &tests_dir.join("opt/Redundant.hdl"),
+            &[tests_dir.join("nand2tetris/solutions")],
+        )
+        .expect("Parse error");
+        let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+
+        let mut pass = StructuralHash::new();
+        let (hashed, _) = pass.apply(&hdl, &provider).expect("Structural hash error");
+
+        // The second And and the Not it feeds are both redundant.
+        assert_eq!(pass.merged, 2);
+        assert_eq!(hashed.parts.len(), hdl.parts.len() - 2);
+
+        // The truth tables must match.
+        let mut original = simulator(&hdl, &provider);
+        let mut optimized = simulator(&hashed, &provider);
+        for (a, b) in [(false, false), (false, true), (true, false), (true, true)] {
+            let inputs = BusMap::try_from([("a", a), ("b", b)]).unwrap();
+            let expected = original.simulate(&inputs).expect("simulation failure");
+            let actual = optimized.simulate(&inputs).expect("simulation failure");
+            for port in ["x", "y", "z", "w"] {
+                assert_eq!(expected.get_name(port), actual.get_name(port));
+            }
+        }
+    }
+}
@@ -1,15 +1,18 @@
+use crate::busmap::parse_binary;
 use crate::error::{ErrorKind, N2VError};
 use crate::expr::*;
+use crate::scanner::Comment;
 use crate::scanner::Token;
 use crate::scanner::TokenType;
 use crate::Scanner;
 use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::fmt;
-
 
 #[derive(Clone)]
 #[allow(clippy::large_enum_variant)]
@@ -17,6 +20,7 @@ pub enum Part {
     Component(Component),
     Loop(Loop),
     AssignmentHDL(AssignmentHDL),
+    Assert(AssertHDL),
 }
 
 impl fmt::Display for Part {
@@ -25,6 +29,7 @@ impl fmt::Display for Part {
             Part::Component(component) => write!(f, "{}", component),
             Part::Loop(loop_part) => write!(f, "{}", loop_part),
             Part::AssignmentHDL(assignment) => write!(f, "{}", assignment),
+            Part::Assert(assert) => write!(f, "{}", assert),
         }
     }
 }
@@ -37,50 +42,237 @@ pub struct ChipHDL {
     pub parts: Vec<Part>,
     pub path: Option<PathBuf>,
     pub generic_decls: Vec<Identifier>,
+    /// Values of `CONST` declarations in the order they are declared.
+    /// Unlike generics, they are fixed when the chip is parsed.
+    pub constants: Vec<(Identifier, usize)>,
     pub provider: Rc<dyn HdlProvider>,
+    /// Name of the registered Rust implementation for a `BUILTIN` chip.
+    /// Builtin chips have no parts.
+    pub builtin: Option<String>,
+    /// Comment documenting the chip, if the scanner kept comments.
+    pub doc: Option<String>,
 }
 
 impl std::fmt::Display for ChipHDL {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "CHIP {} {{", self.name)?;
+        self.write_hdl(f, true)
+    }
+}
 
-        write!(f, "    IN ")?;
-        let input_ports: Vec<_> = self.ports.iter()
-            .filter(|port| matches!(port.direction, PortDirection::In))
-            .collect();
-        for (i, port) in input_ports.iter().enumerate() {
-            if i != 0 {
-                write!(f, ", ")?;
+impl ChipHDL {
+    /// HDL for the interface of this chip (name, generics, and ports) with
+    /// an empty PARTS section.
+    pub fn stub(&self) -> String {
+        let mut res = String::new();
+        self.write_hdl(&mut res, false).unwrap();
+        res
+    }
+
+    /// Markdown summary of the chip's interface built from its doc
+    /// comments.
+    pub fn docs(&self) -> String {
+        let mut res = String::new();
+        self.write_docs(&mut res).unwrap();
+        res
+    }
+
+    fn write_docs(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        write!(f, "# {}", self.name)?;
+        if !self.generic_decls.is_empty() {
+            let generics: Vec<&str> = self
+                .generic_decls
+                .iter()
+                .map(|g| g.value.as_str())
+                .collect();
+            write!(f, "<{}>", generics.join(", "))?;
+        }
+        writeln!(f)?;
+        if let Some(doc) = &self.doc {
+            writeln!(f)?;
+            writeln!(f, "{}", doc)?;
+        }
+
+        for (heading, direction) in [
+            ("Inputs", PortDirection::In),
+            ("Outputs", PortDirection::Out),
+            ("Bidirectional", PortDirection::InOut),
+        ] {
+            if direction == PortDirection::InOut
+                && !self.ports.iter().any(|port| port.direction == direction)
+            {
+                continue;
+            }
+            writeln!(f)?;
+            writeln!(f, "## {}", heading)?;
+            writeln!(f)?;
+            writeln!(f, "| Port | Width | Description |")?;
+            writeln!(f, "| --- | --- | --- |")?;
+            for port in self.ports.iter().filter(|port| port.direction == direction) {
+                // Table cells can't contain line breaks.
+                let doc = port.doc.as_deref().unwrap_or("").replace('\n', " ");
+                writeln!(f, "| `{}` | {} | {} |", port.name, port.width, doc)?;
             }
-            write!(f, "{}", port.name)?;
         }
-        writeln!(f, ";")?;
+        Ok(())
+    }
 
-        write!(f, "    OUT ")?;
-        let output_ports: Vec<_> = self.ports.iter()
-            .filter(|port| matches!(port.direction, PortDirection::Out))
-            .collect();
-        for (i, port) in output_ports.iter().enumerate() {
-            if i != 0 {
-                write!(f, ", ")?;
+    // Writes the chip as HDL. Parts are left out if `include_parts` is false.
+    fn write_hdl(&self, f: &mut impl fmt::Write, include_parts: bool) -> fmt::Result {
+        write!(f, "CHIP {}", self.name)?;
+        if !self.generic_decls.is_empty() {
+            let generics: Vec<&str> = self
+                .generic_decls
+                .iter()
+                .map(|g| g.value.as_str())
+                .collect();
+            write!(f, "<{}>", generics.join(", "))?;
+        }
+        writeln!(f, " {{")?;
+        self.write_constants(f)?;
+
+        for (keyword, direction) in [
+            ("IN", PortDirection::In),
+            ("OUT", PortDirection::Out),
+            ("INOUT", PortDirection::InOut),
+        ] {
+            let mut ports = self
+                .ports
+                .iter()
+                .filter(|port| port.direction == direction)
+                .peekable();
+            if direction == PortDirection::InOut && ports.peek().is_none() {
+                continue;
+            }
+            write!(f, "    {} ", keyword)?;
+            for (i, port) in ports.enumerate() {
+                if i != 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", port.name)?;
+                if port.width != GenericWidth::Terminal(Terminal::Num(1)) {
+                    write!(f, "[{}]", port.width)?;
+                }
+            }
+            writeln!(f, ";")?;
+        }
+
+        writeln!(f)?;
+        if let (Some(builtin), true) = (&self.builtin, include_parts) {
+            writeln!(f, "    BUILTIN {};", builtin)?;
+            return writeln!(f, "}}");
+        }
+        writeln!(f, "    PARTS:")?;
+        if include_parts {
+            for part in &self.parts {
+                writeln!(f, "\t{}", part)?;
+            }
+        }
+
+        writeln!(f, "}}")
+    }
+
+    /// Canonical HDL for comparing chips. Ports are sorted by name within
+    /// each direction, port mappings are sorted by port, and comments and
+    /// spacing are dropped, so chips that differ only in formatting render
+    /// identically. Parts keep their order.
+    pub fn to_normalized(&self) -> String {
+        let mut res = String::new();
+        self.write_normalized(&mut res).unwrap();
+        res
+    }
+
+    fn write_normalized(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        write!(f, "CHIP {}", self.name)?;
+        if !self.generic_decls.is_empty() {
+            let generics: Vec<&str> = self
+                .generic_decls
+                .iter()
+                .map(|g| g.value.as_str())
+                .collect();
+            write!(f, "<{}>", generics.join(", "))?;
+        }
+        writeln!(f, " {{")?;
+        self.write_constants(f)?;
+
+        for (keyword, direction) in [
+            ("IN", PortDirection::In),
+            ("OUT", PortDirection::Out),
+            ("INOUT", PortDirection::InOut),
+        ] {
+            let mut ports: Vec<String> = self
+                .ports
+                .iter()
+                .filter(|port| port.direction == direction)
+                .map(|port| {
+                    if port.width == GenericWidth::Terminal(Terminal::Num(1)) {
+                        port.name.value.clone()
+                    } else {
+                        format!("{}[{}]", port.name, port.width)
+                    }
+                })
+                .collect();
+            if ports.is_empty() {
+                continue;
             }
-            write!(f, "{}", port.name)?;
+            ports.sort();
+            writeln!(f, "    {} {};", keyword, ports.join(", "))?;
         }
-        writeln!(f, ";")?;
 
+        writeln!(f)?;
+        if let Some(builtin) = &self.builtin {
+            writeln!(f, "    BUILTIN {};", builtin)?;
+            return writeln!(f, "}}");
+        }
+        writeln!(f, "    PARTS:")?;
         for part in &self.parts {
-            writeln!(f, "\t{}", part)?;
+            match part {
+                Part::Component(c) => writeln!(f, "    {}", normalized_component(c))?,
+                Part::Loop(l) => {
+                    let (i, start, end) = (&l.iterator, &l.start, &l.end);
+                    let step = l.step_clause();
+                    writeln!(
+                        f,
+                        "    FOR {} IN {} TO {}{} GENERATE {{",
+                        i, start, end, step
+                    )?;
+                    for c in &l.body {
+                        writeln!(f, "        {}", normalized_component(c))?;
+                    }
+                    writeln!(f, "    }}")?;
+                }
+                Part::AssignmentHDL(a) => {
+                    let right: Vec<String> = a.right.iter().map(normalized_bus).collect();
+                    let right = right.join(" & ");
+                    let right = match a.extension {
+                        Extension::None => right,
+                        Extension::Zero => format!("zext({})", right),
+                        Extension::Sign => format!("sext({})", right),
+                    };
+                    writeln!(f, "    {} <= {};", normalized_bus(&a.left), right)?;
+                }
+                Part::Assert(a) => writeln!(f, "    {}", a)?,
+            }
         }
 
-        writeln!(f, "}}")?;
+        writeln!(f, "}}")
+    }
 
+    fn write_constants(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        for (name, value) in &self.constants {
+            writeln!(f, "    CONST {} = {};", name, value)?;
+        }
         Ok(())
     }
-}
-
 
+    /// Values of the chip's constants by name, to be merged into the
+    /// values of its generics.
+    pub fn constant_values(&self) -> HashMap<String, usize> {
+        self.constants
+            .iter()
+            .map(|(name, value)| (name.value.clone(), *value))
+            .collect()
+    }
 
-impl ChipHDL {
     pub fn get_port(&self, name: &str) -> Result<&GenericPort, Box<dyn Error>> {
         let port_idx = self.ports.iter().position(|x| x.name.value == name);
 
@@ -92,42 +284,248 @@ impl ChipHDL {
             })),
         }
     }
+
+    /// Generic parameters that are not used by any port width, part
+    /// generic parameter, loop bound, or bus index.
+    pub fn unused_generics(&self) -> Vec<&Identifier> {
+        let mut widths: Vec<&GenericWidth> = self.ports.iter().map(|p| &p.width).collect();
+        let mut buses: Vec<&BusHDL> = Vec::new();
+        let mut components: Vec<&Component> = Vec::new();
+        for part in &self.parts {
+            match part {
+                Part::Component(c) => components.push(c),
+                Part::Loop(l) => {
+                    widths.push(&l.start);
+                    widths.push(&l.end);
+                    widths.push(&l.step);
+                    components.extend(&l.body);
+                }
+                Part::AssignmentHDL(a) => {
+                    buses.push(&a.left);
+                    buses.extend(&a.right);
+                }
+                Part::Assert(_) => {}
+            }
+        }
+        for c in components {
+            widths.extend(&c.generic_params);
+            for m in &c.mappings {
+                buses.push(&m.port);
+                buses.push(&m.wire);
+            }
+        }
+        for bus in buses {
+            widths.extend(bus.start.iter().chain(bus.end.iter()));
+        }
+
+        self.generic_decls
+            .iter()
+            .filter(|g| !widths.iter().any(|w| w.uses_variable(&g.value)))
+            .collect()
+    }
 }
 
 pub trait HdlProvider {
     fn get_hdl(&self, file_name: &str) -> Result<String, std::io::Error>;
     fn get_path(&self, file_name: &str) -> PathBuf;
+
+    /// Returns the names of files that match `file_name` ignoring case.
+    /// Used to find chips whose names differ in case from their file names.
+    /// More than one name means the chip name is ambiguous.
+    fn find_case_insensitive(&self, _file_name: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Returns the name of a file that defines the chip `name` along with
+    /// other chips, such as a `gates.hdl` with And, Or and Not, for use
+    /// with `get_hdl`. Used when the chip has no file of its own.
+    fn find_chip_file(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    /// Returns a chip parsed earlier from the file at `path` and kept with
+    /// `cache_chips`, so that each file is only parsed once. Without a
+    /// name, returns the first chip in the file. Providers without a cache
+    /// return None.
+    fn cached_chip(&self, _path: &Path, _name: Option<&str>) -> Option<ChipHDL> {
+        None
+    }
+
+    /// Keeps the chips parsed from the file at `path` for `cached_chip`.
+    fn cache_chips(&self, _path: &Path, _chips: &[ChipHDL]) {}
+}
+
+// Provider of chips kept in a parse cache. A cached chip can't hold the
+// provider that owns the cache without a reference cycle, so it holds this
+// instead until `get_hdl` hands it out.
+struct CachedProvider;
+
+impl HdlProvider for CachedProvider {
+    fn get_hdl(&self, file_name: &str) -> Result<String, std::io::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Unable to get HDL for {} from a cached chip.", file_name),
+        ))
+    }
+
+    fn get_path(&self, file_name: &str) -> PathBuf {
+        PathBuf::from(file_name)
+    }
 }
 
 #[derive(Clone)]
 pub struct FileReader {
     base_path: PathBuf,
+    include_paths: Vec<PathBuf>, // Searched in order after base_path.
+    case_insensitive: bool,
+    // File that defines each chip found in the search paths, built the
+    // first time a chip has no file of its own.
+    chip_files: Rc<RefCell<Option<HashMap<String, PathBuf>>>>,
+    // Chips parsed from each file, in order, shared by clones of the reader.
+    parsed: Rc<RefCell<HashMap<PathBuf, Vec<ChipHDL>>>>,
 }
 
 impl FileReader {
+    #[allow(dead_code)] // Only used by tests.
     pub fn new(base_path: &Path) -> FileReader {
-        FileReader { base_path: base_path.to_path_buf() }
+        FileReader::with_includes(base_path, &[])
+    }
+
+    /// Creates a reader that falls back to the include paths, in order,
+    /// when a file is not found in the base path.
+    pub fn with_includes(base_path: &Path, include_paths: &[PathBuf]) -> FileReader {
+        FileReader {
+            base_path: base_path.to_path_buf(),
+            include_paths: include_paths.to_vec(),
+            case_insensitive: true,
+            chip_files: Rc::new(RefCell::new(None)),
+            parsed: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Sets whether chip names may differ in case from file names.
+    /// This is enabled by default.
+    #[allow(dead_code)] // Only used by tests.
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+    }
+
+    fn search_paths(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.base_path).chain(self.include_paths.iter())
+    }
+
+    // Finds the name after each CHIP keyword in the .hdl files of the
+    // search paths. A chip defined more than once is taken from the first
+    // search path, and the first file in name order. Paths are absolute so
+    // that `get_path` does not join them to a search path.
+    fn index_chip_files(&self) -> HashMap<String, PathBuf> {
+        let mut index = HashMap::new();
+        for dir in self.search_paths() {
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            let mut paths: Vec<PathBuf> = entries
+                .filter_map(|entry| fs::canonicalize(entry.ok()?.path()).ok())
+                .filter(|path| path.extension().is_some_and(|e| e == "hdl"))
+                .collect();
+            paths.sort();
+            for path in paths {
+                let Ok(source) = fs::read_to_string(&path) else {
+                    continue;
+                };
+                let mut scanner = Scanner::new(&source, path.clone());
+                while let Some(token) = scanner.next() {
+                    if token.token_type != TokenType::Chip {
+                        continue;
+                    }
+                    if let Some(name) = scanner.next() {
+                        if name.token_type == TokenType::Identifier {
+                            index.entry(name.lexeme).or_insert_with(|| path.clone());
+                        }
+                    }
+                }
+            }
+        }
+        index
     }
 }
 
 impl HdlProvider for FileReader {
     fn get_hdl(&self, file_name: &str) -> Result<String, std::io::Error> {
-        let path = self.base_path.join(file_name);
+        let path = self.get_path(file_name);
         let s = fs::read_to_string(&path);
         if let Err(e) = s {
+            let searched: Vec<&PathBuf> = self.search_paths().collect();
             return Err(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
                 format!(
-                    "Unable to get HDL for {:?}. {} {:?}",
-                    path, e, self.base_path
+                    "Unable to get HDL for {:?}. {} Searched the chip's directory, then -I \
+                     directories, then WHIDL_PATH directories: {:?}",
+                    path, e, searched
                 ),
             ));
         }
         s
     }
 
+    /// Returns the path of the first search path containing the file, or
+    /// the path relative to the base path if the file does not exist.
     fn get_path(&self, file_name: &str) -> PathBuf {
-        self.base_path.join(file_name)
+        self.search_paths()
+            .map(|dir| dir.join(file_name))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| self.base_path.join(file_name))
+    }
+
+    /// Scans the search paths in order and returns the matches from the
+    /// first directory that has any.
+    fn find_case_insensitive(&self, file_name: &str) -> Vec<String> {
+        if !self.case_insensitive {
+            return Vec::new();
+        }
+
+        for dir in self.search_paths() {
+            let entries = match fs::read_dir(dir) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let mut matches: Vec<String> = entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|name| name.eq_ignore_ascii_case(file_name))
+                .collect();
+            if !matches.is_empty() {
+                matches.sort();
+                return matches;
+            }
+        }
+        Vec::new()
+    }
+
+    /// Returns the full path of the file, which `get_hdl` reads as is.
+    fn find_chip_file(&self, name: &str) -> Option<String> {
+        let mut chip_files = self.chip_files.borrow_mut();
+        let index = chip_files.get_or_insert_with(|| self.index_chip_files());
+        Some(index.get(name)?.to_str()?.to_owned())
+    }
+
+    fn cached_chip(&self, path: &Path, name: Option<&str>) -> Option<ChipHDL> {
+        let parsed = self.parsed.borrow();
+        let chips = parsed.get(path)?;
+        match name {
+            Some(name) => chips.iter().find(|c| c.name == name).cloned(),
+            None => chips.first().cloned(),
+        }
+    }
+
+    fn cache_chips(&self, path: &Path, chips: &[ChipHDL]) {
+        let provider: Rc<dyn HdlProvider> = Rc::new(CachedProvider);
+        let chips = chips.iter().map(|chip| ChipHDL {
+            provider: provider.clone(),
+            ..chip.clone()
+        });
+        self.parsed
+            .borrow_mut()
+            .insert(path.to_path_buf(), chips.collect());
     }
 }
 
@@ -172,6 +570,21 @@ impl From<&str> for Identifier {
 pub enum PortDirection {
     In,
     Out,
+    /// Bidirectional port. It is both read and driven, and drivers on the
+    /// same net are resolved like a tri-state bus.
+    InOut,
+}
+
+impl PortDirection {
+    /// True for ports whose value comes from outside the chip.
+    pub fn is_input(&self) -> bool {
+        matches!(self, PortDirection::In | PortDirection::InOut)
+    }
+
+    /// True for ports the chip drives.
+    pub fn is_output(&self) -> bool {
+        matches!(self, PortDirection::Out | PortDirection::InOut)
+    }
 }
 
 #[derive(Serialize, Clone, PartialEq, Eq, Hash, Debug)]
@@ -179,6 +592,8 @@ pub struct GenericPort {
     pub name: Identifier,
     pub width: GenericWidth,
     pub direction: PortDirection,
+    /// Comment documenting the port, if the scanner kept comments.
+    pub doc: Option<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -201,18 +616,108 @@ impl fmt::Display for Component {
     }
 }
 
-
 #[derive(Clone)]
 pub struct Loop {
     pub start: GenericWidth,
     pub end: GenericWidth,
+    /// Distance between iterator values, 1 unless the loop has a `STEP`.
+    pub step: GenericWidth,
     pub iterator: Identifier,
     pub body: Vec<Component>, // Prevent nested loops.
 }
 
+impl Loop {
+    /// Values the iterator takes, from `start` by `step` up to `end`, or
+    /// down to `end` if it is less than `start`.
+    pub fn values(&self, variables: &HashMap<String, usize>) -> Result<Vec<usize>, N2VError> {
+        let start = eval_expr_numeric(&self.start, variables)?;
+        let end = eval_expr_numeric(&self.end, variables)?;
+        let step = eval_expr_numeric(&self.step, variables)?;
+        if step == 0 {
+            return Err(N2VError {
+                msg: format!("The STEP of the loop over {} is 0.", self.iterator.value),
+                kind: ErrorKind::NonNumeric,
+            });
+        }
+        let next = |i: &usize| {
+            if start <= end {
+                i.checked_add(step).filter(|n| *n <= end)
+            } else {
+                i.checked_sub(step).filter(|n| *n >= end)
+            }
+        };
+        Ok(std::iter::successors(Some(start), next).collect())
+    }
+
+    /// Whether the loop counts up, or None if that depends on the values of
+    /// generics. A loop that starts at 0 counts up, and one that ends at 0
+    /// counts down.
+    pub fn ascending(&self, variables: &HashMap<String, usize>) -> Option<bool> {
+        let start = eval_expr_numeric(&self.start, variables).ok();
+        let end = eval_expr_numeric(&self.end, variables).ok();
+        match (start, end) {
+            (Some(start), Some(end)) => Some(start <= end),
+            (Some(0), _) => Some(true),
+            (_, Some(0)) => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Start, end, and direction of the loop for synthesis. Numeric bounds
+    /// are replaced by the smallest and largest values, so the loop counts
+    /// up over the same values. Otherwise the bounds are `start` and `end`,
+    /// and the direction is that of `ascending`.
+    pub fn synthesis_bounds(
+        &self,
+        variables: &HashMap<String, usize>,
+    ) -> (GenericWidth, GenericWidth, Option<bool>) {
+        match self.values(variables) {
+            Ok(values) => {
+                let lowest = *values.iter().min().unwrap();
+                let highest = *values.iter().max().unwrap();
+                (
+                    GenericWidth::Terminal(Terminal::Num(lowest)),
+                    GenericWidth::Terminal(Terminal::Num(highest)),
+                    Some(true),
+                )
+            }
+            Err(_) => (
+                self.start.clone(),
+                self.end.clone(),
+                self.ascending(variables),
+            ),
+        }
+    }
+
+    /// No value of the iterator is larger than this, which is `MAXIMUM`
+    /// of the bounds when the direction depends on generics.
+    pub fn highest(&self, variables: &HashMap<String, usize>) -> GenericWidth {
+        match self.synthesis_bounds(variables) {
+            (_, end, Some(true)) => end,
+            (start, _, Some(false)) => start,
+            (start, end, None) => GenericWidth::Expr(Op::Max, Box::new(start), Box::new(end)),
+        }
+    }
+
+    // ` STEP k` if the loop has a step other than 1.
+    fn step_clause(&self) -> String {
+        match self.step {
+            GenericWidth::Terminal(Terminal::Num(1)) => String::new(),
+            ref step => format!(" STEP {}", step),
+        }
+    }
+}
+
 impl fmt::Display for Loop {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "FOR {} FROM {} TO {}", self.iterator, self.start, self.end)?;
+        writeln!(
+            f,
+            "FOR {} FROM {} TO {}{}",
+            self.iterator,
+            self.start,
+            self.end,
+            self.step_clause()
+        )?;
         for component in &self.body {
             writeln!(f, "{}", component)?;
         }
@@ -224,12 +729,91 @@ impl fmt::Display for Loop {
 /// Designates two wire names. The signal from the right wire will be assigned to the left.
 pub struct AssignmentHDL {
     pub left: BusHDL,
-    pub right: BusHDL,
+    /// Segments of the right side, most significant first. More than one
+    /// segment is a concatenation such as `wide <= high[7..0] & low[7..0];`.
+    pub right: Vec<BusHDL>,
+    pub extension: Extension,
+    /// The part's port, for the assignment made for a concatenation mapped
+    /// to it. Errors name the port instead of the signal made for it.
+    pub port: Option<Identifier>,
+}
+
+/// How the high bits of the left side of an assignment are filled when it is
+/// wider than the right side.
+/// `out <= zext(small);` fills them with false, `out <= sext(small);` copies the
+/// most significant bit of the right side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Extension {
+    /// Widths must match.
+    #[default]
+    None,
+    Zero,
+    Sign,
 }
 
 impl fmt::Display for AssignmentHDL {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} <= {};", self.left, self.right)
+        let right: Vec<String> = self.right.iter().map(|b| b.to_string()).collect();
+        let right = right.join(" & ");
+        match self.extension {
+            Extension::None => write!(f, "{} <= {};", self.left, right),
+            Extension::Zero => write!(f, "{} <= zext({});", self.left, right),
+            Extension::Sign => write!(f, "{} <= sext({});", self.left, right),
+        }
+    }
+}
+
+/// `ASSERT out = 10;` checks the value of one of the chip's signals each time
+/// the chip is simulated. The value is a number, or `true` or `false` for all
+/// ones or all zeros.
+#[derive(Clone)]
+pub struct AssertHDL {
+    pub signal: Identifier,
+    pub value: Identifier,
+    /// Whether the value is a number written without a `0b` or `0x` prefix.
+    pub bare: bool,
+}
+
+impl AssertHDL {
+    /// The bits the signal must have, most significant first, for a signal
+    /// `width` bits wide. Like a value typed in the REPL, a bare number of
+    /// only 0s and 1s with one digit for each bit is binary.
+    pub fn expected(&self, width: usize) -> Result<Vec<bool>, String> {
+        let digits = &self.value.value;
+        if self.bare && digits.len() == width {
+            if let Some(bits) = parse_binary(digits, width) {
+                return Ok(bits);
+            }
+        }
+        let value = match digits.as_str() {
+            "true" => return Ok(vec![true; width]),
+            "false" => return Ok(vec![false; width]),
+            v => numeric_literal(v).ok_or_else(|| format!("The constant {} is too large.", v))?,
+        };
+        if width < 64 && value >> width != 0 {
+            return Err(format!(
+                "The constant {} does not fit in the {} bits of {}.",
+                value, width, self.signal
+            ));
+        }
+        Ok((0..width)
+            .rev()
+            .map(|i| i < 64 && value >> i & 1 == 1)
+            .collect())
+    }
+}
+
+impl fmt::Display for AssertHDL {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // A prefixed number whose decimal digits could be read as bits is
+        // written in binary, so that it is read back the same way.
+        let digits = &self.value.value;
+        match numeric_literal(digits) {
+            Some(value) if !self.bare && digits.chars().all(|c| c == '0' || c == '1') => {
+                write!(f, "ASSERT {} = 0b{:b};", self.signal, value)
+            }
+            _ => write!(f, "ASSERT {} = {};", self.signal, self.value),
+        }
     }
 }
 
@@ -250,12 +834,44 @@ impl fmt::Display for BusHDL {
     }
 }
 
+// A bus in canonical form. A single bit is written `name[i]`.
+fn normalized_bus(bus: &BusHDL) -> String {
+    match (&bus.start, &bus.end) {
+        (Some(start), Some(end)) if start == end => format!("{}[{}]", bus.name, start),
+        (Some(start), Some(end)) => format!("{}[{}..{}]", bus.name, start, end),
+        _ => bus.name.clone(),
+    }
+}
+
+// A component in canonical form, with its port mappings sorted by port.
+fn normalized_component(component: &Component) -> String {
+    let mut mappings: Vec<String> = component
+        .mappings
+        .iter()
+        .map(|m| format!("{}={}", normalized_bus(&m.port), normalized_bus(&m.wire)))
+        .collect();
+    mappings.sort();
+    let mut res = component.name.value.clone();
+    if !component.generic_params.is_empty() {
+        let generics: Vec<String> = component
+            .generic_params
+            .iter()
+            .map(|g| g.to_string())
+            .collect();
+        res += &format!("<{}>", generics.join(", "));
+    }
+    res + &format!("({});", mappings.join(", "))
+}
+
 //  Not(in=sel, out=notSel); has two wires { name : "sel", port: "in" }, { name : "notSel", port: "out" }
 #[derive(Clone, Debug)]
 pub struct PortMappingHDL {
     pub wire_ident: Identifier,
     pub wire: BusHDL,
     pub port: BusHDL,
+    /// Whether the wire is the signal made for a concatenation such as
+    /// `{high, low}`.
+    pub concatenation: bool,
 }
 
 impl fmt::Display for PortMappingHDL {
@@ -271,16 +887,61 @@ impl fmt::Display for PortMappingHDL {
 /// Returns a tuple of the parsed HDL and the accompanying FileReader
 /// HDL provider.
 pub fn parse_hdl_path(hdl_path: &Path) -> Result<(ChipHDL, FileReader), Box<dyn Error>> {
+    parse_hdl_path_with_includes(hdl_path, &[])
+}
+
+/// Parses an on-disk HDL file, resolving components from the file's
+/// directory and then from each of the include paths.
+pub fn parse_hdl_path_with_includes(
+    hdl_path: &Path,
+    include_paths: &[PathBuf],
+) -> Result<(ChipHDL, FileReader), Box<dyn Error>> {
     let base_path = hdl_path.parent().unwrap();
     let hdl_file = hdl_path.file_name().unwrap().to_str().unwrap();
-    let provider = FileReader::new(base_path);
+    let provider = FileReader::with_includes(base_path, include_paths);
     let contents = provider.get_hdl(hdl_file).unwrap();
-    let mut scanner = Scanner::new(contents.as_str(), provider.get_path(hdl_file));
-    let mut parser = Parser::new(&mut scanner, Rc::new(provider.clone()));
-    let hdl = parser.parse()?;
+    let hdl = parse_hdl_str(
+        &contents,
+        Rc::new(provider.clone()),
+        provider.get_path(hdl_file),
+    )?;
     Ok((hdl, provider))
 }
 
+/// Parses HDL source code that is already in memory.
+///
+/// - `source`: HDL source code.
+/// - `provider`: Resolves the components used by the chip.
+/// - `path`: Path reported in error messages.
+pub fn parse_hdl_str(
+    source: &str,
+    provider: Rc<dyn HdlProvider>,
+    path: PathBuf,
+) -> Result<ChipHDL, Box<dyn Error>> {
+    let mut scanner = Scanner::new(source, path);
+    let mut parser = Parser::new(&mut scanner, provider);
+    parser.parse()
+}
+
+/// Value of a wire that is a numeric constant such as `42`, or None if the
+/// wire is a signal or does not fit in 64 bits.
+pub fn numeric_literal(name: &str) -> Option<u64> {
+    name.parse().ok()
+}
+
+// Operator of a binary width expression such as `N / 2`, if the token is
+// one.
+fn binary_op(token_type: &TokenType) -> Option<Op> {
+    match token_type {
+        TokenType::Plus => Some(Op::Add),
+        TokenType::Minus => Some(Op::Sub),
+        TokenType::Star => Some(Op::Mul),
+        TokenType::Slash => Some(Op::Div),
+        TokenType::Percent => Some(Op::Mod),
+        _ => None,
+    }
+}
+
 /// Looks up chip definition for a chip.
 /// name is the name of the chip, not including .hdl extension
 /// provider is responsible for retrieving the HDL file (provider will have its own base path)
@@ -294,22 +955,28 @@ pub fn get_hdl(name: &str, provider: &Rc<dyn HdlProvider>) -> Result<ChipHDL, Bo
                     name: Identifier::from("a"),
                     width: GenericWidth::Terminal(Terminal::Num(1)),
                     direction: PortDirection::In,
+                    doc: None,
                 },
                 GenericPort {
                     name: Identifier::from("b"),
                     width: GenericWidth::Terminal(Terminal::Num(1)),
                     direction: PortDirection::In,
+                    doc: None,
                 },
                 GenericPort {
                     name: Identifier::from("out"),
                     width: GenericWidth::Terminal(Terminal::Num(1)),
                     direction: PortDirection::Out,
+                    doc: None,
                 },
             ],
             parts: Vec::new(),
             path: None,
             generic_decls: Vec::new(),
-            provider: provider.clone()
+            constants: Vec::new(),
+            provider: provider.clone(),
+            builtin: None,
+            doc: None,
         });
     } else if name.to_lowercase() == "dff" {
         // Hard-coded DFF chip
@@ -320,61 +987,162 @@ pub fn get_hdl(name: &str, provider: &Rc<dyn HdlProvider>) -> Result<ChipHDL, Bo
                     name: Identifier::from("in"),
                     width: GenericWidth::Terminal(Terminal::Num(1)),
                     direction: PortDirection::In,
+                    doc: None,
                 },
                 GenericPort {
                     name: Identifier::from("out"),
                     width: GenericWidth::Terminal(Terminal::Num(1)),
                     direction: PortDirection::Out,
+                    doc: None,
                 },
             ],
             parts: Vec::new(),
             path: None,
             generic_decls: Vec::new(),
+            constants: Vec::new(),
             provider: provider.clone(),
+            builtin: None,
+            doc: None,
         });
     }
 
-    let filename = String::from(name) + ".hdl";
-    let path = PathBuf::from(filename);
+    let mut filename = String::from(name) + ".hdl";
+    if let Some(hdl) = cached_chip(provider, &provider.get_path(&filename), None) {
+        return Ok(hdl);
+    }
+    let contents = match provider.get_hdl(&filename) {
+        Ok(x) => x,
+        Err(e) => {
+            // Fall back to a file whose name differs only in case, and then
+            // to a file with several chips.
+            let mut matches = provider.find_case_insensitive(&filename);
+            match matches.len() {
+                0 => match provider.find_chip_file(name) {
+                    Some(file) => return get_hdl_in_file(name, &file, provider),
+                    None => return Err(Box::new(e)),
+                },
+                1 => {
+                    filename = matches.remove(0);
+                    provider.get_hdl(&filename)?
+                }
+                _ => {
+                    return Err(Box::new(N2VError {
+                        msg: format!(
+                            "Chip name {} is ambiguous, it matches {}.",
+                            name,
+                            matches.join(", ")
+                        ),
+                        kind: ErrorKind::Other,
+                    }))
+                }
+            }
+        }
+    };
+    let path = provider.get_path(&filename);
+    let hdl = parse_hdl_str(&contents, provider.clone(), path.clone())?;
+    provider.cache_chips(&path, std::slice::from_ref(&hdl));
+    Ok(hdl)
+}
 
-    let contents = provider.get_hdl(path.to_str().unwrap())?;
-    let mut scanner = Scanner::new(contents.as_str(), path);
-    let mut parser = Parser::new(&mut scanner, provider.clone());
-    parser.parse()
+// The chip named `name`, or the first chip, from the provider's parse cache
+// of the file at `path`, given back its provider.
+fn cached_chip(provider: &Rc<dyn HdlProvider>, path: &Path, name: Option<&str>) -> Option<ChipHDL> {
+    let hdl = provider.cached_chip(path, name)?;
+    Some(ChipHDL {
+        provider: provider.clone(),
+        ..hdl
+    })
+}
+
+// Parses every chip in `file` and returns the one named `name`.
+fn get_hdl_in_file(
+    name: &str,
+    file: &str,
+    provider: &Rc<dyn HdlProvider>,
+) -> Result<ChipHDL, Box<dyn Error>> {
+    let path = provider.get_path(file);
+    if let Some(hdl) = cached_chip(provider, &path, Some(name)) {
+        return Ok(hdl);
+    }
+    let contents = provider.get_hdl(file)?;
+    let mut scanner = Scanner::new(&contents, path.clone());
+    let chips = Parser::new(&mut scanner, provider.clone()).parse_all()?;
+    provider.cache_chips(&path, &chips);
+    chips.into_iter().find(|c| c.name == name).ok_or_else(|| {
+        Box::new(N2VError {
+            msg: format!("Chip {} is not defined in {}.", name, file),
+            kind: ErrorKind::Other,
+        }) as Box<dyn Error>
+    })
 }
 
 pub struct Parser<'a, 'b> {
     pub scanner: &'a mut Scanner<'b>,
     provider: Rc<dyn HdlProvider>,
+    // Parens, brackets, and curly braces that have not been closed yet.
+    open_brackets: Vec<Token>,
+    // Assignments of the concatenations mapped to the ports of the last
+    // component, and the number of concatenations in the current chip.
+    concatenations: Vec<(String, AssignmentHDL)>,
+    concatenation_count: usize,
+    // Port of each concatenation in the current chip, with the index of its
+    // component and its assignment in the chip's parts.
+    concatenation_parts: Vec<(String, usize, usize)>,
+    in_loop: bool,
+    // Number of comments scanned before the current chip, so that a chip's
+    // documentation is not taken from the chip before it.
+    chip_comments_start: usize,
+    // Name token of each port of the current chip, in order.
+    port_tokens: Vec<Token>,
 }
 
 impl<'a, 'b> Parser<'a, 'b> {
     pub fn new(scanner: &'a mut Scanner<'b>, provider: Rc<dyn HdlProvider>) -> Parser<'a, 'b> {
         Parser {
             scanner,
-            provider
+            provider,
+            open_brackets: Vec::new(),
+            concatenations: Vec::new(),
+            concatenation_count: 0,
+            concatenation_parts: Vec::new(),
+            in_loop: false,
+            chip_comments_start: 0,
+            port_tokens: Vec::new(),
         }
     }
 
+    /// Parses the first chip in the file.
     pub fn parse(&mut self) -> Result<ChipHDL, Box<dyn Error>> {
         self.chip()
     }
 
+    /// Parses every chip in the file, in order. A file may define several
+    /// chips, but only one with each name.
+    pub fn parse_all(&mut self) -> Result<Vec<ChipHDL>, Box<dyn Error>> {
+        let mut chips: Vec<ChipHDL> = Vec::new();
+        loop {
+            let chip_token = self.peek_token()?;
+            let chip = self.chip()?;
+            if chips.iter().any(|c| c.name == chip.name) {
+                return Err(Box::new(N2VError {
+                    msg: format!("Chip {} is defined more than once in this file.", chip.name),
+                    kind: ErrorKind::ParseError(chip_token),
+                }));
+            }
+            chips.push(chip);
+            if self.scanner.peek().is_none() {
+                return Ok(chips);
+            }
+        }
+    }
+
     fn consume(&mut self, tt: TokenType) -> Result<Token, Box<dyn Error>> {
         let t = self.scanner.next();
         match &t {
-            None => Err(Box::new(N2VError {
-                msg: format!("Early end of file, expected {}", tt),
-                kind: ErrorKind::ParseError(Token {
-                    lexeme: String::from(""),
-                    path: self.scanner.path.clone(),
-                    line: self.scanner.line,
-                    start: self.scanner.col,
-                    token_type: TokenType::Eof,
-                }),
-            })),
+            None => Err(self.eof_error(&format!("Early end of file, expected {}", tt))),
             Some(t) => {
                 if t.token_type == tt {
+                    self.track_bracket(t);
                     Ok(t.clone())
                 } else {
                     Err(Box::new(N2VError {
@@ -389,15 +1157,82 @@ impl<'a, 'b> Parser<'a, 'b> {
         }
     }
 
+    fn next_token(&mut self) -> Result<Token, Box<dyn Error>> {
+        match self.scanner.next() {
+            Some(t) => {
+                self.track_bracket(&t);
+                Ok(t)
+            }
+            None => Err(self.eof_error("Unexpected end of file.")),
+        }
+    }
+
+    fn peek_token(&mut self) -> Result<Token, Box<dyn Error>> {
+        match self.scanner.peek() {
+            Some(t) => Ok(t),
+            None => Err(self.eof_error("Unexpected end of file.")),
+        }
+    }
+
+    // Keeps the stack of open brackets up to date as tokens are consumed.
+    fn track_bracket(&mut self, t: &Token) {
+        match t.token_type {
+            TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftCurly => {
+                self.open_brackets.push(t.clone());
+            }
+            TokenType::RightParen | TokenType::RightBracket | TokenType::RightCurly => {
+                self.open_brackets.pop();
+            }
+            _ => {}
+        }
+    }
+
+    // Error for reaching the end of the file in the middle of a construct.
+    // An unclosed bracket is the likely cause, so the error points at it.
+    fn eof_error(&self, msg: &str) -> Box<dyn Error> {
+        if let Some(open) = self.open_brackets.last() {
+            let close = match open.token_type {
+                TokenType::LeftParen => ')',
+                TokenType::LeftBracket => ']',
+                _ => '}',
+            };
+            return Box::new(N2VError {
+                msg: format!(
+                    "Unclosed '{}' opened at line {}. Add a matching '{}' before the end \
+                     of the file.",
+                    open.lexeme, open.line, close
+                ),
+                kind: ErrorKind::ParseError(open.clone()),
+            });
+        }
+        Box::new(N2VError {
+            msg: String::from(msg),
+            kind: ErrorKind::ParseError(Token {
+                lexeme: String::from(""),
+                path: self.scanner.path.clone(),
+                line: self.scanner.line,
+                start: self.scanner.col,
+                end: self.scanner.col,
+                token_type: TokenType::Eof,
+                radix: 10,
+            }),
+        })
+    }
+
     fn chip(&mut self) -> Result<ChipHDL, Box<dyn Error>> {
         // TODO: Print location information for token.
-        self.consume(TokenType::Chip)?;
+        let chip_line = self.consume(TokenType::Chip)?.line;
         let chip_name = self.consume(TokenType::Identifier)?;
+        self.port_tokens.clear();
+        self.concatenation_parts.clear();
+        self.concatenation_count = 0;
 
         let generics = self.generic_decls()?;
 
         self.consume(TokenType::LeftCurly)?;
 
+        let constants = self.constants(&generics)?;
+
         self.consume(TokenType::In)?;
 
         let mut ports = self.port_names(PortDirection::In)?;
@@ -405,28 +1240,192 @@ impl<'a, 'b> Parser<'a, 'b> {
 
         ports.append(&mut self.port_names(PortDirection::Out)?);
 
-        self.consume(TokenType::Parts)?;
-        self.consume(TokenType::Colon)?;
+        // Bidirectional ports are optional and come last.
+        if self.scanner.peek().map(|t| t.token_type) == Some(TokenType::InOut) {
+            self.consume(TokenType::InOut)?;
+            ports.append(&mut self.port_names(PortDirection::InOut)?);
+        }
 
-        let parts = self.parts()?;
+        // A BUILTIN chip is simulated by a registered Rust function
+        // instead of parts.
+        let mut builtin = None;
+        let parts = if self.scanner.peek().map(|t| t.token_type) == Some(TokenType::Builtin) {
+            self.consume(TokenType::Builtin)?;
+            builtin = Some(self.consume(TokenType::Identifier)?.lexeme);
+            self.consume(TokenType::Semicolon)?;
+            self.consume(TokenType::RightCurly)?;
+            Vec::new()
+        } else {
+            self.consume(TokenType::Parts)?;
+            self.consume(TokenType::Colon)?;
+            let mut parts = self.parts()?;
+            self.rename_concatenations(&ports, &mut parts);
+            parts
+        };
+
+        // Every comment has been scanned by now, so documentation can be
+        // attached. It is None unless the scanner kept comments.
+        let doc = self.chip_doc(chip_line);
+        let port_lines: Vec<u32> = ports.iter().filter_map(|p| p.name.line).collect();
+        for port in &mut ports {
+            port.doc = self.port_doc(port.name.line.unwrap_or(0), chip_line, &port_lines);
+        }
+
+        self.chip_comments_start = self.scanner.comments.len();
+        self.check_duplicate_ports()?;
 
         // match in ports (can out ports come before in ports?)
         // match out ports
-        Ok(ChipHDL {
+        let hdl = ChipHDL {
             name: Identifier::from(chip_name).value,
             ports,
             parts,
             path: Some(self.scanner.path.clone()),
             generic_decls: generics,
+            constants,
             provider: self.provider.clone(),
-        })
+            builtin,
+            doc,
+        };
+        self.check_port_assertions(&hdl)?;
+        Ok(hdl)
+    }
+
+    // The value of an assertion on a port whose width is known must fit in
+    // the port. Assertions on other signals are checked when the chip is
+    // elaborated.
+    fn check_port_assertions(&self, hdl: &ChipHDL) -> Result<(), Box<dyn Error>> {
+        let constants = hdl.constant_values();
+        for part in &hdl.parts {
+            let Part::Assert(assert) = part else {
+                continue;
+            };
+            let Ok(port) = hdl.get_port(&assert.signal.value) else {
+                continue;
+            };
+            let Ok(width) = eval_expr_numeric(&port.width, &constants) else {
+                continue;
+            };
+            if let Err(msg) = assert.expected(width) {
+                return Err(Box::new(N2VError {
+                    msg,
+                    kind: ErrorKind::ParseIdentError(self.provider.clone(), assert.value.clone()),
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    // A concatenation's signal is named after its port, which may be the
+    // name of a signal in the chip. Such a signal is renamed with the next
+    // number that is free. Each concatenation's own signal is named twice,
+    // once in its assignment and once in its port mapping.
+    fn rename_concatenations(&mut self, ports: &[GenericPort], parts: &mut [Part]) {
+        let mut names: HashMap<String, usize> = HashMap::new();
+        let mut count = |name: &str| *names.entry(String::from(name)).or_insert(0) += 1;
+        for port in ports {
+            count(&port.name.value);
+        }
+        for part in parts.iter() {
+            match part {
+                Part::Component(c) => c.mappings.iter().for_each(|m| count(&m.wire.name)),
+                Part::Loop(l) => l.body.iter().for_each(|c| {
+                    c.mappings.iter().for_each(|m| count(&m.wire.name));
+                }),
+                Part::AssignmentHDL(a) => {
+                    count(&a.left.name);
+                    a.right.iter().for_each(|b| count(&b.name));
+                }
+                Part::Assert(a) => count(&a.signal.value),
+            }
+        }
+
+        for (port, component, assignment) in &self.concatenation_parts {
+            let Part::AssignmentHDL(a) = &mut parts[*assignment] else {
+                continue;
+            };
+            if names.get(&a.left.name).is_some_and(|n| *n <= 2) {
+                continue;
+            }
+            let mut name = format!("{}_cat{}", port, self.concatenation_count);
+            while names.contains_key(&name) {
+                self.concatenation_count += 1;
+                name = format!("{}_cat{}", port, self.concatenation_count);
+            }
+            self.concatenation_count += 1;
+            names.insert(name.clone(), 2);
+
+            let old = std::mem::replace(&mut a.left.name, name.clone());
+            if let Part::Component(c) = &mut parts[*component] {
+                for m in &mut c.mappings {
+                    if m.concatenation && m.wire.name == old {
+                        m.wire.name = name.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    // Port names must be unique, whatever their direction. The error points
+    // at the second port with a name.
+    fn check_duplicate_ports(&self) -> Result<(), Box<dyn Error>> {
+        let mut seen: HashMap<&str, u32> = HashMap::new();
+        for token in &self.port_tokens {
+            if let Some(line) = seen.insert(&token.lexeme, token.line) {
+                return Err(Box::new(N2VError {
+                    msg: format!(
+                        "Port '{}' is already declared on line {}.",
+                        token.lexeme, line
+                    ),
+                    kind: ErrorKind::ParseError(token.clone()),
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    // The last block of comments on consecutive lines before the CHIP
+    // keyword.
+    fn chip_doc(&self, chip_line: u32) -> Option<String> {
+        let comments = &self.scanner.comments[self.chip_comments_start..];
+        let last = comments.iter().rposition(|c| c.end_line < chip_line)?;
+        Some(Self::comment_block(&comments[..=last]))
+    }
+
+    // A comment after the port on the same line, or else the block of
+    // comments ending on the line above it. Comments after the ports on
+    // `port_lines` belong to those ports and are not part of a block.
+    fn port_doc(&self, port_line: u32, chip_line: u32, port_lines: &[u32]) -> Option<String> {
+        let comments = &self.scanner.comments;
+        if let Some(c) = comments.iter().find(|c| c.line == port_line) {
+            return Some(c.text.clone());
+        }
+        let trailing = |c: &Comment| port_lines.contains(&c.line);
+        let last = comments
+            .iter()
+            .rposition(|c| c.end_line + 1 == port_line && c.line >= chip_line && !trailing(c))?;
+        let first = comments[..last]
+            .iter()
+            .rposition(trailing)
+            .map_or(0, |i| i + 1);
+        Some(Self::comment_block(&comments[first..=last]))
+    }
+
+    // Joins the last comment with the comments directly above it.
+    fn comment_block(comments: &[Comment]) -> String {
+        let mut start = comments.len() - 1;
+        while start > 0 && comments[start - 1].end_line + 1 >= comments[start].line {
+            start -= 1;
+        }
+        let texts: Vec<&str> = comments[start..].iter().map(|c| c.text.as_str()).collect();
+        texts.join("\n")
     }
 
     fn generics(&mut self) -> Result<Vec<GenericWidth>, Box<dyn Error>> {
         let mut res: Vec<GenericWidth> = Vec::new();
 
-        if self.scanner.peek().unwrap().token_type != TokenType::Number
-            && self.scanner.peek().unwrap().token_type != TokenType::Identifier
+        if self.peek_token()?.token_type != TokenType::Number
+            && self.peek_token()?.token_type != TokenType::Identifier
         {
             return Ok(Vec::new());
         }
@@ -482,18 +1481,9 @@ impl<'a, 'b> Parser<'a, 'b> {
                     }));
                 }
                 None => {
-                    return Err(Box::new(N2VError {
-                        msg: String::from(
-                            "Unexpected end of file. Expected number, comma, or right angle.",
-                        ),
-                        kind: ErrorKind::ParseError(Token {
-                            lexeme: String::from(""),
-                            path: self.scanner.path.clone(),
-                            line: self.scanner.line,
-                            start: self.scanner.col,
-                            token_type: TokenType::Eof,
-                        }),
-                    }));
+                    return Err(self.eof_error(
+                        "Unexpected end of file. Expected number, comma, or right angle.",
+                    ));
                 }
             }
         }
@@ -502,7 +1492,7 @@ impl<'a, 'b> Parser<'a, 'b> {
     fn generic_decls(&mut self) -> Result<Vec<Identifier>, Box<dyn Error>> {
         let mut res = Vec::new();
 
-        if self.scanner.peek().unwrap().token_type != TokenType::LeftAngle {
+        if self.peek_token()?.token_type != TokenType::LeftAngle {
             return Ok(Vec::new());
         }
         self.consume(TokenType::LeftAngle)?;
@@ -541,23 +1531,56 @@ impl<'a, 'b> Parser<'a, 'b> {
                     }));
                 }
                 None => {
-                    return Err(Box::new(N2VError {
-                        msg: String::from(
-                            "Unexpected end of file. Expected identifier, comma, or right angle.",
-                        ),
-                        kind: ErrorKind::ParseError(Token {
-                            lexeme: String::from(""),
-                            path: self.scanner.path.clone(),
-                            line: self.scanner.line,
-                            start: self.scanner.col,
-                            token_type: TokenType::Eof,
-                        }),
-                    }));
+                    return Err(self.eof_error(
+                        "Unexpected end of file. Expected identifier, comma, or right angle.",
+                    ));
                 }
             }
         }
     }
 
+    // Parses `CONST NAME = expr;` declarations. Each expression may only use
+    // numbers and the constants declared before it.
+    fn constants(
+        &mut self,
+        generics: &[Identifier],
+    ) -> Result<Vec<(Identifier, usize)>, Box<dyn Error>> {
+        let mut res: Vec<(Identifier, usize)> = Vec::new();
+        let mut values: HashMap<String, usize> = HashMap::new();
+        while self.peek_token()?.token_type == TokenType::Const {
+            self.consume(TokenType::Const)?;
+            let name_token = self.consume(TokenType::Identifier)?;
+            let name = Identifier::from(name_token.clone());
+            if values.contains_key(&name.value) || generics.iter().any(|g| g.value == name.value) {
+                return Err(Box::new(N2VError {
+                    msg: format!("'{}' is already defined.", name.value),
+                    kind: ErrorKind::ParseError(name_token),
+                }));
+            }
+            self.consume(TokenType::Equal)?;
+            let expr_token = self.peek_token()?;
+            let expr = self.expr()?;
+            self.consume(TokenType::Semicolon)?;
+
+            if let Some(var) = undefined_variable(&expr, &values) {
+                return Err(Box::new(N2VError {
+                    msg: format!(
+                        "Undefined constant '{}' in CONST {}.",
+                        var.value, name.value
+                    ),
+                    kind: ErrorKind::ParseError(expr_token),
+                }));
+            }
+            let value = eval_expr_numeric(&expr, &values).map_err(|e| N2VError {
+                msg: format!("{} in CONST {}.", e.msg, name.value),
+                kind: ErrorKind::ParseError(expr_token),
+            })?;
+            values.insert(name.value.clone(), value);
+            res.push((name, value));
+        }
+        Ok(res)
+    }
+
     fn port_names(&mut self, direction: PortDirection) -> Result<Vec<GenericPort>, Box<dyn Error>> {
         let mut res = Vec::new();
 
@@ -570,10 +1593,12 @@ impl<'a, 'b> Parser<'a, 'b> {
                         ..
                     },
                 ) => {
+                    self.port_tokens.push(t.clone());
                     let p = GenericPort {
                         name: Identifier::from(t.clone()),
                         width: self.port_width()?,
                         direction,
+                        doc: None,
                     };
                     res.push(p);
                 }
@@ -596,18 +1621,9 @@ impl<'a, 'b> Parser<'a, 'b> {
                     }));
                 }
                 None => {
-                    return Err(Box::new(N2VError {
-                        msg: String::from(
-                            "Unexpected end of file. Expected identifier, comma, or semicolon.",
-                        ),
-                        kind: ErrorKind::ParseError(Token {
-                            lexeme: String::from(""),
-                            path: self.scanner.path.clone(),
-                            line: self.scanner.line,
-                            start: self.scanner.col,
-                            token_type: TokenType::Eof,
-                        }),
-                    }));
+                    return Err(self.eof_error(
+                        "Unexpected end of file. Expected identifier, comma, or semicolon.",
+                    ));
                 }
             }
         }
@@ -624,6 +1640,12 @@ impl<'a, 'b> Parser<'a, 'b> {
                     ..
                 }) => {
                     parts.push(self.component()?);
+                    let component = parts.len() - 1;
+                    for (port, assignment) in self.concatenations.drain(..) {
+                        self.concatenation_parts
+                            .push((port, component, parts.len()));
+                        parts.push(Part::AssignmentHDL(assignment));
+                    }
                 }
                 Some(Token {
                     token_type: TokenType::For,
@@ -631,32 +1653,29 @@ impl<'a, 'b> Parser<'a, 'b> {
                 }) => {
                     parts.push(Part::Loop(self.for_loop()?));
                 }
+                Some(Token {
+                    token_type: TokenType::Assert,
+                    ..
+                }) => {
+                    parts.push(Part::Assert(self.assert()?));
+                }
                 Some(Token {
                     token_type: TokenType::RightCurly,
                     ..
                 }) => {
-                    self.scanner.next();
+                    self.next_token()?;
                     break;
                 }
                 Some(t) => {
                     return Err(Box::new(N2VError {
-                        msg: String::from("Expected identifier, FOR, or right curly."),
+                        msg: String::from("Expected identifier, FOR, ASSERT, or right curly."),
                         kind: ErrorKind::ParseError(t.clone()),
                     }));
                 }
                 None => {
-                    return Err(Box::new(N2VError {
-                        msg: String::from(
-                            "Unexpected end of file. Expected identifier, FOR, or right curly.",
-                        ),
-                        kind: ErrorKind::ParseError(Token {
-                            lexeme: String::from(""),
-                            path: self.scanner.path.clone(),
-                            line: self.scanner.line,
-                            start: self.scanner.col,
-                            token_type: TokenType::Eof,
-                        }),
-                    }));
+                    return Err(self.eof_error(
+                        "Unexpected end of file. Expected identifier, FOR, ASSERT, or right curly.",
+                    ));
                 }
             }
         }
@@ -684,7 +1703,7 @@ impl<'a, 'b> Parser<'a, 'b> {
                     token_type: TokenType::RightCurly,
                     ..
                 }) => {
-                    self.scanner.next();
+                    self.next_token()?;
                     break;
                 }
                 Some(t) => {
@@ -694,18 +1713,8 @@ impl<'a, 'b> Parser<'a, 'b> {
                     }));
                 }
                 None => {
-                    return Err(Box::new(N2VError {
-                        msg: String::from(
-                            "Unexpected end of file. Expected identifier or right curly.",
-                        ),
-                        kind: ErrorKind::ParseError(Token {
-                            lexeme: String::from(""),
-                            path: self.scanner.path.clone(),
-                            line: self.scanner.line,
-                            start: self.scanner.col,
-                            token_type: TokenType::Eof,
-                        }),
-                    }));
+                    return Err(self
+                        .eof_error("Unexpected end of file. Expected identifier or right curly."));
                 }
             }
         }
@@ -713,6 +1722,44 @@ impl<'a, 'b> Parser<'a, 'b> {
         Ok(parts)
     }
 
+    // ASSERT signal = value;
+    fn assert(&mut self) -> Result<AssertHDL, Box<dyn Error>> {
+        self.consume(TokenType::Assert)?;
+        let signal = Identifier::from(self.consume(TokenType::Identifier)?);
+        self.consume(TokenType::Equal)?;
+        let mut bare = false;
+        let value = if self.peek_token()?.token_type == TokenType::Number {
+            let number = self.consume(TokenType::Number)?;
+            bare = number.radix == 10;
+            if numeric_literal(&number.lexeme).is_none() {
+                return Err(Box::new(N2VError {
+                    msg: format!("The constant {} is too large.", number.lexeme),
+                    kind: ErrorKind::ParseError(number),
+                }));
+            }
+            Identifier {
+                value: number.lexeme,
+                path: Some(number.path),
+                line: Some(number.line),
+            }
+        } else {
+            let value = self.consume(TokenType::Identifier)?;
+            if value.lexeme != "true" && value.lexeme != "false" {
+                return Err(Box::new(N2VError {
+                    msg: String::from("Expected a number, true, or false."),
+                    kind: ErrorKind::ParseError(value),
+                }));
+            }
+            Identifier::from(value)
+        };
+        self.consume(TokenType::Semicolon)?;
+        Ok(AssertHDL {
+            signal,
+            value,
+            bare,
+        })
+    }
+
     fn for_loop(&mut self) -> Result<Loop, Box<dyn Error>> {
         self.consume(TokenType::For)?;
         let iterator = Identifier::from(self.consume(TokenType::Identifier)?);
@@ -720,45 +1767,103 @@ impl<'a, 'b> Parser<'a, 'b> {
         let start = self.expr()?;
         self.consume(TokenType::To)?;
         let end = self.expr()?;
+        let step = if self.peek_token()?.token_type == TokenType::Step {
+            let step_token = self.consume(TokenType::Step)?;
+            let step = self.expr()?;
+            if step == GenericWidth::Terminal(Terminal::Num(0)) {
+                return Err(Box::new(N2VError {
+                    msg: String::from("The STEP of a loop must be at least 1."),
+                    kind: ErrorKind::ParseError(step_token),
+                }));
+            }
+            step
+        } else {
+            GenericWidth::Terminal(Terminal::Num(1))
+        };
         self.consume(TokenType::Generate)?;
         self.consume(TokenType::LeftCurly)?;
-        let body = self.components()?;
+        self.in_loop = true;
+        let body = self.components();
+        self.in_loop = false;
+        let body = body?;
 
         Ok(Loop {
             start,
             end,
+            step,
             iterator,
             body,
         })
     }
 
+    /// Parses a width expression. `*`, `/` and `%` bind tighter than `+` and
+    /// `-`, operators of the same precedence group from the left, and
+    /// parentheses group a sub-expression.
     fn expr(&mut self) -> Result<GenericWidth, Box<dyn Error>> {
-        let t1 = self.terminal()?;
+        let lhs = self.product()?;
+        self.sum(lhs)
+    }
 
-        let peeked = self.scanner.peek().unwrap();
-        if peeked.token_type == TokenType::Plus {
+    /// Adds or subtracts any further products to `lhs`, from left to right.
+    fn sum(&mut self, mut lhs: GenericWidth) -> Result<GenericWidth, Box<dyn Error>> {
+        while let Some(op @ (Op::Add | Op::Sub)) = binary_op(&self.peek_token()?.token_type) {
             self.scanner.next();
-            let t2 = self.terminal()?;
-            Ok(GenericWidth::Expr(
-                Op::Add,
-                Box::new(GenericWidth::Terminal(t1)),
-                Box::new(GenericWidth::Terminal(t2)),
-            ))
-        } else if peeked.token_type == TokenType::Minus {
+            let rhs = self.product()?;
+            lhs = GenericWidth::Expr(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    /// Parses factors joined by `*`, `/` or `%`, from left to right.
+    fn product(&mut self) -> Result<GenericWidth, Box<dyn Error>> {
+        let mut lhs = self.factor()?;
+        while let Some(op @ (Op::Mul | Op::Div | Op::Mod)) =
+            binary_op(&self.peek_token()?.token_type)
+        {
             self.scanner.next();
-            let t2 = self.terminal()?;
-            Ok(GenericWidth::Expr(
-                Op::Sub,
-                Box::new(GenericWidth::Terminal(t1)),
-                Box::new(GenericWidth::Terminal(t2)),
-            ))
-        } else {
-            Ok(GenericWidth::Terminal(t1))
+            let rhs = self.factor()?;
+            lhs = GenericWidth::Expr(op, Box::new(lhs), Box::new(rhs));
         }
+        Ok(lhs)
+    }
+
+    /// Parses a number, a generic var, or an expression in parentheses.
+    fn factor(&mut self) -> Result<GenericWidth, Box<dyn Error>> {
+        if self.peek_token()?.token_type == TokenType::LeftParen {
+            self.consume(TokenType::LeftParen)?;
+            let inner = self.expr()?;
+            self.consume(TokenType::RightParen)?;
+            return Ok(inner);
+        }
+        Ok(GenericWidth::Terminal(self.terminal()?))
+    }
+
+    /// Parses the width after `+:` and returns the last bit it selects,
+    /// `start + width - 1`, folded as far as the known values allow.
+    fn indexed_end(&mut self, start: &GenericWidth) -> Result<GenericWidth, Box<dyn Error>> {
+        let width_token = self.peek_token()?;
+        let width = self.expr()?;
+        if width == GenericWidth::Terminal(Terminal::Num(0)) {
+            return Err(Box::new(N2VError {
+                msg: String::from("A `+:` slice must select at least one bit."),
+                kind: ErrorKind::ParseError(width_token),
+            }));
+        }
+
+        let end = GenericWidth::Expr(
+            Op::Sub,
+            Box::new(GenericWidth::Expr(
+                Op::Add,
+                Box::new(start.clone()),
+                Box::new(width),
+            )),
+            Box::new(GenericWidth::Terminal(Terminal::Num(1))),
+        );
+        Ok(eval_expr(&end, &HashMap::new())?)
     }
 
     fn terminal(&mut self) -> Result<Terminal, Box<dyn Error>> {
-        let width_token = self.scanner.next().unwrap();
+        let width_token = self.next_token()?;
         let width = match width_token.token_type {
             TokenType::Number => Terminal::Num(width_token.lexeme.parse::<usize>().unwrap()),
             TokenType::Identifier => Terminal::Var(Identifier::from(width_token)),
@@ -775,13 +1880,10 @@ impl<'a, 'b> Parser<'a, 'b> {
     /// Extracts a component from HDL
     fn component(&mut self) -> Result<Part, Box<dyn Error>> {
         // Turn this
-        let ident = self.scanner.next().unwrap();
+        let ident = self.next_token()?;
         let ident_bus_widths = self.bus_idx()?;
 
-        let peeked = self
-            .scanner
-            .peek()
-            .expect("Expected an angle bracket or paren after an identifier.");
+        let peeked = self.peek_token()?;
         if let Token {
             token_type: TokenType::LeftAngle,
             ..
@@ -789,32 +1891,73 @@ impl<'a, 'b> Parser<'a, 'b> {
         {
             self.consume(TokenType::LeftAngle)?;
 
-            let peeked1 = self
-                .scanner
-                .peek()
-                .expect("Expected and equals sign or a generic declaration");
+            let peeked1 = self.peek_token()?;
             if let Token {
                 token_type: TokenType::Equal,
                 ..
             } = peeked1
             {
                 self.consume(TokenType::Equal)?;
-                let wire_ident = self.scanner.peek().unwrap();
-                self.consume(TokenType::Identifier)?;
+                let mut wire_ident = self.consume(TokenType::Identifier)?;
+
+                // zext(...) and sext(...) extend the right side to the width
+                // of the left side.
+                let mut extension = Extension::None;
+                if let Some(Token {
+                    token_type: TokenType::LeftParen,
+                    ..
+                }) = self.scanner.peek()
+                {
+                    extension = match wire_ident.lexeme.as_str() {
+                        "zext" => Extension::Zero,
+                        "sext" => Extension::Sign,
+                        _ => {
+                            return Err(Box::new(N2VError {
+                                msg: format!(
+                                    "Unknown assignment function `{}`, expected zext or sext.",
+                                    wire_ident.lexeme
+                                ),
+                                kind: ErrorKind::ParseError(wire_ident),
+                            }));
+                        }
+                    };
+                    self.consume(TokenType::LeftParen)?;
+                    wire_ident = self.consume(TokenType::Identifier)?;
+                }
                 let wire_ident_bus_widths = self.bus_idx()?;
+                let mut right = vec![BusHDL {
+                    name: wire_ident.lexeme,
+                    start: wire_ident_bus_widths.0,
+                    end: wire_ident_bus_widths.1,
+                }];
+                if extension != Extension::None {
+                    self.consume(TokenType::RightParen)?;
+                }
+
+                // Concatenated segments, only allowed without an extension.
+                while extension == Extension::None
+                    && self.scanner.peek().map(|t| t.token_type) == Some(TokenType::Ampersand)
+                {
+                    self.consume(TokenType::Ampersand)?;
+                    let segment_ident = self.consume(TokenType::Identifier)?;
+                    let segment_bus_widths = self.bus_idx()?;
+                    right.push(BusHDL {
+                        name: segment_ident.lexeme,
+                        start: segment_bus_widths.0,
+                        end: segment_bus_widths.1,
+                    });
+                }
 
-                // wire_ident if the rhs, ident is the left-hand side
+                // right is the rhs, ident is the left-hand side
                 let assign = AssignmentHDL {
                     left: BusHDL {
                         name: ident.lexeme,
                         start: ident_bus_widths.0,
                         end: ident_bus_widths.1,
                     },
-                    right: BusHDL {
-                        name: wire_ident.lexeme,
-                        start: wire_ident_bus_widths.0,
-                        end: wire_ident_bus_widths.1,
-                    },
+                    right,
+                    extension,
+                    port: None,
                 };
 
                 self.consume(TokenType::Semicolon)?;
@@ -830,7 +1973,7 @@ impl<'a, 'b> Parser<'a, 'b> {
     }
 
     fn port_width(&mut self) -> Result<GenericWidth, Box<dyn Error>> {
-        let peeked = self.scanner.peek().unwrap();
+        let peeked = self.peek_token()?;
         if peeked.token_type != TokenType::LeftBracket {
             return Ok(GenericWidth::Terminal(Terminal::Num(1)));
         }
@@ -843,20 +1986,33 @@ impl<'a, 'b> Parser<'a, 'b> {
     }
 
     fn bus_idx(&mut self) -> Result<(Option<GenericWidth>, Option<GenericWidth>), Box<dyn Error>> {
-        let peeked = self.scanner.peek();
+        let peeked = self.peek_token()?;
 
         if let Token {
             token_type: TokenType::LeftBracket,
             ..
-        } = peeked.unwrap()
+        } = peeked
         {
             self.consume(TokenType::LeftBracket)?;
-            let start = self.expr()?;
+            // Like `expr`, but a `+` followed by `:` ends the sum. Then
+            // `[start +: width]` selects `width` bits starting at `start`.
+            let mut start = self.product()?;
+            while let Some(op @ (Op::Add | Op::Sub)) = binary_op(&self.peek_token()?.token_type) {
+                self.scanner.next();
+                if op == Op::Add && self.peek_token()?.token_type == TokenType::Colon {
+                    self.consume(TokenType::Colon)?;
+                    let end = self.indexed_end(&start)?;
+                    self.consume(TokenType::RightBracket)?;
+                    return Ok((Some(start), Some(end)));
+                }
+                let rhs = self.product()?;
+                start = GenericWidth::Expr(op, Box::new(start), Box::new(rhs));
+            }
 
             let end = if let Token {
                 token_type: TokenType::Dot,
                 ..
-            } = self.scanner.peek().unwrap()
+            } = self.peek_token()?
             {
                 self.consume(TokenType::Dot)?;
                 self.consume(TokenType::Dot)?;
@@ -872,6 +2028,50 @@ impl<'a, 'b> Parser<'a, 'b> {
         }
     }
 
+    /// Parses a concatenation such as `{high, low[0..3]}` mapped to `port`,
+    /// most significant segment first. It is assigned to a new signal named
+    /// after the port, which is mapped in its place.
+    fn concatenation(&mut self, port: &Token) -> Result<String, Box<dyn Error>> {
+        let open = self.consume(TokenType::LeftCurly)?;
+        if self.in_loop {
+            return Err(Box::new(N2VError {
+                msg: String::from("A concatenation can't be mapped to a port in a FOR loop."),
+                kind: ErrorKind::ParseError(open),
+            }));
+        }
+
+        let mut right = Vec::new();
+        loop {
+            let segment = self.consume(TokenType::Identifier)?;
+            let (start, end) = self.bus_idx()?;
+            right.push(BusHDL {
+                name: segment.lexeme,
+                start,
+                end,
+            });
+            if self.peek_token()?.token_type != TokenType::Comma {
+                break;
+            }
+            self.consume(TokenType::Comma)?;
+        }
+        self.consume(TokenType::RightCurly)?;
+
+        let name = format!("{}_cat{}", port.lexeme, self.concatenation_count);
+        self.concatenation_count += 1;
+        let assignment = AssignmentHDL {
+            left: BusHDL {
+                name: name.clone(),
+                start: None,
+                end: None,
+            },
+            right,
+            extension: Extension::None,
+            port: Some(Identifier::from(port.clone())),
+        };
+        self.concatenations.push((port.lexeme.clone(), assignment));
+        Ok(name)
+    }
+
     fn port_mappings(&mut self) -> Result<Vec<PortMappingHDL>, Box<dyn Error>> {
         let mut mappings = Vec::new();
 
@@ -887,13 +2087,31 @@ impl<'a, 'b> Parser<'a, 'b> {
                 ) => {
                     let (port_start, port_end) = self.bus_idx()?;
                     self.consume(TokenType::Equal)?;
-                    let wire = self.consume(TokenType::Identifier)?;
-                    let (wire_start, wire_end) = self.bus_idx()?;
+                    // A number is a constant, which has no bus index.
+                    let concatenation = self.peek_token()?.token_type == TokenType::LeftCurly;
+                    let (wire, wire_start, wire_end) = match self.peek_token()?.token_type {
+                        TokenType::Number => {
+                            let number = self.consume(TokenType::Number)?;
+                            if numeric_literal(&number.lexeme).is_none() {
+                                return Err(Box::new(N2VError {
+                                    msg: format!("The constant {} is too large.", number.lexeme),
+                                    kind: ErrorKind::ParseError(number),
+                                }));
+                            }
+                            (number.lexeme, None, None)
+                        }
+                        TokenType::LeftCurly => (self.concatenation(t)?, None, None),
+                        _ => {
+                            let wire = self.consume(TokenType::Identifier)?;
+                            let (wire_start, wire_end) = self.bus_idx()?;
+                            (wire.lexeme, wire_start, wire_end)
+                        }
+                    };
 
                     mappings.push(PortMappingHDL {
                         wire_ident: Identifier::from(t.clone()),
                         wire: BusHDL {
-                            name: wire.lexeme,
+                            name: wire,
                             start: wire_start,
                             end: wire_end,
                         },
@@ -902,13 +2120,14 @@ impl<'a, 'b> Parser<'a, 'b> {
                             start: port_start,
                             end: port_end,
                         },
+                        concatenation,
                     });
 
-                    let peeked_type = self.scanner.peek().unwrap().token_type;
+                    let peeked_type = self.peek_token()?.token_type;
                     match peeked_type {
                         TokenType::Comma | TokenType::RightParen => {}
                         _ => {
-                            let found_t = self.scanner.peek().unwrap();
+                            let found_t = self.peek_token()?;
                             let found = found_t.lexeme.clone();
                             return Err(Box::new(N2VError {
                                 msg: format!("Expected comma or right paren, found {}", found),
@@ -923,10 +2142,13 @@ impl<'a, 'b> Parser<'a, 'b> {
                 }) => {
                     continue;
                 }
-                Some(Token {
-                    token_type: TokenType::RightParen,
-                    ..
-                }) => {
+                Some(
+                    t @ Token {
+                        token_type: TokenType::RightParen,
+                        ..
+                    },
+                ) => {
+                    self.track_bracket(t);
                     break;
                 }
                 Some(t) => {
@@ -936,16 +2158,9 @@ impl<'a, 'b> Parser<'a, 'b> {
                     }));
                 }
                 None => {
-                    return Err(Box::new(N2VError {
-                        msg: String::from("Unexpected end of file. Expected comma or right paren."),
-                        kind: ErrorKind::ParseError(Token {
-                            lexeme: String::from(""),
-                            path: self.scanner.path.clone(),
-                            line: self.scanner.line,
-                            start: self.scanner.col,
-                            token_type: TokenType::Eof,
-                        }),
-                    }));
+                    return Err(
+                        self.eof_error("Unexpected end of file. Expected comma or right paren.")
+                    );
                 }
             }
         }
@@ -1050,4 +2265,689 @@ mod test {
         let mut parser = Parser::new(&mut scanner, provider);
         parser.parse().expect("Parse error");
     }
+
+    #[test]
+    fn test_include_paths() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let include_dir = manifest_dir.join("resources/tests/include");
+        let top_path = include_dir.join("top/Top.hdl");
+
+        let (_, provider) = parse_hdl_path(&top_path).expect("Parse error");
+        let err = provider
+            .get_hdl("Inv.hdl")
+            .expect_err("Inv is not in the chip's directory");
+        assert!(err
+            .to_string()
+            .contains("then -I directories, then WHIDL_PATH directories"));
+        let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+        assert!(get_hdl("Inv", &provider).is_err());
+
+        let (_, provider) = parse_hdl_path_with_includes(&top_path, &[include_dir.join("lib")])
+            .expect("Parse error");
+        assert_eq!(
+            provider.get_path("Inv.hdl"),
+            include_dir.join("lib").join("Inv.hdl")
+        );
+        let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+        let inv = get_hdl("Inv", &provider).expect("Unable to find Inv in include path");
+        assert_eq!(inv.name, "Inv");
+    }
+
+    #[test]
+    fn test_unused_generics() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let hdl_path = manifest_dir.join("resources/tests/lint/Unused.hdl");
+        let (hdl, _) = parse_hdl_path(&hdl_path).expect("Parse error");
+        let unused: Vec<&str> = hdl
+            .unused_generics()
+            .iter()
+            .map(|g| g.value.as_str())
+            .collect();
+        assert_eq!(unused, vec!["U"]);
+        assert_eq!(hdl.unused_generics()[0].line, Some(2));
+
+        let hdl_path = manifest_dir.join("resources/tests/de1-hdl/NotTwiceGen.hdl");
+        let (hdl, _) = parse_hdl_path(&hdl_path).expect("Parse error");
+        assert!(hdl.unused_generics().is_empty());
+    }
+
+    // A stub must parse back to the same interface with no parts.
+    #[test]
+    fn test_stub_roundtrip() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let hdl_path = manifest_dir.join("resources/tests/arm/Mux4Way.hdl");
+        let (hdl, provider) = parse_hdl_path(&hdl_path).expect("Parse error");
+
+        let stub = hdl.stub();
+        let mut scanner = Scanner::new(&stub, hdl_path.clone());
+        let mut parser = Parser::new(&mut scanner, Rc::new(provider));
+        let stub_hdl = parser.parse().expect("Unable to parse stub");
+
+        assert_eq!(stub_hdl.name, hdl.name);
+        assert!(stub_hdl.parts.is_empty());
+        assert_eq!(stub_hdl.generic_decls.len(), 1);
+        assert_eq!(stub_hdl.generic_decls[0].value, "W");
+        let interface = |h: &ChipHDL| -> Vec<(String, GenericWidth, PortDirection)> {
+            h.ports
+                .iter()
+                .map(|p| (p.name.value.clone(), p.width.clone(), p.direction))
+                .collect()
+        };
+        assert_eq!(interface(&stub_hdl), interface(&hdl));
+    }
+
+    #[test]
+    fn test_inout_ports() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let hdl_path = manifest_dir.join("resources/tests/inout/BusPort.hdl");
+        let (hdl, provider) = parse_hdl_path(&hdl_path).expect("Parse error");
+        let bus = hdl.get_port("bus").expect("Missing port bus");
+        assert_eq!(bus.direction, PortDirection::InOut);
+        assert!(bus.direction.is_input() && bus.direction.is_output());
+
+        let stub = hdl.stub();
+        assert!(stub.contains("    INOUT bus;\n"));
+        let mut scanner = Scanner::new(&stub, hdl_path.clone());
+        let stub_hdl = Parser::new(&mut scanner, Rc::new(provider))
+            .parse()
+            .expect("Unable to parse stub");
+        let directions =
+            |h: &ChipHDL| -> Vec<PortDirection> { h.ports.iter().map(|p| p.direction).collect() };
+        assert_eq!(directions(&stub_hdl), directions(&hdl));
+    }
+
+    #[test]
+    fn test_normalized_hdl() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources/tests/normalize");
+        let (tidy, _) = parse_hdl_path(&base_path.join("tidy/Select.hdl")).expect("Parse error");
+        let (messy, _) = parse_hdl_path(&base_path.join("messy/Select.hdl")).expect("Parse error");
+
+        let expected = "CHIP Select {
+    IN a[4], b[4], sel;
+    OUT out[4], top;
+
+    PARTS:
+    FOR i IN 0 TO 3 GENERATE {
+        Mux(a=a[i], b=b[i], out=out[i], sel=sel);
+    }
+    top <= out[3];
+}
+";
+        assert_eq!(tidy.to_normalized(), expected);
+        assert_eq!(messy.to_normalized(), expected);
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let hdl_path = manifest_dir.join("resources/tests/docs/Register8.hdl");
+        let source = fs::read_to_string(&hdl_path).unwrap();
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(hdl_path.parent().unwrap()));
+
+        // Comments are dropped unless the scanner keeps them.
+        let mut scanner = Scanner::new(&source, hdl_path.clone());
+        let hdl = Parser::new(&mut scanner, provider.clone())
+            .parse()
+            .expect("Parse error");
+        assert_eq!(hdl.doc, None);
+        assert!(hdl.ports.iter().all(|p| p.doc.is_none()));
+
+        let mut scanner = Scanner::new(&source, hdl_path.clone());
+        scanner.keep_comments = true;
+        let hdl = Parser::new(&mut scanner, provider)
+            .parse()
+            .expect("Parse error");
+        assert_eq!(
+            hdl.doc.as_deref(),
+            Some("8-bit register.\nStores in when load is set.")
+        );
+        let docs: Vec<Option<&str>> = hdl.ports.iter().map(|p| p.doc.as_deref()).collect();
+        assert_eq!(
+            docs,
+            vec![
+                Some("Value to store."),
+                Some("Store in on the next tick."),
+                Some("The stored value.")
+            ]
+        );
+
+        let markdown = hdl.docs();
+        assert!(markdown.starts_with("# Register8\n\n8-bit register.\n"));
+        assert!(markdown.contains("| `load` | 1 | Store in on the next tick. |"));
+        assert!(markdown.contains("## Outputs"));
+        assert!(markdown.contains("| `out` | 8 | The stored value. |"));
+
+        // A comment trailing one port does not document the next one.
+        let source = "CHIP Pair {\n    IN a, // first\n       b,\n       // third\n       c;\n\
+                      OUT out;\n    PARTS:\n}";
+        let mut scanner = Scanner::new(source, PathBuf::from("Pair.hdl"));
+        scanner.keep_comments = true;
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+        let hdl = Parser::new(&mut scanner, provider)
+            .parse()
+            .expect("Parse error");
+        let docs: Vec<Option<&str>> = hdl.ports.iter().map(|p| p.doc.as_deref()).collect();
+        assert_eq!(docs, vec![Some("first"), None, Some("third"), None]);
+    }
+
+    #[test]
+    fn test_case_insensitive_chip_names() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let chip = |name: &str| {
+            format!(
+                "CHIP {} {{ IN in; OUT out; PARTS: Nand(a=in, b=in, out=out); }}",
+                name
+            )
+        };
+        // Files differing only in case are kept in separate directories, so
+        // that the test also works on case-insensitive filesystems.
+        let (base, lib) = (temp_dir.path().join("base"), temp_dir.path().join("lib"));
+        fs::create_dir(&base).unwrap();
+        fs::create_dir(&lib).unwrap();
+        fs::write(base.join("Inv.hdl"), chip("Inv")).unwrap();
+        fs::write(base.join("Amb.hdl"), chip("Amb")).unwrap();
+        fs::write(lib.join("AMB.hdl"), chip("AMB")).unwrap();
+
+        // The first directory with a matching file is used.
+        let mut reader = FileReader::with_includes(&base, std::slice::from_ref(&lib));
+        let provider: Rc<dyn HdlProvider> = Rc::new(reader.clone());
+        assert_eq!(
+            get_hdl("inv", &provider).expect("Unable to find Inv").name,
+            "Inv"
+        );
+        assert_eq!(
+            get_hdl("amb", &provider).expect("Unable to find Amb").name,
+            "Amb"
+        );
+        let lib_provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&lib));
+        assert_eq!(
+            get_hdl("amb", &lib_provider)
+                .expect("Unable to find AMB")
+                .name,
+            "AMB"
+        );
+
+        // Two matches in one directory are only possible when the
+        // filesystem is case-sensitive.
+        fs::write(lib.join("Amb.hdl"), chip("Amb")).unwrap();
+        if fs::read_dir(&lib).unwrap().count() == 2 {
+            let lib_provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&lib));
+            let err = get_hdl("amb", &lib_provider)
+                .err()
+                .expect("Expected ambiguous chip error");
+            assert!(err.to_string().contains("ambiguous"));
+            assert!(get_hdl("Amb", &lib_provider).is_ok());
+        }
+
+        reader.set_case_insensitive(false);
+        let provider: Rc<dyn HdlProvider> = Rc::new(reader);
+        assert!(get_hdl("inv", &provider).is_err());
+    }
+
+    // Running out of input inside a bracket names the bracket that is open.
+    #[test]
+    fn test_unclosed_brackets() {
+        let parse_error = |source: &str| {
+            let mut scanner = Scanner::new(source, PathBuf::from("Unclosed.hdl"));
+            let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+            let mut parser = Parser::new(&mut scanner, provider);
+            match parser.parse() {
+                Ok(_) => panic!("Expected a parse error"),
+                Err(e) => e.to_string(),
+            }
+        };
+        let header = "CHIP Not {\n    IN in;\n    OUT out;\n    PARTS:\n";
+
+        let err = parse_error(&format!("{}    Nand(a=in, b=in, out=out", header));
+        assert!(err.contains("Unclosed '(' opened at line 5. Add a matching ')'"));
+
+        let err = parse_error(&format!("{}    Nand(a=in, b=in, out=out[0", header));
+        assert!(err.contains("Unclosed '[' opened at line 5. Add a matching ']'"));
+
+        let err = parse_error(&format!("{}    Nand(a=in, b=in, out=out);\n", header));
+        assert!(err.contains("Unclosed '{' opened at line 1. Add a matching '}'"));
+    }
+
+    #[test]
+    fn test_constants() {
+        let path = PathBuf::from("constants/ConstNot.hdl");
+        let contents = read_hdl(&path);
+        let mut scanner = Scanner::new(contents.as_str(), path.clone());
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+        let hdl = Parser::new(&mut scanner, provider)
+            .parse()
+            .expect("Parse error");
+        assert_eq!(
+            hdl.constant_values(),
+            HashMap::from([(String::from("WIDTH"), 4), (String::from("TOP"), 3)])
+        );
+        assert!(hdl
+            .stub()
+            .contains("    CONST WIDTH = 4;\n    CONST TOP = 3;\n"));
+
+        let parse_error = |source: &str| {
+            let mut scanner = Scanner::new(source, PathBuf::from("Const.hdl"));
+            let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+            match Parser::new(&mut scanner, provider).parse() {
+                Ok(_) => panic!("Expected a parse error"),
+                Err(e) => e.to_string(),
+            }
+        };
+        let err = parse_error("CHIP C {\n    CONST W = 2;\n    CONST W = 3;\n");
+        assert!(err.contains("'W' is already defined."));
+        let err = parse_error("CHIP C<W> {\n    CONST W = 2;\n");
+        assert!(err.contains("'W' is already defined."));
+        let err = parse_error("CHIP C {\n    CONST W = V + 1;\n");
+        assert!(err.contains("Undefined constant 'V' in CONST W."));
+        let err = parse_error("CHIP C {\n    CONST W = 4 / 0;\n");
+        assert!(err.contains("Division by zero in (4 / 0) in CONST W."));
+
+        let source = "CHIP C {\n    CONST H = 7 / 2;\n    CONST R = 7 % 2;\n    IN in[H];\n    \
+                      OUT out;\n    PARTS:\n    Not(in=in[R], out=out); // 7/2\n}\n";
+        let mut scanner = Scanner::new(source, PathBuf::from("C.hdl"));
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+        let hdl = Parser::new(&mut scanner, provider)
+            .parse()
+            .expect("Parse error");
+        assert_eq!(
+            hdl.constant_values(),
+            HashMap::from([(String::from("H"), 3), (String::from("R"), 1)])
+        );
+    }
+
+    // Quoted identifiers can name ports after keywords.
+    #[test]
+    fn test_quoted_port_names() {
+        let source = "CHIP Quoted {\n    IN `IN`, \\FOR\\;\n    OUT `out`;\n    PARTS:\n    \
+                      Nand(a=`IN`, b=\\FOR\\, out=out);\n}\n";
+        let mut scanner = Scanner::new(source, PathBuf::from("Quoted.hdl"));
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+        let hdl = Parser::new(&mut scanner, provider)
+            .parse()
+            .expect("Parse error");
+
+        let names: Vec<&str> = hdl.ports.iter().map(|p| p.name.value.as_str()).collect();
+        assert_eq!(names, vec!["IN", "FOR", "out"]);
+        match &hdl.parts[0] {
+            Part::Component(c) => {
+                assert_eq!(c.mappings[0].wire.name, "IN");
+                assert_eq!(c.mappings[1].wire.name, "FOR");
+            }
+            _ => panic!("Expected a component"),
+        }
+    }
+
+    // Concatenation signals are numbered from 0 in each chip of a file and
+    // remember their port.
+    #[test]
+    fn test_concatenation_names() {
+        let chip = |name: &str| {
+            format!(
+                "CHIP {} {{\n    IN a, b;\n    OUT out[2];\n    PARTS:\n    \
+                 Or8Way(in={{a, b}}, out=out[0]);\n}}\n",
+                name
+            )
+        };
+        let source = chip("First") + &chip("Second");
+        let mut scanner = Scanner::new(&source, PathBuf::from("Concat.hdl"));
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+        let chips = Parser::new(&mut scanner, provider)
+            .parse_all()
+            .expect("Parse error");
+
+        for hdl in chips {
+            match &hdl.parts[1] {
+                Part::AssignmentHDL(a) => {
+                    assert_eq!(a.left.name, "in_cat0");
+                    assert_eq!(a.port.as_ref().map(|p| p.value.as_str()), Some("in"));
+                }
+                _ => panic!("Expected the concatenation's assignment"),
+            }
+        }
+    }
+
+    // Widths and loop bounds can be written in hexadecimal or binary.
+    #[test]
+    fn test_radix_literals() {
+        let source = "CHIP Radix {\n    IN in[0xF];\n    OUT out[0b100];\n    PARTS:\n    \
+                      FOR i IN 0 TO 0b11 GENERATE {\n        Not(in=in[i], out=out[i]);\n    \
+                      }\n}\n";
+        let mut scanner = Scanner::new(source, PathBuf::from("Radix.hdl"));
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+        let hdl = Parser::new(&mut scanner, provider)
+            .parse()
+            .expect("Parse error");
+
+        assert_eq!(
+            hdl.ports[0].width,
+            GenericWidth::Terminal(Terminal::Num(15))
+        );
+        assert_eq!(hdl.ports[1].width, GenericWidth::Terminal(Terminal::Num(4)));
+        match &hdl.parts[0] {
+            Part::Loop(l) => assert_eq!(l.end, GenericWidth::Terminal(Terminal::Num(3))),
+            _ => panic!("Expected a loop"),
+        }
+    }
+
+    // A port name can only be used once, in either direction. The error
+    // points at the second one.
+    #[test]
+    fn test_duplicate_ports() {
+        let parse = |source: &str| {
+            let mut scanner = Scanner::new(source, PathBuf::from("Dup.hdl"));
+            let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+            Parser::new(&mut scanner, provider).parse()
+        };
+        let second = |source: &str| -> (String, u32, usize) {
+            let err = parse(source).err().expect("Expected duplicate port error");
+            let err = err.downcast::<N2VError>().unwrap();
+            match err.kind {
+                ErrorKind::ParseError(t) => (err.msg, t.line, t.start),
+                _ => panic!("Expected a parse error"),
+            }
+        };
+
+        assert_eq!(
+            second("CHIP Dup {\n    IN a, a;\n    OUT out;\n    PARTS:\n}\n"),
+            (
+                String::from("Port 'a' is already declared on line 2."),
+                2,
+                11
+            )
+        );
+        assert_eq!(
+            second("CHIP Dup {\n    IN a[2], b;\n    OUT out, a;\n    PARTS:\n}\n"),
+            (
+                String::from("Port 'a' is already declared on line 2."),
+                3,
+                14
+            )
+        );
+        // Port names are case-sensitive.
+        assert!(parse("CHIP Dup {\n    IN a;\n    OUT A;\n    PARTS:\n}\n").is_ok());
+    }
+
+    // Chips without a file of their own are found in a file with several
+    // chips.
+    #[test]
+    fn test_multiple_chips_in_file() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let dir = manifest_dir.join("resources/tests/multichip");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&dir));
+
+        let source = read_hdl(Path::new("multichip/gates.hdl"));
+        let mut scanner = Scanner::new(&source, dir.join("gates.hdl"));
+        scanner.keep_comments = true;
+        let chips = Parser::new(&mut scanner, provider.clone())
+            .parse_all()
+            .expect("Parse error");
+        let names: Vec<&str> = chips.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["Not", "And", "Or"]);
+        assert_eq!(
+            chips[0].doc.as_deref(),
+            Some("Small gates kept together in one file.")
+        );
+        assert_eq!(chips[1].doc, None);
+
+        let or = get_hdl("Or", &provider).expect("Unable to find Or in gates.hdl");
+        assert_eq!(or.name, "Or");
+        assert_eq!(or.parts.len(), 3);
+        let majority = get_hdl("Majority", &provider).expect("Parse error");
+        assert_eq!(majority.parts.len(), 5);
+        assert!(get_hdl("Xor", &provider).is_err());
+
+        let twice = format!("{}\n{}", source, "CHIP And { IN a, b; OUT out; PARTS: }");
+        let mut scanner = Scanner::new(&twice, dir.join("twice.hdl"));
+        let err = Parser::new(&mut scanner, provider)
+            .parse_all()
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("Chip And is defined more than once"));
+    }
+
+    // A FileReader that counts how often each file is read.
+    struct CountingReader {
+        reader: FileReader,
+        reads: RefCell<HashMap<String, usize>>,
+    }
+
+    impl HdlProvider for CountingReader {
+        fn get_hdl(&self, file_name: &str) -> Result<String, std::io::Error> {
+            *self
+                .reads
+                .borrow_mut()
+                .entry(file_name.to_string())
+                .or_insert(0) += 1;
+            self.reader.get_hdl(file_name)
+        }
+
+        fn get_path(&self, file_name: &str) -> PathBuf {
+            self.reader.get_path(file_name)
+        }
+
+        fn cached_chip(&self, path: &Path, name: Option<&str>) -> Option<ChipHDL> {
+            self.reader.cached_chip(path, name)
+        }
+
+        fn cache_chips(&self, path: &Path, chips: &[ChipHDL]) {
+            self.reader.cache_chips(path, chips)
+        }
+    }
+
+    // Elaborating the CPU creates many parts using the same chips, such as
+    // its Mux16s and the FullAdders of its ALU, but each file is parsed once.
+    #[test]
+    fn test_parse_cache() {
+        use crate::simulator::Chip;
+
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let dir = manifest_dir.join("resources/tests/nand2tetris/solutions");
+        let counter = Rc::new(CountingReader {
+            reader: FileReader::new(&dir),
+            reads: RefCell::new(HashMap::new()),
+        });
+        let provider: Rc<dyn HdlProvider> = counter.clone();
+        let hdl = get_hdl("CPU", &provider).expect("Parse error");
+        let mut chip = Chip::new(&hdl, std::ptr::null_mut(), &provider, false, &[])
+            .expect("Chip creation error");
+        chip.gate_count().expect("Elaboration error");
+
+        let reads = counter.reads.borrow();
+        assert!(reads.contains_key("Mux16.hdl") && reads.contains_key("FullAdder.hdl"));
+        assert!(reads.values().all(|&count| count == 1), "{:?}", reads);
+
+        // Cached chips are handed out with the provider they were asked for.
+        let mux = get_hdl("Mux16", &provider).expect("Parse error");
+        assert!(Rc::ptr_eq(&mux.provider, &provider));
+        assert_eq!(mux.parts.len(), 16);
+    }
+
+    // Widths can nest parentheses, `*`, `/` and `%` bind tighter than `+`
+    // and `-`, and operators of the same precedence group from the left.
+    #[test]
+    fn test_nested_width_exprs() {
+        let source = "CHIP Nested<N, M> {\n    IN a[(N+1)*2], b[N - (M + 1)], c[N - M + 1],\n    \
+                      d[N + M * 2], e[N / 2 % 3], f[((N))];\n    OUT out[N];\n    PARTS:\n    \
+                      out[0] <= a[(N-1)/2];\n    out[1..2] <= a[N*2 +: 2];\n    \
+                      out[3..6] <= a[N + M - 1 +: 4];\n}\n";
+        let mut scanner = Scanner::new(source, PathBuf::from("Nested.hdl"));
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+        let hdl = Parser::new(&mut scanner, provider)
+            .parse()
+            .expect("Parse error");
+
+        let widths: Vec<String> = hdl.ports.iter().map(|p| p.width.to_string()).collect();
+        assert_eq!(
+            widths,
+            vec![
+                "((N + 1) * 2)",
+                "(N - (M + 1))",
+                "((N - M) + 1)",
+                "(N + (M * 2))",
+                "((N / 2) mod 3)",
+                "N",
+                "N",
+            ]
+        );
+
+        let variables = HashMap::from([(String::from("N"), 9), (String::from("M"), 2)]);
+        let values: Vec<usize> = hdl
+            .ports
+            .iter()
+            .map(|p| eval_expr_numeric(&p.width, &variables).unwrap())
+            .collect();
+        assert_eq!(values, vec![20, 6, 8, 13, 1, 9, 9]);
+
+        let Part::AssignmentHDL(a) = &hdl.parts[0] else {
+            panic!("Expected an assignment");
+        };
+        let index = a.right[0].start.as_ref().unwrap();
+        assert_eq!(eval_expr_numeric(index, &variables).unwrap(), 4);
+        let Part::AssignmentHDL(a) = &hdl.parts[1] else {
+            panic!("Expected an assignment");
+        };
+        let end = a.right[0].end.as_ref().unwrap();
+        assert_eq!(eval_expr_numeric(end, &variables).unwrap(), 19);
+        // The start of a `+:` slice may be a sum.
+        let Part::AssignmentHDL(a) = &hdl.parts[2] else {
+            panic!("Expected an assignment");
+        };
+        let start = a.right[0].start.as_ref().unwrap();
+        let end = a.right[0].end.as_ref().unwrap();
+        assert_eq!(eval_expr_numeric(start, &variables).unwrap(), 10);
+        assert_eq!(eval_expr_numeric(end, &variables).unwrap(), 13);
+
+        let mut scanner = Scanner::new(
+            "CHIP Unclosed<N> {\n    IN in[(N+1];\n    OUT out;\n}\n",
+            PathBuf::from("Unclosed.hdl"),
+        );
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+        assert!(Parser::new(&mut scanner, provider).parse().is_err());
+    }
+
+    // A loop counts down when its end is less than its start, and STEP
+    // skips values.
+    #[test]
+    fn test_loop_step() {
+        let parse = |source: &str| {
+            let mut scanner = Scanner::new(source, PathBuf::from("Step.hdl"));
+            let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+            Parser::new(&mut scanner, provider).parse()
+        };
+        let source = "CHIP Step {\n    IN in[8];\n    OUT out[8];\n    PARTS:\n    \
+                      FOR i IN 7 TO 0 STEP 3 GENERATE {\n        \
+                      Not(in=in[i], out=out[i]);\n    }\n}\n";
+        let hdl = parse(source).expect("Parse error");
+        let Part::Loop(l) = &hdl.parts[0] else {
+            panic!("Expected a loop");
+        };
+        let none = HashMap::new();
+        assert_eq!(l.values(&none).unwrap(), vec![7, 4, 1]);
+        let (lowest, highest, ascending) = l.synthesis_bounds(&none);
+        assert_eq!(
+            (lowest.to_string(), highest.to_string(), ascending),
+            (String::from("1"), String::from("7"), Some(true))
+        );
+        assert!(hdl
+            .to_normalized()
+            .contains("    FOR i IN 7 TO 0 STEP 3 GENERATE {\n"));
+
+        let up = Loop {
+            start: GenericWidth::Terminal(Terminal::Num(1)),
+            end: GenericWidth::Terminal(Terminal::Var(Identifier::from("N"))),
+            step: GenericWidth::Terminal(Terminal::Num(2)),
+            ..l.clone()
+        };
+        assert_eq!(
+            up.values(&HashMap::from([(String::from("N"), 6)])).unwrap(),
+            vec![1, 3, 5]
+        );
+        let zero = Loop {
+            step: up.end.clone(),
+            ..up.clone()
+        };
+        let err = zero
+            .values(&HashMap::from([(String::from("N"), 0)]))
+            .unwrap_err();
+        assert_eq!(err.msg, "The STEP of the loop over i is 0.");
+
+        let err = parse(&source.replace("STEP 3", "STEP 0"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("The STEP of a loop must be at least 1."));
+
+        // Without values for generics, only a bound of 0 gives the direction.
+        let n = GenericWidth::Terminal(Terminal::Var(Identifier::from("N")));
+        let down = Loop {
+            start: n.clone(),
+            end: GenericWidth::Terminal(Terminal::Num(0)),
+            ..up.clone()
+        };
+        assert_eq!(down.ascending(&none), Some(false));
+        assert_eq!(down.highest(&none).to_string(), "N");
+        assert_eq!(up.ascending(&none), None);
+        assert_eq!(up.highest(&none).to_string(), "MAXIMUM(1, N)");
+    }
+
+    #[test]
+    fn test_assert() {
+        let parse = |source: &str| {
+            let mut scanner = Scanner::new(source, PathBuf::from("Assert.hdl"));
+            let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(Path::new(".")));
+            Parser::new(&mut scanner, provider).parse()
+        };
+        let source = "CHIP Assert {\n    IN in[4];\n    OUT out[4];\n    PARTS:\n    \
+                      Not4(in=in, out=out);\n    ASSERT out = 0b1010;\n}\n";
+        let hdl = parse(source).expect("Parse error");
+        let Part::Assert(a) = &hdl.parts[1] else {
+            panic!("Expected an assertion");
+        };
+        assert_eq!(
+            (a.signal.value.as_str(), a.value.value.as_str()),
+            ("out", "10")
+        );
+        assert_eq!(a.value.line, Some(6));
+        assert_eq!(a.expected(4).unwrap(), vec![true, false, true, false]);
+        let err = a.expected(3).unwrap_err();
+        assert_eq!(err, "The constant 10 does not fit in the 3 bits of out.");
+        assert!(hdl.to_normalized().contains("    ASSERT out = 0b1010;\n"));
+
+        let all = parse(&source.replace("0b1010", "true")).expect("Parse error");
+        let Part::Assert(a) = &all.parts[1] else {
+            panic!("Expected an assertion");
+        };
+        assert_eq!(a.expected(2).unwrap(), vec![true, true]);
+
+        // Bare digits are binary when there is one for each bit.
+        let bits = parse(&source.replace("0b1010", "0101")).expect("Parse error");
+        let Part::Assert(a) = &bits.parts[1] else {
+            panic!("Expected an assertion");
+        };
+        assert_eq!(a.expected(4).unwrap(), vec![false, true, false, true]);
+        // Otherwise they are decimal.
+        let bits = vec![false, true, true, false, false, true, false, true];
+        assert_eq!(a.expected(8).unwrap(), bits);
+
+        // A value too wide for a port is rejected when the chip is parsed.
+        let err = parse(&source.replace("0b1010", "16"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("The constant 16 does not fit in the 4 bits of out."));
+        let err = parse(&source.replace("0b1010", "10101"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("The constant 10101 does not fit in the 4 bits of out."));
+
+        let err = parse(&source.replace("0b1010", "in"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("Expected a number, true, or false."));
+    }
 }
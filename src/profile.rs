@@ -0,0 +1,127 @@
+//! Elaboration profiling.
+//!
+//! A `Profile` is shared by every chip in a design when profiling is enabled.
+//! Chip construction and elaboration are timed and the self time (excluding
+//! nested chips) is accumulated per chip type.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Default, Clone, Debug)]
+pub struct ChipTiming {
+    /// Number of times a chip of this type was constructed.
+    pub constructed: usize,
+    /// Number of times a chip of this type was elaborated.
+    pub elaborated: usize,
+    /// Time spent constructing and elaborating this chip type, not including
+    /// time spent in its components.
+    pub time: Duration,
+}
+
+#[derive(Default, Debug)]
+pub struct Profile {
+    timings: HashMap<String, ChipTiming>,
+    // Time spent in nested chips for each timer that is currently running.
+    child_time: Vec<Duration>,
+}
+
+/// Which step of building a chip is being timed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Construct,
+    Elaborate,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    /// Starts timing a chip. Every call must be matched by a call to `finish`.
+    pub fn start(&mut self) -> Instant {
+        self.child_time.push(Duration::ZERO);
+        Instant::now()
+    }
+
+    /// Stops timing a chip started at `start` and records its self time.
+    pub fn finish(&mut self, chip_name: &str, phase: Phase, start: Instant) {
+        let elapsed = start.elapsed();
+        let nested = self.child_time.pop().unwrap_or_default();
+        if let Some(parent) = self.child_time.last_mut() {
+            *parent += elapsed;
+        }
+
+        let timing = self.timings.entry(String::from(chip_name)).or_default();
+        match phase {
+            Phase::Construct => timing.constructed += 1,
+            Phase::Elaborate => timing.elaborated += 1,
+        }
+        timing.time += elapsed.saturating_sub(nested);
+    }
+
+    /// Timings for each chip type, slowest first.
+    pub fn sorted(&self) -> Vec<(&String, &ChipTiming)> {
+        let mut timings: Vec<_> = self.timings.iter().collect();
+        timings.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(b.0)));
+        timings
+    }
+
+    pub fn total(&self) -> Duration {
+        self.timings.values().map(|t| t.time).sum()
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        writeln!(
+            f,
+            "{:<24} {:>10} {:>10} {:>12} {:>7}",
+            "Chip", "Built", "Elaborated", "Time (ms)", "%"
+        )?;
+        for (name, timing) in self.sorted() {
+            let percent = if total.is_zero() {
+                0.0
+            } else {
+                100.0 * timing.time.as_secs_f64() / total.as_secs_f64()
+            };
+            writeln!(
+                f,
+                "{:<24} {:>10} {:>10} {:>12.3} {:>7.1}",
+                name,
+                timing.constructed,
+                timing.elaborated,
+                timing.time.as_secs_f64() * 1000.0,
+                percent
+            )?;
+        }
+        write!(f, "Total: {:.3} ms", total.as_secs_f64() * 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    // Time spent in a nested chip is charged to the nested chip only.
+    #[test]
+    fn test_profile_self_time() {
+        let mut profile = Profile::new();
+        let outer = profile.start();
+        let inner = profile.start();
+        thread::sleep(Duration::from_millis(20));
+        profile.finish("Inner", Phase::Elaborate, inner);
+        profile.finish("Outer", Phase::Construct, outer);
+
+        let sorted = profile.sorted();
+        assert_eq!(sorted[0].0, "Inner");
+        assert_eq!(sorted[0].1.elaborated, 1);
+        assert_eq!(sorted[0].1.constructed, 0);
+        assert!(sorted[0].1.time >= Duration::from_millis(20));
+        assert_eq!(sorted[1].0, "Outer");
+        assert_eq!(sorted[1].1.constructed, 1);
+        assert!(sorted[1].1.time < Duration::from_millis(20));
+    }
+}
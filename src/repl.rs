@@ -0,0 +1,257 @@
+//! Interactive simulation of a chip.
+//!
+//! Commands are read one line at a time and applied to a single simulator,
+//! so inputs and clocked state carry over from one command to the next:
+//!
+//! - `set <input> <value>` sets an input port for the next `eval` or `tick`.
+//! - `eval` simulates the chip and prints its outputs.
+//! - `tick` simulates a full clock cycle and prints the outputs after it.
+//! - `show <signal>` prints a port, or an internal signal given as a path
+//!   such as `FullAdder.1/carry`.
+//! - `reset` clears the inputs and the chip's state.
+//! - `quit` or `exit` stops, as does the end of the input.
+//!
+//! A value made of 0s and 1s with as many digits as the port is wide is
+//! binary. Other values are decimal, and negative values are two's
+//! complement. A `%B` prefix forces binary, as in test scripts.
+
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+
+use crate::busmap::{format_bits, parse_binary, BusMap, Radix};
+use crate::error::{ErrorKind, N2VError};
+use crate::simulator::{Bus, Simulator};
+
+/// A simulator and the inputs set so far.
+pub struct Repl {
+    simulator: Simulator,
+    inputs: BusMap,
+}
+
+impl Repl {
+    /// Starts with every input 0.
+    pub fn new(simulator: Simulator) -> Repl {
+        let mut repl = Repl {
+            simulator,
+            inputs: BusMap::new(),
+        };
+        repl.clear_inputs();
+        repl
+    }
+
+    fn clear_inputs(&mut self) {
+        self.inputs = BusMap::new();
+        for (name, width) in self.simulator.input_ports() {
+            // The bus was just created, so this cannot fail.
+            self.inputs.create_bus(&name, width).unwrap();
+            self.inputs.insert(Bus::from(name), vec![false; width]);
+        }
+    }
+
+    /// Reads commands from `input` until it ends or a `quit` command,
+    /// writing what each command prints to `out`. A command that fails
+    /// prints its error and the loop goes on. With `prompt`, `> ` is
+    /// written before each command is read.
+    pub fn run(
+        &mut self,
+        input: impl BufRead,
+        out: &mut impl Write,
+        prompt: bool,
+    ) -> io::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            if prompt {
+                write!(out, "> ")?;
+                out.flush()?;
+            }
+            let Some(line) = lines.next() else {
+                return Ok(());
+            };
+            let line = line?;
+            if matches!(line.trim(), "quit" | "exit") {
+                return Ok(());
+            }
+            match self.command(&line) {
+                Ok(text) => write!(out, "{}", text)?,
+                Err(e) => writeln!(out, "error: {}", e.to_string().trim_end())?,
+            }
+        }
+    }
+
+    /// Runs one command and returns the text it prints. Blank lines do
+    /// nothing.
+    pub fn command(&mut self, line: &str) -> Result<String, Box<dyn Error>> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            [] => Ok(String::new()),
+            ["set", name, value] => {
+                self.set(name, value)?;
+                Ok(String::new())
+            }
+            ["eval"] => {
+                self.simulator.simulate(&self.inputs)?;
+                Ok(self.outputs())
+            }
+            ["tick"] => {
+                self.simulator.simulate(&self.inputs)?;
+                self.simulator.tick()?;
+                self.simulator.simulate(&self.inputs)?;
+                Ok(self.outputs())
+            }
+            ["show", name] => self.show(name),
+            ["reset"] => {
+                self.simulator.reset();
+                self.clear_inputs();
+                Ok(String::new())
+            }
+            _ => Err(repl_error(format!(
+                "Unknown command `{}`. Commands are set <input> <value>, eval, tick, \
+                 show <signal>, reset, and quit.",
+                line.trim()
+            ))),
+        }
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        let Some(width) = self.inputs.get_width(name) else {
+            let names: Vec<String> = self
+                .simulator
+                .input_ports()
+                .into_iter()
+                .map(|(n, _)| n)
+                .collect();
+            return Err(repl_error(format!(
+                "No input named {}. The inputs are: {}",
+                name,
+                names.join(", ")
+            )));
+        };
+        let binary = match value.strip_prefix("%B") {
+            Some(bits) => Some(parse_binary(bits, width).ok_or_else(|| {
+                repl_error(format!(
+                    "{} is not a valid {}-bit binary value for {}.",
+                    value, width, name
+                ))
+            })?),
+            None if value.len() == width => parse_binary(value, width),
+            None => None,
+        };
+        match binary {
+            Some(values) => {
+                self.inputs.insert(Bus::from(name), values);
+            }
+            None => {
+                let decimal = value.parse::<i64>().map_err(|_| {
+                    repl_error(format!("{} is not a valid value for {}.", value, name))
+                })?;
+                self.inputs.set_integer(name, decimal).map_err(repl_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn show(&mut self, name: &str) -> Result<String, Box<dyn Error>> {
+        let path: Vec<&str> = name.split('/').collect();
+        self.simulator.elaborate_path(&path[..path.len() - 1])?;
+        match self.simulator.probe(&path) {
+            Some(bits) => Ok(format!("{}={}\n", name, format_bits(&bits, Radix::Binary))),
+            None => Err(repl_error(format!("No signal named {}.", name))),
+        }
+    }
+
+    // Each output port as `name=bits`, most significant bit first.
+    fn outputs(&self) -> String {
+        let values: Vec<String> = self
+            .simulator
+            .output_ports()
+            .into_iter()
+            .map(|(name, _)| {
+                let bits = self.simulator.probe(&[&name]).unwrap_or_default();
+                format!("{}={}", name, format_bits(&bits, Radix::Binary))
+            })
+            .collect();
+        format!("{}\n", values.join(" "))
+    }
+}
+
+fn repl_error(msg: String) -> Box<dyn Error> {
+    Box::new(N2VError {
+        msg,
+        kind: ErrorKind::Other,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{parse_hdl_path, HdlProvider};
+    use crate::simulator::Chip;
+    use std::env;
+    use std::path::Path;
+    use std::ptr;
+    use std::rc::Rc;
+
+    fn repl(name: &str) -> Repl {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = manifest_dir
+            .join("resources/tests/nand2tetris/solutions")
+            .join(name);
+        let (hdl, provider) = parse_hdl_path(&path).expect("Parse error");
+        let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+        let chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())
+            .expect("Chip creation error");
+        Repl::new(Simulator::new(chip))
+    }
+
+    #[test]
+    fn test_repl_commands() {
+        let mut add = repl("Add16.hdl");
+        assert_eq!(add.command("set a 0000000000001010").unwrap(), "");
+        add.command("set b 5").unwrap();
+        assert_eq!(add.command("eval").unwrap(), "out=0000000000001111\n");
+        add.command("set b -1").unwrap();
+        assert_eq!(add.command("eval").unwrap(), "out=0000000000001001\n");
+        add.command("set b %B11").unwrap();
+        assert_eq!(add.command("show out").unwrap(), "out=0000000000001001\n");
+        add.command("eval").unwrap();
+        assert_eq!(
+            add.command("show FullAdder.0/sum").unwrap(),
+            "FullAdder.0/sum=0\n"
+        );
+
+        assert!(add
+            .command("set c 1")
+            .unwrap_err()
+            .to_string()
+            .contains("No input named c"));
+        assert!(add.command("set a 70000").is_err());
+        assert!(add
+            .command("show nothing")
+            .unwrap_err()
+            .to_string()
+            .contains("nothing"));
+        assert!(add
+            .command("jump")
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown command"));
+
+        add.command("reset").unwrap();
+        assert_eq!(add.command("eval").unwrap(), "out=0000000000000000\n");
+    }
+
+    // Clocked state carries over between commands, and errors don't stop
+    // the loop.
+    #[test]
+    fn test_repl_run() {
+        let mut bit = repl("Bit.hdl");
+        let input = "set in 1\nset load 1\nshow missing\ntick\nset load 0\nset in 0\ntick\n\
+                     quit\neval\n";
+        let mut out = Vec::new();
+        bit.run(input.as_bytes(), &mut out, false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "error: No signal named missing.\nout=1\nout=1\n"
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Running Hack programs on a CPU chip.
+//!
+//! The CPU must have the ports of the nand2tetris CPU: `instruction`, `inM`,
+//! and `reset` inputs, and `outM`, `writeM`, `addressM`, and `pc` outputs.
+//! The program is read from a memory image and fed to `instruction`, and
+//! data memory is kept outside the chip. Each cycle the CPU executes the
+//! instruction at `pc`, memory is written if `writeM` is set, and the clock
+//! ticks.
+//!
+//! A program halts when `pc` leaves the program, or when the CPU comes back
+//! to the state it was in one or two cycles earlier with no memory written
+//! in between, such as the usual `(END) @END 0;JMP` loop.
+//!
+//! A whole computer chip, with its program and data memory inside it, is
+//! run with `run_computer` instead. The program is written straight into
+//! the DFFs of its ROM part, and the computer runs for a fixed number of
+//! cycles.
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::busmap::BusMap;
+use crate::error::{ErrorKind, N2VError};
+use crate::memory::load_memory_image;
+use crate::parser::{parse_hdl_path_with_includes, HdlProvider, PortDirection};
+use crate::simulator::{Bus, Chip, Simulator};
+
+const INPUTS: [&str; 3] = ["instruction", "inM", "reset"];
+const OUTPUTS: [&str; 4] = ["outM", "writeM", "addressM", "pc"];
+
+/// Widest `addressM` that can be run. All of data memory is kept, so this
+/// limits it to 16M words.
+const MAX_ADDRESS_WIDTH: usize = 24;
+
+/// Final state of a program that halted.
+#[derive(Debug)]
+pub struct RunResult {
+    /// Number of instructions executed before the halt was detected. For a
+    /// program that ends in a loop, this includes the few passes through
+    /// the loop needed to see that it repeats.
+    pub cycles: usize,
+    pub pc: u64,
+    /// Data memory, indexed by address, as signed words.
+    pub ram: Vec<i64>,
+}
+
+impl fmt::Display for RunResult {
+    /// Prints the cycle count, the final `pc`, and every memory word that
+    /// is not zero.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Halted after {} cycles at pc {}.", self.cycles, self.pc)?;
+        write_ram(f, &self.ram)
+    }
+}
+
+// Prints every memory word that is not zero, one per line.
+fn write_ram(f: &mut fmt::Formatter, ram: &[i64]) -> fmt::Result {
+    for (address, value) in ram.iter().enumerate() {
+        if *value != 0 {
+            writeln!(f, "RAM[{}] = {}", address, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the program in the memory image `program_path` on the CPU chip in
+/// `cpu_path`, starting with every register and memory word 0. Fails if
+/// the program has not halted after `max_cycles` cycles.
+pub fn run_program(
+    cpu_path: &Path,
+    program_path: &Path,
+    include_paths: &[PathBuf],
+    max_cycles: usize,
+) -> Result<RunResult, Box<dyn Error>> {
+    let (hdl, provider) = parse_hdl_path_with_includes(cpu_path, include_paths)?;
+    let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+    let rom = load_memory_image(program_path)?;
+    let chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())?;
+    let mut simulator = Simulator::new(chip);
+
+    let run_error = |msg: String| -> Box<dyn Error> {
+        Box::new(N2VError {
+            msg,
+            kind: ErrorKind::SimulationError(hdl.path.clone()),
+        })
+    };
+    let ports: Vec<String> = INPUTS
+        .iter()
+        .chain(&OUTPUTS)
+        .map(|p| p.to_string())
+        .collect();
+    for port in &ports {
+        if simulator.port_width(port).is_none() {
+            return Err(run_error(format!(
+                "Chip {} has no {} port, so it can't run Hack programs.",
+                hdl.name, port
+            )));
+        }
+    }
+    let instruction_width = simulator.port_width("instruction").unwrap();
+    if let Some(word) = rom.iter().find(|w| w.len() != instruction_width) {
+        return Err(run_error(format!(
+            "Program words have {} bits but instruction has {}.",
+            word.len(),
+            instruction_width
+        )));
+    }
+    let address_width = simulator.port_width("addressM").unwrap();
+    if address_width > MAX_ADDRESS_WIDTH {
+        return Err(run_error(format!(
+            "addressM is {} bits wide, but it can be at most {} bits wide.",
+            address_width, MAX_ADDRESS_WIDTH
+        )));
+    }
+    let mut ram = vec![0; 1 << address_width];
+
+    let mut inputs = BusMap::new();
+    for (name, width) in simulator.input_ports() {
+        inputs.create_bus(&name, width)?;
+        inputs.insert(Bus::from(name), vec![false; width]);
+    }
+    simulator.simulate(&inputs)?;
+
+    // The pc and state at the start of the last two cycles, and whether
+    // memory was written during them. The state is only recorded when pc
+    // repeats, because it is expensive to compare.
+    let mut history: Vec<(u64, Option<serde_json::Value>, bool)> = Vec::new();
+    for cycle in 0..=max_cycles {
+        let state = simulator
+            .chip
+            .get_port_values_for_direction(PortDirection::Out);
+        let unknown = |port: &str| {
+            run_error(format!(
+                "Output {} of {} is unknown in cycle {}.",
+                port, hdl.name, cycle
+            ))
+        };
+        let pc = state.as_unsigned("pc").ok_or_else(|| unknown("pc"))?;
+        let address = state
+            .as_unsigned("addressM")
+            .ok_or_else(|| unknown("addressM"))?;
+
+        let halted = if pc as usize >= rom.len() {
+            true
+        } else if history.iter().any(|(p, _, _)| *p == pc) {
+            let snapshot = simulator.snapshot();
+            let halted = history
+                .iter()
+                .rev()
+                .take_while(|(_, _, written)| !written)
+                .any(|(p, s, _)| *p == pc && s.as_ref() == Some(&snapshot));
+            history.push((pc, Some(snapshot), false));
+            halted
+        } else {
+            history.push((pc, None, false));
+            false
+        };
+        if halted {
+            return Ok(RunResult {
+                cycles: cycle,
+                pc,
+                ram,
+            });
+        }
+        if cycle == max_cycles {
+            break;
+        }
+        if history.len() > 2 {
+            history.remove(0);
+        }
+
+        inputs.insert(Bus::from("instruction"), rom[pc as usize].clone());
+        inputs.set_integer("inM", ram[address as usize])?;
+        let outputs = simulator.simulate(&inputs)?;
+        match outputs.as_unsigned("writeM") {
+            Some(0) => {}
+            Some(_) => {
+                let value = outputs.as_signed("outM").ok_or_else(|| unknown("outM"))?;
+                ram[address as usize] = value;
+                history.last_mut().unwrap().2 = true;
+            }
+            None => return Err(unknown("writeM")),
+        }
+        simulator.tick()?;
+    }
+
+    Err(run_error(format!(
+        "Program did not halt within {} cycles.",
+        max_cycles
+    )))
+}
+
+/// Data memory of a computer after it has run.
+#[derive(Debug)]
+pub struct ComputerResult {
+    pub cycles: usize,
+    /// Words of the RAM part, indexed by address, as signed words. Empty
+    /// if no RAM part was read.
+    pub ram: Vec<i64>,
+}
+
+impl fmt::Display for ComputerResult {
+    /// Prints the cycle count and every memory word that is not zero.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Ran for {} cycles.", self.cycles)?;
+        write_ram(f, &self.ram)
+    }
+}
+
+/// Loads the program in the memory image `program_path` into the part
+/// `rom` of the computer chip in `computer_path` and runs it for `cycles`
+/// cycles with every input 0. Parts are given as paths such as
+/// `Memory.0/RAM16K.0`. If `ram` names a part, its DFFs are read back as
+/// words as wide as the program's. Any memory part can be read this way,
+/// such as the screen, but only one per run.
+pub fn run_computer(
+    computer_path: &Path,
+    program_path: &Path,
+    include_paths: &[PathBuf],
+    rom: &str,
+    ram: Option<&str>,
+    cycles: usize,
+) -> Result<ComputerResult, Box<dyn Error>> {
+    let (hdl, provider) = parse_hdl_path_with_includes(computer_path, include_paths)?;
+    let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+    let program = load_memory_image(program_path)?;
+    let chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())?;
+    let mut simulator = Simulator::new(chip);
+    let rom: Vec<&str> = rom.split('/').collect();
+    simulator.load_words(&rom, &program)?;
+
+    let mut inputs = BusMap::new();
+    for (name, width) in simulator.input_ports() {
+        inputs.create_bus(&name, width)?;
+        inputs.insert(Bus::from(name), vec![false; width]);
+    }
+    for _ in 0..cycles {
+        simulator.simulate(&inputs)?;
+        simulator.tick()?;
+    }
+
+    let Some(ram) = ram else {
+        return Ok(ComputerResult {
+            cycles,
+            ram: Vec::new(),
+        });
+    };
+    let path: Vec<&str> = ram.split('/').collect();
+    let width = program.first().map_or(16, |w| w.len());
+    let mut words = Vec::new();
+    for (address, bits) in simulator.read_words(&path, width)?.into_iter().enumerate() {
+        let mut value = BusMap::new();
+        value.create_bus("word", width)?;
+        value.insert_option(&Bus::from("word"), bits);
+        let value = value.as_signed("word").ok_or_else(|| {
+            Box::new(N2VError {
+                msg: format!("RAM[{}] is unknown after {} cycles.", address, cycles),
+                kind: ErrorKind::SimulationError(hdl.path.clone()),
+            }) as Box<dyn Error>
+        })?;
+        words.push(value);
+    }
+    Ok(ComputerResult { cycles, ram: words })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn resource(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/tests")
+            .join(path)
+    }
+
+    #[test]
+    fn test_run_program() {
+        let cpu = resource("nand2tetris/solutions/CPU.hdl");
+
+        // Falls off the end of the program.
+        let res = run_program(&cpu, &resource("memory/Add.hack"), &[], 100).unwrap();
+        assert_eq!(res.cycles, 6);
+        assert_eq!(res.ram[0], 5);
+
+        // Ends in an infinite loop.
+        let res = run_program(&cpu, &resource("run/Sum.hack"), &[], 1000).unwrap();
+        assert_eq!(res.pc, 14);
+        assert_eq!(&res.ram[0..2], &[15, 0]);
+        assert!(res.to_string().contains("RAM[0] = 15\n"));
+
+        let err = run_program(&cpu, &resource("run/Sum.hack"), &[], 20).unwrap_err();
+        assert!(err.to_string().contains("did not halt within 20 cycles"));
+
+        let wide = resource("run/WideCPU.hdl");
+        let include = [resource("nand2tetris/solutions")];
+        let err = run_program(&wide, &resource("run/Sum.hack"), &include, 20).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("addressM is 40 bits wide, but it can be at most 24"));
+    }
+
+    // The program is loaded into the registers of a ROM made of RAM, and
+    // its result is read back from the registers of the data memory.
+    #[test]
+    fn test_run_computer() {
+        let computer = resource("computer/Computer.hdl");
+        let include = [resource("nand2tetris/solutions")];
+        let program = resource("memory/Add.hack");
+
+        let res = run_computer(&computer, &program, &include, "ROM8.0", Some("RAM8.0"), 6).unwrap();
+        assert_eq!(res.ram.len(), 8);
+        assert_eq!(res.ram[0], 5);
+        assert_eq!(res.to_string(), "Ran for 6 cycles.\nRAM[0] = 5\n");
+
+        // The last instruction, M=D, has not been executed yet.
+        let res = run_computer(&computer, &program, &include, "ROM8.0", Some("RAM8.0"), 5).unwrap();
+        assert_eq!(res.ram[0], 0);
+
+        let err =
+            run_computer(&computer, &program, &include, "RAM8.0/Register.0", None, 1).unwrap_err();
+        assert!(err.to_string().contains("can't hold 6 words"));
+    }
+}
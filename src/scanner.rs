@@ -18,17 +18,26 @@ pub enum TokenType {
     Colon,
     In,
     Out,
+    InOut,
     Comma,
     Parts,
+    Builtin,
+    Const,
     Number,
     Equal,
     Dot,
     Invalid,
     For,
     To,
+    Step,
     Generate,
+    Assert,
     Plus,
     Minus,
+    Star,
+    Slash,
+    Percent,
+    Ampersand,
     Eof,
 }
 
@@ -49,17 +58,26 @@ impl std::fmt::Display for TokenType {
             TokenType::Colon => write!(f, "a colon `:`"),
             TokenType::In => write!(f, "the `IN` keyword (all caps)"),
             TokenType::Out => write!(f, "the `OUT` keyword (all caps)"),
+            TokenType::InOut => write!(f, "the `INOUT` keyword (all caps)"),
             TokenType::Comma => write!(f, "a comma `,`"),
             TokenType::Parts => write!(f, "the `PARTS` keyword (all caps)"),
-            TokenType::Number => write!(f, "a number such as `2` or `16`."),
+            TokenType::Builtin => write!(f, "the `BUILTIN` keyword (all caps)"),
+            TokenType::Const => write!(f, "the `CONST` keyword (all caps)"),
+            TokenType::Number => write!(f, "a number such as `2`, `16` or `0xF`."),
             TokenType::Equal => write!(f, "an equal sign `=`"),
             TokenType::Dot => write!(f, "a dot `.`"),
             TokenType::Invalid => write!(f, "INVALID TOKEN SOMETHING BAD HERE BE DRAGONS"),
             TokenType::For => write!(f, "the `FOR` keyword (all caps)"),
             TokenType::To => write!(f, "the `TO` keyword (all caps)"),
+            TokenType::Step => write!(f, "the `STEP` keyword (all caps)"),
             TokenType::Generate => write!(f, "the `GENERATE` keyword (all caps)"),
+            TokenType::Assert => write!(f, "the `ASSERT` keyword (all caps)"),
             TokenType::Plus => write!(f, "a plus sign `+`"),
             TokenType::Minus => write!(f, "a minus sign `-`"),
+            TokenType::Star => write!(f, "an asterisk `*`"),
+            TokenType::Slash => write!(f, "a slash `/`"),
+            TokenType::Percent => write!(f, "a percent sign `%`"),
+            TokenType::Ampersand => write!(f, "an ampersand `&`"),
             TokenType::Eof => write!(f, "the end of the file `EOF`"),
         }
     }
@@ -70,32 +88,69 @@ pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: u32,
+    /// Column of the first character of the token, counting from 1.
     pub start: usize,
+    /// Column just past the last character of the token.
+    pub end: usize,
     pub path: PathBuf,
+    /// Base a number was written in: 2 for `0b`, 16 for `0x` and otherwise
+    /// 10. The lexeme of a number with a prefix is its decimal value.
+    pub radix: u32,
+}
+
+/// A comment kept by a scanner with `keep_comments` set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comment {
+    /// Comment text without the comment markers, or the leading `*` of each
+    /// line of a block comment.
+    pub text: String,
+    pub line: u32,
+    pub end_line: u32,
+}
+
+/// A `//!` comment kept by a scanner with `keep_directives` set, such as
+/// `//! ASSERT a=1 b=1 => out=1`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Directive {
+    /// Comment text after the `//!`.
+    pub text: String,
+    pub line: u32,
 }
 
 pub struct Scanner<'a> {
     source_chars: std::iter::Peekable<Chars<'a>>,
     pub line: u32,
+    /// Column of the next character, counting from 1.
     pub col: usize,
     keywords: HashMap<&'a str, TokenType>,
     peeked: Option<Token>,
     pub path: PathBuf,
+    /// Record comments in `comments` instead of discarding them.
+    pub keep_comments: bool,
+    pub comments: Vec<Comment>,
+    /// Record `//!` comments in `directives`.
+    pub keep_directives: bool,
+    pub directives: Vec<Directive>,
 }
 
 impl<'a> Scanner<'a> {
-    pub fn new(source_code: &str, source_path: PathBuf) -> Scanner {
+    pub fn new(source_code: &str, source_path: PathBuf) -> Scanner<'_> {
         let source_chars = source_code.chars().peekable();
 
         // Keywords are case-insensitive
         let keywords = HashMap::from([
             ("CHIP", TokenType::Chip),
             ("PARTS", TokenType::Parts),
+            ("BUILTIN", TokenType::Builtin),
+            ("CONST", TokenType::Const),
             ("IN", TokenType::In),
             ("OUT", TokenType::Out),
+            ("INOUT", TokenType::InOut),
             ("FOR", TokenType::For),
             ("TO", TokenType::To),
+            ("STEP", TokenType::Step),
             ("GENERATE", TokenType::Generate),
+            ("ASSERT", TokenType::Assert),
         ]);
 
         Scanner {
@@ -105,6 +160,10 @@ impl<'a> Scanner<'a> {
             keywords,
             peeked: None,
             path: source_path,
+            keep_comments: false,
+            comments: Vec::new(),
+            keep_directives: false,
+            directives: Vec::new(),
         }
     }
 
@@ -119,163 +178,71 @@ impl<'a> Scanner<'a> {
         let mut token: Option<Token> = None;
 
         while token.is_none() && self.source_chars.peek().is_some() {
-            self.col += 1;
-            token = match self.source_chars.next() {
+            let start = self.col;
+            token = match self.advance() {
                 None => None,
                 Some(c) => match c {
-                    '{' => Some(Token {
-                        token_type: TokenType::LeftCurly,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    '}' => Some(Token {
-                        token_type: TokenType::RightCurly,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    '(' => Some(Token {
-                        token_type: TokenType::LeftParen,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    ')' => Some(Token {
-                        token_type: TokenType::RightParen,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    ';' => Some(Token {
-                        token_type: TokenType::Semicolon,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    ':' => Some(Token {
-                        token_type: TokenType::Colon,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    ',' => Some(Token {
-                        token_type: TokenType::Comma,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    '[' => Some(Token {
-                        token_type: TokenType::LeftBracket,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    ']' => Some(Token {
-                        token_type: TokenType::RightBracket,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    '<' => Some(Token {
-                        token_type: TokenType::LeftAngle,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    '>' => Some(Token {
-                        token_type: TokenType::RightAngle,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    '=' => Some(Token {
-                        token_type: TokenType::Equal,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    '.' => Some(Token {
-                        token_type: TokenType::Dot,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    '+' => Some(Token {
-                        token_type: TokenType::Plus,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    '-' => Some(Token {
-                        token_type: TokenType::Minus,
-                        lexeme: c.to_string(),
-                        line: self.line,
-                        start: self.col,
-                        path: self.path.clone(),
-                    }),
-                    '\n' => {
-                        self.line += 1;
-                        self.col = 0;
-                        None
-                    }
-                    ' ' | '\t' | '\r' => None,
+                    '{' => Some(self.token(TokenType::LeftCurly, c.to_string(), start)),
+                    '}' => Some(self.token(TokenType::RightCurly, c.to_string(), start)),
+                    '(' => Some(self.token(TokenType::LeftParen, c.to_string(), start)),
+                    ')' => Some(self.token(TokenType::RightParen, c.to_string(), start)),
+                    ';' => Some(self.token(TokenType::Semicolon, c.to_string(), start)),
+                    ':' => Some(self.token(TokenType::Colon, c.to_string(), start)),
+                    ',' => Some(self.token(TokenType::Comma, c.to_string(), start)),
+                    '[' => Some(self.token(TokenType::LeftBracket, c.to_string(), start)),
+                    ']' => Some(self.token(TokenType::RightBracket, c.to_string(), start)),
+                    '<' => Some(self.token(TokenType::LeftAngle, c.to_string(), start)),
+                    '>' => Some(self.token(TokenType::RightAngle, c.to_string(), start)),
+                    '=' => Some(self.token(TokenType::Equal, c.to_string(), start)),
+                    '.' => Some(self.token(TokenType::Dot, c.to_string(), start)),
+                    '+' => Some(self.token(TokenType::Plus, c.to_string(), start)),
+                    '-' => Some(self.token(TokenType::Minus, c.to_string(), start)),
+                    '*' => Some(self.token(TokenType::Star, c.to_string(), start)),
+                    '%' => Some(self.token(TokenType::Percent, c.to_string(), start)),
+                    '&' => Some(self.token(TokenType::Ampersand, c.to_string(), start)),
+                    ' ' | '\t' | '\r' | '\n' => None,
                     '/' => {
+                        let slash = self.token(TokenType::Slash, c.to_string(), start);
                         let followup: char = match self.source_chars.peek() {
-                            None => {
-                                return Some(Token {
-                                    lexeme: c.to_string(),
-                                    line: self.line,
-                                    start: self.col,
-                                    path: self.path.clone(),
-                                    token_type: TokenType::Invalid,
-                                });
-                            }
+                            None => return Some(slash),
                             Some(c2) => *c2,
                         };
 
-                        if followup == '/' {
-                            self.finish_single_comment();
+                        let line = self.line;
+                        let text = if followup == '/' {
+                            self.finish_single_comment()
                         } else if followup == '*' {
-                            self.finish_multi_comment();
+                            self.finish_multi_comment()
                         } else {
-                            return Some(Token {
-                                lexeme: c.to_string(),
-                                line: self.line,
-                                start: self.col,
-                                path: self.path.clone(),
-                                token_type: TokenType::Invalid,
+                            return Some(slash);
+                        };
+                        if let Some(directive) = text.strip_prefix('!') {
+                            if self.keep_directives && followup == '/' {
+                                self.directives.push(Directive {
+                                    text: String::from(directive.trim()),
+                                    line,
+                                });
+                            }
+                        }
+                        if self.keep_comments {
+                            // A single line comment ends past its newline.
+                            let end_line = if followup == '/' { line } else { self.line };
+                            self.comments.push(Comment {
+                                text,
+                                line,
+                                end_line,
                             });
                         }
                         None
                     }
+                    '`' | '\\' => Some(self.finish_quoted_identifier(c, start)),
                     _ => {
                         if c.is_alphabetic() || c == '_' {
-                            Some(self.finish_identifier(c))
+                            Some(self.finish_identifier(c, start))
                         } else if c.is_numeric() {
-                            Some(self.finish_number(c))
+                            Some(self.finish_number(c, start))
                         } else {
-                            Some(Token {
-                                lexeme: c.to_string(),
-                                line: self.line,
-                                start: self.col,
-                                path: self.path.clone(),
-                                token_type: TokenType::Invalid,
-                            })
+                            Some(self.token(TokenType::Invalid, c.to_string(), start))
                         }
                     }
                 },
@@ -284,100 +251,157 @@ impl<'a> Scanner<'a> {
         token
     }
 
-    fn finish_single_comment(&mut self) {
+    // Consumes the next character, moving `line` and `col` past it.
+    fn advance(&mut self) -> Option<char> {
+        let c = self.source_chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    // A token on the current line that starts at column `start` and ends
+    // just before the next character.
+    fn token(&self, token_type: TokenType, lexeme: String, start: usize) -> Token {
+        Token {
+            token_type,
+            lexeme,
+            line: self.line,
+            start,
+            end: self.col,
+            path: self.path.clone(),
+            radix: 10,
+        }
+    }
+
+    // Consumes a `//` comment and returns its text.
+    fn finish_single_comment(&mut self) -> String {
+        let mut text = String::new();
         loop {
-            let next = self.source_chars.next();
-            match next {
-                None => break,
-                Some('\n') => {
-                    self.line += 1;
-                    self.col = 0;
-                    break;
-                }
-                _ => {}
+            match self.advance() {
+                None | Some('\n') => break,
+                Some(c) => text.push(c),
             }
         }
+        String::from(text.trim_start_matches('/').trim())
     }
 
-    fn finish_multi_comment(&mut self) {
+    // Consumes a `/* */` comment and returns its text.
+    fn finish_multi_comment(&mut self) -> String {
+        let mut text = String::new();
         loop {
-            let next = self.source_chars.next();
-            self.col += 1;
-
-            match next {
+            match self.advance() {
                 None => {
                     break;
                 }
-                Some('\n') => {
-                    self.line += 1;
-                    self.col = 0;
-                }
                 Some('*') => match self.source_chars.peek() {
                     None => {
                         break;
                     }
                     Some('/') => {
-                        self.source_chars.next();
+                        self.advance();
                         break;
                     }
-                    _ => {}
+                    _ => text.push('*'),
                 },
-                _ => {}
+                Some(c) => text.push(c),
             }
         }
+
+        let lines: Vec<&str> = text
+            .lines()
+            .map(|l| l.trim().trim_start_matches('*').trim())
+            .collect();
+        lines.join("\n").trim().to_string()
     }
 
-    fn finish_number(&mut self, start: char) -> Token {
-        let mut lexeme = start.to_string();
+    // Scans a decimal number, or a hexadecimal or binary number with a `0x`
+    // or `0b` prefix. The lexeme of a hexadecimal or binary number is its
+    // value in decimal, unless it is too large for a u64. A prefix with no
+    // digits after it is an invalid token.
+    fn finish_number(&mut self, first: char, start: usize) -> Token {
+        let mut lexeme = first.to_string();
+        let radix = match (first, self.source_chars.peek()) {
+            ('0', Some('x' | 'X')) => 16,
+            ('0', Some('b' | 'B')) => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            lexeme.push(self.advance().unwrap());
+        }
 
         while let Some(c) = self.source_chars.peek() {
-            if c.is_numeric() {
+            if c.is_digit(radix) {
                 lexeme.push(*c);
-                self.source_chars.next();
-                self.col += 1;
+                self.advance();
             } else {
                 break;
             }
         }
 
+        if radix == 10 {
+            return self.token(TokenType::Number, lexeme, start);
+        }
+        if lexeme.len() == 2 {
+            return self.token(TokenType::Invalid, lexeme, start);
+        }
+        let lexeme = match u64::from_str_radix(&lexeme[2..], radix) {
+            Ok(value) => value.to_string(),
+            Err(_) => lexeme,
+        };
         Token {
-            token_type: TokenType::Number,
-            lexeme,
-            line: self.line,
-            start: self.col,
-            path: self.path.clone(),
+            radix,
+            ..self.token(TokenType::Number, lexeme, start)
         }
     }
 
-    fn finish_identifier(&mut self, start: char) -> Token {
-        let mut lexeme = start.to_string();
+    fn finish_identifier(&mut self, first: char, start: usize) -> Token {
+        let mut lexeme = first.to_string();
 
         while let Some(c) = self.source_chars.peek() {
             if c.is_alphanumeric() || c == &'_' {
                 lexeme.push(*c);
-                self.source_chars.next();
-                self.col += 1;
+                self.advance();
             } else {
                 break;
             }
         }
 
-        self.lexeme_to_identifier_or_keyword(lexeme, self.line, self.col)
-    }
-
-    fn lexeme_to_identifier_or_keyword(&self, lexeme: String, line: u32, col: usize) -> Token {
         let token_type = match self.keywords.get(lexeme.as_str()) {
             None => TokenType::Identifier,
             Some(t) => *t,
         };
+        self.token(token_type, lexeme, start)
+    }
 
-        Token {
-            token_type,
-            lexeme,
-            line,
-            start: col,
-            path: self.path.clone(),
+    // Scans `` `name` `` or `\name\`. The text between the delimiters is the
+    // identifier, even if it is a keyword. An empty or unterminated quoted
+    // identifier is an invalid token.
+    fn finish_quoted_identifier(&mut self, delimiter: char, start: usize) -> Token {
+        let mut lexeme = String::new();
+        let mut closed = false;
+        while let Some(&c) = self.source_chars.peek() {
+            if c == '\n' {
+                break;
+            }
+            self.advance();
+            if c == delimiter {
+                closed = true;
+                break;
+            }
+            lexeme.push(c);
         }
+
+        let token_type = if closed && !lexeme.is_empty() {
+            TokenType::Identifier
+        } else {
+            lexeme.insert(0, delimiter);
+            TokenType::Invalid
+        };
+        self.token(token_type, lexeme, start)
     }
 }
 
@@ -619,4 +643,101 @@ mod test {
 
         assert_eq!(expected_types, actual_types);
     }
+
+    #[test]
+    fn test_quoted_identifiers() {
+        let scanner = Scanner::new("IN `IN`, \\FOR\\, `a b`;", PathBuf::from(""));
+        let tokens: Vec<(TokenType, String)> = scanner.map(|t| (t.token_type, t.lexeme)).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenType::In, String::from("IN")),
+                (TokenType::Identifier, String::from("IN")),
+                (TokenType::Comma, String::from(",")),
+                (TokenType::Identifier, String::from("FOR")),
+                (TokenType::Comma, String::from(",")),
+                (TokenType::Identifier, String::from("a b")),
+                (TokenType::Semicolon, String::from(";")),
+            ]
+        );
+
+        // Empty and unterminated quoted identifiers are invalid.
+        let mut scanner = Scanner::new("``", PathBuf::from(""));
+        assert_eq!(scanner.next().unwrap().token_type, TokenType::Invalid);
+        let mut scanner = Scanner::new("`in\nout", PathBuf::from(""));
+        let token = scanner.next().unwrap();
+        assert_eq!(
+            (token.token_type, token.lexeme),
+            (TokenType::Invalid, String::from("`in"))
+        );
+        assert_eq!(scanner.next().unwrap().lexeme, "out");
+    }
+
+    // Each token has the line and columns of its first character and the
+    // character after it, counted from 1, after comments and tabs.
+    #[test]
+    fn test_token_columns() {
+        let source = "CHIP Not {\n\tIN in; /* a\ncomment */ OUT `out`;\n  \
+                      Nand(a=in,b=in, out=out);";
+        let scanner = Scanner::new(source, PathBuf::from(""));
+        let spans: Vec<(String, u32, usize, usize)> = scanner
+            .map(|t| (t.lexeme, t.line, t.start, t.end))
+            .collect();
+        let expected = [
+            ("CHIP", 1, 1, 5),
+            ("Not", 1, 6, 9),
+            ("{", 1, 10, 11),
+            ("IN", 2, 2, 4),
+            ("in", 2, 5, 7),
+            (";", 2, 7, 8),
+            ("OUT", 3, 12, 15),
+            ("out", 3, 16, 21),
+            (";", 3, 21, 22),
+            ("Nand", 4, 3, 7),
+            ("(", 4, 7, 8),
+            ("a", 4, 8, 9),
+            ("=", 4, 9, 10),
+            ("in", 4, 10, 12),
+            (",", 4, 12, 13),
+            ("b", 4, 13, 14),
+            ("=", 4, 14, 15),
+            ("in", 4, 15, 17),
+            (",", 4, 17, 18),
+            ("out", 4, 19, 22),
+            ("=", 4, 22, 23),
+            ("out", 4, 23, 26),
+            (")", 4, 26, 27),
+            (";", 4, 27, 28),
+        ];
+        let expected: Vec<(String, u32, usize, usize)> = expected
+            .iter()
+            .map(|(l, line, start, end)| (String::from(*l), *line, *start, *end))
+            .collect();
+        assert_eq!(spans, expected);
+    }
+
+    // Hexadecimal and binary numbers are numbers with decimal lexemes, and
+    // keep the base they were written in.
+    #[test]
+    fn test_radix_numbers() {
+        let scanner = Scanner::new("0xF 0Xff 0b1010 0B1 010 0x 0b2", PathBuf::from(""));
+        let tokens: Vec<(TokenType, String, usize, u32)> = scanner
+            .map(|t| (t.token_type, t.lexeme, t.end, t.radix))
+            .collect();
+        let expected = [
+            (TokenType::Number, "15", 4, 16),
+            (TokenType::Number, "255", 9, 16),
+            (TokenType::Number, "10", 16, 2),
+            (TokenType::Number, "1", 20, 2),
+            (TokenType::Number, "010", 24, 10),
+            (TokenType::Invalid, "0x", 27, 10),
+            (TokenType::Invalid, "0b", 30, 10),
+            (TokenType::Number, "2", 31, 10),
+        ];
+        let expected: Vec<(TokenType, String, usize, u32)> = expected
+            .iter()
+            .map(|(t, l, end, radix)| (*t, String::from(*l), *end, *radix))
+            .collect();
+        assert_eq!(tokens, expected);
+    }
 }
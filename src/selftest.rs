@@ -0,0 +1,285 @@
+//! Test cases written next to a chip.
+//!
+//! A comment such as `//! ASSERT a=1 b=1 => out=1` in an HDL file is a test
+//! case. `selftest` sets the inputs on the left of `=>`, simulates the chip,
+//! and compares the outputs on the right. Inputs that are not named are 0.
+//! Values are decimal, or binary with a `%B` prefix as in test scripts.
+//! Other `//!` comments are ignored.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::busmap::{parse_binary, BusMap};
+use crate::error::{ErrorKind, N2VError};
+use crate::parser::{FileReader, HdlProvider, Parser};
+use crate::scanner::{Directive, Scanner};
+use crate::simulator::{Bus, Chip, Simulator};
+
+/// An `ASSERT` directive.
+struct Assertion {
+    line: u32,
+    // Port names and values as written.
+    inputs: Vec<(String, String)>,
+    outputs: Vec<(String, String)>,
+}
+
+/// An assertion whose simulated outputs differ from the expected outputs.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AssertionFailure {
+    pub line: u32,
+    /// Output port, expected value, and simulated value of each output that
+    /// differs.
+    pub mismatches: Vec<(String, String, String)>,
+}
+
+impl fmt::Display for AssertionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let expected: Vec<String> = self
+            .mismatches
+            .iter()
+            .map(|(port, expected, _)| format!("{}={}", port, expected))
+            .collect();
+        let actual: Vec<String> = self
+            .mismatches
+            .iter()
+            .map(|(port, _, actual)| format!("{}={}", port, actual))
+            .collect();
+        write!(
+            f,
+            "Line {}: expected {}, got {}",
+            self.line,
+            expected.join(" "),
+            actual.join(" ")
+        )
+    }
+}
+
+/// Outcome of running the assertions of a chip.
+pub struct SelftestResult {
+    pub passed: usize,
+    pub failures: Vec<AssertionFailure>,
+}
+
+/// Runs every `ASSERT` directive in the HDL file `hdl_path`. Each assertion
+/// starts from a reset chip, so sequential chips do not carry state from one
+/// assertion to the next.
+pub fn selftest(
+    hdl_path: &Path,
+    include_paths: &[PathBuf],
+) -> Result<SelftestResult, Box<dyn Error>> {
+    let source_code = fs::read_to_string(hdl_path)?;
+    let mut scanner = Scanner::new(&source_code, hdl_path.to_path_buf());
+    scanner.keep_directives = true;
+    let base_path = hdl_path.parent().unwrap();
+    let provider: Rc<dyn HdlProvider> =
+        Rc::new(FileReader::with_includes(base_path, include_paths));
+    let hdl = Parser::new(&mut scanner, provider.clone()).parse()?;
+
+    let mut assertions = Vec::new();
+    for directive in &scanner.directives {
+        let assertion = parse_assertion(directive)
+            .map_err(|msg| directive_error(hdl_path, directive.line, &msg))?;
+        assertions.extend(assertion);
+    }
+
+    let chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())?;
+    let mut simulator = Simulator::new(chip);
+    let mut res = SelftestResult {
+        passed: 0,
+        failures: Vec::new(),
+    };
+    for assertion in assertions {
+        let error = |msg: String| directive_error(hdl_path, assertion.line, &msg);
+
+        let mut inputs = BusMap::new();
+        for (name, width) in simulator.input_ports() {
+            inputs.create_bus(&name, width)?;
+            inputs.insert(Bus::from(name), vec![false; width]);
+        }
+        for (name, value) in &assertion.inputs {
+            if !simulator.input_ports().iter().any(|(port, _)| port == name) {
+                return Err(error(format!(
+                    "{} is not an input port of {}.",
+                    name, hdl.name
+                )));
+            }
+            set_value(&mut inputs, name, value).map_err(error)?;
+        }
+
+        simulator.reset();
+        let outputs = simulator.simulate(&inputs)?;
+
+        let mut mismatches = Vec::new();
+        for (name, value) in &assertion.outputs {
+            let width = match simulator
+                .output_ports()
+                .into_iter()
+                .find(|(p, _)| p == name)
+            {
+                Some((_, width)) => width,
+                None => {
+                    return Err(error(format!(
+                        "{} is not an output port of {}.",
+                        name, hdl.name
+                    )))
+                }
+            };
+            let mut expected = BusMap::new();
+            expected.create_bus(name, width)?;
+            set_value(&mut expected, name, value).map_err(error)?;
+            if outputs.get_name(name) != expected.get_name(name) {
+                let actual = format_value(&outputs, name, value);
+                mismatches.push((name.clone(), value.clone(), actual));
+            }
+        }
+
+        if mismatches.is_empty() {
+            res.passed += 1;
+        } else {
+            res.failures.push(AssertionFailure {
+                line: assertion.line,
+                mismatches,
+            });
+        }
+    }
+    Ok(res)
+}
+
+fn directive_error(hdl_path: &Path, line: u32, msg: &str) -> Box<dyn Error> {
+    Box::new(N2VError {
+        msg: format!("{}:{}: {}", hdl_path.display(), line, msg),
+        kind: ErrorKind::Other,
+    })
+}
+
+// Parses `ASSERT a=1 b=1 => out=1`. Returns None for other directives.
+fn parse_assertion(directive: &Directive) -> Result<Option<Assertion>, String> {
+    // `ASSERTION` and other words that start with ASSERT are not assertions.
+    let text = match directive.text.strip_prefix("ASSERT") {
+        Some(text) if text.is_empty() || text.starts_with(char::is_whitespace) => text,
+        _ => return Ok(None),
+    };
+    let (inputs, outputs) = match text.split_once("=>") {
+        Some(sides) => sides,
+        None => return Err(String::from("Expected `=>` between inputs and outputs.")),
+    };
+
+    let assignments = |side: &str| -> Result<Vec<(String, String)>, String> {
+        side.split_whitespace()
+            .map(|term| match term.split_once('=') {
+                Some((name, value)) if !name.is_empty() && !value.is_empty() => {
+                    Ok((String::from(name), String::from(value)))
+                }
+                _ => Err(format!("Expected port=value, found `{}`.", term)),
+            })
+            .collect()
+    };
+    let outputs = assignments(outputs)?;
+    if outputs.is_empty() {
+        return Err(String::from("Expected at least one output after `=>`."));
+    }
+
+    Ok(Some(Assertion {
+        line: directive.line,
+        inputs: assignments(inputs)?,
+        outputs,
+    }))
+}
+
+// Sets an existing bus from a decimal value or a `%B` binary value.
+fn set_value(bus_map: &mut BusMap, name: &str, value: &str) -> Result<(), String> {
+    let width = bus_map.get_width(name).unwrap_or(0);
+    let bits = match value.strip_prefix("%B") {
+        Some(bits) => bits,
+        None => {
+            let value = value
+                .parse::<i64>()
+                .map_err(|_| format!("{} is not a valid value for {}.", value, name))?;
+            return bus_map.set_integer(name, value);
+        }
+    };
+    let values = parse_binary(bits, width).ok_or_else(|| {
+        format!(
+            "%B{} is not a valid {}-bit value for {}.",
+            bits, width, name
+        )
+    })?;
+    bus_map.insert(Bus::from(name), values);
+    Ok(())
+}
+
+// Formats a simulated value the way the expected value was written.
+fn format_value(bus_map: &BusMap, name: &str, like: &str) -> String {
+    let value = if like.starts_with("%B") {
+        bus_map.as_binary(name).map(|b| format!("%B{}", b))
+    } else if like.starts_with('-') {
+        bus_map.as_signed(name).map(|v| v.to_string())
+    } else {
+        bus_map.as_unsigned(name).map(|v| v.to_string())
+    };
+    value.unwrap_or_else(|| String::from("?"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn tests_dir() -> PathBuf {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        manifest_dir
+            .join("resources")
+            .join("tests")
+            .join("selftest")
+    }
+
+    #[test]
+    fn test_selftest_pass() {
+        let res = selftest(&tests_dir().join("And.hdl"), &[]).expect("Selftest error");
+        assert_eq!(res.passed, 4);
+        assert!(res.failures.is_empty());
+    }
+
+    #[test]
+    fn test_selftest_failure() {
+        let res = selftest(&tests_dir().join("Not2.hdl"), &[]).expect("Selftest error");
+        assert_eq!(res.passed, 2);
+        assert_eq!(
+            res.failures,
+            vec![AssertionFailure {
+                line: 3,
+                mismatches: vec![(String::from("out"), String::from("1"), String::from("2"))],
+            }]
+        );
+        assert_eq!(
+            res.failures[0].to_string(),
+            "Line 3: expected out=1, got out=2"
+        );
+    }
+
+    #[test]
+    fn test_parse_assertion() {
+        let directive = |text: &str| Directive {
+            text: String::from(text),
+            line: 1,
+        };
+        assert!(parse_assertion(&directive("Not a test")).unwrap().is_none());
+        assert!(parse_assertion(&directive("ASSERTION a=1 => out=1"))
+            .unwrap()
+            .is_none());
+        let assertion = parse_assertion(&directive("ASSERT a=1 b=%B1 => out=1"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(assertion.inputs.len(), 2);
+        assert_eq!(
+            assertion.outputs,
+            vec![(String::from("out"), String::from("1"))]
+        );
+        assert!(parse_assertion(&directive("ASSERT a=1 out=1")).is_err());
+        assert!(parse_assertion(&directive("ASSERT a => out=1")).is_err());
+    }
+}
@@ -1,7 +1,11 @@
-use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::ops::Range;
+use std::path::Path;
 use std::rc::Rc;
 
 use petgraph::algo::kosaraju_scc;
@@ -11,10 +15,12 @@ use petgraph::Graph;
 use serde::ser::{SerializeStruct, Serializer};
 use serde::Serialize;
 
-use crate::busmap::BusMap;
+use crate::busmap::{format_bits, parse_binary, BusMap, Radix};
 use crate::error::{ErrorKind, N2VError};
 use crate::expr::*;
 use crate::parser::*;
+use crate::profile::{Phase, Profile};
+use crate::vcd::VcdWriter;
 
 /// The main graph connecting components of a chip together.
 /// Each chip is a component such as And, Or, Not, Nand.
@@ -24,9 +30,19 @@ type Circuit = Graph<Chip, Wire>;
 /// Outputs of chips are cached for given inputs.
 type Cache = HashMap<InputCacheEntry, BusMap>;
 
-trait TryMap {
-    fn try_map();
-}
+/// Gate counts of chips by name and generic arguments.
+type GateCounts = HashMap<(String, Vec<(String, usize)>), GateCount>;
+
+/// The node and output bus that drive each bit of a chip's signals, by
+/// signal name. None is a bit with no source.
+type SignalSources = HashMap<String, Vec<Option<(NodeIndex, Bus)>>>;
+
+/// Rust implementation of a `BUILTIN` chip. It is given the values of the
+/// chip's input ports and returns the values of its output ports.
+pub type Builtin = Box<dyn Fn(&BusMap) -> BusMap>;
+
+/// Builtin chip implementations by the name given after `BUILTIN` in HDL.
+pub type Builtins = HashMap<String, Builtin>;
 
 #[derive(Serialize, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Bus {
@@ -34,69 +50,475 @@ pub struct Bus {
     pub range: Option<Range<usize>>,
 }
 
+impl fmt::Display for Bus {
+    /// Prints the bus as it is written in HDL, with an inclusive range.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.range {
+            Some(r) if r.len() == 1 => write!(f, "{}[{}]", self.name, r.start),
+            Some(r) if !r.is_empty() => write!(f, "{}[{}..{}]", self.name, r.start, r.end - 1),
+            _ => write!(f, "{}", self.name),
+        }
+    }
+}
+
 #[derive(Hash, Eq, PartialEq)]
 /// Stores the results of a chip for a given set a of inputs.
 /// Used to avoid recalculating the same results over and over again.
 pub struct InputCacheEntry {
     name: String,
-    signals: BusMap,
+    // Sorted by signal name, so equal inputs give equal entries no matter
+    // how the BusMap they came from was built.
+    signals: Vec<(String, Vec<Option<bool>>)>,
+}
+
+impl InputCacheEntry {
+    fn new(name: &str, inputs: &BusMap) -> InputCacheEntry {
+        let mut signals: Vec<(String, Vec<Option<bool>>)> = inputs
+            .keys()
+            .into_iter()
+            .map(|k| {
+                let bits = inputs.get_name(&k);
+                (k, bits)
+            })
+            .collect();
+        signals.sort();
+        InputCacheEntry {
+            name: String::from(name),
+            signals,
+        }
+    }
+}
+
+/// How often chip outputs were found in the input cache.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Chips whose outputs were taken from the cache.
+    pub hits: usize,
+    /// Chips that were not in the cache and had to be simulated.
+    pub misses: usize,
+    /// Distinct chip inputs stored in the cache.
+    pub entries: usize,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lookups = self.hits + self.misses;
+        let rate = match lookups {
+            0 => 0.0,
+            _ => 100.0 * self.hits as f64 / lookups as f64,
+        };
+        write!(
+            f,
+            "Cache: {} hits, {} misses ({:.1}% hit rate), {} entries",
+            self.hits, self.misses, rate, self.entries
+        )
+    }
 }
 
 pub struct Simulator {
     pub input_cache: Cache,
+    // Hits and misses since the simulator was created or reset.
+    cache_stats: CacheStats,
     pub dirty_dffs: Vec<*mut Chip>,
     pub chip: Chip,
+
+    /// Maximum number of passes over a chip's components before giving up
+    /// on the chip settling. Defaults to four times the number of
+    /// components in each chip.
+    pub settle_iterations: Option<usize>,
+
+    pub builtins: Builtins,
+
+    // Waveform being recorded, and the number of ticks so far.
+    vcd: Option<VcdWriter>,
+    time: u64,
+    // Number of ticks after which `tick` fails, if any.
+    max_cycles: Option<u64>,
 }
 
 impl Simulator {
     pub fn new(chip: Chip) -> Simulator {
         Simulator {
             input_cache: HashMap::new(),
+            cache_stats: CacheStats::default(),
             dirty_dffs: Vec::new(),
             chip,
+            settle_iterations: None,
+            builtins: HashMap::new(),
+            vcd: None,
+            time: 0,
+            max_cycles: None,
+        }
+    }
+
+    /// Starts recording a waveform of every port and the chip's internal
+    /// signals named in `signals`. The values are recorded after each call
+    /// to `simulate` and `tick`, and time advances by one unit per tick.
+    ///
+    /// ```ignore
+    /// let mut simulator = Simulator::new(chip); // Bit
+    /// simulator.record_vcd(&[String::from("dffOut")])?;
+    /// simulator.simulate(&inputs)?;
+    /// simulator.tick()?;
+    /// simulator.write_vcd(Path::new("Bit.vcd"))?;
+    /// ```
+    pub fn record_vcd(&mut self, signals: &[String]) -> Result<(), Box<dyn Error>> {
+        if !self.chip.elaborated {
+            self.chip.elaborate()?;
+        }
+        let mut vars: Vec<(String, usize)> = self
+            .chip
+            .ports
+            .iter()
+            .map(|(name, p)| (name.clone(), p.width))
+            .collect();
+        vars.sort();
+        for name in signals {
+            match self.chip.signal_width(name) {
+                Some(width) => vars.push((name.clone(), width)),
+                None => {
+                    return Err(Box::new(N2VError {
+                        msg: format!("Chip {} has no signal named {}.", self.chip.name, name),
+                        kind: ErrorKind::Other,
+                    }))
+                }
+            }
+        }
+        self.vcd = Some(VcdWriter::new(&self.chip.name, vars));
+        Ok(())
+    }
+
+    /// Writes the waveform recorded since `record_vcd` to `path`.
+    pub fn write_vcd(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let vcd = self.vcd.as_ref().ok_or_else(|| N2VError {
+            msg: String::from("No waveform is being recorded."),
+            kind: ErrorKind::Other,
+        })?;
+        let mut file = BufWriter::new(File::create(path)?);
+        vcd.write(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    // Adds the current signal values to the waveform, if one is recorded.
+    fn sample_vcd(&mut self) {
+        let vcd = match &mut self.vcd {
+            Some(vcd) => vcd,
+            None => return,
+        };
+        let outputs = self.chip.get_port_values();
+        let values = vcd
+            .vars()
+            .iter()
+            .map(|(name, _)| match outputs.get_width(name) {
+                Some(_) => outputs.get_name(name),
+                None => self.chip.signals.get_name(name),
+            })
+            .collect();
+        vcd.sample(self.time, values);
+    }
+
+    /// Registers the Rust implementation used to simulate `BUILTIN name;`
+    /// chips.
+    #[allow(dead_code)] // Only used by tests.
+    pub fn register_builtin(&mut self, name: &str, builtin: Builtin) {
+        self.builtins.insert(String::from(name), builtin);
+    }
+
+    /// Cache hits and misses since the simulator was created or reset, and
+    /// the current number of cache entries.
+    ///
+    /// ```ignore
+    /// let mut simulator = Simulator::new(chip); // Xor
+    /// simulator.simulate(&inputs)?;
+    /// simulator.simulate(&inputs)?;
+    /// assert!(simulator.cache_stats().hits > 0);
+    /// ```
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.input_cache.len(),
+            ..self.cache_stats
+        }
+    }
+
+    /// Names and widths of the chip's input ports, sorted by name.
+    ///
+    /// ```ignore
+    /// let simulator = Simulator::new(chip); // Mux16
+    /// assert_eq!(
+    ///     simulator.input_ports(),
+    ///     vec![(String::from("a"), 16), (String::from("b"), 16), (String::from("sel"), 1)]
+    /// );
+    /// ```
+    pub fn input_ports(&self) -> Vec<(String, usize)> {
+        self.ports_for_direction(PortDirection::is_input)
+    }
+
+    /// Names and widths of the chip's output ports, sorted by name.
+    ///
+    /// ```ignore
+    /// let simulator = Simulator::new(chip); // Mux16
+    /// assert_eq!(simulator.output_ports(), vec![(String::from("out"), 16)]);
+    /// ```
+    pub fn output_ports(&self) -> Vec<(String, usize)> {
+        self.ports_for_direction(PortDirection::is_output)
+    }
+
+    /// Values of every bus in the design, nested by chip, as JSON. Node ids
+    /// match the ids of `CircuitView`.
+    ///
+    /// ```ignore
+    /// let mut simulator = Simulator::new(chip); // And
+    /// simulator.simulate(&inputs)?;
+    /// let snapshot = simulator.snapshot();
+    /// assert_eq!(snapshot["signals"]["out"], serde_json::json!([true]));
+    /// ```
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::to_value(ChipSnapshot::from(&self.chip)).unwrap()
+    }
+
+    /// Width of the port `name`, or None if the chip has no such port.
+    ///
+    /// ```ignore
+    /// let simulator = Simulator::new(chip); // Mux16
+    /// assert_eq!(simulator.port_width("sel"), Some(1));
+    /// assert_eq!(simulator.port_width("c"), None);
+    /// ```
+    pub fn port_width(&self, name: &str) -> Option<usize> {
+        self.chip.ports.get(name).map(|p| p.width)
+    }
+
+    /// Value of an internal signal after simulation. `path` names parts
+    /// from the top-level chip down and ends with a signal of the last
+    /// part. A part is named by its chip and its number among the parts
+    /// using that chip, such as `FullAdder.1` for the second FullAdder.
+    /// Returns None if there is no such signal, or if a part on the path
+    /// was not elaborated because its outputs came from the simulation
+    /// cache; `elaborate_path` avoids that.
+    ///
+    /// ```ignore
+    /// let mut simulator = Simulator::new(chip); // Add16
+    /// simulator.elaborate_path(&["FullAdder.1"])?;
+    /// simulator.simulate(&inputs)?;
+    /// let carry = simulator.probe(&["FullAdder.1", "carry"]);
+    /// ```
+    pub fn probe(&self, path: &[&str]) -> Option<Vec<Option<bool>>> {
+        let (signal, parts) = path.split_last()?;
+        let mut chip = &self.chip;
+        for part in parts {
+            chip = chip.circuit.node_weights().find(|c| c.instance == *part)?;
+        }
+        chip.signal_value(signal)
+    }
+
+    /// Elaborates the chip and the parts named by `path`, from the
+    /// top-level chip down, so that they are fully simulated and their
+    /// signals can be read with `probe`.
+    pub fn elaborate_path(&mut self, path: &[&str]) -> Result<(), Box<dyn Error>> {
+        let mut chip = &mut self.chip;
+        for part in path {
+            if !chip.elaborated {
+                chip.elaborate()?;
+            }
+            let idx = chip
+                .circuit
+                .node_indices()
+                .find(|i| chip.circuit[*i].instance == *part);
+            chip = match idx {
+                Some(idx) => &mut chip.circuit[idx],
+                None => {
+                    let parts: Vec<&str> = chip
+                        .circuit
+                        .node_weights()
+                        .map(|c| c.instance.as_str())
+                        .filter(|i| !i.is_empty())
+                        .collect();
+                    return Err(Box::new(N2VError {
+                        msg: format!(
+                            "Chip {} has no part named {}. Its parts are: {}",
+                            chip.name,
+                            part,
+                            parts.join(", ")
+                        ),
+                        kind: ErrorKind::Other,
+                    }));
+                }
+            };
+        }
+        // Primitive and builtin chips have no parts to elaborate.
+        let primitive = chip.hdl.as_ref().is_none_or(|h| h.builtin.is_some());
+        if !chip.elaborated && !primitive {
+            chip.elaborate()?;
+        }
+        Ok(())
+    }
+
+    /// Writes `words` into the DFFs of the part at `path`, such as a ROM
+    /// built from registers, so that it holds them before the next
+    /// simulation. An empty path loads the top-level chip.
+    ///
+    /// Each word fills the next DFFs starting with its least significant
+    /// bit. DFFs are taken from parts in the order they appear in the HDL,
+    /// depth first, which puts each word in its own register for memory
+    /// chips built like the nand2tetris RAM chips. Parts using different
+    /// chips are taken in order of chip name. DFFs after the last word are
+    /// left as they are.
+    ///
+    /// ```ignore
+    /// let mut simulator = Simulator::new(chip); // Computer
+    /// simulator.load_words(&["ROM8.0"], &load_memory_image(path)?)?;
+    /// ```
+    pub fn load_words(&mut self, path: &[&str], words: &[Vec<bool>]) -> Result<(), Box<dyn Error>> {
+        let part = self.part_mut(path)?;
+        let mut dff_count = 0;
+        part.visit_dffs(&mut |_| dff_count += 1)?;
+        let bit_count: usize = words.iter().map(|w| w.len()).sum();
+        if bit_count > dff_count {
+            return Err(Box::new(N2VError {
+                msg: format!(
+                    "Part {} has {} DFFs, which can't hold {} words of {} bits.",
+                    part.instance,
+                    dff_count,
+                    words.len(),
+                    words.first().map_or(0, |w| w.len())
+                ),
+                kind: ErrorKind::Other,
+            }));
+        }
+
+        let mut bits = words.iter().flat_map(|w| w.iter().rev());
+        part.visit_dffs(&mut |dff| {
+            if let Some(&bit) = bits.next() {
+                for name in ["in", "out"] {
+                    dff.signals.insert_option(&Bus::from(name), vec![Some(bit)]);
+                }
+            }
+        })?;
+        self.input_cache.clear();
+        Ok(())
+    }
+
+    /// Reads the DFFs of the part at `path` as words of `width` bits, in
+    /// the order `load_words` writes them. Bits left over after the last
+    /// full word are ignored.
+    pub fn read_words(
+        &mut self,
+        path: &[&str],
+        width: usize,
+    ) -> Result<Vec<Vec<Option<bool>>>, Box<dyn Error>> {
+        let mut bits = Vec::new();
+        self.part_mut(path)?
+            .visit_dffs(&mut |dff| bits.push(dff.signals.get_name("out")[0]))?;
+        if width == 0 {
+            return Ok(Vec::new());
+        }
+        let words = bits
+            .chunks_exact(width)
+            .map(|w| w.iter().rev().cloned().collect());
+        Ok(words.collect())
+    }
+
+    // The part at `path`, elaborated. The chips on the way to it are marked
+    // dirty and uncached, as their outputs depend on its state.
+    fn part_mut(&mut self, path: &[&str]) -> Result<&mut Chip, Box<dyn Error>> {
+        self.elaborate_path(path)?;
+        let mut chip = &mut self.chip;
+        for part in path {
+            chip.dirty = true;
+            chip.cache = false;
+            chip = chip
+                .circuit
+                .node_weights_mut()
+                .find(|c| c.instance == *part)
+                .unwrap();
         }
+        Ok(chip)
     }
 
+    // Bidirectional ports are listed as both inputs and outputs.
+    fn ports_for_direction(&self, keep: fn(&PortDirection) -> bool) -> Vec<(String, usize)> {
+        let mut ports: Vec<(String, usize)> = self
+            .chip
+            .ports
+            .iter()
+            .filter(|(_, p)| keep(&p.direction))
+            .map(|(name, p)| (name.clone(), p.width))
+            .collect();
+        ports.sort();
+        ports
+    }
+
+    /// Computes the chip's outputs for `inputs`, then checks the `ASSERT`
+    /// statements of the chip and of its parts. A failed assertion is an
+    /// error. Parts whose outputs come from the simulation cache are not
+    /// checked again, as the cached outputs were computed with the same
+    /// inputs.
     pub fn simulate(&mut self, inputs: &BusMap) -> Result<BusMap, Box<dyn Error>> {
         let ports = self.chip.ports.clone();
         for (port_name, port) in ports {
-            if port.direction == PortDirection::Out {
+            if !port.direction.is_input() {
                 continue;
             }
 
-            let bus_idx = Bus {
-                name: port_name.clone(),
-                range: Some(0..port.width),
-            };
-            let port_input = inputs.get_bus(&bus_idx);
-            self.chip.signals.insert_option(&bus_idx, port_input)
+            let port_input = inputs.get_range(&port_name, 0..port.width);
+            self.chip
+                .signals
+                .insert_option(&Bus::from(port_name), port_input)
         }
 
         self.chip.dirty = true;
-        self.chip
-            .compute(&mut self.input_cache, &mut self.dirty_dffs)?;
+        self.chip.compute(
+            &mut self.input_cache,
+            &mut self.cache_stats,
+            &mut self.dirty_dffs,
+            self.settle_iterations,
+            &self.builtins,
+        )?;
+        self.chip.check_assertions("")?;
 
+        self.sample_vcd();
         Ok(self.chip.get_port_values())
     }
 
+    /// Returns the chip to its initial state, as if it had just been
+    /// constructed, so it can be reused for an independent test.
+    pub fn reset(&mut self) {
+        self.chip.reset_state();
+        self.input_cache.clear();
+        self.cache_stats = CacheStats::default();
+        self.dirty_dffs.clear();
+    }
+
+    /// Number of clock cycles since the simulator was created. Unlike the
+    /// cache statistics, this is not cleared by `reset`.
+    pub fn cycles(&self) -> u64 {
+        self.time
+    }
+
+    /// Makes `tick` fail once the clock has ticked `max` times, to stop
+    /// sequential chips that never finish.
+    pub fn set_max_cycles(&mut self, max: u64) {
+        self.max_cycles = Some(max);
+    }
+
     // Tick advances the clock without changing the inputs to the chip.
     pub fn tick(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(max) = self.max_cycles.filter(|max| self.time >= *max) {
+            return Err(Box::new(N2VError {
+                msg: format!("Chip {} ran for more than {} cycles.", self.chip.name, max),
+                kind: ErrorKind::SimulationError(
+                    self.chip.hdl.as_ref().and_then(|h| h.path.clone()),
+                ),
+            }));
+        }
         let dffs_this_tick = self.dirty_dffs.clone();
         self.dirty_dffs.clear();
         let mut parents = Vec::new();
         for dff_ref in dffs_this_tick {
             let dff = unsafe { dff_ref.as_mut().unwrap() };
 
-            dff.signals.insert_option(
-                &Bus {
-                    name: String::from("out"),
-                    range: Some(0..1),
-                },
-                dff.signals.get_bus(&Bus {
-                    name: String::from("in"),
-                    range: Some(0..1),
-                }),
-            );
+            dff.signals
+                .insert_option(&Bus::from("out"), dff.signals.get_name("in"));
             dff.dirty = true;
 
             // chase parents up to the top level chip
@@ -115,11 +537,122 @@ impl Simulator {
         }
 
         for parent_chip in parents {
-            parent_chip.compute(&mut self.input_cache, &mut self.dirty_dffs)?;
+            parent_chip.compute(
+                &mut self.input_cache,
+                &mut self.cache_stats,
+                &mut self.dirty_dffs,
+                self.settle_iterations,
+                &self.builtins,
+            )?;
         }
 
+        self.time += 1;
+        self.sample_vcd();
         Ok(())
     }
+
+    /// Drives the chip with a waveform given as CSV and returns the outputs
+    /// for each row. The header names input ports. Columns are decimal
+    /// unless the port name ends in `%B`, for binary. Each row is simulated
+    /// and then the clock ticks. Ports without a column and empty cells
+    /// hold their last value, starting from 0.
+    ///
+    /// ```ignore
+    /// let mut simulator = Simulator::new(chip); // Register
+    /// let outputs = simulator.drive_csv("in,load\n5,1\n0,0\n")?;
+    /// assert_eq!(outputs[1].as_binary("out"), Some(String::from("0000000000000101")));
+    /// ```
+    #[allow(dead_code)] // Only used by tests.
+    pub fn drive_csv(&mut self, csv: &str) -> Result<Vec<BusMap>, Box<dyn Error>> {
+        let csv_error = |msg: String| -> Box<dyn Error> {
+            Box::new(N2VError {
+                msg,
+                kind: ErrorKind::Other,
+            })
+        };
+
+        let mut lines = csv
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty());
+        let header = match lines.next() {
+            Some((_, header)) => header,
+            None => return Ok(Vec::new()),
+        };
+
+        // Port name, width, and whether the column is binary.
+        let mut columns = Vec::new();
+        for column in header.split(',').map(str::trim) {
+            let (name, binary) = match column.strip_suffix("%B") {
+                Some(name) => (name, true),
+                None => (column, false),
+            };
+            match self.chip.ports.get(name) {
+                Some(port) if port.direction.is_input() => {
+                    columns.push((name, port.width, binary));
+                }
+                _ => {
+                    return Err(csv_error(format!(
+                        "CSV column {} is not an input port of {}.",
+                        name, self.chip.name
+                    )))
+                }
+            }
+        }
+
+        let mut inputs = BusMap::new();
+        for (name, width) in self.input_ports() {
+            inputs.create_bus(&name, width)?;
+            inputs.insert(Bus::from(name), vec![false; width]);
+        }
+
+        let mut outputs = Vec::new();
+        for (line_idx, line) in lines {
+            let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+            if cells.len() > columns.len() {
+                return Err(csv_error(format!(
+                    "Line {} of CSV has {} values but there are {} columns.",
+                    line_idx + 1,
+                    cells.len(),
+                    columns.len()
+                )));
+            }
+
+            for (&(name, width, binary), cell) in columns.iter().zip(cells) {
+                if cell.is_empty() {
+                    continue;
+                }
+                let bad_value = || {
+                    csv_error(format!(
+                        "Line {} of CSV: {} is not a valid {}-bit {} value for {}.",
+                        line_idx + 1,
+                        cell,
+                        width,
+                        if binary { "binary" } else { "decimal" },
+                        name
+                    ))
+                };
+                let bits = if binary {
+                    parse_binary(cell, width).ok_or_else(bad_value)?
+                } else {
+                    let value = cell.parse::<u64>().map_err(|_| bad_value())?;
+                    let value =
+                        BusMap::from_integers(&[(name, value, width)]).map_err(|_| bad_value())?;
+                    value
+                        .get_bus(&Bus::from(name))
+                        .into_iter()
+                        .flatten()
+                        .collect()
+                };
+                inputs.insert(Bus::from(name), bits);
+            }
+
+            outputs.push(self.simulate(&inputs)?);
+            self.tick()?;
+        }
+
+        Ok(outputs)
+    }
 }
 
 impl Serialize for Chip {
@@ -134,6 +667,122 @@ impl Serialize for Chip {
     }
 }
 
+/// Serializable view of a chip's circuit graph. Node ids are indices into
+/// `nodes` and edges refer to nodes by id.
+#[derive(Serialize)]
+#[allow(dead_code)] // Used by the web interface in lib.rs.
+pub struct CircuitView {
+    pub nodes: Vec<NodeView>,
+    pub edges: Vec<EdgeView>,
+}
+
+#[derive(Serialize)]
+pub struct NodeView {
+    pub id: usize,
+    pub name: String,
+    /// Sorted by name.
+    pub ports: Vec<PortView>,
+}
+
+#[derive(Serialize)]
+pub struct PortView {
+    pub name: String,
+    pub width: usize,
+    pub direction: PortDirection,
+}
+
+/// A wire from the `source_bus` of node `from` to the `target_bus` of
+/// node `to`.
+#[derive(Serialize)]
+pub struct EdgeView {
+    pub from: usize,
+    pub to: usize,
+    pub source_bus: Bus,
+    pub target_bus: Bus,
+}
+
+impl From<&Chip> for CircuitView {
+    fn from(chip: &Chip) -> Self {
+        let nodes = chip
+            .circuit
+            .node_indices()
+            .map(|idx| {
+                let component = &chip.circuit[idx];
+                let mut ports: Vec<PortView> = component
+                    .ports
+                    .values()
+                    .map(|p| PortView {
+                        name: p.name.value.clone(),
+                        width: p.width,
+                        direction: p.direction,
+                    })
+                    .collect();
+                ports.sort_by(|a, b| a.name.cmp(&b.name));
+                NodeView {
+                    id: idx.index(),
+                    name: component.name.clone(),
+                    ports,
+                }
+            })
+            .collect();
+
+        let edges = chip
+            .circuit
+            .edge_references()
+            .map(|e| EdgeView {
+                from: e.source().index(),
+                to: e.target().index(),
+                source_bus: e.weight().source.clone(),
+                target_bus: e.weight().target.clone(),
+            })
+            .collect();
+
+        CircuitView { nodes, edges }
+    }
+}
+
+/// Values of every bus of a chip and of the chips inside it, as recorded by
+/// the last simulation.
+#[derive(Serialize)]
+pub struct ChipSnapshot {
+    /// Index of the node in the parent chip's circuit. None for the
+    /// top-level chip.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<usize>,
+    pub name: String,
+    /// Bits of each bus, most significant first. None is a bit with no value.
+    pub signals: BTreeMap<String, Vec<Option<bool>>>,
+    /// Empty for primitive chips and chips that were never elaborated.
+    pub nodes: Vec<ChipSnapshot>,
+}
+
+impl From<&Chip> for ChipSnapshot {
+    fn from(chip: &Chip) -> Self {
+        let signals = chip
+            .signal_names()
+            .into_iter()
+            .map(|name| {
+                let bits = chip.signals.get_name(&name);
+                (name, bits)
+            })
+            .collect();
+        let nodes = chip
+            .circuit
+            .node_indices()
+            .map(|idx| ChipSnapshot {
+                id: Some(idx.index()),
+                ..ChipSnapshot::from(&chip.circuit[idx])
+            })
+            .collect();
+        ChipSnapshot {
+            id: None,
+            name: chip.name.clone(),
+            signals,
+            nodes,
+        }
+    }
+}
+
 // Same as HDL port, but with generic widths resolved.
 #[derive(Clone, Serialize)]
 pub struct Port {
@@ -143,21 +792,37 @@ pub struct Port {
 }
 
 pub struct Assignment {
+    /// The assigned bits, which may be a sub-range such as `out[0]`.
     pub left: Bus,
-    pub right: Bus,
+    /// Segments of the right side, most significant first.
+    pub right: Vec<Bus>,
     pub width: usize,
+    /// Width of the right side. Less than `width` only for extended assignments.
+    pub right_width: usize,
+    pub extension: Extension,
+}
+
+/// Number of primitive gates in a chip and every chip inside it.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct GateCount {
+    pub nand: usize,
+    pub dff: usize,
 }
 
 // A chip constructed from parsed HDL.
 pub struct Chip {
     pub name: String,
+    /// Name of this part in the parent chip, such as `Not.2`, used to find
+    /// it with `Simulator::probe`. Empty for the top-level chip and for the
+    /// ports, literals, and resolvers added during elaboration.
+    pub instance: String,
     pub hdl: Option<ChipHDL>, // This should probably be a reference. We don't need to have a zillion copies of the HDL.
     pub circuit: Circuit,
     pub ports: HashMap<String, Port>,
-    input_port_nodes: Vec<NodeIndex>,
-    output_port_nodes: Vec<NodeIndex>,
+    pub(crate) input_port_nodes: Vec<NodeIndex>,
+    pub(crate) output_port_nodes: Vec<NodeIndex>,
     pub signals: BusMap,
-    elaborated: bool,
+    pub(crate) elaborated: bool,
     parent: *mut Chip,
     pub components: Vec<Component>, // Constructed from HDL parts which may contain for-generate loops.
 
@@ -173,6 +838,44 @@ pub struct Chip {
     // Values of variables (generics and iterators)
     variables: HashMap<String, usize>,
     assignments: Vec<Assignment>,
+
+    // Where each bit of the internal signals comes from once elaborated,
+    // used to read their values.
+    signal_sources: SignalSources,
+
+    // Shared by every chip in the design when profiling is enabled.
+    profile: Option<Rc<RefCell<Profile>>>,
+
+    /// Record output port bits that have no source in `undriven` instead of
+    /// failing elaboration. Components inherit this from their parent.
+    pub allow_undriven_outputs: bool,
+
+    /// Like `allow_undriven_outputs`, but only for this chip's own output
+    /// ports. Components do not inherit it, so their undriven outputs are
+    /// still errors.
+    pub allow_undriven_ports: bool,
+
+    // Output port bits with no source, such as `out[3]`, found when the chip
+    // was elaborated with `allow_undriven_outputs` or `allow_undriven_ports`
+    // set.
+    undriven: Vec<String>,
+
+    /// Bits of bidirectional ports that nothing in this chip drives, so that
+    /// synthesis can release them with 'Z'. Set when the chip is elaborated.
+    pub released: Vec<Bus>,
+
+    /// Check the widths of components with `check_strict_widths` before
+    /// building them. Components inherit this from their parent.
+    pub strict_widths: bool,
+
+    /// Merge parallel wires with `optimize_circuit` after elaborating. Turn
+    /// this off to see the graph as it was built from the parts.
+    /// Components inherit this from their parent.
+    pub optimize: bool,
+
+    // Names of the chips that contain this one, from the top-level chip
+    // down. Used to reject chips that contain themselves.
+    ancestors: Rc<Vec<String>>,
 }
 
 impl fmt::Debug for Chip {
@@ -197,7 +900,45 @@ impl Chip {
         parent: *mut Chip,
         hdl_provider: &Rc<dyn HdlProvider>,
         elaborate: bool,
-        generics: &Vec<usize>, // generic args when this chip is being created.
+        generics: &[usize], // generic args when this chip is being created.
+    ) -> Result<Chip, Box<dyn Error>> {
+        Self::new_with_profile(hdl, parent, hdl_provider, elaborate, generics, None)
+    }
+
+    /// Constructs a Chip from the parse tree, recording construction and
+    /// elaboration times for this chip and its components in `profile`.
+    pub fn new_with_profile(
+        hdl: &ChipHDL,
+        parent: *mut Chip,
+        hdl_provider: &Rc<dyn HdlProvider>,
+        elaborate: bool,
+        generics: &[usize],
+        profile: Option<Rc<RefCell<Profile>>>,
+    ) -> Result<Chip, Box<dyn Error>> {
+        let p = match profile {
+            None => return Self::construct(hdl, parent, hdl_provider, elaborate, generics, None),
+            Some(p) => p,
+        };
+        let start = p.borrow_mut().start();
+        let chip = Self::construct(
+            hdl,
+            parent,
+            hdl_provider,
+            elaborate,
+            generics,
+            Some(p.clone()),
+        );
+        p.borrow_mut().finish(&hdl.name, Phase::Construct, start);
+        chip
+    }
+
+    fn construct(
+        hdl: &ChipHDL,
+        parent: *mut Chip,
+        hdl_provider: &Rc<dyn HdlProvider>,
+        elaborate: bool,
+        generics: &[usize],
+        profile: Option<Rc<RefCell<Profile>>>,
     ) -> Result<Chip, Box<dyn Error>> {
         let circuit = Circuit::new();
 
@@ -208,18 +949,8 @@ impl Chip {
         }
 
         // Assign values to generic variables.
-        if generics.len() != hdl.generic_decls.len() {
-            return Err(Box::new(N2VError {
-                msg: format!(
-                    "Chip {} declares {} generics but instantiated with {}",
-                    hdl.name,
-                    hdl.generic_decls.len(),
-                    generics.len()
-                ),
-                kind: ErrorKind::SimulationError(hdl.path.clone()),
-            }));
-        }
-        let mut variables = HashMap::new();
+        check_generic_count(hdl, generics, false)?;
+        let mut variables = hdl.constant_values();
 
         #[allow(clippy::needless_range_loop)]
         for gv in 0..hdl.generic_decls.len() {
@@ -240,6 +971,9 @@ impl Chip {
                     kind: ErrorKind::ParseIdentError(hdl_provider.clone(), port.name.clone()),
                 }));
             }
+            if port.direction == PortDirection::InOut {
+                signals.create_bus(&drive_signal_name(&port.name.value), width)?;
+            }
         }
 
         // Create component definitions (expand for-generate loops).
@@ -256,10 +990,11 @@ impl Chip {
             &components,
             hdl_provider,
             &general_generics,
+            false,
         )?;
 
         let generated_assignments =
-            Self::generate_assignments(&inferred_widths, assignments, &variables)?;
+            Self::generate_assignments(&inferred_widths, assignments, &variables, hdl_provider)?;
 
         // Create disconnected internal signals.
         // These are connected below.
@@ -277,6 +1012,8 @@ impl Chip {
                         kind: ErrorKind::SimulationError(hdl.path.clone()),
                     }));
                 }
+            } else if let Some(var) = undefined_variable(&iw, &variables) {
+                return Err(undefined_generic_error(hdl_provider, var, &n));
             } else {
                 return Err(Box::new(N2VError {
                     msg: {
@@ -308,6 +1045,7 @@ impl Chip {
 
         let mut chip = Chip {
             name: hdl.name.clone(),
+            instance: String::new(),
             ports,
             signals,
             hdl: Some(hdl.clone()),
@@ -322,6 +1060,15 @@ impl Chip {
             variables,
             components,
             assignments: generated_assignments,
+            signal_sources: HashMap::new(),
+            profile,
+            allow_undriven_outputs: false,
+            allow_undriven_ports: false,
+            undriven: Vec::new(),
+            released: Vec::new(),
+            strict_widths: false,
+            optimize: true,
+            ancestors: Rc::new(Vec::new()),
         };
 
         if elaborate {
@@ -334,25 +1081,12 @@ impl Chip {
     // This expands for-generate loops into components for the chip. This
     // cannot be done during parsing because the values of generic variables
     // may not be known until elaboration.
-    fn generate_components(
-        hdl: &ChipHDL,
-        generics: &Vec<usize>,
-    ) -> Result<Vec<Component>, N2VError> {
+    fn generate_components(hdl: &ChipHDL, generics: &[usize]) -> Result<Vec<Component>, N2VError> {
         let mut res = Vec::new();
 
         // Assign values to generic variables.
-        if generics.len() != hdl.generic_decls.len() {
-            return Err(N2VError {
-                msg: format!(
-                    "Chip {} declares {} generics but instantiated with {}",
-                    hdl.name,
-                    hdl.generic_decls.len(),
-                    generics.len()
-                ),
-                kind: ErrorKind::SimulationError(hdl.path.clone()),
-            });
-        }
-        let mut variables = HashMap::new();
+        check_generic_count(hdl, generics, false)?;
+        let mut variables = hdl.constant_values();
 
         #[allow(clippy::needless_range_loop)]
         for gv in 0..hdl.generic_decls.len() {
@@ -365,11 +1099,8 @@ impl Chip {
                     res.push(c.clone());
                 }
                 Part::Loop(l) => {
-                    let start = eval_expr_numeric(&l.start, &variables)?;
-                    let end = eval_expr_numeric(&l.end, &variables)?;
-
                     // Replace any instances of iterator with current iterator value.
-                    for i in start..(end + 1) {
+                    for i in l.values(&variables)? {
                         let replace = |w: &GenericWidth| -> GenericWidth {
                             replace_expr(
                                 w,
@@ -395,53 +1126,225 @@ impl Chip {
                     }
                 }
                 Part::AssignmentHDL(_a) => {} // ignore assignments for now
+                Part::Assert(_) => {}
             }
         }
 
         Ok(res)
     }
 
-    fn elaborate(&mut self) -> Result<(), Box<dyn Error>> {
-        let self_ptr = self as *mut Chip;
-        self.elaborated = true;
-        if self.hdl.is_none() {
-            return Ok(());
-        }
-
-        // Where each bit of the signal source comes from.
-        let mut signal_sources: HashMap<String, Vec<Option<(NodeIndex, Bus)>>> = HashMap::new();
+    /// Names of the chip's signals, including its ports, sorted by name.
+    /// Signals the parser made for concatenations and the values the chip
+    /// drives onto bidirectional ports are left out.
+    pub fn signal_names(&self) -> Vec<String> {
+        let mut names = self.signals.keys();
+        names.retain(|name| !self.is_hidden_signal(name));
+        names
+    }
 
-        // create input components
-        for (port_name, port) in &self.ports {
-            if port.direction == PortDirection::Out {
-                continue;
-            }
-            let port_chip = make_port_chip(port_name, port.width, self_ptr, &self.hdl_provider);
-            let port_node = self.circuit.add_node(port_chip);
-            self.input_port_nodes.push(port_node);
+    // Signals that are not named in the HDL, such as the ones the parser
+    // made for concatenations mapped to ports, and the drive signals of
+    // bidirectional ports.
+    fn is_hidden_signal(&self, name: &str) -> bool {
+        let inout = self
+            .ports
+            .iter()
+            .filter(|(_, p)| p.direction == PortDirection::InOut);
+        if inout.map(|(n, _)| drive_signal_name(n)).any(|n| n == name) {
+            return true;
+        }
+        let mut parts = self.hdl.iter().flat_map(|hdl| &hdl.parts);
+        parts.any(|part| match part {
+            Part::AssignmentHDL(a) => a.port.is_some() && a.left.name == name,
+            _ => false,
+        })
+    }
 
-            let mut source = Vec::new();
-            for i in 0..port.width {
-                let source_bus = Bus {
-                    name: String::from("in"),
-                    range: Some(i..i + 1),
-                };
-                source.push(Some((port_node, source_bus)));
-            }
+    /// Width of the signal `name`, or None if the chip has no such signal.
+    pub fn signal_width(&self, name: &str) -> Option<usize> {
+        self.signals.get_width(name)
+    }
 
-            signal_sources.insert(port.name.value.clone(), source);
+    /// Output port bits with no source in this chip and in the parts that
+    /// have been elaborated, when they were allowed by
+    /// `allow_undriven_outputs` or `allow_undriven_ports`. Bits of a part
+    /// are prefixed with its path, such as `Undriven.0/out[1]`.
+    pub fn undriven_outputs(&self) -> Vec<String> {
+        let mut res = self.undriven.clone();
+        for part in self.circuit.node_weights() {
+            let bits = part.undriven_outputs();
+            res.extend(bits.iter().map(|b| format!("{}/{}", part.instance, b)));
         }
+        res
+    }
 
-        let mut need_true_literal = false;
-        let mut need_false_literal = false;
+    /// Builds the circuit graph of this chip from its parts, one level
+    /// deep. Simulation does this on demand, so it is only needed to look at
+    /// the graph of a chip created with `elaborate` false.
+    pub fn elaborate(&mut self) -> Result<(), Box<dyn Error>> {
+        let p = match self.profile.clone() {
+            None => return self.elaborate_circuit(),
+            Some(p) => p,
+        };
+        let start = p.borrow_mut().start();
+        let res = self.elaborate_circuit();
+        p.borrow_mut().finish(&self.name, Phase::Elaborate, start);
+        res
+    }
+
+    /// Writes the circuit of an elaborated chip as a Graphviz graph, with a
+    /// node for each component and an edge for each wire. Input ports are
+    /// drawn as `invhouse`, output ports as `house`, and true, false and
+    /// none literals as plain text. Edges are labeled with the buses they
+    /// connect, such as `out[3..7] -> in[0..4]`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = format!("digraph \"{}\" {{\n", self.name);
+        for idx in self.circuit.node_indices() {
+            let component = &self.circuit[idx];
+            let literal = matches!(component.name.as_str(), "true" | "false" | "none");
+            let shape = if self.input_port_nodes.contains(&idx) {
+                "invhouse"
+            } else if self.output_port_nodes.contains(&idx) {
+                "house"
+            } else if literal && component.hdl.is_none() && component.ports.is_empty() {
+                "plaintext"
+            } else {
+                "box"
+            };
+            dot.push_str(&format!(
+                "  n{} [label=\"{}\", shape={}];\n",
+                idx.index(),
+                component.name,
+                shape
+            ));
+        }
+        for e in self.circuit.edge_references() {
+            dot.push_str(&format!(
+                "  n{} -> n{} [label=\"{} -> {}\"];\n",
+                e.source().index(),
+                e.target().index(),
+                e.weight().source,
+                e.weight().target
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Counts the NAND and DFF gates of the fully elaborated chip. Every
+    /// instance is counted, so a part used twice counts twice. Parts are
+    /// elaborated as needed.
+    pub fn gate_count(&mut self) -> Result<GateCount, Box<dyn Error>> {
+        self.gate_count_cached(&mut HashMap::new())
+    }
+
+    // Counts are cached by chip name and generic arguments, so each distinct
+    // part is only elaborated once.
+    fn gate_count_cached(&mut self, counts: &mut GateCounts) -> Result<GateCount, Box<dyn Error>> {
+        match self.name.to_uppercase().as_str() {
+            "NAND" => return Ok(GateCount { nand: 1, dff: 0 }),
+            "DFF" => return Ok(GateCount { nand: 0, dff: 1 }),
+            _ => {}
+        }
+        if self.hdl.is_none() {
+            return Ok(GateCount::default());
+        }
+
+        let mut variables: Vec<(String, usize)> = self
+            .variables
+            .iter()
+            .map(|(k, v)| (k.clone(), *v))
+            .collect();
+        variables.sort();
+        let key = (self.name.clone(), variables);
+        if let Some(count) = counts.get(&key) {
+            return Ok(*count);
+        }
+
+        if !self.elaborated {
+            self.elaborate()?;
+        }
+        let mut count = GateCount::default();
+        for node in self.circuit.node_indices() {
+            let part_count = self.circuit[node].gate_count_cached(counts)?;
+            count.nand += part_count.nand;
+            count.dff += part_count.dff;
+        }
+        counts.insert(key, count);
+        Ok(count)
+    }
+
+    fn elaborate_circuit(&mut self) -> Result<(), Box<dyn Error>> {
+        let self_ptr = self as *mut Chip;
+        self.elaborated = true;
+        if self.hdl.is_none() {
+            return Ok(());
+        }
+
+        // Where each bit of the signal source comes from.
+        let mut signal_sources: SignalSources = HashMap::new();
+
+        // Every driver of bits that may have more than one. These are bits
+        // driven by a bidirectional port, including the bits of this chip's
+        // own bidirectional ports.
+        let mut shared_drivers: HashMap<String, Vec<Vec<(NodeIndex, Bus)>>> = HashMap::new();
+
+        // Input nodes of bidirectional ports, which join the other drivers
+        // of the port once they are known.
+        let mut inout_nodes: Vec<(String, NodeIndex)> = Vec::new();
+
+        // create input components
+        for (port_name, port) in &self.ports {
+            if !port.direction.is_input() {
+                continue;
+            }
+            let port_chip = make_port_chip(port_name, port.width, self_ptr, &self.hdl_provider);
+            let port_node = self.circuit.add_node(port_chip);
+            self.input_port_nodes.push(port_node);
+            if port.direction == PortDirection::InOut {
+                inout_nodes.push((port_name.clone(), port_node));
+                shared_drivers.insert(port_name.clone(), vec![Vec::new(); port.width]);
+                signal_sources.insert(port_name.clone(), vec![None; port.width]);
+                continue;
+            }
+
+            let mut source = Vec::new();
+            for i in 0..port.width {
+                let source_bus = Bus {
+                    name: String::from("in"),
+                    range: Some(i..i + 1),
+                };
+                source.push(Some((port_node, source_bus)));
+            }
+
+            signal_sources.insert(port.name.value.clone(), source);
+        }
+
+        // Widths of the true/false/none literals, zero if a literal is unused.
+        let mut true_literal_width = 0;
+        let mut false_literal_width = 0;
+        let mut none_literal_width = 0;
+        // Numeric constants mapped to parts and the number of bits read from
+        // each. Their bits are read from the true and false literals.
+        let mut numeric_literals: BTreeMap<String, usize> = BTreeMap::new();
+
+        // Pass-through nodes created for assignments. These are removed
+        // from the circuit once all edges have been added.
+        let mut assignment_port_nodes: Vec<NodeIndex> = Vec::new();
 
         // Insert signal sources for every assignment.
         for a in &self.assignments {
-            if &a.right.name == "true" {
-                need_true_literal = true;
+            for r in &a.right {
+                let end = r.range.as_ref().unwrap().end;
+                match r.name.as_str() {
+                    "true" => true_literal_width = true_literal_width.max(end),
+                    "false" => false_literal_width = false_literal_width.max(end),
+                    "none" => none_literal_width = none_literal_width.max(end),
+                    _ => {}
+                }
             }
-            if &a.right.name == "false" {
-                need_false_literal = true;
+            if a.extension == Extension::Zero {
+                false_literal_width = false_literal_width.max(1);
             }
             let port_chip = make_port_chip(
                 a.left.name.clone().as_str(),
@@ -450,26 +1353,70 @@ impl Chip {
                 &self.hdl_provider,
             );
             let assignment_port_node = self.circuit.add_node(port_chip);
+            assignment_port_nodes.push(assignment_port_node);
 
-            let mut source = Vec::new();
-            for i in 0..a.width {
+            // Assignments to different bits of a signal drive it together.
+            let left_range = a.left.range.clone().unwrap();
+            let signal_width = self
+                .signals
+                .get_width(&a.left.name)
+                .unwrap_or(left_range.end);
+            let sources = signal_sources
+                .entry(a.left.name.clone())
+                .or_insert_with(|| vec![None; signal_width]);
+            for (i, bit) in left_range.enumerate() {
+                let assignment_error = |msg: String| {
+                    Box::new(N2VError {
+                        msg,
+                        kind: ErrorKind::ParseIdentError(
+                            self.hdl_provider.clone(),
+                            Identifier::from(a.left.name.as_str()),
+                        ),
+                    })
+                };
+                if bit >= sources.len() {
+                    return Err(assignment_error(format!(
+                        "Bit {} for signal name {} is out of range.",
+                        bit, a.left.name
+                    )));
+                }
+                if sources[bit].is_some() {
+                    return Err(assignment_error(format!(
+                        "Duplicate source for signal name {}.",
+                        a.left.name
+                    )));
+                }
                 let source_bus = Bus {
                     name: String::from("in"),
                     range: Some(i..i + 1),
                 };
-                source.push(Some((assignment_port_node, source_bus)));
+                sources[bit] = Some((assignment_port_node, source_bus));
             }
-
-            signal_sources.insert(a.left.name.clone(), source);
         }
 
         // Create components and handle out ports from components into signals
         // indices of created_components needs to match order of parts
         // Also checks if true/false literals are used.
         let mut created_components: Vec<NodeIndex> = Vec::new();
-        for (_, part) in self.components.iter().enumerate() {
+        let mut chain = (*self.ancestors).clone();
+        chain.push(self.name.clone());
+        let chain = Rc::new(chain);
+        // Parts are numbered from 0 among the parts using the same chip, as
+        // in the gate paths of `diff`.
+        let mut instance_counts: HashMap<String, usize> = HashMap::new();
+        for part in self.components.iter() {
             let part_hdl = get_hdl(&part.name.value, &self.hdl_provider)?;
 
+            // A chip that contains itself would be elaborated forever.
+            if let Some(i) = chain.iter().position(|n| n == &part_hdl.name) {
+                let mut names = chain[i..].to_vec();
+                names.push(part_hdl.name.clone());
+                return Err(Box::new(N2VError {
+                    msg: format!("Recursive chip instantiation: {}", names.join(" -> ")),
+                    kind: ErrorKind::ParseIdentError(self.hdl_provider.clone(), part.name.clone()),
+                }));
+            }
+
             // Convert generics with vars to concrete generics for component.
             // e.g. Mux<W> needs to become Mux<4> if W=4. At this point
             // we need actual bus widths.
@@ -483,13 +1430,25 @@ impl Chip {
                 }
             }
 
-            let part_chip = Chip::new(
+            if self.strict_widths {
+                check_strict_widths(&part_hdl, &self.hdl_provider, &resolved_generics)?;
+            }
+
+            let mut part_chip = Chip::new_with_profile(
                 &part_hdl,
                 self_ptr,
                 &Rc::clone(&self.hdl_provider),
                 false, // Only elaborate one level deep.
                 &resolved_generics,
+                self.profile.clone(),
             )?;
+            part_chip.allow_undriven_outputs = self.allow_undriven_outputs;
+            part_chip.strict_widths = self.strict_widths;
+            part_chip.optimize = self.optimize;
+            part_chip.ancestors = Rc::clone(&chain);
+            let count = instance_counts.entry(part_chip.name.clone()).or_insert(0);
+            part_chip.instance = format!("{}.{}", part_chip.name, count);
+            *count += 1;
             let part_variables = part_chip.variables.clone();
 
             let mut used_port_buses: BusMap = BusMap::new();
@@ -516,21 +1475,41 @@ impl Chip {
                         return Err(Box::new(err_more_info));
                     }
                 };
+                if m.concatenation && port.direction.is_output() {
+                    return Err(Box::new(N2VError {
+                        kind: ErrorKind::ParseIdentError(
+                            self.hdl_provider.clone(),
+                            m.wire_ident.clone(),
+                        ),
+                        msg: format!(
+                            "A concatenation cannot be mapped to output port {}.",
+                            m.port.name
+                        ),
+                    }));
+                }
+                let port_width = eval_expr_numeric(&port.width, &part_variables)?;
+
+                // A literal must be wide enough for every bit read from it.
+                let literal_width = match &m.wire.end {
+                    Some(end) => port_width.max(self.eval_index(end, &m.wire.name)? + 1),
+                    None => port_width,
+                };
                 if signal_name == "true" {
-                    need_true_literal = true;
+                    true_literal_width = true_literal_width.max(literal_width);
                 }
                 if signal_name == "false" {
-                    need_false_literal = true;
+                    false_literal_width = false_literal_width.max(literal_width);
+                }
+                if signal_name == "none" {
+                    none_literal_width = none_literal_width.max(literal_width);
                 }
-
-                let port_width = eval_expr_numeric(&port.width, &part_variables)?;
                 let port_start = match &m.port.start {
                     None => 0,
-                    Some(x) => eval_expr_numeric(x, &self.variables)?,
+                    Some(x) => self.eval_index(x, &m.port.name)?,
                 };
                 let port_end = match &m.port.end {
                     None => port_width - 1,
-                    Some(x) => eval_expr_numeric(x, &self.variables)?,
+                    Some(x) => self.eval_index(x, &m.port.name)?,
                 };
                 // Convert inclusive range in HDL to exclusive Range in Rust
                 let port_range: Range<usize> = Range {
@@ -538,25 +1517,81 @@ impl Chip {
                     end: port_end + 1,
                 };
 
+                if let Some(value) = numeric_literal(signal_name) {
+                    let constant_error = |msg: String| {
+                        Box::new(N2VError {
+                            kind: ErrorKind::ParseIdentError(
+                                self.hdl_provider.clone(),
+                                m.wire_ident.clone(),
+                            ),
+                            msg,
+                        })
+                    };
+                    if port.direction.is_output() {
+                        return Err(constant_error(format!(
+                            "The constant {} cannot be mapped to output port {}.",
+                            value, m.port.name
+                        )));
+                    }
+                    let bits = port_range.len();
+                    if bits < 64 && value >> bits != 0 {
+                        return Err(constant_error(format!(
+                            "The constant {} does not fit in the {} bits of port {}.",
+                            value, bits, m.port.name
+                        )));
+                    }
+                    let high_bit = (u64::BITS - value.leading_zeros()) as usize;
+                    true_literal_width = true_literal_width.max(high_bit);
+                    if (value.count_ones() as usize) < bits {
+                        false_literal_width = false_literal_width.max(bits);
+                    }
+                    let width = numeric_literals.entry(signal_name.clone()).or_insert(0);
+                    *width = (*width).max(bits);
+                }
+
                 // Insert port range for the pupose of verifying that we have
-                // inputs for all of the input pins. Skip the rest of the loop.
-                if port.direction == PortDirection::In {
+                // inputs for all of the input pins. Skip the rest of the loop
+                // unless the port also drives the signal.
+                if port.direction.is_input() {
                     let used_bus = Bus {
                         name: m.port.name.clone(),
                         range: Some(port_range.clone()),
                     };
+                    // An input bit can only be driven by one signal.
+                    if used_port_buses
+                        .get_bus(&used_bus)
+                        .iter()
+                        .any(|b| b.is_some())
+                    {
+                        return Err(Box::new(N2VError {
+                            kind: ErrorKind::ParseIdentError(
+                                self.hdl_provider.clone(),
+                                m.wire_ident.clone(),
+                            ),
+                            msg: format!(
+                                "Input port {} of component {} is mapped more than once.",
+                                m.port.name, part.name.value
+                            ),
+                        }));
+                    }
                     used_port_buses.insert_option(&used_bus, vec![Some(true); port_range.len()]);
-
+                }
+                if !port.direction.is_output() {
                     continue;
                 }
+                let (bidirectional, driven_name) = if port.direction == PortDirection::InOut {
+                    (true, drive_signal_name(&port.name.value))
+                } else {
+                    (false, port.name.value.clone())
+                };
 
                 let wire_start = match &m.wire.start {
                     None => 0,
-                    Some(x) => eval_expr_numeric(x, &self.variables)?,
+                    Some(x) => self.eval_index(x, &m.wire.name)?,
                 };
                 let wire_end = match &m.wire.end {
                     None => port_width - 1,
-                    Some(x) => eval_expr_numeric(x, &self.variables)?,
+                    Some(x) => self.eval_index(x, &m.wire.name)?,
                 };
                 // Convert inclusive range in HDL to exclusive Range in Rust
                 let wire_range: Range<usize> = Range {
@@ -564,7 +1599,7 @@ impl Chip {
                     end: wire_end + 1,
                 };
 
-                if signal_sources.get(signal_name).is_none() {
+                if !signal_sources.contains_key(signal_name) {
                     signal_sources.insert(
                         signal_name.clone(),
                         vec![None; self.signals.get_width(signal_name).unwrap()],
@@ -574,9 +1609,29 @@ impl Chip {
                 let mut i = port_range.start;
                 let mut j = wire_range.start;
                 while i < port_range.end {
-                    // Check here to see if a bit already has a source.
-                    // If it does we have an error in the HDL.
-                    if signal_sources.get(signal_name).unwrap()[j].is_some() {
+                    let driver = (
+                        part_node,
+                        Bus {
+                            name: driven_name.clone(),
+                            range: Some(i..i + 1),
+                        },
+                    );
+                    let sources = signal_sources.get_mut(signal_name).unwrap();
+                    let shared = shared_drivers
+                        .get(signal_name)
+                        .is_some_and(|bits| !bits[j].is_empty());
+                    let own_inout = inout_nodes.iter().any(|(name, _)| name == signal_name);
+
+                    // A bit may only have several drivers if one of them is
+                    // a bidirectional port. Otherwise we have an error in
+                    // the HDL.
+                    if bidirectional || shared || own_inout {
+                        let bits = shared_drivers
+                            .entry(signal_name.clone())
+                            .or_insert_with(|| vec![Vec::new(); sources.len()]);
+                        bits[j].extend(sources[j].take());
+                        bits[j].push(driver);
+                    } else if sources[j].is_some() {
                         return Err(Box::new(N2VError {
                             kind: ErrorKind::ParseIdentError(
                                 self.hdl_provider.clone(),
@@ -584,56 +1639,69 @@ impl Chip {
                             ),
                             msg: format!("Duplicate source for signal name {}.", signal_name),
                         }));
+                    } else {
+                        sources[j] = Some(driver);
                     }
-
-                    signal_sources.get_mut(signal_name).unwrap()[j] = Some((
-                        part_node,
-                        Bus {
-                            name: port.name.value.clone(),
-                            range: Some(i..i + 1),
-                        },
-                    ));
                     i += 1;
                     j += 1;
                 }
             }
 
-            // Make sure we have inputs for every port bit.
-            // for each port in the used busmap
-            // if any bit is None, then warn.
+            // Make sure we have inputs for every port bit. Ports with no
+            // inputs at all are named whole, otherwise each missing bit is
+            // listed.
+            let mut missing_bits = Vec::new();
             for port in part_hdl.ports {
                 if port.direction == PortDirection::Out {
                     continue;
                 }
 
-                let used_bits = used_port_buses.get_name(&port.name.value);
-                for b in used_bits {
-                    if b.is_none() {
-                        return Err(Box::new(N2VError {
-                            kind: ErrorKind::ParseIdentError(
-                                self.hdl_provider.clone(),
-                                part.name.clone(),
-                            ),
-                            msg: format!(
-                                "Component does not provide inputs for all bits of {}.",
-                                &port.name.value
-                            ),
-                        }));
-                    }
+                let width = used_port_buses.get_name(&port.name.value).len();
+                let missing: Vec<usize> = (0..width)
+                    .filter(|&j| {
+                        let bit = Bus {
+                            name: port.name.value.clone(),
+                            range: Some(j..j + 1),
+                        };
+                        used_port_buses.get_bus(&bit)[0].is_none()
+                    })
+                    .collect();
+                if !missing.is_empty() && missing.len() == width {
+                    missing_bits.push(port.name.value.clone());
+                } else {
+                    let name = &port.name.value;
+                    missing_bits.extend(missing.iter().map(|j| format!("{}[{}]", name, j)));
                 }
             }
+            if !missing_bits.is_empty() {
+                return Err(Box::new(N2VError {
+                    kind: ErrorKind::ParseIdentError(self.hdl_provider.clone(), part.name.clone()),
+                    msg: format!(
+                        "Component {} does not provide inputs for {}.",
+                        part.name.value,
+                        missing_bits.join(", ")
+                    ),
+                }));
+            }
         }
 
-        // Create true/false literals only if a port mapping requires it.
-        let false_chip;
-        let false_node;
-        if need_false_literal {
-            false_chip = make_literal_chip(Some(false), self_ptr, &self.hdl_provider);
-            false_node = self.circuit.add_node(false_chip);
-            let false_vector: Vec<_> = (0..16)
+        // Create literals only if a port mapping or assignment requires it.
+        // `none` drives every bit as unknown.
+        let literals = [
+            ("false", Some(false), false_literal_width),
+            ("true", Some(true), true_literal_width),
+            ("none", None, none_literal_width),
+        ];
+        for (literal_name, value, width) in literals {
+            if width == 0 {
+                continue;
+            }
+            let literal_chip = make_literal_chip(value, width, self_ptr, &self.hdl_provider);
+            let literal_node = self.circuit.add_node(literal_chip);
+            let literal_vector: Vec<_> = (0..width)
                 .map(|i| {
                     Some((
-                        false_node,
+                        literal_node,
                         Bus {
                             name: String::from("out"),
                             range: Some(i..i + 1),
@@ -641,25 +1709,53 @@ impl Chip {
                     ))
                 })
                 .collect();
-            signal_sources.insert(String::from("false"), false_vector);
-        }
-        let true_chip;
-        let true_node;
-        if need_true_literal {
-            true_chip = make_literal_chip(Some(true), self_ptr, &self.hdl_provider);
-            true_node = self.circuit.add_node(true_chip);
-            let true_vector: Vec<_> = (0..16)
+            signal_sources.insert(String::from(literal_name), literal_vector);
+        }
+        for (name, width) in &numeric_literals {
+            let value = numeric_literal(name).unwrap();
+            let bits: Vec<_> = (0..*width)
                 .map(|i| {
-                    Some((
-                        true_node,
-                        Bus {
-                            name: String::from("out"),
-                            range: Some(i..i + 1),
-                        },
-                    ))
+                    let literal = if i < 64 && value >> i & 1 == 1 {
+                        "true"
+                    } else {
+                        "false"
+                    };
+                    signal_sources[literal][i].clone()
                 })
                 .collect();
-            signal_sources.insert(String::from("true"), true_vector);
+            signal_sources.insert(name.clone(), bits);
+        }
+
+        // Bits with several drivers are read through a resolver. A
+        // bidirectional port reads the value of every driver but its own,
+        // so the output node of this chip's bidirectional port only sees the
+        // drivers inside the chip.
+        let mut inside_sources: SignalSources = HashMap::new();
+        let mut shared_names: Vec<String> = shared_drivers.keys().cloned().collect();
+        shared_names.sort();
+        for signal_name in shared_names {
+            let sources = signal_sources.get_mut(&signal_name).unwrap();
+            let bits = shared_drivers.get_mut(&signal_name).unwrap();
+            let outside = inout_nodes.iter().find(|(name, _)| name == &signal_name);
+            let mut inside = Vec::new();
+            for (j, drivers) in bits.iter_mut().enumerate() {
+                // Assignments to a bidirectional port drive it too.
+                drivers.extend(sources[j].take());
+                inside.push(resolve_drivers(&mut self.circuit, drivers, self_ptr));
+                if let Some((_, node)) = outside {
+                    drivers.push((
+                        *node,
+                        Bus {
+                            name: String::from("in"),
+                            range: Some(j..j + 1),
+                        },
+                    ));
+                }
+                sources[j] = resolve_drivers(&mut self.circuit, drivers, self_ptr);
+            }
+            if outside.is_some() {
+                inside_sources.insert(signal_name, inside);
+            }
         }
 
         // Closure for retrieving signal source and returning an error if the source is not found.
@@ -703,11 +1799,27 @@ impl Chip {
 
         // Add edges for assignments
         for a in &self.assignments {
+            // Bits of the right side, least significant first.
+            let mut right_bits = Vec::new();
+            for segment in a.right.iter().rev() {
+                for i in segment.range.clone().unwrap() {
+                    right_bits.push((segment.name.as_str(), i));
+                }
+            }
+
             for j in 0..a.width {
+                // Bits past the right side come from the extension.
+                let (source_name, source_idx) = if j < a.right_width {
+                    right_bits[j]
+                } else if a.extension == Extension::Sign {
+                    right_bits[a.right_width - 1]
+                } else {
+                    ("false", 0)
+                };
                 let (source_node, source_bus) = match get_signal_source(
-                    a.right.name.as_str(),
-                    j,
-                    &Identifier::from(a.right.name.as_str()),
+                    source_name,
+                    source_idx,
+                    &Identifier::from(source_name),
                 )? {
                     Some(x) => x,
                     None => {
@@ -716,7 +1828,7 @@ impl Chip {
                 };
                 let (target_node, _target_bus) = match get_signal_source(
                     a.left.name.as_str(),
-                    j,
+                    a.left.range.as_ref().unwrap().start + j,
                     &Identifier::from(a.left.name.as_str()),
                 )? {
                     Some(x) => x,
@@ -754,11 +1866,11 @@ impl Chip {
                 let port_width = self.eval_port_width(port, &part_hdl, part)?;
                 let port_start = match &m.port.start {
                     None => 0,
-                    Some(x) => eval_expr_numeric(x, &self.variables)?,
+                    Some(x) => self.eval_index(x, &m.port.name)?,
                 };
                 let port_end = match &m.port.end {
                     None => port_width - 1,
-                    Some(x) => eval_expr_numeric(x, &self.variables)?,
+                    Some(x) => self.eval_index(x, &m.port.name)?,
                 };
                 // Convert inclusive range in HDL to exclusive Range in Rust
                 let port_range: Range<usize> = Range {
@@ -768,11 +1880,11 @@ impl Chip {
 
                 let wire_start = match &m.wire.start {
                     None => 0,
-                    Some(x) => eval_expr_numeric(x, &self.variables)?,
+                    Some(x) => self.eval_index(x, &m.wire.name)?,
                 };
                 let wire_end = match &m.wire.end {
                     None => port_width - 1,
-                    Some(x) => eval_expr_numeric(x, &self.variables)?,
+                    Some(x) => self.eval_index(x, &m.wire.name)?,
                 };
                 // Convert inclusive range in HDL to exclusive Range in Rust
                 let wire_range: Range<usize> = Range {
@@ -789,15 +1901,26 @@ impl Chip {
                     // source_node is the graph node for the chip feeding into signal
                     // source_bus is the port/range creating this particular bit.
                     // signal_idx is the index of bit in the signal.
-                    let (source_node, source_bus) =
-                        match get_signal_source(signal_name, i, &m.wire_ident)? {
-                            Some(x) => x,
-                            None => {
-                                i += 1;
-                                j += 1;
-                                continue;
-                            }
-                        };
+                    let drivers = shared_drivers.get(signal_name).and_then(|bits| bits.get(i));
+                    let source = match drivers {
+                        Some(drivers) if port.direction == PortDirection::InOut => {
+                            let others: Vec<(NodeIndex, Bus)> = drivers
+                                .iter()
+                                .filter(|(node, _)| *node != created_components[part_idx])
+                                .cloned()
+                                .collect();
+                            resolve_drivers(&mut self.circuit, &others, self_ptr)
+                        }
+                        _ => get_signal_source(signal_name, i, &m.wire_ident)?.cloned(),
+                    };
+                    let (source_node, source_bus) = match source {
+                        Some(x) => x,
+                        None => {
+                            i += 1;
+                            j += 1;
+                            continue;
+                        }
+                    };
 
                     let wire = Wire {
                         source: source_bus.clone(),
@@ -808,7 +1931,7 @@ impl Chip {
                     };
 
                     self.circuit
-                        .add_edge(*source_node, created_components[part_idx], wire);
+                        .add_edge(source_node, created_components[part_idx], wire);
 
                     i += 1;
                     j += 1;
@@ -816,18 +1939,46 @@ impl Chip {
             }
         }
 
+        // Output port bits without a source, reported together below.
+        let mut undriven: Vec<(&Port, Vec<usize>)> = Vec::new();
         for (port_name, port) in &self.ports {
-            if port.direction == PortDirection::In {
+            if !port.direction.is_output() {
                 continue;
             }
             let port_chip = make_port_chip(port_name, port.width, self_ptr, &self.hdl_provider);
             let port_node = self.circuit.add_node(port_chip);
             self.output_port_nodes.push(port_node);
 
+            // A bidirectional port is left undriven to let others drive it.
+            let bidirectional = port.direction == PortDirection::InOut;
+            let sources = if bidirectional {
+                &inside_sources
+            } else {
+                &signal_sources
+            };
+            let mut undriven_bits = Vec::new();
             for j in 0..port.width {
-                let (source_node, source_bus) = match get_signal_source(port_name, j, &port.name)? {
+                let source = sources
+                    .get(port_name)
+                    .and_then(|s| s.get(j))
+                    .and_then(|s| s.as_ref());
+                let (source_node, source_bus) = match source {
                     Some(x) => x,
+                    None if bidirectional => {
+                        match self.released.last_mut() {
+                            Some(Bus {
+                                name,
+                                range: Some(range),
+                            }) if *name == *port_name && range.end == j => range.end += 1,
+                            _ => self.released.push(Bus {
+                                name: port_name.clone(),
+                                range: Some(j..j + 1),
+                            }),
+                        }
+                        continue;
+                    }
                     None => {
+                        undriven_bits.push(j);
                         continue;
                     }
                 };
@@ -840,53 +1991,161 @@ impl Chip {
                 };
                 self.circuit.add_edge(*source_node, port_node, wire);
             }
+            if !undriven_bits.is_empty() {
+                undriven.push((port, undriven_bits));
+            }
+        }
+
+        if !undriven.is_empty() {
+            undriven.sort_by(|a, b| a.0.name.value.cmp(&b.0.name.value));
+            let mut bit_names = Vec::new();
+            for (port, bits) in &undriven {
+                if bits.len() == port.width {
+                    bit_names.push(port.name.value.clone());
+                } else {
+                    bit_names.extend(bits.iter().map(|j| format!("{}[{}]", port.name.value, j)));
+                }
+            }
+            let msg = format!(
+                "Chip {} has output bits with no source: {}.",
+                self.name,
+                bit_names.join(", ")
+            );
+            if !self.allow_undriven_outputs && !self.allow_undriven_ports {
+                return Err(Box::new(N2VError {
+                    msg,
+                    kind: ErrorKind::ParseIdentError(
+                        self.hdl_provider.clone(),
+                        undriven[0].0.name.clone(),
+                    ),
+                }));
+            }
+            self.undriven = bit_names;
         }
 
-        optimize_circuit(&mut self.circuit);
+        remove_passthrough_nodes(
+            &mut self.circuit,
+            &assignment_port_nodes,
+            &mut [&mut self.input_port_nodes, &mut self.output_port_nodes],
+            &mut signal_sources,
+        );
+        self.signal_sources = signal_sources;
+        if self.optimize {
+            optimize_circuit(&mut self.circuit);
+        }
 
+        // Assertions name signals of the chip and values that fit in them.
+        let parts = self.hdl.iter().flat_map(|hdl| &hdl.parts);
+        for part in parts {
+            if let Part::Assert(assert) = part {
+                self.assertion_expected(assert)?;
+            }
+        }
         Ok(())
     }
 
+    // Signals of a loop of parts with no DFF in it, used to explain why the
+    // chip did not settle. Parts can be wired in a loop while their bits are
+    // not, so a loop is only reported once settling fails. A loop through a
+    // part that keeps state may be broken inside that part, and loops
+    // through bidirectional ports are resolved, so neither is named.
+    fn loop_signals(&self) -> Result<Vec<&str>, Box<dyn Error>> {
+        let mut stateful: HashMap<String, bool> = HashMap::new();
+        for scc in kosaraju_scc(&self.circuit) {
+            if scc.len() == 1 && !self.circuit.contains_edge(scc[0], scc[0]) {
+                continue;
+            }
+            let mut broken = false;
+            for &node in &scc {
+                let part = &self.circuit[node];
+                broken |= match &part.hdl {
+                    _ if part.name.to_uppercase() == "DFF" => true,
+                    Some(hdl) => keeps_state(hdl, &self.hdl_provider, &mut stateful)?,
+                    None => part.name == "RESOLVE",
+                };
+            }
+            if broken {
+                continue;
+            }
+
+            // Signals driven by one part of the loop and read by another.
+            // Parallel wires may have been merged, so buses only need to
+            // overlap.
+            let mut signals: Vec<&str> = self
+                .signal_sources
+                .iter()
+                .filter(|(_, bits)| {
+                    bits.iter().flatten().any(|(node, bus)| {
+                        scc.contains(node)
+                            && self.circuit.edges(*node).any(|e| {
+                                scc.contains(&e.target()) && buses_overlap(&e.weight().source, bus)
+                            })
+                    })
+                })
+                .map(|(name, _)| name.as_str())
+                .collect();
+            signals.sort();
+            return Ok(signals);
+        }
+        Ok(Vec::new())
+    }
+
+    /// Values of the ports the chip reads (`In`) or drives (`Out`).
+    /// Bidirectional ports are in both, with the value the chip drives onto
+    /// them as the output. `InOut` returns only the bidirectional ports.
     pub fn get_port_values_for_direction(&self, direction: PortDirection) -> BusMap {
         // Return output signals as a BusMap
         let mut values = BusMap::new();
         let ports = self.ports.clone();
         for (port_name, port) in ports {
-            if port.direction != direction {
+            let keep = match direction {
+                PortDirection::In => port.direction.is_input(),
+                PortDirection::Out => port.direction.is_output(),
+                PortDirection::InOut => port.direction == PortDirection::InOut,
+            };
+            if !keep {
                 continue;
             }
 
-            let idx = Bus {
-                name: port_name.clone(),
-                range: Some(0..port.width),
+            let signal_name = match direction {
+                PortDirection::Out => self.driven_signal(&port_name),
+                _ => port_name.clone(),
             };
-            values.create_bus(&idx.name, port.width).unwrap();
-            values.insert_option(&idx, self.signals.get_bus(&idx));
+            values.create_bus(&port_name, port.width).unwrap();
+            let value = self.signals.get_range(&signal_name, 0..port.width);
+            values.insert_option(&Bus::from(port_name), value);
         }
         values
     }
 
+    // Bidirectional ports report the value the chip drives onto them.
     fn get_port_values(&self) -> BusMap {
         // Return output signals as a BusMap
         let mut values = BusMap::new();
         let ports = self.ports.clone();
         for (port_name, port) in ports {
-            let idx = Bus {
-                name: port_name.clone(),
-                range: Some(0..port.width),
-            };
             values.create_bus(&port_name, port.width).unwrap();
-            values.insert_option(&idx, self.signals.get_bus(&idx));
+            let value = self
+                .signals
+                .get_range(&self.driven_signal(&port_name), 0..port.width);
+            values.insert_option(&Bus::from(port_name), value);
         }
         values
     }
 
+    // The signal holding the value the chip drives onto a port. For a
+    // bidirectional port this differs from the signal holding the value
+    // read from outside.
+    fn driven_signal(&self, port_name: &str) -> String {
+        match self.ports.get(port_name) {
+            Some(port) if port.direction == PortDirection::InOut => drive_signal_name(port_name),
+            _ => String::from(port_name),
+        }
+    }
+
     fn insert_cache_entry(&mut self, input_cache: &mut Cache) {
         let inputs = self.get_port_values_for_direction(PortDirection::In);
-        let cache_entry = InputCacheEntry {
-            name: self.name.clone(),
-            signals: inputs,
-        };
+        let cache_entry = InputCacheEntry::new(&self.name, &inputs);
         input_cache.insert(
             cache_entry,
             self.get_port_values_for_direction(PortDirection::Out),
@@ -935,39 +2194,163 @@ impl Chip {
         inferred_widths: &HashMap<String, GenericWidth>,
         assignments: Vec<AssignmentHDL>,
         generic_state: &HashMap<String, usize>,
+        provider: &Rc<dyn HdlProvider>,
     ) -> Result<Vec<Assignment>, Box<dyn Error>> {
         let mut converted_assignments = Vec::<Assignment>::new();
         for a in assignments {
-            // This could possibly return None--add dummy width to infer_widths
-            let w = inferred_widths.get(&a.left.name).unwrap();
-            let usize_w = eval_expr_numeric(w, generic_state)?;
+            let assignment_error = |msg: String| -> Box<dyn Error> {
+                Box::new(N2VError {
+                    msg,
+                    kind: ErrorKind::ParseIdentError(
+                        provider.clone(),
+                        Identifier::from(a.left.name.as_str()),
+                    ),
+                })
+            };
+            let w = inferred_widths.get(&a.left.name).ok_or_else(|| {
+                assignment_error(format!(
+                    "Width of signal {} cannot be inferred.",
+                    a.left.name
+                ))
+            })?;
+            let left_range = match Self::bus_range(&a.left, generic_state)? {
+                Some(range) => range,
+                None => 0..eval_expr_numeric(w, generic_state)?,
+            };
+            let usize_w = left_range.len();
             let left_bus = Bus {
-                name: a.left.name,
-                range: Some(0..usize_w),
+                name: a.left.name.clone(),
+                range: Some(left_range),
             };
-            let right_bus = Bus {
-                name: a.right.name,
-                range: Some(0..usize_w),
+
+            let right_buses = if let [right] = a.right.as_slice() {
+                // Only extended assignments may have a narrower right side.
+                // Literals have no inferred width and fill the whole left side.
+                let literal = matches!(right.name.as_str(), "true" | "false" | "none");
+                let right_range = match Self::bus_range(right, generic_state)? {
+                    Some(range) => range,
+                    None => match inferred_widths.get(&right.name) {
+                        Some(w) if !literal => 0..eval_expr_numeric(w, generic_state)?,
+                        _ => 0..usize_w,
+                    },
+                };
+                if a.extension == Extension::None && right_range.len() != usize_w {
+                    return Err(assignment_error(format!(
+                        "Cannot assign {} bits of {} to {} bits of {}.",
+                        right_range.len(),
+                        right.name,
+                        usize_w,
+                        a.left.name
+                    )));
+                }
+                vec![Bus {
+                    name: right.name.clone(),
+                    range: Some(right_range),
+                }]
+            } else {
+                Self::concat_buses(inferred_widths, &a, usize_w, generic_state, provider)?
             };
+            let right_w = right_buses
+                .iter()
+                .map(|b| b.range.as_ref().unwrap().len())
+                .sum();
 
             // Create a Bus for left and right
             let new_assignment = Assignment {
                 left: left_bus,
-                right: right_bus,
+                right: right_buses,
                 width: usize_w,
+                right_width: right_w,
+                extension: a.extension,
             };
             converted_assignments.push(new_assignment);
         }
         Ok(converted_assignments)
     }
 
+    // Bits selected by an index such as `out[0]` or `in[2..5]`, or None if
+    // the bus has no index.
+    fn bus_range(
+        bus: &BusHDL,
+        generic_state: &HashMap<String, usize>,
+    ) -> Result<Option<Range<usize>>, N2VError> {
+        match (&bus.start, &bus.end) {
+            (Some(start), Some(end)) => {
+                let start = eval_expr_numeric(start, generic_state)?;
+                Ok(Some(start..eval_expr_numeric(end, generic_state)? + 1))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Resolves the segments of a concatenation to buses. The segments must
+    /// add up to the width of the left side.
+    fn concat_buses(
+        inferred_widths: &HashMap<String, GenericWidth>,
+        a: &AssignmentHDL,
+        width: usize,
+        generic_state: &HashMap<String, usize>,
+        provider: &Rc<dyn HdlProvider>,
+    ) -> Result<Vec<Bus>, Box<dyn Error>> {
+        let mut buses = Vec::new();
+        for segment in &a.right {
+            let range = match (&segment.start, &segment.end) {
+                (Some(start), end) => {
+                    let start = eval_expr_numeric(start, generic_state)?;
+                    let end = match end {
+                        Some(end) => eval_expr_numeric(end, generic_state)?,
+                        None => start,
+                    };
+                    start..end + 1
+                }
+                (None, _) => match inferred_widths.get(&segment.name) {
+                    Some(w) => 0..eval_expr_numeric(w, generic_state)?,
+                    None => {
+                        return Err(Box::new(N2VError {
+                            msg: format!(
+                                "Width of signal {} in concatenation is unknown.",
+                                &segment.name
+                            ),
+                            kind: ErrorKind::ParseIdentError(
+                                provider.clone(),
+                                Identifier::from(segment.name.as_str()),
+                            ),
+                        }));
+                    }
+                },
+            };
+            buses.push(Bus {
+                name: segment.name.clone(),
+                range: Some(range),
+            });
+        }
+
+        let concat_width: usize = buses.iter().map(|b| b.range.as_ref().unwrap().len()).sum();
+        if concat_width != width {
+            let (target, ident) = concatenation_target(a);
+            return Err(Box::new(N2VError {
+                msg: format!(
+                    "Concatenation is {} bits wide but {} is {} bits wide.",
+                    concat_width, target, width
+                ),
+                kind: ErrorKind::ParseIdentError(provider.clone(), ident),
+            }));
+        }
+        Ok(buses)
+    }
+
     fn compute(
         &mut self,
         input_cache: &mut Cache,
+        cache_stats: &mut CacheStats,
         dirty_dffs: &mut Vec<*mut Chip>,
+        settle_iterations: Option<usize>,
+        builtins: &Builtins,
     ) -> Result<(), Box<dyn Error>> {
+        let mut iterations = 0;
         while self.dirty {
             self.dirty = false;
+            iterations += 1;
 
             if self.name.to_uppercase() == "NAND" {
                 // Why not use get_name here?
@@ -996,34 +2379,44 @@ impl Chip {
                     parent_chip.cache = false;
                     parent = parent_chip.parent;
                 }
+            } else if self.name == "RESOLVE" && self.hdl.is_none() && !self.ports.is_empty() {
+                let r = resolve(&self.signals.get_name("in"));
+                self.signals.insert_option(&Bus::from("out"), vec![r]);
+                return Ok(());
             } else if self.name.to_uppercase() == "BUFFER" {
                 let r = self.signals.get_name("in");
                 self.signals.insert_option(&Bus::from("out"), r);
                 return Ok(());
+            } else if let Some(builtin_name) = self.hdl.as_ref().and_then(|h| h.builtin.as_ref()) {
+                return self.compute_builtin(builtin_name.clone(), builtins);
             }
 
-            let cache_entry = InputCacheEntry {
-                name: self.name.clone(),
-                signals: self.get_port_values_for_direction(PortDirection::In),
-            };
+            let cache_entry = InputCacheEntry::new(
+                &self.name,
+                &self.get_port_values_for_direction(PortDirection::In),
+            );
 
             if !self.elaborated && self.cache && input_cache.contains_key(&cache_entry) {
+                cache_stats.hits += 1;
                 let cached_outputs = input_cache.get(&cache_entry).unwrap();
 
                 // set output signals directly
                 for o in cached_outputs.keys() {
-                    let width = cached_outputs.get_width(&o).unwrap();
-                    let bus = Bus {
-                        name: o.clone(),
-                        range: Some(0..width),
-                    };
+                    let bus = cached_outputs.full_bus(&o).unwrap();
                     let value = cached_outputs.get_bus(&bus);
-                    self.signals.insert_option(&bus, value);
+                    let target = Bus {
+                        name: self.driven_signal(&o),
+                        ..bus
+                    };
+                    self.signals.insert_option(&target, value);
                 }
                 return Ok(());
             }
 
             if !self.elaborated {
+                if self.cache {
+                    cache_stats.misses += 1;
+                }
                 self.elaborate()?;
             }
 
@@ -1031,13 +2424,9 @@ impl Chip {
             for &port_idx in &self.input_port_nodes {
                 let port_component = self.circuit.node_weight_mut(port_idx).unwrap();
                 let new_val = self.signals.get_name(&port_component.name);
-                port_component.signals.insert_option(
-                    &Bus {
-                        name: String::from("in"),
-                        range: Some(0..new_val.len()),
-                    },
-                    new_val,
-                );
+                port_component
+                    .signals
+                    .insert_option(&Bus::from("in"), new_val);
             }
 
             // Compute our value by computing subcomponents.
@@ -1050,25 +2439,46 @@ impl Chip {
                     // Compute component bus values.
                     {
                         let component = self.circuit.node_weight_mut(component_idx).unwrap();
-                        component.compute(input_cache, dirty_dffs)?;
+                        component.compute(
+                            input_cache,
+                            cache_stats,
+                            dirty_dffs,
+                            settle_iterations,
+                            builtins,
+                        )?;
                     }
 
                     self.mark_neighbors(component_idx, dirty_dffs);
                 }
             }
+
+            // A circuit that keeps changing would otherwise loop forever.
+            let limit = settle_iterations.unwrap_or(4 * self.circuit.node_count());
+            if self.dirty && iterations >= limit {
+                let signals = self.loop_signals()?;
+                let cause = if signals.is_empty() {
+                    String::from("possible combinational loop")
+                } else {
+                    format!("combinational loop through {}", signals.join(", "))
+                };
+                return Err(Box::new(N2VError {
+                    msg: format!(
+                        "Chip {} did not settle after {} iterations, {}.",
+                        self.name, iterations, cause
+                    ),
+                    kind: ErrorKind::SimulationError(
+                        self.hdl.as_ref().and_then(|h| h.path.clone()),
+                    ),
+                }));
+            }
         }
 
         // populate output buses
         for &port_idx in &self.output_port_nodes {
-            let port_component = self.circuit.node_weight_mut(port_idx).unwrap();
+            let port_component = &self.circuit[port_idx];
             let new_val = port_component.signals.get_name("in");
-            self.signals.insert_option(
-                &Bus {
-                    name: port_component.name.clone(),
-                    range: Some(0..new_val.len()),
-                },
-                new_val,
-            );
+            let name = self.driven_signal(&port_component.name);
+            self.signals.insert_option(&Bus::from(name), new_val);
         }
 
         if self.cache {
@@ -1078,6 +2488,191 @@ impl Chip {
         Ok(())
     }
 
+    // Simulates a BUILTIN chip with its registered implementation.
+    fn compute_builtin(
+        &mut self,
+        builtin_name: String,
+        builtins: &Builtins,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = self.hdl.as_ref().and_then(|h| h.path.clone());
+        let builtin = match builtins.get(&builtin_name) {
+            Some(b) => b,
+            None => {
+                return Err(Box::new(N2VError {
+                    msg: format!(
+                        "No implementation is registered for builtin chip {}.",
+                        builtin_name
+                    ),
+                    kind: ErrorKind::SimulationError(path),
+                }));
+            }
+        };
+
+        let outputs = builtin(&self.get_port_values_for_direction(PortDirection::In));
+        for (port_name, port) in &self.ports {
+            if !port.direction.is_output() {
+                continue;
+            }
+            if outputs.get_width(port_name) != Some(port.width) {
+                return Err(Box::new(N2VError {
+                    msg: format!(
+                        "Builtin chip {} did not return {} bits for output {}.",
+                        builtin_name, port.width, port_name
+                    ),
+                    kind: ErrorKind::SimulationError(path),
+                }));
+            }
+            let bus = Bus::from(self.driven_signal(port_name));
+            self.signals
+                .insert_option(&bus, outputs.get_name(port_name));
+        }
+        Ok(())
+    }
+
+    /// Sets every DFF in this chip and its components back to false.
+    /// All chips are marked dirty so the next simulation recomputes every
+    /// signal from the reset DFF values.
+    pub fn reset_state(&mut self) {
+        if self.name.to_uppercase() == "DFF" {
+            for name in ["in", "out"] {
+                self.signals
+                    .insert_option(&Bus::from(name), vec![Some(false)]);
+            }
+        }
+
+        for component in self.circuit.node_weights_mut() {
+            component.reset_state();
+        }
+        self.dirty = true;
+    }
+
+    // Value of a port or internal signal, most significant bit first, or
+    // None if the chip has no such signal.
+    fn signal_value(&self, name: &str) -> Option<Vec<Option<bool>>> {
+        if self.is_hidden_signal(name) {
+            return None;
+        }
+        if self.ports.contains_key(name) {
+            return Some(self.signals.get_name(name));
+        }
+
+        // Internal signals are read from the output ports driving them.
+        let sources = self.signal_sources.get(name)?;
+        let bits = sources.iter().rev().map(|source| match source {
+            Some((idx, bus)) => self.circuit[*idx].signals.get_bus(bus)[0],
+            None => None,
+        });
+        Some(bits.collect())
+    }
+
+    // The bits the signal of an ASSERT statement must have, most significant
+    // first.
+    fn assertion_expected(&self, assert: &AssertHDL) -> Result<Vec<bool>, Box<dyn Error>> {
+        let assert_error = |msg: String| {
+            Box::new(N2VError {
+                msg,
+                kind: ErrorKind::ParseIdentError(self.hdl_provider.clone(), assert.signal.clone()),
+            })
+        };
+        let Some(actual) = self.signal_value(&assert.signal.value) else {
+            return Err(assert_error(format!(
+                "Chip {} has no signal named {} to assert.",
+                self.name, assert.signal
+            )));
+        };
+        Ok(assert.expected(actual.len()).map_err(assert_error)?)
+    }
+
+    // Checks the ASSERT statements of this chip and of its elaborated parts.
+    // `path` names this chip's instance from the top-level chip down, such
+    // as `Add16.0/FullAdder.1`, and is empty for the top-level chip.
+    fn check_assertions(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let Some(hdl) = self.hdl.as_ref().filter(|_| self.elaborated) else {
+            return Ok(());
+        };
+        for part in &hdl.parts {
+            let Part::Assert(assert) = part else {
+                continue;
+            };
+            let expected = self.assertion_expected(assert)?;
+            let actual = self.signal_value(&assert.signal.value).unwrap_or_default();
+            if actual.iter().zip(&expected).any(|(a, e)| *a != Some(*e)) {
+                let expected: Vec<Option<bool>> = expected.into_iter().map(Some).collect();
+                let mut place = String::new();
+                if let (Some(line), Some(file)) = (
+                    assert.signal.line,
+                    hdl.path.as_ref().and_then(|p| p.file_name()),
+                ) {
+                    place.push_str(&format!(" on line {} of {}", line, file.to_string_lossy()));
+                }
+                if !path.is_empty() {
+                    place.push_str(&format!(" in part {}", path));
+                }
+                return Err(Box::new(N2VError {
+                    msg: format!(
+                        "`{}`{} failed: {} is {}, expected {}.",
+                        assert,
+                        place,
+                        assert.signal,
+                        format_bits(&actual, Radix::Binary),
+                        format_bits(&expected, Radix::Binary)
+                    ),
+                    kind: ErrorKind::SimulationError(hdl.path.clone()),
+                }));
+            }
+        }
+        for part in self
+            .circuit
+            .node_weights()
+            .filter(|c| !c.instance.is_empty())
+        {
+            let part_path = if path.is_empty() {
+                part.instance.clone()
+            } else {
+                format!("{}/{}", path, part.instance)
+            };
+            part.check_assertions(&part_path)?;
+        }
+        Ok(())
+    }
+
+    // Calls `f` on every DFF in this chip and its parts, elaborating parts
+    // as needed. Parts using the same chip are visited in the order they
+    // appear in the HDL, and parts using different chips in order of chip
+    // name. Visited chips are marked dirty and uncached, because `f` may
+    // change their state.
+    fn visit_dffs(&mut self, f: &mut dyn FnMut(&mut Chip)) -> Result<(), Box<dyn Error>> {
+        self.dirty = true;
+        self.cache = false;
+        if self.name.to_uppercase() == "DFF" {
+            f(self);
+            return Ok(());
+        }
+        if self.hdl.as_ref().is_none_or(|h| h.builtin.is_some()) {
+            return Ok(());
+        }
+        if !self.elaborated {
+            self.elaborate()?;
+        }
+
+        // Instances are named after their chip and their number among the
+        // parts using that chip, such as `Register.3`. Nodes without an
+        // instance name are ports and literals.
+        let mut parts: Vec<(String, usize, NodeIndex)> = self
+            .circuit
+            .node_indices()
+            .filter_map(|idx| {
+                let (name, number) = self.circuit[idx].instance.rsplit_once('.')?;
+                Some((name.to_string(), number.parse().ok()?, idx))
+            })
+            .collect();
+        parts.sort();
+        for (_, _, idx) in parts {
+            self.circuit[idx].visit_dffs(f)?;
+        }
+        Ok(())
+    }
+
     pub fn eval_port_width(
         &self,
         port: &GenericPort,
@@ -1094,15 +2689,24 @@ impl Chip {
             }
         }
 
-        let component_variables: HashMap<String, usize> = component_hdl
+        let mut component_variables: HashMap<String, usize> = component_hdl
             .generic_decls
             .iter()
             .map(|x| x.value.clone())
             .zip(resolved_generics)
             .collect();
+        component_variables.extend(component_hdl.constant_values());
 
         eval_expr_numeric(&port.width, &component_variables)
     }
+
+    // Evaluates an index into `signal`, naming the generic if one is undefined.
+    fn eval_index(&self, index: &GenericWidth, signal: &str) -> Result<usize, Box<dyn Error>> {
+        if let Some(var) = undefined_variable(index, &self.variables) {
+            return Err(undefined_generic_error(&self.hdl_provider, var, signal));
+        }
+        Ok(eval_expr_numeric(index, &self.variables)?)
+    }
 }
 
 // Combines adjacent edges
@@ -1217,17 +2821,122 @@ fn optimize_circuit(circuit: &mut Circuit) {
     }
 }
 
-/// Creates chips for 16-bit true/false literals.
+/// Removes pass-through port nodes from the circuit, wiring each node's
+/// predecessors directly to its successors. Edges must not have been merged
+/// yet. A node is left in place if any bit it forwards has no source.
+///
+/// * `candidates` - Pass-through nodes to remove, such as assignment ports.
+/// * `tracked` - Node indices held elsewhere (e.g. top-level port nodes).
+///   These are updated when node removal relocates a node.
+/// * `sources` - Signal sources, updated to the predecessor of a removed
+///   node and when node removal relocates a node.
+fn remove_passthrough_nodes(
+    circuit: &mut Circuit,
+    candidates: &[NodeIndex],
+    tracked: &mut [&mut Vec<NodeIndex>],
+    sources: &mut SignalSources,
+) {
+    // Removing a node moves the last node into its slot. Removing in
+    // descending order keeps the remaining candidate indices valid.
+    let mut candidates = candidates.to_vec();
+    candidates.sort_by(|a, b| b.cmp(a));
+
+    for node in candidates {
+        // Source of each bit arriving on the node's input bus.
+        let mut bit_sources: HashMap<usize, (NodeIndex, String, usize)> = HashMap::new();
+        let mut has_self_edge = false;
+        for e in circuit.edges_directed(node, petgraph::Direction::Incoming) {
+            if e.source() == node {
+                has_self_edge = true;
+            }
+            let wire = e.weight();
+            let source_range = wire.source.range.clone().unwrap_or(0..1);
+            let target_range = wire.target.range.clone().unwrap_or(0..1);
+            for (s, t) in source_range.zip(target_range) {
+                bit_sources.insert(t, (e.source(), wire.source.name.clone(), s));
+            }
+        }
+        if has_self_edge {
+            continue;
+        }
+
+        let mut new_edges: Vec<(NodeIndex, NodeIndex, Wire)> = Vec::new();
+        let mut complete = true;
+        for e in circuit.edges_directed(node, petgraph::Direction::Outgoing) {
+            let wire = e.weight();
+            let source_range = wire.source.range.clone().unwrap_or(0..1);
+            let target_range = wire.target.range.clone().unwrap_or(0..1);
+            for (s, t) in source_range.zip(target_range) {
+                match bit_sources.get(&s) {
+                    Some((source_node, source_name, source_bit)) => new_edges.push((
+                        *source_node,
+                        e.target(),
+                        Wire {
+                            source: Bus {
+                                name: source_name.clone(),
+                                range: Some(*source_bit..source_bit + 1),
+                            },
+                            target: Bus {
+                                name: wire.target.name.clone(),
+                                range: Some(t..t + 1),
+                            },
+                        },
+                    )),
+                    None => complete = false,
+                }
+            }
+        }
+        if !complete {
+            continue;
+        }
+
+        for (source, target, wire) in new_edges {
+            circuit.add_edge(source, target, wire);
+        }
+
+        let last = NodeIndex::new(circuit.node_count() - 1);
+        circuit.remove_node(node);
+        for indices in tracked.iter_mut() {
+            for idx in indices.iter_mut() {
+                if *idx == last {
+                    *idx = node;
+                }
+            }
+        }
+        for source in sources.values_mut().flatten() {
+            if let Some((idx, bus)) = source {
+                if *idx == node {
+                    let bit = bus.range.as_ref().map_or(0, |r| r.start);
+                    *source = bit_sources.get(&bit).map(|(n, name, b)| {
+                        let bus = Bus {
+                            name: name.clone(),
+                            range: Some(*b..b + 1),
+                        };
+                        (*n, bus)
+                    });
+                }
+            }
+            if let Some((idx, _)) = source {
+                if *idx == last {
+                    *idx = node;
+                }
+            }
+        }
+    }
+}
+
+/// Creates chips for true/false/none literals that are `width` bits wide.
 fn make_literal_chip(
     value: Option<bool>,
+    width: usize,
     parent: *mut Chip,
     hdl_provider: &Rc<dyn HdlProvider>,
 ) -> Chip {
     let circuit = Circuit::new();
 
     let mut signals = BusMap::new();
-    signals.create_bus("out", 16).unwrap();
-    signals.insert_option(&Bus::from("out"), vec![value; 16]);
+    signals.create_bus("out", width).unwrap();
+    signals.insert_option(&Bus::from("out"), vec![value; width]);
 
     let name = match value {
         None => String::from("none"),
@@ -1236,6 +2945,7 @@ fn make_literal_chip(
 
     Chip {
         name,
+        instance: String::new(),
         ports: HashMap::new(),
         signals,
         hdl: None,
@@ -1250,6 +2960,15 @@ fn make_literal_chip(
         variables: HashMap::new(),
         components: Vec::new(),
         assignments: Vec::new(),
+        signal_sources: HashMap::new(),
+        profile: None,
+        allow_undriven_outputs: false,
+        allow_undriven_ports: false,
+        undriven: Vec::new(),
+        released: Vec::new(),
+        strict_widths: false,
+        optimize: true,
+        ancestors: Rc::new(Vec::new()),
     }
 }
 
@@ -1289,6 +3008,7 @@ fn make_nand_chip(parent: *mut Chip, hdl_provider: &Rc<dyn HdlProvider>) -> Chip
 
     Chip {
         name: String::from("nand"),
+        instance: String::new(),
         ports,
         signals,
         hdl: None,
@@ -1303,6 +3023,15 @@ fn make_nand_chip(parent: *mut Chip, hdl_provider: &Rc<dyn HdlProvider>) -> Chip
         variables: HashMap::new(),
         components: Vec::new(),
         assignments: Vec::new(),
+        signal_sources: HashMap::new(),
+        profile: None,
+        allow_undriven_outputs: false,
+        allow_undriven_ports: false,
+        undriven: Vec::new(),
+        released: Vec::new(),
+        strict_widths: false,
+        optimize: true,
+        ancestors: Rc::new(Vec::new()),
     }
 }
 
@@ -1320,6 +3049,7 @@ fn make_port_chip(
 
     Chip {
         name: String::from(name),
+        instance: String::new(),
         ports,
         signals,
         hdl: None,
@@ -1334,6 +3064,117 @@ fn make_port_chip(
         variables: HashMap::new(),
         components: Vec::new(),
         assignments: Vec::new(),
+        signal_sources: HashMap::new(),
+        profile: None,
+        allow_undriven_outputs: false,
+        allow_undriven_ports: false,
+        undriven: Vec::new(),
+        released: Vec::new(),
+        strict_widths: false,
+        optimize: true,
+        ancestors: Rc::new(Vec::new()),
+    }
+}
+
+// Name of the signal holding the value a chip drives onto a bidirectional
+// port. The port's own signal holds the value read from outside.
+fn drive_signal_name(port_name: &str) -> String {
+    format!("{}.drive", port_name)
+}
+
+// Value of a bit with several drivers. Undriven drivers are ignored and
+// drivers that disagree leave the bit unknown.
+fn resolve(drivers: &[Option<bool>]) -> Option<bool> {
+    let mut driven = drivers.iter().flatten();
+    let first = *driven.next()?;
+    if driven.all(|&d| d == first) {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+// Source of a bit driven by all of `drivers`. A resolver node is added to
+// the circuit if there is more than one.
+fn resolve_drivers(
+    circuit: &mut Circuit,
+    drivers: &[(NodeIndex, Bus)],
+    parent: *mut Chip,
+) -> Option<(NodeIndex, Bus)> {
+    if drivers.len() < 2 {
+        return drivers.first().cloned();
+    }
+    let hdl_provider = Rc::clone(&circuit[drivers[0].0].hdl_provider);
+    let resolver = circuit.add_node(make_resolver_chip(drivers.len(), parent, &hdl_provider));
+    for (i, (node, bus)) in drivers.iter().enumerate() {
+        let wire = Wire {
+            source: bus.clone(),
+            target: Bus {
+                name: String::from("in"),
+                range: Some(i..i + 1),
+            },
+        };
+        circuit.add_edge(*node, resolver, wire);
+    }
+    Some((
+        resolver,
+        Bus {
+            name: String::from("out"),
+            range: Some(0..1),
+        },
+    ))
+}
+
+fn make_resolver_chip(width: usize, parent: *mut Chip, hdl_provider: &Rc<dyn HdlProvider>) -> Chip {
+    let circuit = Circuit::new();
+    let mut signals = BusMap::new();
+    signals.create_bus("in", width).unwrap();
+    signals.create_bus("out", 1).unwrap();
+    let ports = HashMap::from([
+        (
+            String::from("in"),
+            Port {
+                name: Identifier::from("in"),
+                width,
+                direction: PortDirection::In,
+            },
+        ),
+        (
+            String::from("out"),
+            Port {
+                name: Identifier::from("out"),
+                width: 1,
+                direction: PortDirection::Out,
+            },
+        ),
+    ]);
+
+    Chip {
+        name: String::from("RESOLVE"),
+        instance: String::new(),
+        ports,
+        signals,
+        hdl: None,
+        elaborated: false,
+        circuit,
+        dirty: false,
+        input_port_nodes: Vec::new(),
+        output_port_nodes: Vec::new(),
+        cache: false,
+        parent,
+        hdl_provider: Rc::clone(hdl_provider),
+        variables: HashMap::new(),
+        components: Vec::new(),
+        assignments: Vec::new(),
+        signal_sources: HashMap::new(),
+        profile: None,
+        allow_undriven_outputs: false,
+        allow_undriven_ports: false,
+        undriven: Vec::new(),
+        released: Vec::new(),
+        strict_widths: false,
+        optimize: true,
+        ancestors: Rc::new(Vec::new()),
     }
 }
 
@@ -1342,23 +3183,12 @@ fn make_dff_chip(parent: *mut Chip, hdl_provider: &Rc<dyn HdlProvider>) -> Chip
     let mut signals = BusMap::new();
     signals.create_bus("in", 1).unwrap();
     signals.create_bus("out", 1).unwrap();
-    signals.insert_option(
-        &Bus {
-            name: String::from("in"),
-            range: Some(0..1),
-        },
-        vec![Some(false)],
-    );
-    signals.insert_option(
-        &Bus {
-            name: String::from("out"),
-            range: Some(0..1),
-        },
-        vec![Some(false)],
-    );
+    signals.insert_option(&Bus::from("in"), vec![Some(false)]);
+    signals.insert_option(&Bus::from("out"), vec![Some(false)]);
 
     Chip {
         name: String::from("DFF"),
+        instance: String::new(),
         ports: HashMap::from([
             (
                 String::from("in"),
@@ -1390,7 +3220,71 @@ fn make_dff_chip(parent: *mut Chip, hdl_provider: &Rc<dyn HdlProvider>) -> Chip
         variables: HashMap::new(),
         components: Vec::new(),
         assignments: Vec::new(),
+        signal_sources: HashMap::new(),
+        profile: None,
+        allow_undriven_outputs: false,
+        allow_undriven_ports: false,
+        undriven: Vec::new(),
+        released: Vec::new(),
+        strict_widths: false,
+        optimize: true,
+        ancestors: Rc::new(Vec::new()),
+    }
+}
+
+/// Verifies that a chip is instantiated with the number of generics it
+/// declares. The error lists the declared generic names and the values given.
+///
+/// * `allow_fewer` - Accept fewer generics than declared.
+fn check_generic_count<T: fmt::Display>(
+    hdl: &ChipHDL,
+    generics: &[T],
+    allow_fewer: bool,
+) -> Result<(), N2VError> {
+    let declared = hdl.generic_decls.len();
+    if generics.len() == declared || (allow_fewer && generics.len() < declared) {
+        return Ok(());
     }
+
+    let expected: Vec<String> = hdl.generic_decls.iter().map(|g| g.value.clone()).collect();
+    let got: Vec<String> = generics.iter().map(|g| g.to_string()).collect();
+    let expected = if expected.is_empty() {
+        String::from("no generics")
+    } else {
+        format!("<{}>", expected.join(", "))
+    };
+    Err(N2VError {
+        msg: format!(
+            "{} expects {} but got [{}]",
+            hdl.name,
+            expected,
+            got.join(", ")
+        ),
+        kind: ErrorKind::SimulationError(hdl.path.clone()),
+    })
+}
+
+/// Values of the constants of `hdl` as widths, for evaluating expressions
+/// that may still contain generics.
+pub fn constant_widths(hdl: &ChipHDL) -> HashMap<String, GenericWidth> {
+    hdl.constant_values()
+        .into_iter()
+        .map(|(name, value)| (name, GenericWidth::Terminal(Terminal::Num(value))))
+        .collect()
+}
+
+fn undefined_generic_error(
+    hdl_provider: &Rc<dyn HdlProvider>,
+    var: &Identifier,
+    signal: &str,
+) -> Box<dyn Error> {
+    Box::new(N2VError {
+        msg: format!(
+            "Undefined generic '{}' in index of signal {}.",
+            var.value, signal
+        ),
+        kind: ErrorKind::ParseIdentError(hdl_provider.clone(), var.clone()),
+    })
 }
 
 // Return the width of port name in hdl instantiated as component under parent variables.
@@ -1403,29 +3297,23 @@ fn make_dff_chip(parent: *mut Chip, hdl_provider: &Rc<dyn HdlProvider>) -> Chip
 /// * `hdl` - HDL for the chip that the signals belong to
 /// * 'assignments' - Vector of assignments pulled from the parts of hdl
 /// * `components` - Components to use when inferring widths. May or may not be
-///                  the same as HDL components due to loop expansion.
+///   the same as HDL components due to loop expansion.
 /// * `provider` - Responsible for retrieving HDL
 /// * `generics` - Generic values for instantiating chip corresponding to HDL (not a subcomponent).
+/// * `strict` - Error when mappings constrain a signal to different widths
+///   instead of widening it to the largest.
 pub fn infer_widths(
     hdl: &ChipHDL,
-    assignments: &Vec<AssignmentHDL>,
-    components: &Vec<Component>,
+    assignments: &[AssignmentHDL],
+    components: &[Component],
     provider: &Rc<dyn HdlProvider>,
-    generics: &Vec<GenericWidth>,
+    generics: &[GenericWidth],
+    strict: bool,
 ) -> Result<HashMap<String, GenericWidth>, Box<dyn Error>> {
-    // Assign values to generic variables.
-    if generics.len() > hdl.generic_decls.len() {
-        return Err(Box::new(N2VError {
-            msg: format!(
-                "Chip {} declares {} generics but instantiated with {}",
-                hdl.name,
-                hdl.generic_decls.len(),
-                generics.len()
-            ),
-            kind: ErrorKind::SimulationError(hdl.path.clone()),
-        }));
-    }
-    let mut variables = HashMap::new();
+    // Assign values to generic variables. Fewer generics than declared is
+    // allowed here because widths may still be symbolic.
+    check_generic_count(hdl, generics, true)?;
+    let mut variables = constant_widths(hdl);
 
     #[allow(clippy::needless_range_loop)]
     for gv in 0..generics.len() {
@@ -1434,8 +3322,31 @@ pub fn infer_widths(
 
     let mut inferred_widths: HashMap<String, GenericWidth> = HashMap::new();
     for port in &hdl.ports {
-        inferred_widths.insert(port.name.value.clone(), eval_expr(&port.width, &variables));
+        inferred_widths.insert(port.name.value.clone(), eval_expr(&port.width, &variables)?);
+    }
+
+    // Concrete widths each signal is constrained to, for strict checking.
+    // Recorded on the first pass over the mappings only.
+    let mut constraints: HashMap<String, Vec<WidthConstraint>> = HashMap::new();
+    if strict {
+        for port in &hdl.ports {
+            if let GenericWidth::Terminal(Terminal::Num(width)) =
+                eval_expr(&port.width, &variables)?
+            {
+                constraints
+                    .entry(port.name.value.clone())
+                    .or_default()
+                    .push(WidthConstraint {
+                        width,
+                        exact: true,
+                        source: format!("the declaration of port {}", port.name.value),
+                        ident: port.name.clone(),
+                    });
+            }
+        }
     }
+    let mut first_pass = true;
+
     let mut last_inferred_widths;
     loop {
         last_inferred_widths = inferred_widths.clone();
@@ -1448,26 +3359,28 @@ pub fn infer_widths(
                 .generic_params
                 .iter()
                 .map(|g| eval_expr(g, &variables))
-                .collect();
+                .collect::<Result<_, _>>()?;
 
             // Do not create a component chip here because that will
             // trigger elaboration of the entire component tree.
             // We only need the ports, and ports cannot be created with
             // for  loops, so this is sufficient enough to get
             // the variables map for looking up port widths.
-            let component_variables: HashMap<String, GenericWidth> = component_hdl
+            let mut component_variables: HashMap<String, GenericWidth> = component_hdl
                 .generic_decls
                 .iter()
                 .map(|x| x.value.clone())
                 .zip(generic_params)
                 .collect();
+            component_variables.extend(constant_widths(&component_hdl));
 
             for m in &part.mappings {
-                // skip false and true pseudo-signals
+                // skip literals and numeric constants
                 // TODO: Check to make sure that no chip is writing to false/true.
                 if &m.wire.name.to_lowercase() == "false"
                     || &m.wire.name.to_ascii_lowercase() == "true"
                     || &m.wire.name.to_ascii_lowercase() == "none"
+                    || numeric_literal(&m.wire.name).is_some()
                 {
                     continue;
                 }
@@ -1488,10 +3401,16 @@ pub fn infer_widths(
                 // I need to make the port_width from the component match the wire
                 // Get the width of the port referred to in the mapping.
                 // This uses the component chip variables because the width of the port is defined inside the component
-                let hdl_port_width = eval_expr(&port.width, &component_variables);
+                let hdl_port_width = eval_expr(&port.width, &component_variables)?;
 
                 let wire_start = m.wire.start.as_ref().map(|x| eval_expr(x, &variables));
-                let wire_end = m.wire.end.as_ref().map(|x| eval_expr(x, &variables));
+                let wire_start = wire_start.transpose()?;
+                let wire_end = m
+                    .wire
+                    .end
+                    .as_ref()
+                    .map(|x| eval_expr(x, &variables))
+                    .transpose()?;
 
                 // Convert inclusive range in HDL to exclusive Range in Rust
                 let mp_wire_range: Option<Range<GenericWidth>> = wire_start.map(|ws| Range {
@@ -1499,13 +3418,42 @@ pub fn infer_widths(
                     end: wire_end.unwrap() + GenericWidth::Terminal(Terminal::Num(1)),
                 });
                 let port_start = m.port.start.as_ref().map(|x| eval_expr(x, &variables));
-                let port_end = m.port.end.as_ref().map(|x| eval_expr(x, &variables));
+                let port_start = port_start.transpose()?;
+                let port_end = m
+                    .port
+                    .end
+                    .as_ref()
+                    .map(|x| eval_expr(x, &variables))
+                    .transpose()?;
                 // Convert inclusive range in HDL to exclusive Range in Rust
                 let mp_port_range: Option<Range<GenericWidth>> = port_start.map(|ps| Range {
                     start: ps,
                     end: port_end.unwrap() + GenericWidth::Terminal(Terminal::Num(1)),
                 });
 
+                // A whole signal must be exactly as wide as the port or port
+                // range. A slice only needs the signal to reach its end.
+                if strict && first_pass {
+                    let (width, exact) = match (&mp_wire_range, &mp_port_range) {
+                        (None, None) => (hdl_port_width.clone(), true),
+                        (None, Some(pr)) => (&pr.end - &pr.start, true),
+                        (Some(wr), _) => (wr.end.clone(), false),
+                    };
+                    if let GenericWidth::Terminal(Terminal::Num(width)) =
+                        eval_expr(&width, &variables)?
+                    {
+                        constraints
+                            .entry(m.wire.name.clone())
+                            .or_default()
+                            .push(WidthConstraint {
+                                width,
+                                exact,
+                                source: format!("{}({}={})", part.name.value, m.port, m.wire),
+                                ident: m.wire_ident.clone(),
+                            });
+                    }
+                }
+
                 // To line up widths, use the extracted, port range, wire range from the mapping, and any width previously found
                 match (
                     &mp_wire_range,
@@ -1579,7 +3527,7 @@ pub fn infer_widths(
                                 Box::new(w.clone()),
                             ),
                             &variables,
-                        );
+                        )?;
                         inferred_widths.insert(m.wire.name.clone(), max_width);
                     }
 
@@ -1623,35 +3571,76 @@ pub fn infer_widths(
                                 Box::new(w.clone()),
                             ),
                             &variables,
-                        );
+                        )?;
                         inferred_widths.insert(m.wire.name.clone(), max_width);
                     }
                 }
             }
         }
+        first_pass = false;
         if inferred_widths == last_inferred_widths {
             loop {
                 // This runs until fixpoint as well to deal with multiple layers of redirection
                 last_inferred_widths = inferred_widths.clone();
                 for a in assignments {
+                    // The left side of a concatenation is as wide as its
+                    // segments combined.
+                    if a.right.len() > 1 {
+                        if !inferred_widths.contains_key(&a.left.name) {
+                            let w = concat_width(&a.right, &inferred_widths, &variables)?;
+                            if let Some(w) = w {
+                                inferred_widths.insert(a.left.name.clone(), w);
+                            }
+                        }
+                        continue;
+                    }
+
+                    // An indexed side is a slice, so the widths of the two
+                    // signals need not match.
+                    let right = &a.right[0];
+                    if a.left.start.is_some() || right.start.is_some() {
+                        continue;
+                    }
                     let wl = inferred_widths.get(&a.left.name.clone());
-                    let wr = inferred_widths.get(&a.right.name.clone());
+                    let wr = inferred_widths.get(&right.name.clone());
 
                     match (wl, wr) {
+                        // The right side of an extension can be narrower, so
+                        // its width can't be inferred from the left side.
+                        (Some(_), None) if a.extension != Extension::None => {}
                         (Some(w), None) => {
-                            inferred_widths.insert(a.right.name.clone(), w.clone());
+                            inferred_widths.insert(right.name.clone(), w.clone());
                         }
                         (None, Some(w)) => {
                             inferred_widths.insert(a.left.name.clone(), w.clone());
                         }
+                        (Some(w1), Some(w2)) if a.extension != Extension::None => {
+                            if let (Ok(n1), Ok(n2)) = (
+                                eval_expr_numeric(w1, &HashMap::new()),
+                                eval_expr_numeric(w2, &HashMap::new()),
+                            ) {
+                                if n1 < n2 {
+                                    return Err(Box::new(N2VError {
+                                        msg: format!(
+                                            "Signal {} is narrower than {} and cannot be extended to it.",
+                                            &a.left.name, &right.name,
+                                        ),
+                                        kind: ErrorKind::ParseIdentError(
+                                            provider.clone(),
+                                            Identifier::from(right.name.as_str()),
+                                        ),
+                                    }));
+                                }
+                            }
+                        }
                         (Some(w1), Some(w2)) => {
-                            if w1 != w2 {
-                                let wname = a.right.name.clone();
+                            if !same_width(w1, w2) {
+                                let wname = right.name.clone();
                                 return Err(Box::new(N2VError {
                                     msg: format!(
                                         "Signal widths of {} and {} are not equal.",
                                         &a.left.name.clone(),
-                                        &a.right.name.clone(),
+                                        &right.name.clone(),
                                     ),
                                     kind: ErrorKind::ParseIdentError(
                                         provider.clone(),
@@ -1668,20 +3657,31 @@ pub fn infer_widths(
                 }
             }
             for a in assignments {
+                let right = &a.right[0];
+                if a.right.len() > 1 && !inferred_widths.contains_key(&a.left.name) {
+                    let (target, ident) = concatenation_target(a);
+                    return Err(Box::new(N2VError {
+                        msg: format!(
+                            "Width of concatenation assigned to {} cannot be inferred.",
+                            target
+                        ),
+                        kind: ErrorKind::ParseIdentError(provider.clone(), ident),
+                    }));
+                }
                 if let (None, None) = (
                     inferred_widths.get(&a.left.name.clone()),
-                    inferred_widths.get(&a.right.name.clone()),
+                    inferred_widths.get(&right.name.clone()),
                 ) {
                     // If neither widths have a source, throw an error. This allows us to make assumptions about widths later on.
                     return Err(Box::new(N2VError {
                         msg: format!(
                             "Signals {} and {} have no source or destination.",
                             &a.left.name.clone(),
-                            &a.right.name.clone(),
+                            &right.name.clone(),
                         ),
                         kind: ErrorKind::ParseIdentError(
                             provider.clone(),
-                            Identifier::from(a.right.name.clone().as_str()),
+                            Identifier::from(right.name.clone().as_str()),
                         ),
                     }));
                 }
@@ -1690,9 +3690,124 @@ pub fn infer_widths(
         }
     }
 
+    if strict {
+        check_width_constraints(&constraints, provider)?;
+    }
+
     Ok(inferred_widths)
 }
 
+/// Infers the widths of the signals of a chip like `infer_widths`, but fails
+/// when a port declaration or mapping implies a different width for a signal
+/// instead of widening the signal.
+/// Slices of a signal that does not have an exact width are allowed to
+/// widen it, so a signal can still be driven one bit at a time.
+pub fn check_strict_widths(
+    hdl: &ChipHDL,
+    provider: &Rc<dyn HdlProvider>,
+    generics: &[usize],
+) -> Result<(), Box<dyn Error>> {
+    let components = Chip::generate_components(hdl, generics)?;
+    let generics: Vec<GenericWidth> = generics
+        .iter()
+        .map(|g| GenericWidth::Terminal(Terminal::Num(*g)))
+        .collect();
+    infer_widths(
+        hdl,
+        &gather_assignments(&hdl.parts),
+        &components,
+        provider,
+        &generics,
+        true,
+    )?;
+    Ok(())
+}
+
+// A width that a port declaration or mapping requires of a signal.
+struct WidthConstraint {
+    width: usize,
+    // Exact widths come from whole signals. Slices only set a minimum.
+    exact: bool,
+    source: String,
+    ident: Identifier,
+}
+
+// Strict width inference: every exact width of a signal must agree and no
+// slice may reach past an exact width.
+fn check_width_constraints(
+    constraints: &HashMap<String, Vec<WidthConstraint>>,
+    provider: &Rc<dyn HdlProvider>,
+) -> Result<(), Box<dyn Error>> {
+    let mut names: Vec<&String> = constraints.keys().collect();
+    names.sort();
+    for name in names {
+        let signal_constraints = &constraints[name];
+        let reference = match signal_constraints.iter().find(|c| c.exact) {
+            Some(c) => c,
+            None => continue,
+        };
+        let conflict = signal_constraints.iter().find(|c| {
+            if c.exact {
+                c.width != reference.width
+            } else {
+                c.width > reference.width
+            }
+        });
+        if let Some(conflict) = conflict {
+            return Err(Box::new(N2VError {
+                msg: format!(
+                    "Signal {} is {} bits wide from {} but {} bits wide from {}.",
+                    name, reference.width, reference.source, conflict.width, conflict.source
+                ),
+                kind: ErrorKind::ParseIdentError(provider.clone(), conflict.ident.clone()),
+            }));
+        }
+    }
+    Ok(())
+}
+
+// What a concatenation is assigned to, for errors, and where it is. A
+// concatenation mapped to a part's port is named after the port rather than
+// the signal made for it.
+fn concatenation_target(a: &AssignmentHDL) -> (String, Identifier) {
+    match &a.port {
+        Some(port) => (format!("port {}", port.value), port.clone()),
+        None => (a.left.name.clone(), Identifier::from(a.left.name.as_str())),
+    }
+}
+
+// Width of a concatenation, if the width of every segment is known.
+fn concat_width(
+    segments: &[BusHDL],
+    inferred_widths: &HashMap<String, GenericWidth>,
+    variables: &HashMap<String, GenericWidth>,
+) -> Result<Option<GenericWidth>, N2VError> {
+    let mut total: Option<GenericWidth> = None;
+    for segment in segments {
+        let w = match (&segment.start, &segment.end) {
+            (Some(start), Some(end)) => GenericWidth::Expr(
+                Op::Add,
+                Box::new(GenericWidth::Expr(
+                    Op::Sub,
+                    Box::new(end.clone()),
+                    Box::new(start.clone()),
+                )),
+                Box::new(GenericWidth::Terminal(Terminal::Num(1))),
+            ),
+            (Some(_), None) => GenericWidth::Terminal(Terminal::Num(1)),
+            (None, _) => match inferred_widths.get(&segment.name) {
+                Some(w) => w.clone(),
+                None => return Ok(None),
+            },
+        };
+        total = Some(match total {
+            None => w,
+            Some(t) => GenericWidth::Expr(Op::Add, Box::new(t), Box::new(w)),
+        });
+    }
+    total.map(|t| eval_expr(&t, variables)).transpose()
+}
+
 /// Consolidates all assignments within the vector of parts passed as argument.
 pub fn gather_assignments(parts: &Vec<Part>) -> Vec<AssignmentHDL> {
     let mut assignment_vec = Vec::new();
@@ -1705,18 +3820,60 @@ pub fn gather_assignments(parts: &Vec<Part>) -> Vec<AssignmentHDL> {
     assignment_vec
 }
 
-fn nand(a: Option<bool>, b: Option<bool>) -> Option<bool> {
-    if a.is_none() || b.is_none() {
-        return None;
-    }
-
-    Some(!(a.unwrap() && b.unwrap()))
+// Whether two buses share a bit. A bus without a range is the whole signal.
+fn buses_overlap(a: &Bus, b: &Bus) -> bool {
+    a.name == b.name
+        && match (&a.range, &b.range) {
+            (Some(x), Some(y)) => x.start < y.end && y.start < x.end,
+            _ => true,
+        }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-
+// Whether a chip keeps state: it is a DFF or a builtin chip, or one of its
+// parts keeps state. Answers are cached in `stateful` by chip name.
+fn keeps_state(
+    hdl: &ChipHDL,
+    provider: &Rc<dyn HdlProvider>,
+    stateful: &mut HashMap<String, bool>,
+) -> Result<bool, Box<dyn Error>> {
+    if hdl.name.to_uppercase() == "DFF" || hdl.builtin.is_some() {
+        return Ok(true);
+    }
+    if let Some(&known) = stateful.get(&hdl.name) {
+        return Ok(known);
+    }
+    // Recursive chips are reported elsewhere, so stop at one.
+    stateful.insert(hdl.name.clone(), false);
+    let mut result = false;
+    for part in &hdl.parts {
+        let components = match part {
+            Part::Component(c) => std::slice::from_ref(c),
+            Part::Loop(l) => l.body.as_slice(),
+            Part::AssignmentHDL(_) | Part::Assert(_) => continue,
+        };
+        for c in components {
+            if keeps_state(&get_hdl(&c.name.value, provider)?, provider, stateful)? {
+                result = true;
+                break;
+            }
+        }
+    }
+    stateful.insert(hdl.name.clone(), result);
+    Ok(result)
+}
+
+fn nand(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    if a.is_none() || b.is_none() {
+        return None;
+    }
+
+    Some(!(a.unwrap() && b.unwrap()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
     use crate::scanner::Scanner;
     use std::env;
     use std::path::Path;
@@ -1739,6 +3896,21 @@ mod test {
         Simulator::new(chip)
     }
 
+    // Simulates the chip `name` in resources/tests/`dir`. Parts that are not
+    // in `dir` come from the nand2tetris solutions.
+    fn make_simulator_in(dir: &str, name: &str) -> Simulator {
+        let tests_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("tests");
+        let includes = [tests_dir.join("nand2tetris").join("solutions")];
+        let provider: Rc<dyn HdlProvider> =
+            Rc::new(FileReader::with_includes(&tests_dir.join(dir), &includes));
+        let hdl = get_hdl(name, &provider).expect("Parse error");
+        let chip =
+            Chip::new(&hdl, ptr::null_mut(), &provider, false, &[]).expect("Chip creation error");
+        Simulator::new(chip)
+    }
+
     #[test]
     fn test_nand2tetris_solution_not() {
         let mut simulator = make_simulator("Not.hdl");
@@ -1749,7 +3921,7 @@ mod test {
 
     #[test]
     fn test_simulator_buffer() {
-        let mut simulator = make_simulator("../../buffer/Buffer.hdl");
+        let mut simulator = make_simulator_in("buffer", "Buffer");
         let inputs = BusMap::try_from([("testin", false)]).expect("Error creating inputs");
         let outputs = simulator.simulate(&inputs).expect("simulation failure");
         assert_eq!(outputs.get_bus(&Bus::from("testout")), vec![Some(false)]);
@@ -1757,7 +3929,7 @@ mod test {
 
     #[test]
     fn test_simulator_buffer2() {
-        let mut simulator = make_simulator("../../buffer/Buffer2.hdl");
+        let mut simulator = make_simulator_in("buffer", "Buffer2");
         let inputs = BusMap::try_from([("testin", false)]).expect("Error creating inputs");
         let outputs = simulator.simulate(&inputs).expect("simulation failure");
         assert_eq!(outputs.get_bus(&Bus::from("testout")), vec![Some(false)]);
@@ -1765,7 +3937,7 @@ mod test {
 
     #[test]
     fn test_simulator_buffer3() {
-        let mut simulator = make_simulator("../../buffer/BufferTest3.hdl");
+        let mut simulator = make_simulator_in("buffer", "BufferTest3");
         let inputs = BusMap::try_from([("testin", false)]).expect("Error creating inputs");
         let outputs = simulator.simulate(&inputs).expect("simulation failure");
         assert_eq!(outputs.get_bus(&Bus::from("testout")), vec![Some(false)]);
@@ -1773,7 +3945,7 @@ mod test {
 
     #[test]
     fn test_simulator_buffer4() {
-        let mut simulator = make_simulator("../../buffer/Buffer4.hdl");
+        let mut simulator = make_simulator_in("buffer", "Buffer4");
         let inputs = BusMap::try_from([("in", false)]).expect("Error creating inputs");
         let outputs = simulator.simulate(&inputs).expect("simulation failure");
         assert_eq!(outputs.get_bus(&Bus::from("out")), vec![Some(false)]);
@@ -1781,18 +3953,44 @@ mod test {
 
     #[test]
     fn test_simulator_buffer5() {
-        let mut simulator = make_simulator("../../buffer/Buffer5.hdl");
+        let mut simulator = make_simulator_in("buffer", "Buffer5");
         let inputs = BusMap::try_from([("in", vec![true, false])]).expect("Error creating inputs");
         let outputs = simulator.simulate(&inputs).expect("simulation failure");
         assert_eq!(
             outputs.get_bus(&Bus::from("out")),
-            vec![Some(true), Some(false)]
+            vec![Some(false), Some(true)]
         );
     }
 
+    #[test]
+    fn test_simulator_constant_output_bit() {
+        let mut simulator = make_simulator_in("buffer", "ConstBit");
+        let inputs =
+            BusMap::try_from([("in", vec![true, false, true])]).expect("Error creating inputs");
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(
+            outputs.get_bus(&Bus::from("out")),
+            vec![Some(true), Some(true), Some(false), Some(false)]
+        );
+    }
+
+    #[test]
+    fn test_constant_output_bit_conflict() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("bad");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("ConstBitConflict", &provider).expect("Parse error");
+        match Chip::new(&hdl, ptr::null_mut(), &provider, true, &[]) {
+            Ok(_) => panic!("Expected a duplicate source error"),
+            Err(e) => assert!(e
+                .to_string()
+                .contains("Duplicate source for signal name out.")),
+        }
+    }
+
     #[test]
     fn test_simulator_buffer_literal() {
-        let mut simulator = make_simulator("../../buffer/BufferLiterals.hdl");
+        let mut simulator = make_simulator_in("buffer", "BufferLiterals");
         let inputs = BusMap::try_from([("in", true)]).expect("Error creating inputs");
         let outputs = simulator.simulate(&inputs).expect("simulation failure");
         assert_eq!(outputs.get_bus(&Bus::from("out")), vec![Some(true)]);
@@ -1907,11 +4105,7 @@ mod test {
         let outputs = simulator
             .simulate(&BusMap::try_from([("in", vec![false; 16])]).unwrap())
             .expect("simulation failure");
-        let b = Bus {
-            name: String::from("out"),
-            range: Some(0..16),
-        };
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
     }
 
     #[test]
@@ -1920,11 +4114,7 @@ mod test {
         let outputs = simulator
             .simulate(&BusMap::try_from([("a", vec![true; 16]), ("b", vec![true; 16])]).unwrap())
             .expect("simulation failure");
-        let b = Bus {
-            name: String::from("out"),
-            range: Some(0..16),
-        };
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
     }
 
     #[test]
@@ -1945,11 +4135,7 @@ mod test {
                 .unwrap(),
             )
             .expect("simulation failure");
-        let b = Bus {
-            name: String::from("out"),
-            range: Some(0..16),
-        };
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
     }
 
     #[test]
@@ -1958,10 +4144,7 @@ mod test {
         let outputs = simulator
             .simulate(&BusMap::try_from([("in", vec![true]), ("load", vec![true])]).unwrap())
             .expect("simulation failure");
-        assert_eq!(
-            outputs.get_bus(&Bus::try_from("out").unwrap()),
-            vec![Some(false)]
-        );
+        assert_eq!(outputs.get_bus(&Bus::from("out")), vec![Some(false)]);
         simulator.tick().expect("Tick failure");
         simulator
             .simulate(&BusMap::try_from([("in", vec![false]), ("load", vec![false])]).unwrap())
@@ -1979,22 +4162,256 @@ mod test {
     }
 
     #[test]
-    fn test_nand2tetris_solution_register() {
+    fn test_max_cycles() {
+        let mut simulator = make_simulator("Bit.hdl");
+        simulator.set_max_cycles(2);
+        assert_eq!(simulator.cycles(), 0);
+        simulator.tick().expect("Tick failure");
+        simulator.tick().expect("Tick failure");
+        assert_eq!(simulator.cycles(), 2);
+        assert!(simulator.tick().is_err());
+        assert_eq!(simulator.cycles(), 2);
+    }
+
+    #[test]
+    fn test_cache_stats() {
+        let mut simulator = make_simulator("Xor.hdl");
+        assert_eq!(simulator.cache_stats(), CacheStats::default());
+
+        let inputs = BusMap::try_from([("a", true), ("b", false)]).expect("Error creating inputs");
+        simulator.simulate(&inputs).expect("simulation failure");
+        let stats = simulator.cache_stats();
+        assert!(stats.misses > 0);
+        assert_eq!(stats.entries, simulator.input_cache.len());
+
+        // A second Xor sees the inputs the first one did.
+        let mut second = make_simulator("Xor.hdl");
+        second.input_cache = std::mem::take(&mut simulator.input_cache);
+        second.simulate(&inputs).expect("simulation failure");
+        assert_eq!(second.cache_stats().hits, 1);
+        assert_eq!(second.cache_stats().misses, 0);
+
+        second.reset();
+        assert_eq!(second.cache_stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn test_cache_entry_insertion_order() {
+        let mut ab = BusMap::new();
+        ab.create_bus("a", 2).unwrap();
+        ab.create_bus("b", 1).unwrap();
+        ab.insert(Bus::from("a"), vec![true, false]);
+        ab.insert(Bus::from("b"), vec![true]);
+
+        let mut ba = BusMap::new();
+        ba.create_bus("b", 1).unwrap();
+        ba.insert(Bus::from("b"), vec![true]);
+        ba.create_bus("a", 2).unwrap();
+        ba.insert(Bus::from("a"), vec![true, false]);
+
+        let mut cache: Cache = HashMap::new();
+        cache.insert(InputCacheEntry::new("And", &ab), BusMap::new());
+        assert!(cache.contains_key(&InputCacheEntry::new("And", &ba)));
+        assert!(!cache.contains_key(&InputCacheEntry::new("Or", &ba)));
+
+        ba.insert(Bus::from("b"), vec![false]);
+        assert!(!cache.contains_key(&InputCacheEntry::new("And", &ba)));
+    }
+
+    #[test]
+    fn test_drive_csv() {
         let mut simulator = make_simulator("Register.hdl");
-        let b = Bus {
-            name: String::from("out"),
-            range: Some(0..16),
+        let outputs = simulator
+            .drive_csv("in,load%B\n5,1\n,0\n9\n\n,1\n,0\n")
+            .expect("simulation failure");
+        let values: Vec<u64> = outputs
+            .iter()
+            .map(|o| o.as_unsigned("out").unwrap())
+            .collect();
+        assert_eq!(values, vec![0, 5, 5, 5, 9]);
+
+        let err = simulator
+            .drive_csv("in,clk\n1,1\n")
+            .expect_err("Expected unknown column error");
+        assert!(err
+            .to_string()
+            .contains("CSV column clk is not an input port of Register."));
+
+        let err = simulator
+            .drive_csv("in,load%B\n1,2\n")
+            .expect_err("Expected bad value error");
+        assert!(err
+            .to_string()
+            .contains("Line 2 of CSV: 2 is not a valid 1-bit binary value for load."));
+    }
+
+    #[test]
+    fn test_gate_count() {
+        let count = |dir: &str, name: &str, generics: &[usize]| {
+            let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+            let base_path = manifest_dir.join("resources").join("tests").join(dir);
+            let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+            let hdl = get_hdl(name, &provider).expect("Parse error");
+            let mut chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, generics)
+                .expect("Chip construction failure");
+            chip.gate_count().expect("Elaboration failure")
         };
 
+        let solutions = "nand2tetris/solutions";
+        assert_eq!(count(solutions, "And", &[]), GateCount { nand: 2, dff: 0 });
+        assert_eq!(count(solutions, "Mux", &[]), GateCount { nand: 8, dff: 0 });
+        assert_eq!(
+            count(solutions, "Register", &[]),
+            GateCount { nand: 128, dff: 16 }
+        );
+        assert_eq!(
+            count("de1-hdl", "NotGen", &[5]),
+            GateCount { nand: 5, dff: 0 }
+        );
+        assert_eq!(count("de1-hdl", "NotTwiceGen", &[3]).nand, 6);
+    }
+
+    #[test]
+    fn test_signal_accessors() {
+        let simulator = make_simulator("Mux16.hdl");
+        let names = simulator.chip.signal_names();
+        assert!(names.contains(&String::from("a")));
+        assert!(names.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(simulator.chip.signal_width("a"), Some(16));
+        assert_eq!(simulator.chip.signal_width("sel"), Some(1));
+        assert_eq!(simulator.chip.signal_width("c"), None);
+    }
+
+    #[test]
+    fn test_probe() {
+        let mut simulator = make_simulator("Add16.hdl");
+        simulator
+            .elaborate_path(&["FullAdder.6", "HalfAdder.1"])
+            .unwrap();
+        let err = simulator.elaborate_path(&["FullAdder.15"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Chip Add16 has no part named FullAdder.15."));
+
+        let inputs = BusMap::from_integers(&[("a", 0x00FF, 16), ("b", 1, 16)]).unwrap();
+        simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(simulator.probe(&["c7"]), Some(vec![Some(true)]));
+        assert_eq!(simulator.probe(&["c8"]), Some(vec![Some(false)]));
+        assert_eq!(
+            simulator.probe(&["FullAdder.6", "absum"]),
+            Some(vec![Some(true)])
+        );
+        assert_eq!(
+            simulator.probe(&["FullAdder.6", "HalfAdder.1", "carry"]),
+            Some(vec![Some(true)])
+        );
+        assert_eq!(simulator.probe(&["FullAdder.6", "missing"]), None);
+        assert_eq!(simulator.probe(&["Missing", "carry"]), None);
+
+        // Same inputs as the first FullAdder, so its outputs came from the
+        // cache and it has no internal signals.
+        assert_eq!(
+            simulator.probe(&["FullAdder.5", "carry"]),
+            Some(vec![Some(true)])
+        );
+        assert_eq!(simulator.probe(&["FullAdder.5", "absum"]), None);
+
+        // Signals driven by assignments, whose nodes are removed.
+        let mut simulator = make_simulator_in("buffer", "Concat");
+        let inputs = BusMap::from_integers(&[("high", 0x12, 8), ("low", 0x34, 8)]);
+        simulator
+            .simulate(&inputs.unwrap())
+            .expect("simulation failure");
+        let bits: Vec<Option<bool>> = (0..16).rev().map(|i| Some(0x3412 >> i & 1 == 1)).collect();
+        assert_eq!(simulator.probe(&["wide"]), Some(bits));
+    }
+
+    #[test]
+    fn test_reset_state() {
+        let mut simulator = make_simulator("Register.hdl");
+        simulator
+            .simulate(&BusMap::try_from([("in", vec![true; 16]), ("load", vec![true])]).unwrap())
+            .expect("simulation failure");
+        simulator.tick().expect("Tick failure");
+        let hold = BusMap::try_from([("in", vec![false; 16]), ("load", vec![false])]).unwrap();
+        let outputs = simulator.simulate(&hold).expect("simulation failure");
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
+
+        simulator.reset();
+        let outputs = simulator.simulate(&hold).expect("simulation failure");
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
+
+        // DFFs nested several chips deep, in RAM8 > Register > Bit > DFF.
+        let mut simulator = make_simulator("RAM8.hdl");
+        let write = BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 1, 1), ("address", 5, 3)])
+            .unwrap();
+        simulator.simulate(&write).expect("simulation failure");
+        simulator.tick().expect("Tick failure");
+        let read =
+            BusMap::from_integers(&[("in", 0, 16), ("load", 0, 1), ("address", 5, 3)]).unwrap();
+        let outputs = simulator.simulate(&read).expect("simulation failure");
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
+
+        simulator.reset();
+        assert!(simulator.dirty_dffs.is_empty());
+        assert_eq!(simulator.cache_stats().entries, 0);
+        let outputs = simulator.simulate(&read).expect("simulation failure");
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
+    }
+
+    #[test]
+    fn test_load_words() {
+        let mut simulator = make_simulator("RAM8.hdl");
+        let word = |value: u16| {
+            (0..16)
+                .rev()
+                .map(|i| value >> i & 1 == 1)
+                .collect::<Vec<bool>>()
+        };
+        let read = |address| {
+            BusMap::from_integers(&[("in", 0, 16), ("load", 0, 1), ("address", address, 3)])
+                .unwrap()
+        };
+        let outputs = simulator.simulate(&read(2)).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("out"), Some(0));
+
+        // Each word goes to the register at its address.
+        simulator
+            .load_words(&[], &[word(1), word(2), word(0xABCD)])
+            .unwrap();
+        let outputs = simulator.simulate(&read(2)).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("out"), Some(0xABCD));
+
+        simulator.load_words(&["Register.7"], &[word(7)]).unwrap();
+        let outputs = simulator.simulate(&read(7)).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("out"), Some(7));
+
+        let words = simulator.read_words(&[], 16).unwrap();
+        assert_eq!(words.len(), 8);
+        assert_eq!(
+            words[2],
+            word(0xABCD).into_iter().map(Some).collect::<Vec<_>>()
+        );
+        assert_eq!(words[3], vec![Some(false); 16]);
+
+        let err = simulator
+            .load_words(&["Register.0"], &[word(1), word(2)])
+            .unwrap_err();
+        assert!(err.to_string().contains("has 16 DFFs"));
+    }
+
+    #[test]
+    fn test_nand2tetris_solution_register() {
+        let mut simulator = make_simulator("Register.hdl");
         let outputs = simulator
             .simulate(&BusMap::try_from([("in", vec![true; 16]), ("load", vec![true])]).unwrap())
             .expect("simulation failure");
-        assert_eq!(outputs.get_bus(&b), vec![Some(false); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator.tick().expect("Tick failure");
         simulator
             .simulate(&BusMap::try_from([("in", vec![false; 16]), ("load", vec![false])]).unwrap())
@@ -2002,285 +4419,191 @@ mod test {
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
     }
 
     #[test]
     fn test_nand2tetris_solution_ram8() {
         let mut simulator = make_simulator("RAM8.hdl");
-        let b = Bus {
-            name: String::from("out"),
-            range: Some(0..16),
-        };
-
         let outputs = simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![true]),
-                    ("address", vec![false, true, false]),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 1, 1), ("address", 2, 3)])
+                    .unwrap(),
             )
             .expect("simulation failure");
-        assert_eq!(outputs.get_bus(&b), vec![Some(false); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![true]),
-                    ("address", vec![false, true, false]),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 1, 1), ("address", 2, 3)])
+                    .unwrap(),
             )
             .expect("simulation failure");
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![false]),
-                    ("address", vec![false, false, false]),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 0, 1), ("address", 0, 3)])
+                    .unwrap(),
             )
             .expect("simulation failure");
         //simulator.tick();
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(false); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
     }
 
     #[test]
     fn test_nand2tetris_solution_ram512() {
         let mut simulator = make_simulator("RAM512.hdl");
-        let b = Bus {
-            name: String::from("out"),
-            range: Some(0..16),
-        };
-
-        let address1 = vec![false, false, false, false, false, false, false, true, false];
-        let address2 = vec![false, false, false, false, false, false, true, false, false];
-
         let outputs = simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![true]),
-                    ("address", address1.clone()),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 1, 1), ("address", 2, 9)])
+                    .unwrap(),
             )
             .expect("simulation failure");
-        assert_eq!(outputs.get_bus(&b), vec![Some(false); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![true]),
-                    ("address", address1),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 1, 1), ("address", 2, 9)])
+                    .unwrap(),
             )
             .expect("simulation failure");
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![false]),
-                    ("address", address2),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 0, 1), ("address", 4, 9)])
+                    .unwrap(),
             )
             .expect("simulation failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(false); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
     }
 
     #[test]
     fn test_nand2tetris_solution_ram4k() {
         let mut simulator = make_simulator("RAM4K.hdl");
-        let b = Bus {
-            name: String::from("out"),
-            range: Some(0..16),
-        };
-
-        let address1 = vec![
-            false, false, false, false, false, false, false, true, false, false, false, false,
-        ];
-        let address2 = vec![
-            false, false, false, false, false, false, true, false, false, false, false, false,
-        ];
-
         let outputs = simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![true]),
-                    ("address", address1.clone()),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 1, 1), ("address", 16, 12)])
+                    .unwrap(),
             )
             .expect("simulation failure");
-        assert_eq!(outputs.get_bus(&b), vec![Some(false); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![true]),
-                    ("address", address1),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 1, 1), ("address", 16, 12)])
+                    .unwrap(),
             )
             .expect("simulation failure");
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![false]),
-                    ("address", address2),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 0, 1), ("address", 32, 12)])
+                    .unwrap(),
             )
             .expect("simulation failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(false); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
     }
 
     #[test]
     fn test_nand2tetris_solution_ram16k() {
         let mut simulator = make_simulator("RAM16K.hdl");
-        let b = Bus {
-            name: String::from("out"),
-            range: Some(0..16),
-        };
-
-        let address1 = vec![
-            false, false, false, false, false, false, false, true, false, false, false, false,
-            true, false,
-        ];
-        let address2 = vec![
-            false, false, false, false, false, false, true, false, false, false, false, false,
-            true, true,
-        ];
-        let address3 = vec![
-            true, true, true, true, true, true, true, true, false, false, true, true, true, true,
-        ];
-
         let outputs = simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![true]),
-                    ("address", address1.clone()),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 1, 1), ("address", 66, 14)])
+                    .unwrap(),
             )
             .expect("simulation failure");
-        assert_eq!(outputs.get_bus(&b), vec![Some(false); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![true]),
-                    ("address", address1),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 1, 1), ("address", 66, 14)])
+                    .unwrap(),
             )
             .expect("simulation failure");
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
 
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
         simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![true; 16]),
-                    ("load", vec![true]),
-                    ("address", address2),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0xFFFF, 16), ("load", 1, 1), ("address", 131, 14)])
+                    .unwrap(),
             )
             .expect("simulation failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(false); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(true); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true); 16]);
 
         simulator
             .simulate(
-                &BusMap::try_from([
-                    ("in", vec![false; 16]),
-                    ("load", vec![true]),
-                    ("address", address3),
-                ])
-                .unwrap(),
+                &BusMap::from_integers(&[("in", 0, 16), ("load", 1, 1), ("address", 16335, 14)])
+                    .unwrap(),
             )
             .expect("simulation failure");
         simulator.tick().expect("Tick failure");
         let outputs = simulator
             .chip
             .get_port_values_for_direction(PortDirection::Out);
-        assert_eq!(outputs.get_bus(&b), vec![Some(false); 16]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false); 16]);
     }
 
     #[test]
@@ -2356,6 +4679,635 @@ mod test {
         let chip = Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new())
             .expect("Chip creation error");
         assert_eq!(chip.circuit.edge_count(), 4);
+
+        // Merged wires are labeled with their full ranges.
+        let dot = chip.to_dot();
+        assert!(dot.starts_with("digraph \"Inc16\" {\n"));
+        assert!(dot.contains("[label=\"in\", shape=invhouse];"));
+        assert!(dot.contains("[label=\"out\", shape=house];"));
+        assert!(dot.contains("[label=\"true\", shape=plaintext];"));
+        assert!(dot.contains("[label=\"Add16\", shape=box];"));
+        assert!(dot.contains("n0 -> n1 [label=\"in[0..15] -> a[0..15]\"];"));
+        assert!(dot.contains("[label=\"out[0..14] -> b[1..15]\"];"));
+    }
+
+    // Assignment chains should be collapsed so that only the top-level
+    // port nodes and the Or component remain.
+    #[test]
+    fn test_optimize_circuit_passthrough() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("buffer");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let contents = provider.get_hdl("BufferTest3.hdl").unwrap();
+        let mut scanner = Scanner::new(contents.as_str(), provider.get_path("BufferTest3.hdl"));
+        let mut parser = Parser::new(&mut scanner, provider.clone());
+        let hdl = parser.parse().expect("Parse error");
+        let chip = Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new())
+            .expect("Chip creation error");
+
+        // testin port, testout port, Or, false literal.
+        assert_eq!(chip.circuit.node_count(), 4);
+        assert_eq!(chip.circuit.edge_count(), 3);
+        assert_eq!(chip.circuit[chip.input_port_nodes[0]].name, "testin");
+        assert_eq!(chip.circuit[chip.output_port_nodes[0]].name, "testout");
+    }
+
+    #[test]
+    fn test_passthrough_simulation() {
+        let mut simulator = make_simulator_in("buffer", "BufferTest3");
+        let inputs = BusMap::try_from([("testin", true)]).expect("Error creating inputs");
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.get_bus(&Bus::from("testout")), vec![Some(true)]);
+    }
+
+    #[test]
+    fn test_extend_assignment() {
+        let mut simulator = make_simulator_in("buffer", "Extend");
+        let mut inputs = BusMap::new();
+        inputs.create_bus("small", 8).unwrap();
+        for (small, zout, sout) in [(5, 5, 5), (0x80, 0x80, 0xFF80), (0xFF, 0xFF, 0xFFFF)] {
+            inputs.set_integer("small", small).unwrap();
+            let outputs = simulator.simulate(&inputs).expect("simulation failure");
+            assert_eq!(outputs.as_unsigned("zout"), Some(zout));
+            assert_eq!(outputs.as_unsigned("sout"), Some(sout));
+        }
+    }
+
+    #[test]
+    fn test_extend_narrower_error() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("bad");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("NarrowExtend", &provider).expect("Parse error");
+        let err = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())
+            .expect_err("Expected narrow extension error");
+        assert!(err.to_string().contains("narrower"));
+    }
+
+    #[test]
+    fn test_concat_assignment() {
+        let mut simulator = make_simulator_in("buffer", "Concat");
+        let mut inputs = BusMap::new();
+        inputs.create_bus("high", 8).unwrap();
+        inputs.create_bus("low", 8).unwrap();
+        inputs.set_integer("high", 0xAB).unwrap();
+        inputs.set_integer("low", 0xCD).unwrap();
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("out"), Some(0xABCD));
+        assert_eq!(outputs.as_unsigned("nibbles"), Some(0xDA));
+        assert_eq!(outputs.as_unsigned("swap"), Some(0xCDAB));
+    }
+
+    #[test]
+    fn test_concat_width_error() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("bad");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("ConcatWidth", &provider).expect("Parse error");
+        let err = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())
+            .expect_err("Expected concatenation width error");
+        assert!(err
+            .to_string()
+            .contains("Concatenation is 12 bits wide but out is 16 bits wide."));
+    }
+
+    #[test]
+    fn test_concat_port_mapping() {
+        let mut simulator = make_simulator_in("buffer", "ConcatPort");
+        let mut inputs = BusMap::new();
+        inputs.create_bus("high", 8).unwrap();
+        inputs.create_bus("low", 8).unwrap();
+        inputs.create_bus("sel", 1).unwrap();
+        inputs.set_integer("high", 0xAB).unwrap();
+        inputs.set_integer("low", 0xCD).unwrap();
+        inputs.set_integer("sel", 0).unwrap();
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("out"), Some(0xABCD));
+        assert_eq!(outputs.as_unsigned("mixed"), Some(0xDACB));
+
+        inputs.set_integer("sel", 1).unwrap();
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("out"), Some(0xCDAB));
+
+        // The signals made for the concatenations are not the chip's own.
+        assert!(!simulator
+            .chip
+            .signal_names()
+            .iter()
+            .any(|n| n.contains("_cat")));
+        assert_eq!(simulator.probe(&["in_cat0"]), None);
+
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = manifest_dir.join("resources/tests/bad/ConcatPortWidth.hdl");
+        let solutions = manifest_dir.join("resources/tests/nand2tetris/solutions");
+        let (hdl, provider) =
+            parse_hdl_path_with_includes(&path, &[solutions]).expect("Parse error");
+        let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+        let err = Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Concatenation is 12 bits wide but port in is 16 bits wide."));
+    }
+
+    #[test]
+    fn test_concat_port_names() {
+        // The chip's own in_cat0 keeps its name, so out is not inverted twice.
+        let mut simulator = make_simulator_in("buffer", "ConcatName");
+        let inputs = BusMap::from_integers(&[("high", 0x12, 8), ("low", 0x34, 8)]).unwrap();
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("out"), Some(0x1234));
+        assert_eq!(outputs.as_unsigned("swapped"), Some(0xCBED));
+        assert!(simulator
+            .chip
+            .signal_names()
+            .contains(&String::from("in_cat0")));
+        assert!(simulator.probe(&["in_cat0"]).is_some());
+
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = manifest_dir.join("resources/tests/bad/ConcatOutput.hdl");
+        let solutions = manifest_dir.join("resources/tests/nand2tetris/solutions");
+        let (hdl, provider) =
+            parse_hdl_path_with_includes(&path, &[solutions]).expect("Parse error");
+        let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+        let err = Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("A concatenation cannot be mapped to output port out."));
+    }
+
+    fn add8(inputs: &BusMap) -> BusMap {
+        let mut outputs = BusMap::new();
+        outputs.create_bus("out", 8).unwrap();
+        if let (Some(a), Some(b)) = (inputs.as_unsigned("a"), inputs.as_unsigned("b")) {
+            outputs.set_integer("out", ((a + b) % 256) as i64).unwrap();
+        }
+        outputs
+    }
+
+    #[test]
+    fn test_builtin_chip() {
+        let simulator = || make_simulator_in("builtin", "Sum3");
+
+        let mut inputs = BusMap::new();
+        for (name, value) in [("a", 100), ("b", 50), ("c", 200)] {
+            inputs.create_bus(name, 8).unwrap();
+            inputs.set_integer(name, value).unwrap();
+        }
+        let err = simulator()
+            .simulate(&inputs)
+            .expect_err("Expected missing builtin error");
+        assert!(err
+            .to_string()
+            .contains("No implementation is registered for builtin chip Add8."));
+
+        let mut simulator = simulator();
+        simulator.register_builtin("Add8", Box::new(add8));
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("out"), Some(94));
+    }
+
+    fn tri_buf(inputs: &BusMap) -> BusMap {
+        let mut outputs = BusMap::new();
+        outputs.create_bus("out", 1).unwrap();
+        if inputs.get_name("en") == vec![Some(true)] {
+            outputs.insert_option(&Bus::from("out"), inputs.get_name("in"));
+        }
+        outputs
+    }
+
+    #[test]
+    fn test_inout_ports() {
+        let simulator = |name: &str| {
+            let mut simulator = make_simulator_in("inout", name);
+            simulator.register_builtin("TriBuf", Box::new(tri_buf));
+            simulator
+        };
+
+        // A bidirectional port is both an input and an output.
+        let mut port = simulator("BusPort");
+        assert_eq!(
+            port.input_ports(),
+            vec![
+                (String::from("bus"), 1),
+                (String::from("en"), 1),
+                (String::from("in"), 1)
+            ]
+        );
+        assert_eq!(
+            port.output_ports(),
+            vec![(String::from("bus"), 1), (String::from("out"), 1)]
+        );
+        assert_eq!(port.chip.signal_names(), vec!["bus", "en", "in", "out"]);
+        assert_eq!(port.probe(&["bus.drive"]), None);
+
+        // The port reports what the chip drives, and the chip reads the
+        // resolved bus.
+        let mut inputs = BusMap::try_from([("in", true), ("en", true)]).unwrap();
+        inputs.create_bus("bus", 1).unwrap();
+        let outputs = port.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.get_name("bus"), vec![Some(true)]);
+        assert_eq!(outputs.get_name("out"), vec![Some(true)]);
+
+        let inputs = BusMap::try_from([("in", true), ("en", false), ("bus", false)]).unwrap();
+        let outputs = port.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.get_name("bus"), vec![None]);
+        assert_eq!(outputs.get_name("out"), vec![Some(false)]);
+
+        // Drivers that disagree leave the bus unknown.
+        let inputs = BusMap::try_from([("in", true), ("en", true), ("bus", false)]).unwrap();
+        let outputs = port.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.get_name("out"), vec![None]);
+
+        let mut shared = simulator("SharedBus");
+        let cases = [
+            ((true, true, false, false), Some(true)),
+            ((false, false, true, true), Some(true)),
+            ((true, false, false, false), None),
+            ((true, true, false, true), None),
+            ((true, true, true, true), Some(true)),
+        ];
+        for ((a, ena, b, enb), expected) in cases {
+            let inputs =
+                BusMap::try_from([("a", a), ("ena", ena), ("b", b), ("enb", enb)]).unwrap();
+            let outputs = shared.simulate(&inputs).expect("simulation failure");
+            assert_eq!(outputs.get_name("ra"), vec![expected]);
+            assert_eq!(outputs.get_name("rb"), vec![expected]);
+        }
+    }
+
+    #[test]
+    fn test_undriven_output_bits() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("bad");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("Undriven", &provider).expect("Parse error");
+        let err = Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new())
+            .expect_err("Expected undriven output error");
+        assert!(err
+            .to_string()
+            .contains("Chip Undriven has output bits with no source: flag, out[1], out[3]."));
+
+        // When allowed, the undriven bits are recorded and are unknown.
+        let mut chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())
+            .expect("Chip creation error");
+        chip.allow_undriven_outputs = true;
+        let mut simulator = Simulator::new(chip);
+        let inputs = BusMap::try_from([("a", vec![false; 4])]).unwrap();
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(
+            outputs.get_name("out"),
+            vec![None, Some(true), None, Some(true)]
+        );
+        assert_eq!(outputs.get_name("flag"), vec![None]);
+        assert_eq!(
+            simulator.chip.undriven_outputs(),
+            vec!["flag", "out[1]", "out[3]"]
+        );
+
+        // Bits of a part are named by the part's path.
+        let hdl = get_hdl("UsesUndriven", &provider).expect("Parse error");
+        let mut chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())
+            .expect("Chip creation error");
+        chip.allow_undriven_outputs = true;
+        let mut simulator = Simulator::new(chip);
+        simulator
+            .elaborate_path(&["Undriven.0"])
+            .expect("Elaboration error");
+        assert_eq!(
+            simulator.chip.undriven_outputs(),
+            vec!["Undriven.0/flag", "Undriven.0/out[1]", "Undriven.0/out[3]"]
+        );
+
+        // Allowing a chip's own undriven ports does not carry over to its
+        // parts.
+        let hdl = get_hdl("Undriven", &provider).expect("Parse error");
+        let mut chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())
+            .expect("Chip creation error");
+        chip.allow_undriven_ports = true;
+        chip.elaborate().expect("Elaboration error");
+        assert_eq!(chip.undriven_outputs(), vec!["flag", "out[1]", "out[3]"]);
+
+        let hdl = get_hdl("UsesUndriven", &provider).expect("Parse error");
+        let mut chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())
+            .expect("Chip creation error");
+        chip.allow_undriven_ports = true;
+        let mut simulator = Simulator::new(chip);
+        let err = simulator
+            .elaborate_path(&["Undriven.0"])
+            .expect_err("Expected undriven output error");
+        assert!(err
+            .to_string()
+            .contains("Chip Undriven has output bits with no source"));
+    }
+
+    #[test]
+    fn test_strict_widths() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&tests_dir.join("bad")));
+        let hdl = get_hdl("WidthPromotion", &provider).expect("Parse error");
+
+        // Lenient inference widens a to 9 bits.
+        let components = Chip::generate_components(&hdl, &[]).expect("Component error");
+        let widths = infer_widths(&hdl, &[], &components, &provider, &[], false)
+            .expect("Width inference error");
+        assert_eq!(widths["a"], GenericWidth::Terminal(Terminal::Num(9)));
+
+        let err = check_strict_widths(&hdl, &provider, &[]).expect_err("Expected width error");
+        assert!(err.to_string().contains(
+            "Signal a is 8 bits wide from the declaration of port a but 9 bits wide from Nand(b=a[8..8])."
+        ));
+
+        // Signals driven one bit at a time are still allowed.
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&tests_dir.join("arm")));
+        let hdl = get_hdl("Add16", &provider).expect("Parse error");
+        check_strict_widths(&hdl, &provider, &[]).expect("Strict width error");
+    }
+
+    #[test]
+    fn test_wide_literals() {
+        let mut simulator = make_simulator_in("literal", "Tie32");
+
+        let inputs = BusMap::try_from([("in", false)]).unwrap();
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.get_name("zeros"), vec![Some(false); 32]);
+        assert_eq!(outputs.get_name("ones"), vec![Some(true); 32]);
+        assert_eq!(outputs.get_name("wide"), vec![Some(false); 40]);
+    }
+
+    // Numeric constants are read from the true and false literals, bit 0
+    // first.
+    #[test]
+    fn test_numeric_literal() {
+        let mut simulator = make_simulator_in("literal", "Const");
+
+        let inputs = BusMap::try_from([("in", false)]).unwrap();
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("answer"), Some(42));
+        assert_eq!(outputs.as_unsigned("low"), Some(5));
+        assert_eq!(outputs.as_unsigned("big"), Some(4294967295));
+
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("literal");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("ConstTooWide", &provider).expect("Parse error");
+        let err = Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("The constant 16 does not fit in the 4 bits of port in."));
+    }
+
+    // Tying a port to none is intentional: the port and everything computed
+    // from it are unknown.
+    #[test]
+    fn test_none_literal() {
+        let mut simulator = make_simulator_in("literal", "TieNone");
+
+        for a in [false, true] {
+            let inputs = BusMap::try_from([("a", a)]).unwrap();
+            let outputs = simulator.simulate(&inputs).expect("simulation failure");
+            assert_eq!(outputs.get_name("out"), vec![None]);
+            assert_eq!(outputs.get_name("unknown"), vec![None; 32]);
+            assert_eq!(outputs.get_name("wide"), vec![None; 8]);
+        }
+    }
+
+    // `in[i +: 2]` selects in[i] and in[i+1] on each loop iteration.
+    #[test]
+    fn test_indexed_slice_loop() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("slice");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("NandWindows", &provider).expect("Parse error");
+
+        let Part::Loop(loop_part) = &hdl.parts[0] else {
+            panic!("Expected a loop");
+        };
+        let window = &loop_part.body[0].mappings[0].wire;
+        assert_eq!(window.start.as_ref().unwrap().to_string(), "i");
+        assert_eq!(window.end.as_ref().unwrap().to_string(), "(i + 1)");
+
+        let chip = Chip::new(&hdl, ptr::null_mut(), &provider, false, &Vec::new())
+            .expect("Chip creation error");
+        let mut simulator = Simulator::new(chip);
+        for value in 0..16 {
+            let mut inputs = BusMap::new();
+            inputs.create_bus("in", 4).unwrap();
+            inputs.set_integer("in", value).unwrap();
+            let outputs = simulator.simulate(&inputs).expect("simulation failure");
+            for i in 0..3 {
+                let expected = !((value >> i) & 1 == 1 && (value >> (i + 1)) & 1 == 1);
+                assert_eq!(outputs.get_name("out")[2 - i], Some(expected));
+            }
+        }
+    }
+
+    // Loops can count down and skip values with STEP.
+    #[test]
+    fn test_loop_step() {
+        let simulate = |name: &str, value: i64| {
+            let mut inputs = BusMap::new();
+            inputs.create_bus("in", 8).unwrap();
+            inputs.set_integer("in", value).unwrap();
+            let outputs = make_simulator_in("step", name)
+                .simulate(&inputs)
+                .expect("simulation failure");
+            outputs.as_unsigned("out")
+        };
+
+        assert_eq!(simulate("ReverseNot8", 0b0000_0011), Some(0b0011_1111));
+        assert_eq!(simulate("ReverseNot8", 0b1010_0000), Some(0b1111_1010));
+        assert_eq!(simulate("PairNand8", 0b1100_1011), Some(0b0110));
+    }
+
+    // Majority uses And and Or from gates.hdl.
+    #[test]
+    fn test_chips_from_multi_chip_file() {
+        let mut simulator = make_simulator_in("multichip", "Majority");
+        for i in 0..8 {
+            let bits = [i & 1 != 0, i & 2 != 0, i & 4 != 0];
+            let inputs = BusMap::try_from([
+                ("a", vec![bits[0]]),
+                ("b", vec![bits[1]]),
+                ("c", vec![bits[2]]),
+            ])
+            .unwrap();
+            let outputs = simulator.simulate(&inputs).expect("simulation failure");
+            let majority = bits.iter().filter(|b| **b).count() >= 2;
+            assert_eq!(outputs.get_name("out"), vec![Some(majority)]);
+        }
+    }
+
+    #[test]
+    fn test_recursive_chip() {
+        let inputs = BusMap::try_from([("in", false)]).unwrap();
+
+        for (name, chain) in [
+            ("Itself", "Itself -> Itself"),
+            ("Ping", "Ping -> Pong -> Ping"),
+        ] {
+            let err = make_simulator_in("bad", name)
+                .simulate(&inputs)
+                .expect_err("Expected recursion error");
+            assert!(err
+                .to_string()
+                .contains(&format!("Recursive chip instantiation: {}", chain)));
+        }
+    }
+
+    #[test]
+    fn test_port_accessors() {
+        let simulator = make_simulator("Mux16.hdl");
+        assert_eq!(
+            simulator.input_ports(),
+            vec![
+                (String::from("a"), 16),
+                (String::from("b"), 16),
+                (String::from("sel"), 1)
+            ]
+        );
+        assert_eq!(simulator.output_ports(), vec![(String::from("out"), 16)]);
+        assert_eq!(simulator.port_width("sel"), Some(1));
+        assert_eq!(simulator.port_width("c"), None);
+    }
+
+    #[test]
+    fn test_circuit_view() {
+        let mut simulator = make_simulator("And.hdl");
+        simulator.chip.elaborate().expect("Elaboration error");
+        let view = CircuitView::from(&simulator.chip);
+
+        let names: Vec<&str> = view.nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(view.nodes.len(), simulator.chip.circuit.node_count());
+        assert!(names.contains(&"nand"));
+        assert!(names.contains(&"Not"));
+        for (i, node) in view.nodes.iter().enumerate() {
+            assert_eq!(node.id, i);
+        }
+        assert_eq!(view.edges.len(), simulator.chip.circuit.edge_count());
+        for edge in &view.edges {
+            assert!(edge.from < view.nodes.len() && edge.to < view.nodes.len());
+        }
+
+        let json: serde_json::Value = serde_json::to_value(&view).unwrap();
+        let not = view.nodes.iter().find(|n| n.name == "Not").unwrap();
+        assert_eq!(
+            json["nodes"][not.id]["ports"][0],
+            serde_json::json!({"name": "in", "width": 1, "direction": "In"})
+        );
+        assert!(json["edges"][0]["source_bus"]["name"].is_string());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut simulator = make_simulator("And.hdl");
+        let inputs = BusMap::try_from([("a", true), ("b", true)]).unwrap();
+        simulator.simulate(&inputs).expect("Simulation error");
+        let snapshot = simulator.snapshot();
+
+        assert_eq!(snapshot["name"], "And");
+        assert!(snapshot.get("id").is_none());
+        assert_eq!(snapshot["signals"]["out"], serde_json::json!([true]));
+
+        // And is a Nand followed by a Not, which is a Nand too.
+        let nodes = snapshot["nodes"].as_array().unwrap();
+        let not = nodes.iter().find(|n| n["name"] == "Not").unwrap();
+        assert_eq!(not["signals"]["in"], serde_json::json!([false]));
+        assert_eq!(not["signals"]["out"], serde_json::json!([true]));
+        let nand = not["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|n| n["name"] == "nand");
+        assert_eq!(nand.unwrap()["signals"]["a"], serde_json::json!([false]));
+        let view = CircuitView::from(&simulator.chip);
+        assert_eq!(view.nodes[not["id"].as_u64().unwrap() as usize].name, "Not");
+    }
+
+    #[test]
+    fn test_settle_iterations() {
+        let mut simulator = make_simulator_in("bad", "Oscillator");
+        let inputs = BusMap::try_from([("in", false)]).unwrap();
+
+        // Unknown values never oscillate, so the loop settles.
+        simulator.simulate(&inputs).expect("simulation failure");
+
+        // Once the loop has a known value it flips on every pass.
+        for component in simulator.chip.circuit.node_weights_mut() {
+            if component.name.to_uppercase() == "NAND" {
+                for port in ["a", "b"] {
+                    component
+                        .signals
+                        .insert_option(&Bus::from(port), vec![Some(false)]);
+                }
+                component.dirty = true;
+            }
+        }
+        simulator.settle_iterations = Some(10);
+        let err = simulator
+            .simulate(&inputs)
+            .expect_err("Expected settle error");
+        assert!(err
+            .to_string()
+            .contains("did not settle after 10 iterations"));
+    }
+
+    #[test]
+    fn test_combinational_loop() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+
+        // Parts wired in a loop settle if their bits are not in a loop.
+        let includes = [tests_dir.join("nand2tetris").join("solutions")];
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::with_includes(
+            &tests_dir.join("slice"),
+            &includes,
+        ));
+        let hdl = get_hdl("BitFeedback", &provider).expect("Parse error");
+        let chip = Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new())
+            .expect("Chip creation error");
+        let mut simulator = Simulator::new(chip);
+        let outputs = simulator
+            .simulate(&BusMap::try_from([("a", false)]).unwrap())
+            .expect("simulation failure");
+        assert_eq!(outputs.get_name("out"), vec![Some(false), Some(true)]);
+
+        // A loop that doesn't settle is named.
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&tests_dir.join("bad")));
+        let hdl = get_hdl("Oscillator", &provider).expect("Parse error");
+        let chip = Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new())
+            .expect("Chip creation error");
+        let mut simulator = Simulator::new(chip);
+        for component in simulator.chip.circuit.node_weights_mut() {
+            if component.name.to_uppercase() == "NAND" {
+                for port in ["a", "b"] {
+                    component
+                        .signals
+                        .insert_option(&Bus::from(port), vec![Some(false)]);
+                }
+                component.dirty = true;
+            }
+        }
+        let err = simulator
+            .simulate(&BusMap::try_from([("in", false)]).unwrap())
+            .expect_err("Expected settle error");
+        assert!(err.to_string().contains("combinational loop through x."));
+    }
+
+    #[test]
+    fn test_generic_count_error() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("arm");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("MuxGen", &provider).expect("Parse error");
+
+        let err = Chip::new(&hdl, ptr::null_mut(), &provider, false, &[4, 8])
+            .expect_err("Expected generic count error");
+        assert!(err
+            .to_string()
+            .contains("MuxGen expects <X> but got [4, 8]"));
+
+        assert!(Chip::new(&hdl, ptr::null_mut(), &provider, false, &[4]).is_ok());
     }
 
     // Tests that multiple assignments to the same bit of a signal produce
@@ -2388,6 +5340,91 @@ mod test {
         assert!(chip.is_ok());
     }
 
+    #[test]
+    fn test_duplicate_input_mapping() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("bad");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        for name in ["DoubleMap", "OverlapMap"] {
+            let hdl = get_hdl(name, &provider).expect("Parse error");
+            let err = match Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new()) {
+                Ok(_) => panic!("Expected duplicate mapping error for {}", name),
+                Err(e) => e,
+            };
+            let port = if name == "DoubleMap" {
+                "a of component Mux"
+            } else {
+                "in of component Buffer3"
+            };
+            assert!(err
+                .to_string()
+                .contains(&format!("Input port {} is mapped more than once.", port)));
+        }
+    }
+
+    #[test]
+    fn test_constants() {
+        let mut simulator = make_simulator_in("constants", "ConstNot");
+        assert_eq!(simulator.port_width("in"), Some(4));
+
+        let inputs = BusMap::try_from([("in", vec![false, true, true, false])])
+            .expect("Error creating inputs");
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(
+            outputs.get_bus(&Bus::from("out")),
+            vec![Some(true), Some(false), Some(false), Some(true)]
+        );
+        assert_eq!(outputs.get_bus(&Bus::from("topclear")), vec![Some(true)]);
+    }
+
+    #[test]
+    fn test_multiplied_width() {
+        let mut simulator = make_simulator_in("multiply", "Join4");
+        let mut inputs = BusMap::new();
+        inputs.create_bus("high", 4).unwrap();
+        inputs.create_bus("low", 4).unwrap();
+        inputs.set_integer("high", 0xA).unwrap();
+        inputs.set_integer("low", 0x5).unwrap();
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("out"), Some(0xA5));
+    }
+
+    #[test]
+    fn test_divided_width() {
+        let mut simulator = make_simulator_in("divide", "Split8");
+        let inputs = BusMap::from_integers(&[("in", 0b1011_0110, 8)]).unwrap();
+        let outputs = simulator.simulate(&inputs).expect("simulation failure");
+        assert_eq!(outputs.as_unsigned("low"), Some(0b0110));
+        assert_eq!(outputs.as_unsigned("middle"), Some(1));
+        assert_eq!(outputs.as_unsigned("rest"), Some(0b10));
+
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("bad");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("DivideByZero", &provider).expect("Parse error");
+        assert!(Chip::new(&hdl, ptr::null_mut(), &provider, false, &[2]).is_ok());
+        let err = match Chip::new(&hdl, ptr::null_mut(), &provider, false, &[0]) {
+            Ok(_) => panic!("Expected division by zero error"),
+            Err(e) => e,
+        };
+        assert!(err.to_string().contains("Division by zero in (8 / 0)"));
+    }
+
+    #[test]
+    fn test_undefined_generic_index() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("bad");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("UndefinedGeneric", &provider).expect("Parse error");
+        let err = match Chip::new(&hdl, ptr::null_mut(), &provider, true, &[4]) {
+            Ok(_) => panic!("Expected undefined generic error"),
+            Err(e) => e,
+        };
+        let msg = err.to_string();
+        assert!(msg.contains("Undefined generic 'M' in index of signal in."));
+        assert!(msg.contains("Not(in=in[M], out=out);"));
+    }
+
     // Tests that component instantiations provide inputs for all bits of component input ports.
     #[test]
     fn test_disconnected_component_inputs() {
@@ -2398,7 +5435,66 @@ mod test {
         let mut scanner = Scanner::new(contents.as_str(), provider.get_path("TwoAssign.hdl"));
         let mut parser = Parser::new(&mut scanner, provider.clone());
         let hdl = parser.parse().expect("Parse error");
-        let chip = Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new());
-        assert!(chip.is_err());
+        let err = match Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new()) {
+            Ok(_) => panic!("Expected missing input error"),
+            Err(e) => e.to_string(),
+        };
+        assert!(err.contains("Component And does not provide inputs for b."));
+
+        // Each missing bit of a wide port is listed.
+        let solutions = manifest_dir.join("resources/tests/nand2tetris/solutions");
+        let provider: Rc<dyn HdlProvider> =
+            Rc::new(FileReader::with_includes(&base_path, &[solutions]));
+        let hdl = get_hdl("DisconnectedBits", &provider).expect("Parse error");
+        let err = match Chip::new(&hdl, ptr::null_mut(), &provider, true, &Vec::new()) {
+            Ok(_) => panic!("Expected missing input error"),
+            Err(e) => e.to_string(),
+        };
+        assert!(err.contains("Component Mux16 does not provide inputs for b[3], b[15], sel."));
+    }
+
+    // Assertions are checked after every simulation, including those of
+    // parts.
+    #[test]
+    fn test_assert() {
+        let simulator = |name: &str| make_simulator_in("assert", name);
+        let mut inputs = BusMap::new();
+        inputs.create_bus("in", 1).unwrap();
+
+        let mut ten = simulator("Ten");
+        for value in [0, 1] {
+            inputs.set_integer("in", value).unwrap();
+            let outputs = ten.simulate(&inputs).expect("simulation failure");
+            assert_eq!(outputs.as_unsigned("out"), Some(10));
+        }
+
+        let err = simulator("Broken")
+            .simulate(&inputs)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(
+            "`ASSERT out = 5;` on line 8 of Broken.hdl failed: out is 1010, expected 0101."
+        ));
+        let err = simulator("Outer")
+            .simulate(&inputs)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("on line 8 of Broken.hdl in part Broken.0 failed: out is 1010"));
+        simulator("Bits")
+            .simulate(&inputs)
+            .expect("simulation failure");
+
+        // A value that doesn't fit is an error as soon as the chip is
+        // elaborated.
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("assert");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("TooWide", &provider).expect("Parse error");
+        let err = Chip::new(&hdl, ptr::null_mut(), &provider, true, &[])
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("The constant 16 does not fit in the 4 bits of ten."));
     }
 }
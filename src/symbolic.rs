@@ -0,0 +1,327 @@
+//! Symbolic simulation of combinational chips.
+//!
+//! Instead of true and false, each bit carries a boolean expression over the
+//! chip's input bits. The expressions are built from the NAND gates of the
+//! elaborated circuit, so they show how a chip's gates add up to boolean
+//! algebra. Expressions are trees, which grow quickly with the size of the
+//! chip, so this is meant for small chips.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ptr;
+use std::rc::Rc;
+
+use petgraph::algo::toposort;
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+
+use crate::error::{ErrorKind, N2VError};
+use crate::parser::{get_hdl, HdlProvider, PortDirection};
+use crate::simulator::Chip;
+
+/// Value of one bit, in terms of the input bits of the top-level chip.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BoolExpr {
+    Const(bool),
+    /// An input bit, such as `a` for a one bit port or `a[3]`.
+    Input(String),
+    Nand(Box<BoolExpr>, Box<BoolExpr>),
+    // Only produced by `simplify`.
+    Not(Box<BoolExpr>),
+    And(Box<BoolExpr>, Box<BoolExpr>),
+    Or(Box<BoolExpr>, Box<BoolExpr>),
+}
+
+impl BoolExpr {
+    /// Rewrites NAND gates as NOT, AND and OR where possible and folds
+    /// constants. For example `nand(nand(a, b), nand(a, b))` becomes `a & b`.
+    pub fn simplify(&self) -> BoolExpr {
+        match self {
+            BoolExpr::Nand(a, b) => {
+                let a = a.simplify();
+                let b = b.simplify();
+                match (a, b) {
+                    (BoolExpr::Const(x), BoolExpr::Const(y)) => BoolExpr::Const(!(x && y)),
+                    (BoolExpr::Const(false), _) | (_, BoolExpr::Const(false)) => {
+                        BoolExpr::Const(true)
+                    }
+                    (BoolExpr::Const(true), x) | (x, BoolExpr::Const(true)) => not(x),
+                    (a, b) if a == b => not(a),
+                    (BoolExpr::Not(a), BoolExpr::Not(b)) => BoolExpr::Or(a, b),
+                    (a, b) => BoolExpr::Nand(Box::new(a), Box::new(b)),
+                }
+            }
+            BoolExpr::Not(x) => not(x.simplify()),
+            BoolExpr::And(a, b) => BoolExpr::And(Box::new(a.simplify()), Box::new(b.simplify())),
+            BoolExpr::Or(a, b) => BoolExpr::Or(Box::new(a.simplify()), Box::new(b.simplify())),
+            BoolExpr::Const(_) | BoolExpr::Input(_) => self.clone(),
+        }
+    }
+}
+
+// Negates an expression, cancelling a negation it already has.
+fn not(x: BoolExpr) -> BoolExpr {
+    match x {
+        BoolExpr::Const(v) => BoolExpr::Const(!v),
+        BoolExpr::Not(x) => *x,
+        BoolExpr::Nand(a, b) => BoolExpr::And(a, b),
+        x => BoolExpr::Not(Box::new(x)),
+    }
+}
+
+impl fmt::Display for BoolExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoolExpr::Const(v) => write!(f, "{}", v),
+            BoolExpr::Input(name) => write!(f, "{}", name),
+            BoolExpr::Nand(a, b) => write!(f, "nand({}, {})", a, b),
+            BoolExpr::Not(x) => write!(f, "!{}", x),
+            BoolExpr::And(a, b) => write!(f, "({} & {})", a, b),
+            BoolExpr::Or(a, b) => write!(f, "({} | {})", a, b),
+        }
+    }
+}
+
+// Bits of every bus of a node. None is a bit with no known value.
+type NodeValues = HashMap<String, Vec<Option<BoolExpr>>>;
+
+/// Simulates the chip `chip` symbolically and returns an expression for each
+/// bit of each output port, with bit 0 first. The chip must not have DFFs,
+/// bidirectional ports or builtin parts.
+///
+/// ```ignore
+/// let outputs = symbolic("And", &provider)?;
+/// assert_eq!(outputs["out"][0].simplify().to_string(), "(a & b)");
+/// ```
+pub fn symbolic(
+    chip: &str,
+    provider: &Rc<dyn HdlProvider>,
+) -> Result<HashMap<String, Vec<BoolExpr>>, Box<dyn Error>> {
+    let hdl = get_hdl(chip, provider)?;
+    let mut chip = Chip::new(&hdl, ptr::null_mut(), provider, false, &[])?;
+    if has_inout_ports(&chip) {
+        return Err(symbolic_error(format!(
+            "Chip {} is bidirectional, so it cannot be simulated symbolically.",
+            chip.name
+        )));
+    }
+
+    let mut inputs = NodeValues::new();
+    for (name, port) in &chip.ports {
+        if !port.direction.is_input() {
+            continue;
+        }
+        let bits = (0..port.width)
+            .map(|i| {
+                let bit = match port.width {
+                    1 => name.clone(),
+                    _ => format!("{}[{}]", name, i),
+                };
+                Some(BoolExpr::Input(bit))
+            })
+            .collect();
+        inputs.insert(name.clone(), bits);
+    }
+
+    let mut res = HashMap::new();
+    for (name, bits) in chip_outputs(&mut chip, &inputs)? {
+        let mut exprs = Vec::new();
+        for (i, bit) in bits.into_iter().enumerate() {
+            match bit {
+                Some(expr) => exprs.push(expr),
+                None => {
+                    return Err(symbolic_error(format!(
+                        "Bit {} of output {} of chip {} has no known value.",
+                        i, name, chip.name
+                    )))
+                }
+            }
+        }
+        res.insert(name, exprs);
+    }
+    Ok(res)
+}
+
+fn symbolic_error(msg: String) -> Box<dyn Error> {
+    Box::new(N2VError {
+        msg,
+        kind: ErrorKind::SimulationError(None),
+    })
+}
+
+// Output port values of a chip given its input port values.
+fn chip_outputs(chip: &mut Chip, inputs: &NodeValues) -> Result<NodeValues, Box<dyn Error>> {
+    if !chip.elaborated {
+        chip.elaborate()?;
+    }
+    let order = toposort(&chip.circuit, None).map_err(|_| {
+        symbolic_error(format!(
+            "Chip {} has a loop, so it cannot be simulated symbolically.",
+            chip.name
+        ))
+    })?;
+
+    let mut values: HashMap<NodeIndex, NodeValues> = HashMap::new();
+    for node in order {
+        let mut node_inputs = NodeValues::new();
+        for e in chip.circuit.edges_directed(node, Direction::Incoming) {
+            let wire = e.weight();
+            let source = values
+                .get(&e.source())
+                .and_then(|v| v.get(&wire.source.name));
+            let width = chip.circuit[node]
+                .signal_width(&wire.target.name)
+                .unwrap_or(0);
+            let bits = node_inputs
+                .entry(wire.target.name.clone())
+                .or_insert_with(|| vec![None; width]);
+            let source_range = wire.source.range.clone().unwrap_or(0..1);
+            let target_range = wire.target.range.clone().unwrap_or(0..1);
+            for (s, t) in source_range.zip(target_range) {
+                bits[t] = source.and_then(|bits| bits[s].clone());
+            }
+        }
+
+        let component = &mut chip.circuit[node];
+        let node_values = if chip.input_port_nodes.contains(&node) {
+            let port_values = inputs.get(&component.name).cloned().unwrap_or_default();
+            HashMap::from([(String::from("in"), port_values)])
+        } else {
+            component_outputs(component, node_inputs)?
+        };
+        values.insert(node, node_values);
+    }
+
+    let mut outputs = NodeValues::new();
+    for node in &chip.output_port_nodes {
+        let port_name = chip.circuit[*node].name.clone();
+        let bits = values[node].get("in").cloned().unwrap_or_default();
+        outputs.insert(port_name, bits);
+    }
+    Ok(outputs)
+}
+
+fn has_inout_ports(chip: &Chip) -> bool {
+    chip.ports
+        .values()
+        .any(|p| p.direction == PortDirection::InOut)
+}
+
+// Values of the buses of one node in a circuit, including its inputs.
+fn component_outputs(
+    component: &mut Chip,
+    mut inputs: NodeValues,
+) -> Result<NodeValues, Box<dyn Error>> {
+    let unsupported = |kind: &str| {
+        symbolic_error(format!(
+            "Chip {} is {}, so it cannot be simulated symbolically.",
+            component.name, kind
+        ))
+    };
+
+    // Port, assignment and literal nodes.
+    if component.ports.is_empty() {
+        let literal = match component.name.as_str() {
+            "true" => Some(Some(BoolExpr::Const(true))),
+            "false" => Some(Some(BoolExpr::Const(false))),
+            "none" => Some(None),
+            _ => None,
+        };
+        if let (Some(value), Some(width)) = (literal, component.signal_width("out")) {
+            inputs.insert(String::from("out"), vec![value; width]);
+        }
+        return Ok(inputs);
+    }
+
+    let bit = |inputs: &NodeValues, name: &str| inputs.get(name).and_then(|b| b[0].clone());
+    match component.name.to_uppercase().as_str() {
+        "NAND" => {
+            let out = match (bit(&inputs, "a"), bit(&inputs, "b")) {
+                (Some(a), Some(b)) => Some(BoolExpr::Nand(Box::new(a), Box::new(b))),
+                _ => None,
+            };
+            inputs.insert(String::from("out"), vec![out]);
+            return Ok(inputs);
+        }
+        "DFF" => return Err(unsupported("sequential")),
+        "BUFFER" => {
+            let out = inputs.get("in").cloned().unwrap_or_default();
+            inputs.insert(String::from("out"), out);
+            return Ok(inputs);
+        }
+        _ => {}
+    }
+    if component
+        .hdl
+        .as_ref()
+        .and_then(|h| h.builtin.as_ref())
+        .is_some()
+    {
+        return Err(unsupported("a builtin chip"));
+    }
+    // Resolver nodes join the drivers of a bidirectional port.
+    if component.hdl.is_none() || has_inout_ports(component) {
+        return Err(unsupported("bidirectional"));
+    }
+
+    let outputs = chip_outputs(component, &inputs)?;
+    inputs.extend(outputs);
+    Ok(inputs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::FileReader;
+    use std::path::Path;
+
+    fn provider(dir: &str) -> Rc<dyn HdlProvider> {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        Rc::new(FileReader::new(
+            &manifest_dir.join("resources/tests").join(dir),
+        ))
+    }
+
+    #[test]
+    fn test_symbolic_and() {
+        let outputs = symbolic("And", &provider("nand2tetris/solutions")).unwrap();
+        let ab = || {
+            BoolExpr::Nand(
+                Box::new(BoolExpr::Input(String::from("a"))),
+                Box::new(BoolExpr::Input(String::from("b"))),
+            )
+        };
+        assert_eq!(
+            outputs["out"],
+            vec![BoolExpr::Nand(Box::new(ab()), Box::new(ab()))]
+        );
+        assert_eq!(outputs["out"][0].simplify().to_string(), "(a & b)");
+    }
+
+    #[test]
+    fn test_symbolic_simplify() {
+        let provider = provider("nand2tetris/solutions");
+        let outputs = symbolic("Mux", &provider).unwrap();
+        assert_eq!(
+            outputs["out"][0].simplify().to_string(),
+            "((a & !sel) | (b & sel))"
+        );
+
+        let outputs = symbolic("Mux4Way16", &provider).unwrap();
+        assert_eq!(outputs["out"].len(), 16);
+        assert_eq!(
+            outputs["out"][3].simplify().to_string(),
+            "((((a[3] & !sel[0]) | (b[3] & sel[0])) & !sel[1]) \
+             | (((c[3] & !sel[0]) | (d[3] & sel[0])) & sel[1]))"
+        );
+    }
+
+    #[test]
+    fn test_symbolic_sequential() {
+        let err = symbolic("Bit", &provider("nand2tetris/solutions"))
+            .expect_err("Expected an error for a sequential chip");
+        assert!(err.to_string().contains("cannot be simulated symbolically"));
+    }
+}
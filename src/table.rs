@@ -0,0 +1,227 @@
+//! Exhaustive truth tables.
+//!
+//! Every combination of input values is simulated, so the number of rows
+//! doubles with each input bit. `TableOptions` caps the number of rows and
+//! can report progress and cache statistics for long runs. `format_table`
+//! writes the values of each column in binary, decimal or hex.
+
+use crate::busmap::{format_bits, BusMap, Radix};
+use crate::error::{ErrorKind, N2VError};
+use crate::expr::eval_expr_numeric;
+use crate::parser::{ChipHDL, HdlProvider};
+use crate::simulator::{Chip, Simulator};
+use std::collections::HashMap;
+use std::error::Error;
+use std::ptr;
+use std::rc::Rc;
+
+pub type Table = Vec<Vec<Vec<Option<bool>>>>;
+
+/// A table whose values have been written out by `format_table`.
+#[allow(dead_code)] // Used by the web interface in lib.rs.
+pub type FormattedTable = Vec<Vec<String>>;
+
+/// Number of rows between progress reports.
+pub const PROGRESS_INTERVAL: u64 = 4096;
+
+pub struct TableOptions {
+    /// Tables with more rows than this are an error.
+    pub max_rows: u64,
+    /// Print the number of rows simulated so far to stderr.
+    pub progress: bool,
+    /// Print how often chip outputs were found in the input cache to
+    /// stderr after the last row.
+    pub cache_stats: bool,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions {
+            max_rows: 1024,
+            progress: false,
+            cache_stats: false,
+        }
+    }
+}
+
+/// Formats each cell of a table with the radix of its column in `formats`.
+/// Columns missing from `formats` are binary.
+#[allow(dead_code)] // Used by the web interface in lib.rs.
+pub fn format_table(
+    columns: &[String],
+    table: &Table,
+    formats: &HashMap<String, Radix>,
+) -> FormattedTable {
+    let radixes: Vec<Radix> = columns
+        .iter()
+        .map(|c| formats.get(c).copied().unwrap_or_default())
+        .collect();
+    table
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(&radixes)
+                .map(|(bits, r)| format_bits(bits, *r))
+                .collect()
+        })
+        .collect()
+}
+
+/// Simulates every input combination for a chip, with `generics` as the
+/// arguments of a generic chip.
+/// Returns (column list, row values)
+pub fn full_table(
+    hdl: &ChipHDL,
+    provider: &Rc<dyn HdlProvider>,
+    generics: &[usize],
+    options: &TableOptions,
+) -> Result<(Vec<String>, Table), Box<dyn Error>> {
+    let chip = Chip::new(hdl, ptr::null_mut(), provider, false, generics)?;
+    let mut simulator = Simulator::new(chip);
+
+    let mut variables = hdl.constant_values();
+    variables.extend(
+        hdl.generic_decls
+            .iter()
+            .map(|g| g.value.clone())
+            .zip(generics.to_vec()),
+    );
+    let mut input_widths: Vec<(String, usize)> = Vec::new();
+    for p in hdl.ports.iter().filter(|p| p.direction.is_input()) {
+        input_widths.push((
+            p.name.value.clone(),
+            eval_expr_numeric(&p.width, &variables)?,
+        ));
+    }
+    let total_width: usize = input_widths.iter().map(|(_, w)| w).sum();
+
+    let column_names: Vec<String> = hdl.ports.iter().map(|p| p.name.value.clone()).collect();
+
+    let mut column_values: Vec<Vec<Vec<Option<bool>>>> = vec![];
+
+    // Checked before simulating anything so a huge table fails right away.
+    let total_rows = match 1u64.checked_shl(total_width as u32) {
+        Some(rows) if total_width < 64 && rows <= options.max_rows => rows,
+        rows => {
+            let rows = match rows {
+                Some(rows) if total_width < 64 => rows.to_string(),
+                _ => format!("2^{}", total_width),
+            };
+            return Err(Box::new(N2VError {
+                msg: format!(
+                    "Truth table has {} rows, more than the maximum of {}.",
+                    rows, options.max_rows
+                ),
+                kind: ErrorKind::Other,
+            }));
+        }
+    };
+
+    for i in 0..total_rows {
+        if options.progress && i > 0 && i % PROGRESS_INTERVAL == 0 {
+            eprintln!("Simulated {} of {} rows", i, total_rows);
+        }
+
+        let binary_string = format!("{:0total_width$b}", i);
+        let mut bools: Vec<bool> = binary_string
+            .chars()
+            .map(|c| match c {
+                '0' => false,
+                '1' => true,
+                _ => {
+                    panic!("expected 0 or 1");
+                }
+            })
+            .collect();
+
+        let mut m: HashMap<String, Vec<bool>> = HashMap::new();
+        for (name, w) in &input_widths {
+            let port_bools = bools.split_off(bools.len() - w);
+            m.insert(name.clone(), port_bools);
+        }
+
+        let inputs = match BusMap::try_from(m) {
+            Ok(x) => x,
+            Err(s) => {
+                return Err(Box::new(N2VError {
+                    msg: s,
+                    kind: ErrorKind::Other,
+                }));
+            }
+        };
+        let outputs = simulator.simulate(&inputs)?;
+
+        let mut row: Vec<Vec<Option<bool>>> = vec![];
+        for s in &column_names {
+            row.push(outputs.get_name(s));
+        }
+        column_values.push(row);
+    }
+
+    if options.progress {
+        eprintln!("Simulated {} of {} rows", total_rows, total_rows);
+    }
+    if options.cache_stats {
+        eprintln!("{}", simulator.cache_stats());
+    }
+
+    Ok((column_names, column_values))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_hdl_path;
+
+    use std::env;
+    use std::path::Path;
+
+    #[test]
+    fn test_max_rows() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let solutions = manifest_dir.join("resources/tests/nand2tetris/solutions");
+        let (hdl, provider) = parse_hdl_path(&solutions.join("Xor.hdl")).expect("Parse error");
+        let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+
+        let options = TableOptions {
+            max_rows: 2,
+            progress: false,
+            cache_stats: false,
+        };
+        let err = full_table(&hdl, &provider, &[], &options).expect_err("Expected row error");
+        assert!(err
+            .to_string()
+            .contains("Truth table has 4 rows, more than the maximum of 2."));
+
+        let options = TableOptions {
+            max_rows: 4,
+            progress: true,
+            cache_stats: true,
+        };
+        let (_, table) = full_table(&hdl, &provider, &[], &options).expect("Table error");
+        assert_eq!(table.len(), 4);
+    }
+
+    // The widths of a generic chip's ports come from the generic arguments.
+    #[test]
+    fn test_generic_table() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = manifest_dir.join("resources/tests/multiply/JoinGen.hdl");
+        let (hdl, provider) = parse_hdl_path(&path).expect("Parse error");
+        let provider: Rc<dyn HdlProvider> = Rc::new(provider);
+        let options = TableOptions::default();
+
+        let (columns, table) = full_table(&hdl, &provider, &[2], &options).expect("Table error");
+        assert_eq!(columns, vec!["high", "low", "out"]);
+        assert_eq!(table.len(), 16);
+        for row in &table {
+            assert_eq!(row[0].len(), 2);
+            let joined: Vec<Option<bool>> = row[0].iter().chain(&row[1]).cloned().collect();
+            assert_eq!(row[2], joined);
+        }
+
+        let err = full_table(&hdl, &provider, &[6], &options).expect_err("Expected row error");
+        assert!(err.to_string().contains("Truth table has 4096 rows"));
+        assert!(full_table(&hdl, &provider, &[], &options).is_err());
+    }
+}
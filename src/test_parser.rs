@@ -10,8 +10,10 @@ use crate::test_scanner::{TestScanner, Token, TokenType};
 ///
 #[derive(Clone)]
 pub struct TestScript {
+    #[allow(dead_code)] // Parsed, but not needed to run the script.
     pub test_path: PathBuf,
     pub hdl_path: PathBuf,
+    #[allow(dead_code)]
     pub output_path: PathBuf,
     pub cmp_path: PathBuf,
     pub output_list: Vec<OutputFormat>,
@@ -72,27 +74,6 @@ pub enum NumberSystem {
     String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Identifier {
-    pub value: String,
-    pub path: PathBuf, // Set to None if chip not read from disk, e.g. NAND and DFF.
-    pub line: u32,
-}
-
-impl From<Token> for Identifier {
-    fn from(t: Token) -> Self {
-        if t.token_type != TokenType::Identifier {
-            panic!("Attempt to create Identifier from non-identifier token");
-        }
-
-        Identifier {
-            value: t.lexeme,
-            path: t.path,
-            line: t.line,
-        }
-    }
-}
-
 pub struct TestParser<'a, 'b> {
     pub scanner: &'a mut TestScanner<'b>,
 }
@@ -30,7 +30,9 @@ pub enum TokenType {
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
+    #[allow(dead_code)] // Kept in TestParseError for callers.
     pub line: u32,
+    #[allow(dead_code)]
     pub path: PathBuf,
 }
 
@@ -43,7 +45,7 @@ pub struct TestScanner<'a> {
 }
 
 impl<'a> TestScanner<'a> {
-    pub fn new(source_code: &str, source_path: PathBuf) -> TestScanner {
+    pub fn new(source_code: &str, source_path: PathBuf) -> TestScanner<'_> {
         let source_chars = source_code.chars().peekable();
 
         let keywords = HashMap::from([
@@ -165,7 +167,7 @@ impl<'a> TestScanner<'a> {
                     _ => {
                         if c.is_alphabetic() {
                             Some(self.finish_identifier(c))
-                        } else if c.is_numeric() || c == '-' {
+                        } else if c.is_numeric() || c == '-' || c == '*' {
                             Some(self.finish_number(c))
                         } else {
                             panic!("Unexpected character: {}", c)
@@ -216,8 +218,9 @@ impl<'a> TestScanner<'a> {
     fn finish_number(&mut self, start: char) -> Token {
         let mut lexeme = start.to_string();
 
+        // A * stands for an unknown bit.
         while let Some(c) = self.source_chars.peek() {
-            if c.is_numeric() {
+            if c.is_numeric() || c == &'*' {
                 lexeme.push(*c);
                 self.source_chars.next();
             } else {
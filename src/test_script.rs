@@ -4,22 +4,23 @@
 //!
 //! The maximum test input size is 16 bits.
 
-use crate::busmap::BusMap;
-use crate::error::{ErrorKind, N2VError};
+use crate::busmap::{format_bits, BusMap, Radix};
+use crate::error::{ErrorKind, N2VError, TransformedError};
+use crate::expr::eval_expr_numeric;
 use crate::parser::*;
-use crate::simulator::{Bus, Chip, Simulator};
+use crate::profile::Profile;
+use crate::simulator::{check_strict_widths, Bus, Chip, Simulator};
 use crate::test_parser::*;
 use crate::test_scanner::TestScanner;
-use crate::expr::eval_expr_numeric;
 
 use bitvec::prelude::*;
+use std::cell::RefCell;
 use std::error::Error;
 use std::fs;
 use std::io::{prelude::*, BufReader};
 use std::path::{Path, PathBuf};
 use std::ptr;
 use std::rc::Rc;
-use std::collections::HashMap;
 
 /// Converts a test input (string + number system) to a bit vector.
 ///
@@ -58,6 +59,45 @@ pub fn test_input_to_bitvec(input: &InputValue) -> BitVec<u16, Msb0> {
     }
 }
 
+/// Converts a test input to bits, most significant bit first.
+///
+/// A `*` in a binary value is an unknown bit, and a decimal value of `*`
+/// makes every bit unknown. Any other character that is not a digit of the
+/// number system is an error.
+pub fn test_input_to_bits(input: &InputValue) -> Result<Vec<Option<bool>>, Box<dyn Error>> {
+    let error = |msg: String| -> Box<dyn Error> {
+        Box::new(N2VError {
+            msg,
+            kind: ErrorKind::Other,
+        })
+    };
+    match input.number_system {
+        NumberSystem::Decimal if input.value == "*" => Ok(vec![None; 16]),
+        NumberSystem::Decimal if input.value.parse::<i16>().is_err() => Err(error(format!(
+            "Expected a 16-bit decimal number or *, found {}.",
+            input.value
+        ))),
+        NumberSystem::Binary => input
+            .value
+            .chars()
+            .map(|c| match c {
+                '0' => Ok(Some(false)),
+                '1' => Ok(Some(true)),
+                '*' => Ok(None),
+                _ => Err(error(format!(
+                    "Expected 0, 1, or * in binary value {}, found {}.",
+                    input.value, c
+                ))),
+            })
+            .collect(),
+        NumberSystem::Hex | NumberSystem::String => Err(error(format!(
+            "Unsupported number system for input value {}.",
+            input.value
+        ))),
+        NumberSystem::Decimal => Ok(bitvec_to_vecbool(test_input_to_bitvec(input))),
+    }
+}
+
 /// Converts a bitvec to a vector of option bools. This conversion is
 /// necessary because the simulator uses `Vec<Option<bool>>` to represent inputs.
 pub fn bitvec_to_vecbool(bv: BitVec<u16, Msb0>) -> Vec<Option<bool>> {
@@ -166,12 +206,14 @@ pub fn read_cmp(test_script: &TestScript) -> Result<Vec<BusMap>, Box<dyn Error>>
             }
 
             let (hdl, _) = parse_hdl_path(&test_script.hdl_path)?;
-            let mut variables = HashMap::new();
+            let mut variables = hdl.constant_values();
             for gv in 0..hdl.generic_decls.len() {
-                variables.insert(hdl.generic_decls[gv].value.clone(), test_script.generics[gv]);
+                variables.insert(
+                    hdl.generic_decls[gv].value.clone(),
+                    test_script.generics[gv],
+                );
             }
 
-
             let mut port_width = 0;
             for p in hdl.ports {
                 if p.name.value == port_order[i] {
@@ -182,11 +224,7 @@ pub fn read_cmp(test_script: &TestScript) -> Result<Vec<BusMap>, Box<dyn Error>>
 
             value.reverse();
             step_result.create_bus(&port_order[i], value.len()).unwrap();
-            let bus = Bus {
-                name: port_order[i].clone(),
-                range: Some(0..value.len()),
-            };
-            step_result.insert_option(&bus, value);
+            step_result.insert_option(&Bus::from(port_order[i].as_str()), value);
         }
         res.push(step_result);
     }
@@ -204,29 +242,100 @@ pub fn parse_test(test_script_path: &Path) -> Result<TestScript, Box<dyn Error>>
     test_parser.parse()
 }
 
+/// Options controlling how a test script is run.
+#[derive(Default, Clone)]
+pub struct TestOptions {
+    /// Additional directories to search for component HDL files.
+    pub include_paths: Vec<PathBuf>,
+    /// Print every output vector, and a diff for each failing vector.
+    pub verbose: bool,
+    /// Records chip construction and elaboration times when set.
+    pub profile: Option<Rc<RefCell<Profile>>>,
+    /// Warn instead of failing when output bits have no source.
+    pub allow_undriven_outputs: bool,
+    /// Fail when mappings imply different widths for a signal.
+    pub strict_widths: bool,
+    /// Print nothing when the test passes.
+    pub quiet: bool,
+    /// Write a waveform of the ports to this VCD file.
+    pub vcd: Option<PathBuf>,
+    /// Internal signals of the chip to add to the waveform.
+    pub vcd_signals: Vec<String>,
+    /// Fail the test once the clock has ticked this many times.
+    pub max_cycles: Option<u64>,
+    /// Format each output row as the output-list describes and compare it
+    /// with the compare file line by line, stopping at the first line that
+    /// differs.
+    pub cmp: bool,
+}
+
 /// Runs a test script.
 ///
 /// If a test fails a message will print to stdout and this function
-/// returns an error.
-pub fn run_test(test_script_path: &Path) -> Result<(), Box<dyn Error>> {
+/// returns an error. Otherwise it returns the output port bits that had no
+/// source, which are only allowed by `allow_undriven_outputs`.
+#[allow(dead_code)] // Only used by tests.
+pub fn run_test(test_script_path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    run_test_with_options(test_script_path, &TestOptions::default())
+}
+
+/// Runs a test script with the given options. See `run_test`.
+pub fn run_test_with_options(
+    test_script_path: &Path,
+    options: &TestOptions,
+) -> Result<Vec<String>, Box<dyn Error>> {
     //let hdl_path = test_pathbuf.parent().unwrap().join(&test_script.hdl_file);
     let test_script = parse_test(test_script_path)?;
-    let (hdl, file_reader) = parse_hdl_path(&test_script.hdl_path)?;
+    let (hdl, file_reader) =
+        parse_hdl_path_with_includes(&test_script.hdl_path, &options.include_paths)?;
     let provider: Rc<dyn HdlProvider> = Rc::new(file_reader);
 
+    if options.strict_widths {
+        check_strict_widths(&hdl, &provider, &test_script.generics)?;
+    }
+
     // Create simulator for HDL file referenced by test script.
 
-    let chip = Chip::new(
+    let mut chip = Chip::new_with_profile(
         &hdl,
         ptr::null_mut(),
         &provider,
         false,
         &test_script.generics,
+        options.profile.clone(),
     )?;
+    chip.allow_undriven_outputs = options.allow_undriven_outputs;
+    chip.strict_widths = options.strict_widths;
 
     let mut simulator = Simulator::new(chip);
+    if let Some(max) = options.max_cycles {
+        simulator.set_max_cycles(max);
+    }
+    if options.vcd.is_some() {
+        simulator.record_vcd(&options.vcd_signals)?;
+    }
 
-    let expected = read_cmp(&test_script)?;
+    // A compare file is optional in verbose mode, where the outputs are
+    // printed for inspection.
+    let expected = if options.cmp || options.verbose && !test_script.cmp_path.is_file() {
+        None
+    } else {
+        Some(read_cmp(&test_script)?)
+    };
+    let cmp_lines = if options.cmp {
+        read_cmp_lines(&test_script.cmp_path)?
+    } else {
+        Vec::new()
+    };
+
+    let mut output_names: Vec<String> = simulator
+        .chip
+        .ports
+        .values()
+        .filter(|p| p.direction.is_output())
+        .map(|p| p.name.value.clone())
+        .collect();
+    output_names.sort();
 
     let mut inputs = BusMap::new();
     let mut cmp_idx = 0;
@@ -242,7 +351,7 @@ pub fn run_test(test_script_path: &Path) -> Result<(), Box<dyn Error>> {
                         .get(port)
                         .unwrap_or_else(|| panic!("No width for port {}", port))
                         .width;
-                    let mut bool_values = bitvec_to_vecbool(test_input_to_bitvec(value));
+                    let mut bool_values = test_input_to_bits(value)?;
                     bool_values.reverse();
                     bool_values.truncate(width);
                     bool_values.reverse();
@@ -251,16 +360,43 @@ pub fn run_test(test_script_path: &Path) -> Result<(), Box<dyn Error>> {
                 }
                 Instruction::Eval => {
                     outputs = simulator.simulate(&inputs)?;
-                    print!(".");
+                    if !options.verbose && !options.quiet {
+                        print!(".");
+                    }
                 }
                 Instruction::Output => {
-                    #[allow(clippy::neg_cmp_op_on_partial_ord)]
-                    if !(expected[cmp_idx] <= outputs.clone()) {
-                        println!("❌ Step: {}", cmp_idx + 1);
-                        println!("Expected: {}", expected[cmp_idx]);
-                        println!("Actual: {}", outputs);
-                        println!();
-                        failures += 1;
+                    let row = format!(
+                        "Row {}: {} | {}",
+                        cmp_idx + 1,
+                        format_values(&inputs, &inputs.keys()),
+                        format_values(&outputs, &output_names)
+                    );
+                    match &expected {
+                        None if options.cmp => {
+                            compare_row(&test_script, &cmp_lines, cmp_idx, &inputs, &outputs)?;
+                            if options.verbose {
+                                println!("✓ {}", row);
+                            }
+                        }
+                        None => println!("{}", row),
+                        Some(expected) =>
+                        {
+                            #[allow(clippy::neg_cmp_op_on_partial_ord)]
+                            if !(expected[cmp_idx] <= outputs.clone()) {
+                                if options.verbose {
+                                    println!("✗ {}", row);
+                                    print_diff(&expected[cmp_idx], &outputs);
+                                } else {
+                                    println!("❌ Step: {}", cmp_idx + 1);
+                                    println!("Expected: {}", expected[cmp_idx]);
+                                    println!("Actual: {}", outputs);
+                                    println!();
+                                }
+                                failures += 1;
+                            } else if options.verbose {
+                                println!("✓ {}", row);
+                            }
+                        }
                     }
                     cmp_idx += 1;
                 }
@@ -268,30 +404,208 @@ pub fn run_test(test_script_path: &Path) -> Result<(), Box<dyn Error>> {
                     outputs = simulator.simulate(&inputs)?;
                 }
                 Instruction::Tock => {
-                    simulator.tick().expect("Tick failure");
+                    simulator.tick()?;
                     outputs = simulator.simulate(&inputs)?;
                 }
             }
         }
     }
 
+    if let Some(path) = &options.vcd {
+        simulator.write_vcd(path)?;
+    }
+    let undriven = simulator.chip.undriven_outputs();
+
+    if expected.is_none() && !options.cmp {
+        if !options.quiet {
+            println!();
+            println!("{} vectors evaluated, no compare file.", cmp_idx);
+        }
+        return Ok(undriven);
+    }
+
     if failures > 0 {
         println!(
-            "❌️️️ {} failures, {} successes, {} total. ",
-            failures,
-            test_script.steps.len() - failures,
-            test_script.steps.len()
+            "❌️️️ {}/{} vectors passed, {} failures.",
+            cmp_idx - failures,
+            cmp_idx,
+            failures
         );
 
+        let mut msg = String::from("Test failed.");
+        if !undriven.is_empty() {
+            msg += &format!(
+                " These output bits have no source: {}.",
+                undriven.join(", ")
+            );
+        }
         return Err(Box::new(N2VError {
-            msg: String::from("Test failed."),
+            msg,
             kind: ErrorKind::Other,
         }));
     }
 
-    println!();
-    println!("✔️️️    {} tests passed.", test_script.steps.len());
-    Ok(())
+    if !options.quiet {
+        println!();
+        match simulator.cycles() {
+            0 => println!("✔️️️    {}/{} vectors passed.", cmp_idx, cmp_idx),
+            cycles => println!(
+                "✔️️️    {}/{} vectors passed in {} cycles.",
+                cmp_idx, cmp_idx, cycles
+            ),
+        }
+    }
+    Ok(undriven)
+}
+
+/// Formats the named signals of a busmap on a single line.
+fn format_values(values: &BusMap, names: &[String]) -> String {
+    names
+        .iter()
+        .filter(|n| values.get_width(n).is_some())
+        .map(|n| format!("{}={}", n, format_bits(&values.get_name(n), Radix::Binary)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Prints each signal whose actual value differs from the expected value.
+fn print_diff(expected: &BusMap, actual: &BusMap) {
+    for name in expected.keys() {
+        let expected_bits = expected.get_name(&name);
+        let actual_bits = match actual.get_width(&name) {
+            Some(_) => format_bits(&actual.get_name(&name), Radix::Binary),
+            None => String::from("missing"),
+        };
+        if format_bits(&expected_bits, Radix::Binary) != actual_bits {
+            println!(
+                "    {}: expected {}, got {}",
+                name,
+                format_bits(&expected_bits, Radix::Binary),
+                actual_bits
+            );
+        }
+    }
+}
+
+/// Reads the rows of a compare file, skipping the header line and blank
+/// lines. Each row is returned with its line number.
+fn read_cmp_lines(cmp_path: &Path) -> Result<Vec<(usize, String)>, Box<dyn Error>> {
+    let contents = fs::read_to_string(cmp_path).map_err(|e| {
+        Box::new(TransformedError {
+            msg: format!("Unable to read compare file {:?}.", cmp_path),
+            kind: ErrorKind::IOError,
+            source: Some(Box::new(e)),
+        })
+    })?;
+    Ok(contents
+        .lines()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| (i + 1, l.to_string()))
+        .collect())
+}
+
+/// Compares output row `row` with the matching line of the compare file,
+/// column by column. A column that is a string, or whose expected value
+/// contains `*`, always matches. On the first mismatch both lines and the
+/// columns that differ are printed and an error is returned.
+fn compare_row(
+    test_script: &TestScript,
+    cmp_lines: &[(usize, String)],
+    row: usize,
+    inputs: &BusMap,
+    outputs: &BusMap,
+) -> Result<(), Box<dyn Error>> {
+    let (line, expected_line) = match cmp_lines.get(row) {
+        Some(l) => l,
+        None => {
+            return Err(Box::new(N2VError {
+                msg: format!(
+                    "The compare file {:?} has {} rows, but the test script outputs more.",
+                    test_script.cmp_path,
+                    cmp_lines.len()
+                ),
+                kind: ErrorKind::Other,
+            }))
+        }
+    };
+
+    let expected: Vec<&str> = expected_line.trim().trim_matches('|').split('|').collect();
+    let actual: Vec<String> = test_script
+        .output_list
+        .iter()
+        .map(|format| {
+            let name = &format.port_name;
+            let bits = if outputs.get_width(name).is_some() {
+                Some(outputs.get_name(name))
+            } else if inputs.get_width(name).is_some() {
+                Some(inputs.get_name(name))
+            } else {
+                None
+            };
+            format_cell(format, bits.as_deref())
+        })
+        .collect();
+
+    let mut mismatches = Vec::new();
+    for (i, format) in test_script.output_list.iter().enumerate() {
+        let expected_value = expected.get(i).map_or("", |v| v.trim());
+        let actual_value = actual[i].trim();
+        if format.number_system == NumberSystem::String
+            || expected_value.contains('*')
+            || expected_value == actual_value
+        {
+            continue;
+        }
+        mismatches.push(format!(
+            "    {}: expected {}, got {}",
+            format.port_name, expected_value, actual_value
+        ));
+    }
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+
+    println!(
+        "❌ Comparison failure at line {} of {:?}",
+        line, test_script.cmp_path
+    );
+    println!("Expected: {}", expected_line.trim());
+    println!("Actual:   |{}|", actual.join("|"));
+    for m in mismatches {
+        println!("{}", m);
+    }
+    Err(Box::new(N2VError {
+        msg: format!("Comparison failure at line {}.", line),
+        kind: ErrorKind::Other,
+    }))
+}
+
+/// Formats a value, most significant bit first, as one column of an
+/// output row. Binary values print one character per bit, decimal values
+/// are right aligned and signed for 16 bit buses as in nand2tetris, and
+/// hex values have leading zeros. A value that is unknown, or not a port
+/// of the chip, prints as `?`.
+fn format_cell(format: &OutputFormat, bits: Option<&[Option<bool>]>) -> String {
+    let columns = format.output_columns;
+    let value = match (&format.number_system, bits) {
+        (NumberSystem::String, _) => String::new(),
+        (_, None) => String::from("?"),
+        (NumberSystem::Binary, Some(b)) => format_bits(b, Radix::Binary),
+        (NumberSystem::Hex, Some(b)) => format_bits(b, Radix::Hex),
+        (_, Some(b)) if b.len() == 16 => format_bits(b, Radix::Signed),
+        (_, Some(b)) => format_bits(b, Radix::Decimal),
+    };
+    // Keep the least significant digits of a value that is too wide.
+    let value = &value[value.len().saturating_sub(columns)..];
+    format!(
+        "{}{:>w$}{}",
+        " ".repeat(format.space_before),
+        value,
+        " ".repeat(format.space_after),
+        w = columns
+    )
 }
 
 /// Reads test script file and returns its contents as a String.
@@ -469,6 +783,99 @@ mod test {
         let path = construct_path(&PathBuf::from("buffer/Buffer.tst"));
         assert!(run_test(&path).is_ok());
     }
+
+    #[test]
+    fn test_buffer_verbose() {
+        let path = construct_path(&PathBuf::from("buffer/Buffer.tst"));
+        let options = TestOptions {
+            verbose: true,
+            ..TestOptions::default()
+        };
+        assert!(run_test_with_options(&path, &options).is_ok());
+    }
+
+    // The compare file expects Not(1) = 1, so the second vector fails.
+    #[test]
+    fn test_wrong_cmp_verbose() {
+        let path = construct_path(&PathBuf::from("bad/NotWrong.tst"));
+        assert!(run_test(&path).is_err());
+        let options = TestOptions {
+            verbose: true,
+            ..TestOptions::default()
+        };
+        assert!(run_test_with_options(&path, &options).is_err());
+    }
+
+    // Undriven output bits are an error unless allowed, and then they are
+    // returned to the caller.
+    #[test]
+    fn test_undriven_outputs() {
+        let path = construct_path(&PathBuf::from("bad/Undriven.tst"));
+        let err = run_test(&path).unwrap_err();
+        assert!(err.to_string().contains("has output bits with no source"));
+
+        let options = TestOptions {
+            allow_undriven_outputs: true,
+            quiet: true,
+            ..TestOptions::default()
+        };
+        let undriven = run_test_with_options(&path, &options).expect("Test error");
+        assert_eq!(undriven, vec!["flag", "out[1]", "out[3]"]);
+    }
+
+    #[test]
+    fn test_cmp_lines() {
+        let options = TestOptions {
+            cmp: true,
+            ..TestOptions::default()
+        };
+        let path = construct_path(&PathBuf::from("nand2tetris/solutions/CPU.tst"));
+        assert!(run_test_with_options(&path, &options).is_ok());
+
+        let path = construct_path(&PathBuf::from("bad/NotWrong.tst"));
+        let err = run_test_with_options(&path, &options).unwrap_err();
+        assert!(err.to_string().contains("Comparison failure at line 3."));
+    }
+
+    #[test]
+    fn test_format_cell() {
+        let format = |number_system, output_columns| OutputFormat {
+            port_name: String::from("out"),
+            number_system,
+            space_before: 1,
+            output_columns,
+            space_after: 1,
+        };
+        let bits = |s: &str| -> Vec<Option<bool>> { s.chars().map(|c| Some(c == '1')).collect() };
+        let minus_two = bits("1111111111111110");
+
+        assert_eq!(
+            format_cell(&format(NumberSystem::Decimal, 6), Some(&minus_two)),
+            "     -2 "
+        );
+        assert_eq!(
+            format_cell(&format(NumberSystem::Decimal, 3), Some(&bits("110"))),
+            "   6 "
+        );
+        assert_eq!(
+            format_cell(&format(NumberSystem::Hex, 4), Some(&minus_two)),
+            " FFFE "
+        );
+        assert_eq!(
+            format_cell(&format(NumberSystem::Binary, 3), Some(&bits("0110"))),
+            " 110 "
+        );
+        assert_eq!(
+            format_cell(&format(NumberSystem::Binary, 2), Some(&[None, Some(true)])),
+            " ?1 "
+        );
+        assert_eq!(
+            format_cell(&format(NumberSystem::Decimal, 2), Some(&[None, None])),
+            "  ? "
+        );
+        assert_eq!(format_cell(&format(NumberSystem::String, 2), None), "    ");
+    }
+
     #[test]
     fn test_buffer2() {
         let path = construct_path(&PathBuf::from("buffer/Buffer2.tst"));
@@ -485,6 +892,86 @@ mod test {
         assert!(run_test(&path).is_ok());
     }
 
+    #[test]
+    fn test_dont_care_inputs() {
+        let bits = |number_system, value: &str| {
+            test_input_to_bits(&InputValue {
+                number_system,
+                value: String::from(value),
+            })
+        };
+        assert_eq!(
+            bits(NumberSystem::Binary, "1*0").unwrap(),
+            vec![Some(true), None, Some(false)]
+        );
+        assert_eq!(bits(NumberSystem::Decimal, "*").unwrap(), vec![None; 16]);
+
+        // A `*` can't be mixed with decimal digits, and binary values only
+        // hold 0, 1, and *.
+        let err = bits(NumberSystem::Decimal, "1*").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Expected a 16-bit decimal number or *, found 1*."));
+        let err = bits(NumberSystem::Binary, "1*2").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Expected 0, 1, or * in binary value 1*2, found 2."));
+        let path = construct_path(&PathBuf::from("dontcare/BadStar.tst"));
+        let err = run_test(&path).unwrap_err();
+        assert!(err.to_string().contains("found 1*."));
+
+        let path = construct_path(&PathBuf::from("dontcare/NotPair.tst"));
+        assert!(run_test(&path).is_ok());
+    }
+
+    #[test]
+    fn test_vcd() {
+        let path = construct_path(&PathBuf::from("nand2tetris/solutions/Bit.tst"));
+        let temp_dir = tempfile::tempdir().unwrap();
+        let vcd_path = temp_dir.path().join("Bit.vcd");
+        let options = TestOptions {
+            vcd: Some(vcd_path.clone()),
+            vcd_signals: vec![String::from("muxOut")],
+            quiet: true,
+            ..TestOptions::default()
+        };
+        assert!(run_test_with_options(&path, &options).is_ok());
+
+        let vcd = fs::read_to_string(&vcd_path).unwrap();
+        assert!(vcd.contains("$var wire 1 ! in $end\n"));
+        assert!(vcd.contains("$var wire 1 $ muxOut $end\n"));
+        // Bit.tst ticks 107 times.
+        assert!(vcd.contains("\n#107\n"));
+        assert!(!vcd.contains("\n#108\n"));
+
+        let options = TestOptions {
+            vcd: Some(vcd_path),
+            vcd_signals: vec![String::from("missing")],
+            quiet: true,
+            ..TestOptions::default()
+        };
+        assert!(run_test_with_options(&path, &options).is_err());
+    }
+
+    #[test]
+    fn test_max_cycles() {
+        let path = construct_path(&PathBuf::from("nand2tetris/solutions/Bit.tst"));
+        let options = TestOptions {
+            max_cycles: Some(107),
+            quiet: true,
+            ..TestOptions::default()
+        };
+        assert!(run_test_with_options(&path, &options).is_ok());
+
+        let options = TestOptions {
+            max_cycles: Some(106),
+            quiet: true,
+            ..TestOptions::default()
+        };
+        let err = run_test_with_options(&path, &options).unwrap_err();
+        assert!(err.to_string().contains("more than 106 cycles"));
+    }
+
     #[test]
     fn test_arm_add16() {
         let path = construct_path(&PathBuf::from("arm/Add16.tst"));
@@ -0,0 +1,157 @@
+//! Value Change Dump (VCD) waveforms.
+//!
+//! A `VcdWriter` collects the values of a fixed set of signals at points in
+//! time and writes them in the VCD format read by waveform viewers such as
+//! GTKWave. Only the signals that changed are written at each time. Unknown
+//! bits are written as `x`.
+
+use std::io;
+
+/// Records signal values over time and writes them as a VCD file.
+pub struct VcdWriter {
+    /// Name of the scope the signals are declared in, usually the chip.
+    scope: String,
+    /// Name and width of each signal.
+    vars: Vec<(String, usize)>,
+    /// Time and the value of every signal, most significant bit first.
+    samples: Vec<(u64, Vec<Vec<Option<bool>>>)>,
+}
+
+impl VcdWriter {
+    pub fn new(scope: &str, vars: Vec<(String, usize)>) -> VcdWriter {
+        VcdWriter {
+            scope: String::from(scope),
+            vars,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Name and width of each signal.
+    pub fn vars(&self) -> &[(String, usize)] {
+        &self.vars
+    }
+
+    /// Records the value of every signal, in the order they were given to
+    /// `new`, at `time`. A later sample at the same time replaces the
+    /// earlier one.
+    pub fn sample(&mut self, time: u64, values: Vec<Vec<Option<bool>>>) {
+        match self.samples.last_mut() {
+            Some((t, last)) if *t == time => *last = values,
+            _ => self.samples.push((time, values)),
+        }
+    }
+
+    /// Writes the header and every sample. The time unit is 1 ns.
+    pub fn write(&self, w: &mut impl io::Write) -> io::Result<()> {
+        writeln!(w, "$version whidl $end")?;
+        writeln!(w, "$timescale 1 ns $end")?;
+        writeln!(w, "$scope module {} $end", self.scope)?;
+        for (i, (name, width)) in self.vars.iter().enumerate() {
+            match width {
+                1 => writeln!(w, "$var wire 1 {} {} $end", identifier(i), name)?,
+                _ => writeln!(
+                    w,
+                    "$var wire {} {} {} [{}:0] $end",
+                    width,
+                    identifier(i),
+                    name,
+                    width - 1
+                )?,
+            }
+        }
+        writeln!(w, "$upscope $end")?;
+        writeln!(w, "$enddefinitions $end")?;
+
+        let mut previous: Option<&Vec<Vec<Option<bool>>>> = None;
+        for (time, values) in &self.samples {
+            writeln!(w, "#{}", time)?;
+            if previous.is_none() {
+                writeln!(w, "$dumpvars")?;
+            }
+            for (i, value) in values.iter().enumerate() {
+                if previous.is_some_and(|p| p[i] == *value) {
+                    continue;
+                }
+                let bits: String = value.iter().map(|b| bit(*b)).collect();
+                match self.vars[i].1 {
+                    1 => writeln!(w, "{}{}", bits, identifier(i))?,
+                    _ => writeln!(w, "b{} {}", bits, identifier(i))?,
+                }
+            }
+            if previous.is_none() {
+                writeln!(w, "$end")?;
+            }
+            previous = Some(values);
+        }
+        Ok(())
+    }
+}
+
+fn bit(b: Option<bool>) -> char {
+    match b {
+        Some(false) => '0',
+        Some(true) => '1',
+        None => 'x',
+    }
+}
+
+// Short code for the i-th signal, in base 94 using the printable ASCII
+// characters from ! to ~.
+fn identifier(mut i: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((b'!' + (i % 94) as u8) as char);
+        i /= 94;
+        if i == 0 {
+            return code;
+        }
+        i -= 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_identifier() {
+        assert_eq!(identifier(0), "!");
+        assert_eq!(identifier(93), "~");
+        assert_eq!(identifier(94), "!!");
+        assert_eq!(identifier(95), "\"!");
+    }
+
+    #[test]
+    fn test_write_vcd() {
+        let mut vcd = VcdWriter::new(
+            "Bit",
+            vec![(String::from("in"), 1), (String::from("out"), 2)],
+        );
+        vcd.sample(0, vec![vec![Some(true)], vec![None, None]]);
+        vcd.sample(1, vec![vec![Some(true)], vec![Some(false), None]]);
+        vcd.sample(1, vec![vec![Some(false)], vec![Some(false), Some(true)]]);
+        vcd.sample(2, vec![vec![Some(false)], vec![Some(false), Some(true)]]);
+
+        let mut out = Vec::new();
+        vcd.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "$version whidl $end\n\
+             $timescale 1 ns $end\n\
+             $scope module Bit $end\n\
+             $var wire 1 ! in $end\n\
+             $var wire 2 \" out [1:0] $end\n\
+             $upscope $end\n\
+             $enddefinitions $end\n\
+             #0\n\
+             $dumpvars\n\
+             1!\n\
+             bxx \"\n\
+             $end\n\
+             #1\n\
+             0!\n\
+             b01 \"\n\
+             #2\n"
+        );
+    }
+}
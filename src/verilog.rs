@@ -0,0 +1,1116 @@
+//! Verilog synthesis.
+//!
+//! Each HDL chip is synthesized as one Verilog module, the same way each chip
+//! becomes a VHDL entity in `vhdl`. Generic chips become modules with
+//! parameters, and their loops become generate loops. Nand and DFF are
+//! written by hand.
+//!
+//! Verilog cannot connect a slice of a port, so a port that is sliced or
+//! mapped more than once is connected to a wire as wide as the port, and the
+//! mapped signals are assigned to or from slices of that wire.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::ptr;
+
+use crate::error::{ErrorKind, N2VError};
+use crate::expr::{eval_expr, GenericWidth, Op, Terminal};
+use crate::opt::optimization::{OptimizationInfo, OptimizationPass};
+use crate::opt::sequential::{SequentialFlagMap, SequentialPass};
+use crate::parser::*;
+use crate::simulator::{constant_widths, gather_assignments, infer_widths, Chip};
+use crate::vhdl::{
+    dependency_order, generic_components, group_port_mappings, ram_widths, RAM_BUILTIN,
+};
+
+pub struct VerilogModule {
+    pub name: String,
+    /// Generics, declared as parameters.
+    pub parameters: Vec<String>,
+    pub ports: Vec<VerilogPort>,
+    /// Constants, declared as localparams.
+    pub localparams: Vec<(String, usize)>,
+    pub wires: Vec<WireVerilog>,
+    pub items: Vec<Item>,
+}
+
+pub struct VerilogPort {
+    pub name: String,
+    pub width: GenericWidth,
+    pub direction: PortDirection,
+}
+
+/// wire [width - 1:0] name;
+pub struct WireVerilog {
+    pub name: String,
+    pub width: GenericWidth,
+}
+
+#[allow(clippy::large_enum_variant)]
+pub enum Item {
+    Instance(InstanceVerilog),
+    Assign(AssignVerilog),
+    Generate(GenerateVerilog),
+    /// The memory array of a `BUILTIN RAM` chip.
+    Memory(MemoryVerilog),
+}
+
+/// An instance of a module, with ports connected by name.
+pub struct InstanceVerilog {
+    pub module: String,
+    pub name: String,
+    /// Values of the module's parameters, by parameter name.
+    pub parameters: Vec<(String, GenericWidth)>,
+    pub connections: Vec<(String, ExprVerilog)>,
+    /// Wires for ports that are sliced or mapped more than once, and the
+    /// assignments between those wires and the mapped signals.
+    pub wires: Vec<WireVerilog>,
+    pub assigns: Vec<AssignVerilog>,
+}
+
+/// assign left = right;
+pub struct AssignVerilog {
+    pub left: SliceVerilog,
+    pub right: ExprVerilog,
+}
+
+pub enum ExprVerilog {
+    Slice(SliceVerilog),
+    /// A value of the given width with every bit the same. None is unknown.
+    Fill(Option<bool>, GenericWidth),
+    /// A numeric constant of the given width.
+    Number(u64, GenericWidth),
+    /// Values joined with {}, most significant first.
+    Concat(Vec<ExprVerilog>),
+    /// A value that is sign extended to the width it is assigned to.
+    Signed(Box<ExprVerilog>),
+}
+
+/// Verilog example: foo[3:0] or bar[X:0]. The end is inclusive, as in HDL.
+pub struct SliceVerilog {
+    pub name: String,
+    pub start: Option<GenericWidth>,
+    pub end: Option<GenericWidth>,
+}
+
+/// A for loop in a generic chip.
+/// for (i = start; i <= end; i = i + 1) begin : label ... end
+pub struct GenerateVerilog {
+    pub label: String,
+    pub iterator: String,
+    pub start: GenericWidth,
+    pub end: GenericWidth,
+    pub step: GenericWidth,
+    /// Whether the loop counts up from `start` to `end` or down. If this
+    /// depends on generics, an if-generate picks one of the two loops.
+    pub ascending: Option<bool>,
+    pub items: Vec<Item>,
+}
+
+/// Memory of a `BUILTIN RAM` chip, with one word for each address. Words
+/// are written on the rising edge of `clk` when `load` is set and are read
+/// without waiting for the clock.
+pub struct MemoryVerilog {
+    pub word_width: GenericWidth,
+    pub address_width: GenericWidth,
+}
+
+const NAND_VERILOG: &str = r#"module Nand (
+input [0:0] a,
+input [0:0] b,
+output [0:0] out
+);
+assign out = ~(a & b);
+endmodule
+"#;
+
+const DFF_VERILOG: &str = r#"module DFF (
+input [0:0] in,
+output reg [0:0] out,
+input [0:0] clk
+);
+initial out = 1'b0;
+always @(posedge clk[0]) out <= in;
+endmodule
+"#;
+
+// ========= DISPLAY ========== //
+
+impl fmt::Display for VerilogModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "module {} ", module_name(&self.name))?;
+        if !self.parameters.is_empty() {
+            // Parameters need a default. Any width works, since a parent
+            // module always sets them.
+            let parameters: Vec<String> = self
+                .parameters
+                .iter()
+                .map(|p| format!("parameter {} = 1", keyv(p)))
+                .collect();
+            write!(f, "#({}) ", parameters.join(", "))?;
+        }
+        writeln!(f, "(")?;
+        for (i, port) in self.ports.iter().enumerate() {
+            if i != 0 {
+                writeln!(f, ",")?;
+            }
+            write!(f, "{}", port)?;
+        }
+        writeln!(f)?;
+        writeln!(f, ");")?;
+
+        for (name, value) in &self.localparams {
+            writeln!(f, "localparam {} = {};", keyv(name), value)?;
+        }
+        for wire in &self.wires {
+            writeln!(f, "{}", wire)?;
+        }
+        let mut genvars: Vec<&String> = Vec::new();
+        for item in &self.items {
+            if let Item::Generate(g) = item {
+                if !genvars.contains(&&g.iterator) {
+                    genvars.push(&g.iterator);
+                    writeln!(f, "genvar {};", keyv(&g.iterator))?;
+                }
+            }
+        }
+        for item in &self.items {
+            write!(f, "{}", item)?;
+        }
+        writeln!(f, "endmodule")
+    }
+}
+
+impl fmt::Display for VerilogPort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self.direction {
+            PortDirection::In => "input",
+            PortDirection::Out => "output",
+            PortDirection::InOut => "inout",
+        };
+        write!(
+            f,
+            "{} {} {}",
+            direction,
+            range(&self.width),
+            keyv(&self.name)
+        )
+    }
+}
+
+impl fmt::Display for WireVerilog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "wire {} {};", range(&self.width), keyv(&self.name))
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Item::Instance(x) => write!(f, "{}", x),
+            Item::Assign(x) => writeln!(f, "{}", x),
+            Item::Generate(x) => write!(f, "{}", x),
+            Item::Memory(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl fmt::Display for InstanceVerilog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for wire in &self.wires {
+            writeln!(f, "{}", wire)?;
+        }
+        write!(f, "{} ", module_name(&self.module))?;
+        if !self.parameters.is_empty() {
+            let parameters: Vec<String> = self
+                .parameters
+                .iter()
+                .map(|(name, value)| format!(".{}({})", keyv(name), expr(value)))
+                .collect();
+            write!(f, "#({}) ", parameters.join(", "))?;
+        }
+        let connections: Vec<String> = self
+            .connections
+            .iter()
+            .map(|(port, value)| format!(".{}({})", keyv(port), value))
+            .collect();
+        writeln!(f, "{} ({});", self.name, connections.join(", "))?;
+        for assign in &self.assigns {
+            writeln!(f, "{}", assign)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AssignVerilog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "assign {} = {};", self.left, self.right)
+    }
+}
+
+impl fmt::Display for ExprVerilog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Slice(x) => write!(f, "{}", x),
+            Self::Fill(value, width) => {
+                let bit = match value {
+                    Some(true) => '1',
+                    Some(false) => '0',
+                    None => 'x',
+                };
+                write!(f, "{{{}{{1'b{}}}}}", expr(width), bit)
+            }
+            // A sized constant must have a number for its width. An unsized
+            // one is extended to the width of the port.
+            Self::Number(value, GenericWidth::Terminal(Terminal::Num(width))) => {
+                write!(f, "{}'d{}", width, value)
+            }
+            Self::Number(value, _) => write!(f, "'d{}", value),
+            Self::Concat(x) => {
+                let parts: Vec<String> = x.iter().map(|e| e.to_string()).collect();
+                write!(f, "{{{}}}", parts.join(", "))
+            }
+            Self::Signed(x) => write!(f, "$signed({})", x),
+        }
+    }
+}
+
+impl fmt::Display for SliceVerilog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.start, &self.end) {
+            (Some(start), Some(end)) => {
+                write!(f, "{}[{}:{}]", keyv(&self.name), expr(end), expr(start))
+            }
+            _ => write!(f, "{}", keyv(&self.name)),
+        }
+    }
+}
+
+impl fmt::Display for GenerateVerilog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "generate")?;
+        match self.ascending {
+            Some(true) => self.write_loop(f, &self.start, &self.end)?,
+            Some(false) => self.write_loop(f, &self.descending_lowest(), &self.start)?,
+            None => {
+                writeln!(f, "if ({} <= {})", expr(&self.start), expr(&self.end))?;
+                self.write_loop(f, &self.start, &self.end)?;
+                writeln!(f, "else")?;
+                self.write_loop(f, &self.descending_lowest(), &self.start)?;
+            }
+        }
+        writeln!(f, "endgenerate")
+    }
+}
+
+impl GenerateVerilog {
+    // A loop counting up from `low` to `high` by the step. Both loops of an
+    // if-generate have the same label, as only one of them is generated.
+    fn write_loop(
+        &self,
+        f: &mut fmt::Formatter,
+        low: &GenericWidth,
+        high: &GenericWidth,
+    ) -> fmt::Result {
+        let i = keyv(&self.iterator);
+        writeln!(
+            f,
+            "for ({} = {}; {} <= {}; {} = {} + {}) begin : {}",
+            i,
+            expr(low),
+            i,
+            expr(high),
+            i,
+            i,
+            expr(&self.step),
+            self.label
+        )?;
+        for item in &self.items {
+            write!(f, "{}", item)?;
+        }
+        writeln!(f, "end")
+    }
+
+    // Lowest value of a loop that counts down from `start` to `end`. A
+    // genvar can't be negative, which counting down to 0 would need, so
+    // such loops count up over the same values instead.
+    fn descending_lowest(&self) -> GenericWidth {
+        if self.step == GenericWidth::Terminal(Terminal::Num(1)) {
+            return self.end.clone();
+        }
+        let distance = GenericWidth::Expr(
+            Op::Sub,
+            Box::new(self.start.clone()),
+            Box::new(self.end.clone()),
+        );
+        let step = Box::new(self.step.clone());
+        let remainder = GenericWidth::Expr(Op::Mod, Box::new(distance), step);
+        GenericWidth::Expr(Op::Add, Box::new(self.end.clone()), Box::new(remainder))
+    }
+}
+
+impl fmt::Display for MemoryVerilog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let last_address = match self.address_width {
+            GenericWidth::Terminal(Terminal::Num(a)) => ((1usize << a) - 1).to_string(),
+            _ => format!("2**{} - 1", expr(&self.address_width)),
+        };
+        let (address, load, input, output) =
+            (keyv("address"), keyv("load"), keyv("in"), keyv("out"));
+        writeln!(
+            f,
+            "reg {} memory [0:{}];",
+            range(&self.word_width),
+            last_address
+        )?;
+        writeln!(f, "always @(posedge clk[0]) begin")?;
+        writeln!(f, "if ({}[0]) memory[{}] <= {};", load, address, input)?;
+        writeln!(f, "end")?;
+        writeln!(f, "assign {} = memory[{}];", output, address)
+    }
+}
+
+// Width and index expressions. Verilog has no maximum function, so Max is
+// written with the conditional operator.
+fn expr(width: &GenericWidth) -> String {
+    match width {
+        GenericWidth::Terminal(Terminal::Num(n)) => n.to_string(),
+        GenericWidth::Terminal(Terminal::Var(v)) => keyv(&v.value),
+        GenericWidth::Expr(Op::Add, a, b) => format!("({} + {})", expr(a), expr(b)),
+        GenericWidth::Expr(Op::Sub, a, b) => format!("({} - {})", expr(a), expr(b)),
+        GenericWidth::Expr(Op::Mul, a, b) => format!("({} * {})", expr(a), expr(b)),
+        GenericWidth::Expr(Op::Div, a, b) => format!("({} / {})", expr(a), expr(b)),
+        GenericWidth::Expr(Op::Mod, a, b) => format!("({} % {})", expr(a), expr(b)),
+        GenericWidth::Expr(Op::Max, a, b) => {
+            let (a, b) = (expr(a), expr(b));
+            format!("({} > {} ? {} : {})", a, b, a, b)
+        }
+    }
+}
+
+// The [width - 1:0] range of a signal. Left unfolded if it can't be
+// evaluated.
+fn range(width: &GenericWidth) -> String {
+    let end = width - &GenericWidth::Terminal(Terminal::Num(1));
+    let end = eval_expr(&end, &HashMap::new()).unwrap_or(end);
+    format!("[{}:0]", expr(&end))
+}
+
+// Width of the inclusive slice start..end.
+fn slice_width(start: &GenericWidth, end: &GenericWidth) -> GenericWidth {
+    let width = &(end - start) + &GenericWidth::Terminal(Terminal::Num(1));
+    eval_expr(&width, &HashMap::new()).unwrap_or(width)
+}
+
+fn literal(name: &str) -> Option<Option<bool>> {
+    match name {
+        "true" => Some(Some(true)),
+        "false" => Some(Some(false)),
+        "none" => Some(None),
+        _ => None,
+    }
+}
+
+// ========= CONVERSIONS ========== //
+
+impl From<&BusHDL> for SliceVerilog {
+    fn from(hdl: &BusHDL) -> Self {
+        SliceVerilog {
+            name: hdl.name.clone(),
+            start: hdl.start.clone(),
+            end: hdl.end.clone(),
+        }
+    }
+}
+
+/// Synthesizes a Verilog module for an HDL chip.
+impl TryFrom<&ChipHDL> for VerilogModule {
+    type Error = Box<dyn Error>;
+
+    fn try_from(hdl: &ChipHDL) -> Result<Self, Box<dyn Error>> {
+        if hdl.builtin.as_deref() == Some(RAM_BUILTIN) {
+            return ram_module(hdl);
+        }
+        if let Some(builtin) = &hdl.builtin {
+            return Err(Box::new(N2VError {
+                msg: format!(
+                    "Chip {} is implemented by builtin {} and cannot be synthesized to Verilog.",
+                    hdl.name, builtin
+                ),
+                kind: ErrorKind::Other,
+            }));
+        }
+        check_names(hdl)?;
+
+        // As in VHDL, a generic chip is written once for every width, so
+        // its loops become generate loops instead of being expanded.
+        let parameters: Vec<String> = hdl.generic_decls.iter().map(|g| g.value.clone()).collect();
+        let (components, width_components) = if parameters.is_empty() {
+            let chip = Chip::new(hdl, ptr::null_mut(), &hdl.provider, true, &Vec::new())?;
+            (chip.components.clone(), chip.components)
+        } else {
+            generic_components(hdl)
+        };
+
+        let (_, info) = SequentialPass::new().apply(hdl, &hdl.provider)?;
+        let sequential = match info {
+            OptimizationInfo::SequentialFlagMap(map) => map,
+            OptimizationInfo::None => SequentialFlagMap::new(),
+        };
+
+        let mut ports: Vec<VerilogPort> = hdl
+            .ports
+            .iter()
+            .map(|p| verilog_port(p, hdl))
+            .collect::<Result<_, _>>()?;
+        if sequential.get(&hdl.name) == Some(&true) {
+            ports.push(clock_port());
+        }
+
+        let widths = infer_widths(
+            hdl,
+            &gather_assignments(&hdl.parts),
+            &width_components,
+            &hdl.provider,
+            &Vec::new(),
+            false,
+        )?;
+        let mut wires: Vec<WireVerilog> = widths
+            .iter()
+            .filter(|(name, _)| literal(name).is_none())
+            .filter(|(name, _)| !hdl.ports.iter().any(|p| &p.name.value == *name))
+            .map(|(name, width)| WireVerilog {
+                name: name.clone(),
+                width: width.clone(),
+            })
+            .collect();
+        // Sort so that output does not depend on hash map ordering.
+        wires.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let synthesis = Synthesis {
+            hdl,
+            constants: constant_widths(hdl),
+            sequential,
+            widths,
+        };
+        let mut items = Vec::new();
+        for c in &components {
+            let name = format!("u{}", items.len());
+            items.push(Item::Instance(synthesis.instance(c, name)?));
+        }
+
+        // Loops are only left in generic chips.
+        if !parameters.is_empty() {
+            for part in &hdl.parts {
+                if let Part::Loop(loop_hdl) = part {
+                    let mut body = Vec::new();
+                    for c in &loop_hdl.body {
+                        let name = format!("u{}", body.len());
+                        body.push(Item::Instance(synthesis.instance(c, name)?));
+                    }
+                    let (start, end, ascending) = loop_hdl.synthesis_bounds(&hdl.constant_values());
+                    items.push(Item::Generate(GenerateVerilog {
+                        label: format!("g{}", items.len()),
+                        iterator: loop_hdl.iterator.value.clone(),
+                        start,
+                        end,
+                        step: loop_hdl.step.clone(),
+                        ascending,
+                        items: body,
+                    }));
+                }
+            }
+        }
+
+        for part in &hdl.parts {
+            if let Part::AssignmentHDL(assignment) = part {
+                items.push(Item::Assign(synthesis.assign(assignment)?));
+            }
+        }
+
+        Ok(VerilogModule {
+            name: hdl.name.clone(),
+            parameters,
+            ports,
+            localparams: hdl
+                .constants
+                .iter()
+                .map(|(name, value)| (name.value.clone(), *value))
+                .collect(),
+            wires,
+            items,
+        })
+    }
+}
+
+// What a chip's parts are synthesized from.
+struct Synthesis<'a> {
+    hdl: &'a ChipHDL,
+    constants: HashMap<String, GenericWidth>,
+    sequential: SequentialFlagMap,
+    /// Width of every port and signal of the chip.
+    widths: HashMap<String, GenericWidth>,
+}
+
+impl Synthesis<'_> {
+    fn instance(
+        &self,
+        component: &Component,
+        name: String,
+    ) -> Result<InstanceVerilog, Box<dyn Error>> {
+        let unit = get_hdl(&component.name.value, &self.hdl.provider)?;
+        let parameters: Vec<(String, GenericWidth)> = unit
+            .generic_decls
+            .iter()
+            .map(|g| g.value.clone())
+            .zip(component.generic_params.iter().cloned())
+            .collect();
+        // Port widths of the unit, in terms of this chip's generics.
+        let mut variables: HashMap<String, GenericWidth> = parameters
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), eval_expr(value, &self.constants)?)))
+            .collect::<Result<_, N2VError>>()?;
+        variables.extend(constant_widths(&unit));
+
+        let mut instance = InstanceVerilog {
+            module: component.name.value.clone(),
+            name,
+            parameters,
+            connections: Vec::new(),
+            wires: Vec::new(),
+            assigns: Vec::new(),
+        };
+        for (port_name, mappings) in group_port_mappings(component) {
+            let port = unit
+                .ports
+                .iter()
+                .find(|p| p.name.value == port_name)
+                .ok_or_else(|| N2VError {
+                    msg: format!("Chip {} has no port {}.", unit.name, port_name),
+                    kind: ErrorKind::ParseIdentError(
+                        self.hdl.provider.clone(),
+                        component.name.clone(),
+                    ),
+                })?;
+            let width = eval_expr(&port.width, &variables)?;
+            let output = port.direction == PortDirection::Out;
+
+            if let [m] = mappings.as_slice() {
+                if m.port.start.is_none() {
+                    if !(output && literal(&m.wire.name).is_some()) {
+                        let value = self.value(&m.wire, &width);
+                        instance.connections.push((port_name, value));
+                    }
+                    continue;
+                }
+            }
+
+            let wire = format!("{}_{}", instance.name, port_name);
+            for m in mappings {
+                let port_slice = SliceVerilog {
+                    name: wire.clone(),
+                    start: m.port.start.clone(),
+                    end: m.port.end.clone(),
+                };
+                let assign = match (&m.port.start, &m.port.end) {
+                    _ if output && literal(&m.wire.name).is_some() => continue,
+                    _ if output => AssignVerilog {
+                        left: SliceVerilog::from(&m.wire),
+                        right: ExprVerilog::Slice(port_slice),
+                    },
+                    (Some(start), Some(end)) => AssignVerilog {
+                        right: self.value(&m.wire, &slice_width(start, end)),
+                        left: port_slice,
+                    },
+                    _ => AssignVerilog {
+                        right: self.value(&m.wire, &width),
+                        left: port_slice,
+                    },
+                };
+                instance.assigns.push(assign);
+            }
+            instance.wires.push(WireVerilog {
+                name: wire.clone(),
+                width,
+            });
+            instance.connections.push((
+                port_name,
+                ExprVerilog::Slice(SliceVerilog {
+                    name: wire,
+                    start: None,
+                    end: None,
+                }),
+            ));
+        }
+
+        if self.sequential.get(&component.name.value) == Some(&true) {
+            let clk = SliceVerilog {
+                name: String::from("clk"),
+                start: None,
+                end: None,
+            };
+            instance
+                .connections
+                .push((String::from("clk"), ExprVerilog::Slice(clk)));
+        }
+        Ok(instance)
+    }
+
+    fn assign(&self, assignment: &AssignmentHDL) -> Result<AssignVerilog, Box<dyn Error>> {
+        let left = &assignment.left;
+        let width = match (&left.start, &left.end) {
+            (Some(start), Some(end)) => slice_width(start, end),
+            _ => self
+                .widths
+                .get(&left.name)
+                .cloned()
+                .ok_or_else(|| N2VError {
+                    msg: format!("Width of signal {} cannot be inferred.", left.name),
+                    kind: ErrorKind::Other,
+                })?,
+        };
+        let mut right: Vec<ExprVerilog> = assignment
+            .right
+            .iter()
+            .map(|b| self.value(b, &width))
+            .collect();
+        let right = match right.len() {
+            1 => right.pop().unwrap(),
+            _ => ExprVerilog::Concat(right),
+        };
+        Ok(AssignVerilog {
+            left: SliceVerilog::from(left),
+            right: match assignment.extension {
+                Extension::Sign => ExprVerilog::Signed(Box::new(right)),
+                Extension::None | Extension::Zero => right,
+            },
+        })
+    }
+
+    // The value of a mapped or assigned signal. Literals fill `width` bits,
+    // or their slice if they have one.
+    fn value(&self, bus: &BusHDL, width: &GenericWidth) -> ExprVerilog {
+        match (literal(&bus.name), &bus.start, &bus.end) {
+            (Some(bit), Some(start), Some(end)) => ExprVerilog::Fill(bit, slice_width(start, end)),
+            (Some(bit), _, _) => ExprVerilog::Fill(bit, width.clone()),
+            (None, _, _) if numeric_literal(&bus.name).is_some() => {
+                ExprVerilog::Number(numeric_literal(&bus.name).unwrap(), width.clone())
+            }
+            (None, _, _) => ExprVerilog::Slice(SliceVerilog::from(bus)),
+        }
+    }
+}
+
+fn clock_port() -> VerilogPort {
+    VerilogPort {
+        name: String::from("clk"),
+        width: GenericWidth::Terminal(Terminal::Num(1)),
+        direction: PortDirection::In,
+    }
+}
+
+// Ports are declared before localparams, so the values of the chip's
+// constants are filled in.
+fn verilog_port(port: &GenericPort, hdl: &ChipHDL) -> Result<VerilogPort, N2VError> {
+    Ok(VerilogPort {
+        name: port.name.value.clone(),
+        width: eval_expr(&port.width, &constant_widths(hdl))?,
+        direction: port.direction,
+    })
+}
+
+/// Synthesizes a `BUILTIN RAM` chip as a memory array instead of a DFF for
+/// every bit.
+fn ram_module(hdl: &ChipHDL) -> Result<VerilogModule, Box<dyn Error>> {
+    let (word_width, address_width) = ram_widths(hdl)?;
+    let mut ports: Vec<VerilogPort> = hdl
+        .ports
+        .iter()
+        .map(|p| verilog_port(p, hdl))
+        .collect::<Result<_, _>>()?;
+    ports.push(clock_port());
+    Ok(VerilogModule {
+        name: hdl.name.clone(),
+        parameters: hdl.generic_decls.iter().map(|g| g.value.clone()).collect(),
+        ports,
+        localparams: Vec::new(),
+        wires: Vec::new(),
+        items: vec![Item::Memory(MemoryVerilog {
+            word_width,
+            address_width,
+        })],
+    })
+}
+
+/// Writes nand.v, dff.v, and a file for the chip and each chip it depends
+/// on, named after the chip, to `output_dir`.
+pub fn write_verilog_project(
+    hdl: &ChipHDL,
+    output_dir: &Path,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(output_dir.join("nand.v"), NAND_VERILOG)?;
+    fs::write(output_dir.join("dff.v"), DFF_VERILOG)?;
+
+    let mut chips = Vec::new();
+    for name in dependency_order(hdl)? {
+        chips.push(get_hdl(&name, &hdl.provider)?);
+    }
+    chips.push(hdl.clone());
+    for chip in &chips {
+        let filename = format!("{}.v", chip.name);
+        if verbose {
+            println!("Writing {}", filename);
+        }
+        let module = VerilogModule::try_from(chip)?;
+        fs::write(output_dir.join(&filename), module.to_string())?;
+    }
+    Ok(())
+}
+
+// Nand and DFF have hand-written modules whose names don't depend on how
+// the HDL spells them.
+fn module_name(name: &str) -> String {
+    match name.to_lowercase().as_str() {
+        "nand" => String::from("Nand"),
+        "dff" => String::from("DFF"),
+        _ => keyv(name),
+    }
+}
+
+/// Verilog-2005 reserved words. These cannot be used as module, port or
+/// wire names.
+pub const VERILOG_RESERVED: &[&str] = &[
+    "always",
+    "and",
+    "assign",
+    "automatic",
+    "begin",
+    "buf",
+    "bufif0",
+    "bufif1",
+    "case",
+    "casex",
+    "casez",
+    "cell",
+    "cmos",
+    "config",
+    "deassign",
+    "default",
+    "defparam",
+    "design",
+    "disable",
+    "edge",
+    "else",
+    "end",
+    "endcase",
+    "endconfig",
+    "endfunction",
+    "endgenerate",
+    "endmodule",
+    "endprimitive",
+    "endspecify",
+    "endtable",
+    "endtask",
+    "event",
+    "for",
+    "force",
+    "forever",
+    "fork",
+    "function",
+    "generate",
+    "genvar",
+    "highz0",
+    "highz1",
+    "if",
+    "ifnone",
+    "incdir",
+    "include",
+    "initial",
+    "inout",
+    "input",
+    "instance",
+    "integer",
+    "join",
+    "large",
+    "liblist",
+    "library",
+    "localparam",
+    "macromodule",
+    "medium",
+    "module",
+    "nand",
+    "negedge",
+    "nmos",
+    "nor",
+    "noshowcancelled",
+    "not",
+    "notif0",
+    "notif1",
+    "or",
+    "output",
+    "parameter",
+    "pmos",
+    "posedge",
+    "primitive",
+    "pull0",
+    "pull1",
+    "pulldown",
+    "pullup",
+    "pulsestyle_ondetect",
+    "pulsestyle_onevent",
+    "rcmos",
+    "real",
+    "realtime",
+    "reg",
+    "release",
+    "repeat",
+    "rnmos",
+    "rpmos",
+    "rtran",
+    "rtranif0",
+    "rtranif1",
+    "scalared",
+    "showcancelled",
+    "signed",
+    "small",
+    "specify",
+    "specparam",
+    "strong0",
+    "strong1",
+    "supply0",
+    "supply1",
+    "table",
+    "task",
+    "time",
+    "tran",
+    "tranif0",
+    "tranif1",
+    "tri",
+    "tri0",
+    "tri1",
+    "triand",
+    "trior",
+    "trireg",
+    "unsigned",
+    "use",
+    "uwire",
+    "vectored",
+    "wait",
+    "wand",
+    "weak0",
+    "weak1",
+    "while",
+    "wire",
+    "wor",
+    "xnor",
+    "xor",
+];
+
+// Verilog keywords that we can't use. Verilog is case sensitive, so only
+// names spelled exactly like a keyword are escaped. A quoted HDL name that
+// isn't a Verilog identifier is written as an escaped identifier, which ends
+// at the next space: `a.b` becomes `\a.b `.
+pub fn keyv(name: &str) -> String {
+    if VERILOG_RESERVED.contains(&name) {
+        format!("{}_n2v", name)
+    } else if !is_simple_identifier(name) {
+        format!("\\{} ", name)
+    } else {
+        String::from(name)
+    }
+}
+
+// A Verilog simple identifier is a letter or underscore followed by letters,
+// digits, underscores, and dollar signs.
+fn is_simple_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+// An escaped identifier ends at whitespace, so names with whitespace in
+// them, such as `my port`, can't be written in Verilog.
+fn check_names(hdl: &ChipHDL) -> Result<(), Box<dyn Error>> {
+    let wires = hdl
+        .parts
+        .iter()
+        .flat_map(|part| match part {
+            Part::Component(c) => std::slice::from_ref(c),
+            Part::Loop(l) => l.body.as_slice(),
+            _ => &[],
+        })
+        .flat_map(|c| {
+            c.mappings
+                .iter()
+                .map(|m| Identifier::from(m.wire.name.as_str()))
+        });
+    for name in hdl.ports.iter().map(|p| p.name.clone()).chain(wires) {
+        if name.value.contains(char::is_whitespace) {
+            return Err(Box::new(N2VError {
+                msg: format!(
+                    "The name {} has whitespace in it, so it can't be written in Verilog.",
+                    name.value
+                ),
+                kind: ErrorKind::ParseIdentError(hdl.provider.clone(), name),
+            }));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+    use std::rc::Rc;
+
+    fn synthesize(dir: &str, name: &str) -> Result<String, Box<dyn Error>> {
+        let base_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/tests")
+            .join(dir);
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl(name, &provider)?;
+        Ok(VerilogModule::try_from(&hdl)?.to_string())
+    }
+
+    #[test]
+    fn test_verilog_mux() {
+        assert_eq!(
+            synthesize("nand2tetris/solutions", "Mux").unwrap(),
+            "module Mux (\n\
+             input [0:0] a,\n\
+             input [0:0] b,\n\
+             input [0:0] sel,\n\
+             output [0:0] out\n\
+             );\n\
+             wire [0:0] Notsel;\n\
+             wire [0:0] NotselAnda;\n\
+             wire [0:0] selAndb;\n\
+             Not u0 (.in(sel), .out(Notsel));\n\
+             And u1 (.a(a), .b(Notsel), .out(NotselAnda));\n\
+             And u2 (.a(b), .b(sel), .out(selAndb));\n\
+             Or u3 (.a(NotselAnda), .b(selAndb), .out(out));\n\
+             endmodule\n"
+        );
+    }
+
+    // Ports mapped more than once go through a wire, and sequential parts
+    // get the clock.
+    #[test]
+    fn test_verilog_port_wires() {
+        let bit = synthesize("nand2tetris/solutions", "Bit").unwrap();
+        assert!(bit.contains("output [0:0] out,\ninput [0:0] clk\n);"));
+        assert!(bit.contains(
+            "wire [0:0] u1_out;\n\
+             DFF u1 (.in(muxOut), .out(u1_out), .clk(clk));\n\
+             assign out = u1_out;\n\
+             assign dffOut = u1_out;\n"
+        ));
+
+        let cpu = synthesize("nand2tetris/solutions", "CPU").unwrap();
+        assert!(cpu.contains("Mux u1 (.a({1{1'b1}}), .b(instruction[5:5]),"));
+        assert!(cpu.contains("assign addressM = u2_out[14:0];\n"));
+    }
+
+    #[test]
+    fn test_verilog_generic() {
+        let generic = synthesize("arm", "MuxGen").unwrap();
+        assert!(generic.contains("module MuxGen #(parameter X = 1) (\n"));
+        assert!(generic.contains("input [(X - 1):0] in0,\n"));
+        assert!(generic.contains(
+            "genvar i;\n\
+             generate\n\
+             for (i = 0; i <= (X - 1); i = i + 1) begin : g0\n\
+             Mux u0 (.in0(in0[i:i]), .in1(in1[i:i]), .sel(sel), .out(out[i:i]));\n\
+             end\n\
+             endgenerate\n"
+        ));
+
+        let user = synthesize("arm", "MuxGen4").unwrap();
+        assert!(user.contains("MuxGen #(.X(4)) u0 (.in0(a), .in1(b), .sel(sel), .out(out));"));
+
+        let step = synthesize("step", "StepGen").unwrap();
+        assert!(step.contains(
+            "if (1 <= (W - 1))\n\
+             for (i = 1; i <= (W - 1); i = i + 2) begin : g1\n"
+        ));
+        assert!(step.contains("else\nfor (i = ((W - 1) + ((1 - (W - 1)) % 2)); i <= 1;"));
+
+        let reverse = synthesize("step", "RevGen").unwrap();
+        assert!(reverse.contains("for (i = 0; i <= (W - 1); i = i + 1) begin : g0\n"));
+    }
+
+    #[test]
+    fn test_verilog_constants() {
+        let constants = synthesize("literal", "Const").unwrap();
+        assert!(constants.contains("Id32 u0 (.in(32'd42), .out(answer));"));
+        assert!(constants.contains("assign u1_in[3:0] = 4'd5;"));
+
+        let generic = synthesize("literal", "ConstGen").unwrap();
+        assert!(generic.contains("IdGen #(.W(W)) u0 (.in('d3), .out(out));"));
+    }
+
+    #[test]
+    fn test_verilog_ram() {
+        let ram = synthesize("ram", "RAM8").unwrap();
+        assert!(ram.contains(
+            "reg [15:0] memory [0:7];\n\
+             always @(posedge clk[0]) begin\n\
+             if (load[0]) memory[address] <= in;\n\
+             end\n\
+             assign out = memory[address];\n"
+        ));
+
+        let err = synthesize("ram", "BadRAM").unwrap_err();
+        assert!(err.to_string().contains("its ports must be"));
+
+        let err = synthesize("ram", "HugeRAM").unwrap_err();
+        assert!(err.to_string().contains("it can be at most 31 bits wide"));
+    }
+
+    #[test]
+    fn test_keyv() {
+        assert_eq!(keyv("wire"), "wire_n2v");
+        assert_eq!(keyv("Wire"), "Wire");
+        assert_eq!(keyv("_x$1"), "_x$1");
+        assert_eq!(keyv("1st"), "\\1st ");
+        assert_eq!(module_name("dff"), "DFF");
+        assert_eq!(module_name("NAND"), "Nand");
+    }
+
+    // Quoted names that aren't Verilog identifiers are escaped, unless they
+    // have whitespace in them.
+    #[test]
+    fn test_verilog_escaped_identifiers() {
+        let symbols = synthesize("keyword", "Symbols").unwrap();
+        assert!(symbols.contains("input [0:0] \\a.b ,\ninput [0:0] \\a\\b ,\n"));
+        assert!(symbols.contains("Nand u0 (.a(\\a.b ), .b(\\a\\b ), .out(\\1st ));"));
+
+        let err = synthesize("vhdl", "Spaces").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("The name my in has whitespace in it"));
+        let err = synthesize("keyword", "SpacedWire").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("The name my wire has whitespace in it"));
+    }
+
+    #[test]
+    fn test_write_verilog_project() {
+        let hdl_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/tests/nand2tetris/solutions/Mux.hdl");
+        let (hdl, _) = parse_hdl_path(&hdl_path).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        write_verilog_project(&hdl, dir.path(), false).unwrap();
+        for file in ["nand.v", "dff.v", "Not.v", "And.v", "Or.v", "Mux.v"] {
+            assert!(dir.path().join(file).exists(), "{} was not written", file);
+        }
+    }
+}
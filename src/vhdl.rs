@@ -2,28 +2,34 @@
 // producing equivalent VHDL code.
 
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
 use std::fmt;
 use std::fmt::Write;
-use std::fs;
 use std::fs::File;
 use std::hash::{Hash, Hasher};
-use std::io::Write as OtherWrite;
+use std::io::{self, BufWriter, Write as OtherWrite};
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::ptr;
 use std::rc::Rc;
 
+use crate::error::{ErrorKind, N2VError};
 use crate::expr::{eval_expr, GenericWidth, Op, Terminal};
 use crate::opt::optimization::OptimizationInfo::{self};
 use crate::opt::optimization::OptimizationPass;
+use crate::opt::output_mirror::OutputMirror;
 use crate::opt::portmap_dedupe::PortMapDedupe;
 use crate::opt::sequential::SequentialPass;
+use crate::opt::structural_hash::StructuralHash;
 use crate::parser::*;
-use crate::simulator::Chip;
-use crate::simulator::{infer_widths};
-use crate::Scanner;
+use crate::profile::Profile;
+use crate::simulator::{constant_widths, gather_assignments, infer_widths};
+use crate::simulator::{Chip, Wire};
+use petgraph::algo::toposort;
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
 
 // ========= STRUCTS ========== //
 pub struct VhdlEntity {
@@ -33,7 +39,8 @@ pub struct VhdlEntity {
     pub signals: Vec<Signal>,       // Declared signals.
     pub statements: Vec<Statement>, // VHDL statements.
     pub optimization_info: Option<Rc<RefCell<OptimizationInfo>>>,
-    pub chip: Chip,
+    pub hdl: ChipHDL,       // HDL whose parts are declared as components.
+    pub target: VhdlTarget, // Standard the VHDL is written for.
 }
 impl Hash for VhdlEntity {
     fn hash<H: Hasher>(&self, state: &mut H) {
@@ -53,13 +60,43 @@ pub struct IdStatement {
 }
 
 #[derive(Clone)]
-//#[allow(clippy::large_enum_variant)]
+#[allow(clippy::large_enum_variant)]
 pub enum Statement {
     Component(VhdlComponent),
     Process(Process),
     Assignment(AssignmentVHDL),
     Assert(AssertVHDL),
     Wait(WaitVHDL),
+    Generate(GenerateVHDL),
+    /// An assignment made on the rising edge of `clk`, for a flattened DFF.
+    Register(AssignmentVHDL),
+    /// The memory array of a `BUILTIN RAM` chip.
+    Memory(MemoryVHDL),
+}
+
+/// Memory of a `BUILTIN RAM` chip, with one word for each address. Words
+/// are written on the rising edge of `clk` when `load` is set and are read
+/// without waiting for the clock.
+#[derive(Clone)]
+pub struct MemoryVHDL {
+    pub word_width: GenericWidth,
+    pub address_width: GenericWidth,
+}
+
+/// A for-generate loop in a generic chip.
+/// for i in start to end generate ... end generate;
+#[derive(Clone)]
+pub struct GenerateVHDL {
+    pub iterator: String,
+    pub start: GenericWidth,
+    pub end: GenericWidth,
+    /// Only every `step`th value from `start` generates the statements.
+    pub step: GenericWidth,
+    /// Whether the loop counts up (`to`) or down (`downto`). If this
+    /// depends on generics, both loops are written, each in an if-generate
+    /// that compares `start` with `end`.
+    pub ascending: Option<bool>,
+    pub statements: Vec<Statement>,
 }
 
 #[derive(Clone)]
@@ -76,6 +113,12 @@ pub struct WaitVHDL {}
 pub enum SignalRhs {
     Slice(SliceVHDL),
     Literal(LiteralVHDL),
+    /// Slices joined with `&`, most significant first.
+    Concat(Vec<SliceVHDL>),
+    /// NAND of two slices of the same width.
+    Nand(SliceVHDL, SliceVHDL),
+    /// High impedance, for bidirectional port bits the chip does not drive.
+    Released,
 }
 
 #[derive(Clone)]
@@ -83,6 +126,7 @@ pub enum SignalRhs {
 pub struct AssignmentVHDL {
     pub left: SliceVHDL,
     pub right: SignalRhs,
+    pub extension: Extension,
 }
 
 #[derive(Clone)]
@@ -107,6 +151,8 @@ pub struct Signal {
 pub struct VhdlComponent {
     pub unit: String,
     pub generic_params: Vec<GenericWidth>,
+    /// Generics declared by the unit, in the same order as `generic_params`.
+    pub generic_decls: Vec<String>,
     pub port_mappings: Vec<PortMappingVHDL>,
 }
 
@@ -125,15 +171,35 @@ pub struct SliceVHDL {
 
 #[derive(Clone)]
 pub struct PortMappingVHDL {
-    pub wire_name: String,
     pub port: SliceVHDL,
     pub wire: SignalRhs,
 }
 
+/// The VHDL standard that synthesized code is written for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+pub enum VhdlTarget {
+    /// VHDL-93, the newest standard Quartus Prime Lite supports.
+    #[default]
+    Vhdl93,
+    /// VHDL-2008, which allows reading out ports.
+    Vhdl2008,
+}
+
 pub struct QuartusProject {
     pub chip_hdl: ChipHDL,
     pub chip_vhdl: VhdlEntity,
     pub project_dir: PathBuf,
+    /// Merge structurally identical components in dependency chips.
+    pub structural_hash: bool,
+    /// Print each file as it is written.
+    pub verbose: bool,
+    /// `chip_vhdl` is a flattened entity, so no other files are written.
+    pub flatten: bool,
+    /// Standard that dependency chips are synthesized for. It should match
+    /// the standard `chip_vhdl` was synthesized for.
+    pub target: VhdlTarget,
+    /// Records the time spent building dependency chips, if set.
+    pub profile: Option<Rc<RefCell<Profile>>>,
 }
 
 pub struct VhdlPort {
@@ -171,20 +237,148 @@ impl<'a> Cage<'a> for &Vec<VhdlPort> {}
 // ========= DISPLAY ========== //
 //
 // The fmt::Display trait is used to convert VHDL abstract syntax nodes
-// to VHDL concrete syntax.
+// to VHDL concrete syntax. Nodes that contain widths are written with
+// `WriteVhdl` instead, because a width may use the maximum function, which
+// is spelled differently for each target standard.
+
+/// Converts a VHDL abstract syntax node to concrete syntax for a standard.
+trait WriteVhdl {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result;
+
+    /// The concrete syntax as a string, for use in a format string.
+    fn vhdl(&self, target: VhdlTarget) -> String {
+        let mut s = String::new();
+        self.write_vhdl(&mut s, target).unwrap();
+        s
+    }
+}
 
-impl fmt::Display for IdStatement {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl WriteVhdl for GenericWidth {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
+        match target {
+            VhdlTarget::Vhdl93 => write!(f, "{}", self),
+            VhdlTarget::Vhdl2008 => self.write_with_maximum(f, "maximum"),
+        }
+    }
+}
+
+impl WriteVhdl for IdStatement {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
         match &self.stmt {
-            Statement::Component(x) => write!(f, "cn2v{}: {}", self.id, x),
-            Statement::Process(x) => write!(f, "cn2v{}: {}", self.id, x),
-            Statement::Assignment(x) => write!(f, "{}", x),
+            Statement::Component(x) => write!(f, "cn2v{}: {}", self.id, x.vhdl(target)),
+            Statement::Process(x) => write!(f, "cn2v{}: {}", self.id, x.vhdl(target)),
+            Statement::Assignment(x) => x.write_vhdl(f, target),
             Statement::Assert(x) => write!(f, "cn2v{}: {}", self.id, x),
             Statement::Wait(_) => write!(f, "wait for 10 ns;"),
+            Statement::Generate(x) => write!(f, "gn2v{}: {}", self.id, x.vhdl(target)),
+            Statement::Register(x) => {
+                writeln!(f, "cn2v{}: process (clk) begin", self.id)?;
+                writeln!(f, "if rising_edge(clk(0)) then")?;
+                writeln!(f, "{}", x.vhdl(target))?;
+                writeln!(f, "end if;")?;
+                write!(f, "end process;")
+            }
+            Statement::Memory(_) => {
+                let address = format!("to_integer(unsigned({}))", keyw("address"));
+                writeln!(f, "cn2v{}: process (clk) begin", self.id)?;
+                writeln!(f, "if rising_edge(clk(0)) then")?;
+                writeln!(f, "if load(0) = '1' then")?;
+                writeln!(f, "memory({}) <= {};", address, keyw("in"))?;
+                writeln!(f, "end if;")?;
+                writeln!(f, "end if;")?;
+                writeln!(f, "end process;")?;
+                write!(f, "{} <= memory({});", keyw("out"), address)
+            }
+        }
+    }
+}
+
+impl MemoryVHDL {
+    // Declares the memory array type and signal.
+    fn write_declarations(&self, w: &mut impl fmt::Write, target: VhdlTarget) -> fmt::Result {
+        let last_address = match self.address_width {
+            GenericWidth::Terminal(Terminal::Num(a)) => ((1usize << a) - 1).to_string(),
+            _ => format!("2**{} - 1", self.address_width.vhdl(target)),
+        };
+        let word_end = GenericWidth::Expr(
+            Op::Sub,
+            Box::new(self.word_width.clone()),
+            Box::new(GenericWidth::Terminal(Terminal::Num(1))),
+        );
+        writeln!(
+            w,
+            "type memory_t is array (0 to {}) of std_logic_vector({} downto 0);",
+            last_address,
+            eval_expr(&word_end, &HashMap::new())
+                .unwrap_or(word_end)
+                .vhdl(target)
+        )?;
+        writeln!(w, "signal memory : memory_t;")
+    }
+}
+
+impl WriteVhdl for GenerateVHDL {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
+        let (start, end) = (self.start.vhdl(target), self.end.vhdl(target));
+        match self.ascending {
+            Some(true) => self.write_loop(f, "to", target),
+            Some(false) => self.write_loop(f, "downto", target),
+            None => {
+                writeln!(f, "block begin")?;
+                writeln!(f, "un2v: if {} <= {} generate", start, end)?;
+                write!(f, "ln2v: ")?;
+                self.write_loop(f, "to", target)?;
+                writeln!(f, "\nend generate;")?;
+                writeln!(f, "dn2v: if {} > {} generate", start, end)?;
+                write!(f, "ln2v: ")?;
+                self.write_loop(f, "downto", target)?;
+                writeln!(f, "\nend generate;")?;
+                write!(f, "end block;")
+            }
         }
     }
 }
 
+impl GenerateVHDL {
+    // The loop, counting up with `to` or down with `downto`.
+    fn write_loop(
+        &self,
+        f: &mut dyn fmt::Write,
+        direction: &str,
+        target: VhdlTarget,
+    ) -> fmt::Result {
+        let iterator = keyw(&self.iterator);
+        let (start, end) = (self.start.vhdl(target), self.end.vhdl(target));
+        writeln!(
+            f,
+            "for {} in {} {} {} generate",
+            iterator, start, direction, end
+        )?;
+        let stepped = self.step != GenericWidth::Terminal(Terminal::Num(1));
+        if stepped {
+            writeln!(
+                f,
+                "sn2v: if (({} - {}) mod {}) = 0 generate",
+                iterator,
+                start,
+                self.step.vhdl(target)
+            )?;
+        }
+        for (i, x) in self.statements.iter().enumerate() {
+            let id_stmt = IdStatement {
+                id: i,
+                stmt: x.clone(),
+            };
+            id_stmt.write_vhdl(f, target)?;
+            writeln!(f)?;
+        }
+        if stepped {
+            writeln!(f, "end generate;")?;
+        }
+        write!(f, "end generate;")
+    }
+}
+
 impl fmt::Display for AssertVHDL {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -197,9 +391,27 @@ impl fmt::Display for AssertVHDL {
     }
 }
 
-impl fmt::Display for AssignmentVHDL {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} <= {};", self.left, self.right)
+impl WriteVhdl for AssignmentVHDL {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
+        let (left, right) = (self.left.vhdl(target), self.right.vhdl(target));
+        let conversion = match self.extension {
+            Extension::None => return write!(f, "{} <= {};", left, right),
+            Extension::Zero => "unsigned",
+            Extension::Sign => "signed",
+        };
+
+        // 'length is not allowed on a slice, so compute the slice width.
+        let length = match (&self.left.start, &self.left.end) {
+            (Some(start), Some(end)) => {
+                format!("{} - {} + 1", end.vhdl(target), start.vhdl(target))
+            }
+            _ => format!("{}'length", keyw(&self.left.name)),
+        };
+        write!(
+            f,
+            "{} <= std_logic_vector(resize({}({}), {}));",
+            left, conversion, right, length
+        )
     }
 }
 
@@ -225,128 +437,213 @@ impl fmt::Display for LiteralVHDL {
     }
 }
 
-impl fmt::Display for SignalRhs {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl WriteVhdl for SignalRhs {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
         match self {
-            Self::Slice(x) => write!(f, "{}", x),
+            Self::Slice(x) => x.write_vhdl(f, target),
             Self::Literal(x) => write!(f, "{}", x),
+            Self::Concat(x) => {
+                let slices: Vec<String> = x.iter().map(|s| s.vhdl(target)).collect();
+                write!(f, "{}", slices.join(" & "))
+            }
+            Self::Nand(a, b) => write!(f, "{} nand {}", a.vhdl(target), b.vhdl(target)),
+            Self::Released => write!(f, "(others => 'Z')"),
         }
     }
 }
 
 impl fmt::Display for VhdlEntity {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "library ieee;")?;
-        writeln!(f, "use ieee.std_logic_1164.all;")?;
-        writeln!(f)?;
+        self.write(f)
+    }
+}
+
+impl VhdlEntity {
+    /// Writes the VHDL for this entity to `w` without building the whole
+    /// architecture in memory first.
+    pub fn write(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(w, "library ieee;")?;
+        writeln!(w, "use ieee.std_logic_1164.all;")?;
+        if self.uses_numeric_std() {
+            writeln!(w, "use ieee.numeric_std.all;")?;
+        }
+        writeln!(w)?;
 
         // Final VHDL generated for the top-level chip.
-        writeln!(f, "entity {} is", keyw(&self.name))?;
+        writeln!(w, "entity {} is", keyw(&self.name))?;
 
-        for x in &self.generics {
-            writeln!(f, "{}", x)?;
+        if !self.generics.is_empty() {
+            write_generic_clause(w, &self.generics)?;
         }
 
-        let port_vec: Vec<String> = self.ports.iter().map(|x| keyw(&x.to_string())).collect();
-        if !port_vec.is_empty() {
-            writeln!(f, "port (")?;
-            writeln!(f, "{}", port_vec.join(";\n"))?;
-            writeln!(f, ");")?;
+        if !self.ports.is_empty() {
+            writeln!(w, "port (")?;
+            for (i, x) in self.ports.iter().enumerate() {
+                if i != 0 {
+                    writeln!(w, ";")?;
+                }
+                x.write_vhdl(w, self.target)?;
+            }
+            writeln!(w)?;
+            writeln!(w, ");")?;
         }
 
-        writeln!(f, "end entity {};", keyw(&self.name))?;
-        writeln!(f)?;
+        writeln!(w, "end entity {};", keyw(&self.name))?;
+        writeln!(w)?;
 
-        writeln!(f, "architecture arch of {} is", keyw(&self.name))?;
+        writeln!(w, "architecture arch of {} is", keyw(&self.name))?;
 
         // We need to iterate over HDL parts in order to generate declarations for them.
         let mut seen = HashSet::new();
-        for part in &self.chip.hdl.as_ref().unwrap().parts {
+        for part in &self.hdl.parts {
             match part {
                 Part::Component(component) => {
                     if seen.insert(&component.name.value) {
                         // If it's a Component, we generate its declaration
-                        let decl = self.declaration(component, Rc::clone(&self.chip.hdl_provider))?;
-                        writeln!(f, "{}", decl)?;
+                        self.write_declaration(w, component, Rc::clone(&self.hdl.provider))?;
+                        writeln!(w)?;
                     }
                 }
                 Part::Loop(loop_hdl) => {
                     for component in &loop_hdl.body {
                         if seen.insert(&component.name.value) {
-                            let decl = self.declaration(component, Rc::clone(&self.chip.hdl_provider))?;
-                            writeln!(f, "{}", decl)?;
+                            self.write_declaration(w, component, Rc::clone(&self.hdl.provider))?;
+                            writeln!(w)?;
                         }
                     }
                 }
-                Part::AssignmentHDL(_) => {
+                Part::AssignmentHDL(_) | Part::Assert(_) => {
                     // Do nothing for AssignmentHDL
                 }
             }
         }
 
+        for (name, value) in &self.hdl.constants {
+            writeln!(w, "constant {} : natural := {};", keyw(&name.value), value)?;
+        }
         for x in &self.signals {
-            writeln!(f, "signal {}", x)?;
+            writeln!(w, "signal {}", x.vhdl(self.target))?;
+        }
+        for x in &self.statements {
+            if let Statement::Memory(memory) = x {
+                memory.write_declarations(w, self.target)?;
+            }
         }
 
-        writeln!(f, "begin")?;
+        writeln!(w, "begin")?;
         for (i, x) in self.statements.iter().enumerate() {
             let id_stmt = IdStatement {
                 id: i,
                 stmt: x.clone(),
             };
-            writeln!(f, "{}", id_stmt)?;
+            id_stmt.write_vhdl(w, self.target)?;
+            writeln!(w)?;
         }
 
-        writeln!(f, "end arch;")?;
-        write!(f, "")
+        writeln!(w, "end arch;")
     }
-}
 
-// Declaration VHDL for an entity.
-impl VhdlEntity {
-    fn declaration(
+    // Extended assignments are synthesized with numeric_std's resize,
+    // memory addresses are converted with to_integer, and constants of
+    // generic width with to_unsigned.
+    fn uses_numeric_std(&self) -> bool {
+        self.statements.iter().any(|s| match s {
+            Statement::Assignment(a) => a.extension != Extension::None,
+            Statement::Memory(_) => true,
+            Statement::Component(c) => uses_to_unsigned(c),
+            Statement::Generate(g) => g.statements.iter().any(|s| match s {
+                Statement::Component(c) => uses_to_unsigned(c),
+                _ => false,
+            }),
+            _ => false,
+        })
+    }
+
+    // Declaration VHDL for an entity.
+    fn write_declaration(
         &self,
+        w: &mut impl fmt::Write,
         dep: &Component,
         provider: Rc<dyn HdlProvider>,
-    ) -> Result<String, std::fmt::Error> {
-        let mut decl = String::new();
-
+    ) -> fmt::Result {
         // Just parse it again to get the chip_hdl.
         // It's not ideal, but it's the easiest way to get the chip_hdl for now.
         let chip_hdl = get_hdl(&dep.name.value, &provider).unwrap();
 
-        writeln!(decl, "component {} is", keyw(&dep.name.value))?;
-        writeln!(decl, "port (")?;
+        writeln!(w, "component {} is", keyw(&dep.name.value))?;
+        let generics: Vec<String> = chip_hdl
+            .generic_decls
+            .iter()
+            .map(|g| g.value.clone())
+            .collect();
+        if !generics.is_empty() {
+            write_generic_clause(w, &generics)?;
+        }
+        writeln!(w, "port (")?;
 
-        match &self.optimization_info {
-            Some(info) => match RefCell::borrow(info).deref() {
+        if let Some(info) = &self.optimization_info {
+            match RefCell::borrow(info).deref() {
                 OptimizationInfo::SequentialFlagMap(seq_flag_map) => {
                     if seq_flag_map.get(&dep.name.value) == Some(&true) {
-                        writeln!(decl, "clk : in std_logic_vector(0 downto 0);")?;
+                        writeln!(w, "clk : in std_logic_vector(0 downto 0);")?;
                     }
                 }
                 OptimizationInfo::None => unimplemented!(),
-            },
-            None => (),
+            }
         }
 
-        let vhdl_ports: Vec<String> = chip_hdl
-            .ports
-            .iter()
-            .map(|port| VhdlPort::from(port).to_string())
-            .collect();
-        writeln!(decl, "{}", vhdl_ports.join(";\n"))?;
+        for (i, port) in chip_hdl.ports.iter().enumerate() {
+            if i != 0 {
+                writeln!(w, ";")?;
+            }
+            vhdl_port(port, &chip_hdl).write_vhdl(w, self.target)?;
+        }
+        writeln!(w)?;
 
+        writeln!(w, ");")?;
+        writeln!(w, "end component {};", keyw(&dep.name.value))
+    }
 
-        writeln!(decl, ");")?;
-        writeln!(decl, "end component {};", keyw(&dep.name.value))?;
+    /// Writes the VHDL for this entity to a new file at `path`.
+    pub fn write_file(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.write_io(File::create(path)?)
+    }
 
-        Ok(decl)
+    /// Streams the VHDL for this entity to an io::Write such as a File.
+    pub fn write_io(&self, w: impl io::Write) -> Result<(), Box<dyn Error>> {
+        let mut writer = IoWriter {
+            inner: BufWriter::new(w),
+            error: None,
+        };
+        if self.write(&mut writer).is_err() {
+            return Err(match writer.error {
+                Some(e) => Box::new(e),
+                None => Box::new(fmt::Error),
+            });
+        }
+        writer.inner.flush()?;
+        Ok(())
     }
 }
 
-impl fmt::Display for Signal {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+/// Adapts an io::Write to fmt::Write so that VHDL can be streamed to a file.
+/// The underlying io error is kept since fmt::Error carries no information.
+struct IoWriter<W: io::Write> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+impl WriteVhdl for Signal {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
         write!(f, "{} : ", keyw(&self.name))?;
         match self.width {
             GenericWidth::Terminal(Terminal::Num(port_width_num)) => {
@@ -358,19 +655,26 @@ impl fmt::Display for Signal {
                     Box::new(self.width.clone()),
                     Box::new(GenericWidth::Terminal(Terminal::Num(1))),
                 );
-                write!(
-                    f,
-                    "std_logic_vector({} downto 0);",
-                    eval_expr(&sub1, &HashMap::new())
-                )
+                let end = eval_expr(&sub1, &HashMap::new()).unwrap_or(sub1);
+                write!(f, "std_logic_vector({} downto 0);", end.vhdl(target))
             }
         }
     }
 }
 
-impl fmt::Display for VhdlComponent {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} port map(", keyw(&self.unit))?;
+impl WriteVhdl for VhdlComponent {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
+        write!(f, "{} ", keyw(&self.unit))?;
+        if !self.generic_params.is_empty() {
+            let generic_map: Vec<String> = self
+                .generic_decls
+                .iter()
+                .zip(&self.generic_params)
+                .map(|(name, value)| format!("{} => {}", keyw(name), value.vhdl(target)))
+                .collect();
+            write!(f, "generic map({}) ", generic_map.join(", "))?;
+        }
+        write!(f, "port map(")?;
 
         // At this point the VHDL AST should be valid, so here
         // we don't need to handle the case where the same output port
@@ -379,14 +683,23 @@ impl fmt::Display for VhdlComponent {
             if i != 0 {
                 write!(f, ", ")?;
             }
-            write!(f, "{}", mapping)?;
+            mapping.write_vhdl(f, target)?;
         }
         writeln!(f, ");")
     }
 }
 
-impl fmt::Display for Process {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+/// Writes `generic (X : natural; ...);` for an entity or component.
+fn write_generic_clause(w: &mut impl fmt::Write, generics: &[String]) -> fmt::Result {
+    let decls: Vec<String> = generics
+        .iter()
+        .map(|g| format!("{} : natural", keyw(g)))
+        .collect();
+    writeln!(w, "generic ({});", decls.join("; "))
+}
+
+impl WriteVhdl for Process {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
         writeln!(f, "process begin")?;
 
         for (i, x) in self.statements.iter().enumerate() {
@@ -394,7 +707,8 @@ impl fmt::Display for Process {
                 id: i,
                 stmt: x.clone(),
             };
-            writeln!(f, "{}", id_stmt)?;
+            id_stmt.write_vhdl(f, target)?;
+            writeln!(f)?;
         }
 
         writeln!(f, "end process;")
@@ -402,33 +716,57 @@ impl fmt::Display for Process {
 }
 
 /// Synthesizes VHDL for BusVHDL.
-impl std::fmt::Display for SliceVHDL {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl WriteVhdl for SliceVHDL {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
         if self.name == "true" {
             return write!(f, "(others => '1')");
         } else if self.name == "false" {
             return write!(f, "(others => '0')");
+        } else if self.name == "none" {
+            return write!(f, "(others => 'X')");
+        }
+        if let (Some(value), Some(start), Some(end)) =
+            (numeric_literal(&self.name), &self.start, &self.end)
+        {
+            let one = GenericWidth::Terminal(Terminal::Num(1));
+            let width = &(end - start) + &one;
+            return match eval_expr(&width, &HashMap::new()).unwrap_or(width) {
+                GenericWidth::Terminal(Terminal::Num(width)) => {
+                    write!(f, "\"{:0width$b}\"", value, width = width)
+                }
+                width => write!(
+                    f,
+                    "std_logic_vector(to_unsigned({}, {}))",
+                    value,
+                    width.vhdl(target)
+                ),
+            };
         }
 
         // Only write out downto syntax if this is an array.
-        if self.start.is_some() {
-            let start: &GenericWidth = self.start.as_ref().unwrap();
+        if let Some(start) = &self.start {
             let end: &GenericWidth = self.end.as_ref().unwrap();
-            write!(f, "{}({} downto {})", keyw(&self.name), end, start)
+            write!(
+                f,
+                "{}({} downto {})",
+                keyw(&self.name),
+                end.vhdl(target),
+                start.vhdl(target)
+            )
         } else {
             write!(f, "{}", keyw(&self.name))
         }
     }
 }
 
-impl fmt::Display for VhdlPort {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl WriteVhdl for VhdlPort {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
         write!(f, "{} : ", keyw(&self.name))?;
 
-        if self.direction == PortDirection::In {
-            write!(f, "in ")?;
-        } else {
-            write!(f, "out ")?;
+        match self.direction {
+            PortDirection::In => write!(f, "in ")?,
+            PortDirection::Out => write!(f, "out ")?,
+            PortDirection::InOut => write!(f, "inout ")?,
         }
 
         match self.width {
@@ -441,11 +779,8 @@ impl fmt::Display for VhdlPort {
                     Box::new(self.width.clone()),
                     Box::new(GenericWidth::Terminal(Terminal::Num(1))),
                 );
-                write!(
-                    f,
-                    "std_logic_vector({} downto 0)",
-                    eval_expr(&sub1, &HashMap::new())
-                )?;
+                let end = eval_expr(&sub1, &HashMap::new()).unwrap_or(sub1);
+                write!(f, "std_logic_vector({} downto 0)", end.vhdl(target))?;
             }
         };
 
@@ -453,38 +788,96 @@ impl fmt::Display for VhdlPort {
     }
 }
 
-impl std::fmt::Display for PortMappingVHDL {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} => {}", &self.port, &self.wire)
+impl WriteVhdl for PortMappingVHDL {
+    fn write_vhdl(&self, f: &mut dyn fmt::Write, target: VhdlTarget) -> fmt::Result {
+        write!(
+            f,
+            "{} => {}",
+            self.port.vhdl(target),
+            self.wire.vhdl(target)
+        )
     }
 }
 
 // ========= CONVERSIONS ========== //
 
-/// This is where VHDL is synthesized for an HDL chip.
+/// Synthesizes VHDL-93 for an HDL chip.
 impl TryFrom<&ChipHDL> for VhdlEntity {
     type Error = Box<dyn Error>;
 
     fn try_from(raw_hdl: &ChipHDL) -> Result<Self, Box<dyn Error>> {
+        VhdlEntity::synthesize(raw_hdl, VhdlTarget::Vhdl93)
+    }
+}
+
+impl VhdlEntity {
+    /// This is where VHDL is synthesized for an HDL chip.
+    pub fn synthesize(raw_hdl: &ChipHDL, target: VhdlTarget) -> Result<Self, Box<dyn Error>> {
+        VhdlEntity::synthesize_with_profile(raw_hdl, target, None)
+    }
+
+    /// Synthesizes VHDL for an HDL chip, recording the time spent building
+    /// its chip and components in `profile`.
+    pub fn synthesize_with_profile(
+        raw_hdl: &ChipHDL,
+        target: VhdlTarget,
+        profile: Option<Rc<RefCell<Profile>>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if raw_hdl.builtin.as_deref() == Some(RAM_BUILTIN) {
+            return ram_entity(raw_hdl, target);
+        }
+        if let Some(builtin) = &raw_hdl.builtin {
+            return Err(Box::new(N2VError {
+                msg: format!(
+                    "Chip {} is implemented by builtin {} and cannot be synthesized to VHDL.",
+                    raw_hdl.name, builtin
+                ),
+                kind: ErrorKind::Other,
+            }));
+        }
+
         let mut dedupe_pass = PortMapDedupe::new();
-        let (chip_hdl, _) = &dedupe_pass.apply(raw_hdl, &raw_hdl.provider)?;
+        let (deduped_hdl, _) = dedupe_pass.apply(raw_hdl, &raw_hdl.provider)?;
+
+        // VHDL-93 cannot read out ports, so read outputs go through a mirror
+        // signal. VHDL-2008 reads them directly.
+        let chip_hdl = &match target {
+            VhdlTarget::Vhdl93 => {
+                OutputMirror::new()
+                    .apply(&deduped_hdl, &raw_hdl.provider)?
+                    .0
+            }
+            VhdlTarget::Vhdl2008 => deduped_hdl,
+        };
 
-        let chip = Chip::new(
-            chip_hdl,
-            ptr::null_mut(),
-            &chip_hdl.provider,
-            true,
-            &Vec::new(),
-        )?;
+        // A generic chip is written once for every width, so its loops
+        // become generate statements instead of being expanded.
+        let generics: Vec<String> = chip_hdl
+            .generic_decls
+            .iter()
+            .map(|g| g.value.clone())
+            .collect();
+        let (components, width_components, released) = if generics.is_empty() {
+            let chip = Chip::new_with_profile(
+                chip_hdl,
+                ptr::null_mut(),
+                &chip_hdl.provider,
+                true,
+                &Vec::new(),
+                profile,
+            )?;
+            (chip.components.clone(), chip.components, chip.released)
+        } else {
+            let (components, width_components) = generic_components(chip_hdl);
+            (components, width_components, Vec::new())
+        };
 
         let mut vhdl_components: Vec<VhdlComponent> =
-            chip.components.iter().map(VhdlComponent::from).collect();
-
-        let generics: Vec<String> = Vec::new();
+            components.iter().map(VhdlComponent::from).collect();
         let mut ports: Vec<VhdlPort> = Vec::new();
 
         for port in &chip_hdl.ports {
-            ports.push(VhdlPort::from(port));
+            ports.push(vhdl_port(port, chip_hdl));
         }
 
         // Create a clock port if this is a sequential chip.
@@ -508,15 +901,16 @@ impl TryFrom<&ChipHDL> for VhdlEntity {
 
         let inferred_widths = infer_widths(
             chip_hdl,
-            &Vec::new(),
-            &chip.components,
+            &gather_assignments(&chip_hdl.parts),
+            &width_components,
             &chip_hdl.provider,
             &Vec::new(),
+            false,
         )?;
 
         let ports_ref = &ports;
 
-        let signals = inferred_widths
+        let mut signals: Vec<Signal> = inferred_widths
             .iter()
             .filter(|(signal_name, _)| !ports_ref.contains_name(signal_name))
             .map(|(signal_name, signal_width)| Signal {
@@ -524,16 +918,24 @@ impl TryFrom<&ChipHDL> for VhdlEntity {
                 width: signal_width.clone(),
             })
             .collect();
-
-        let mut statements = Vec::new();
-        for c in &mut vhdl_components {
-            // If this is a sequential chip, we need to add a clock port mapping.
+        // Sort so that output does not depend on hash map ordering.
+        signals.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // If this is a sequential chip, we need to add a clock port mapping.
+        let finish_component = |c: &mut VhdlComponent| -> Result<(), Box<dyn Error>> {
+            if !c.generic_params.is_empty() {
+                c.generic_decls = get_hdl(&c.unit, &chip_hdl.provider)?
+                    .generic_decls
+                    .iter()
+                    .map(|g| g.value.clone())
+                    .collect();
+            }
+            size_constants(c, &chip_hdl.provider)?;
             if let OptimizationInfo::SequentialFlagMap(sequential_flag_map) =
                 &*sequential_pass_info.borrow()
             {
                 if sequential_flag_map.get(&c.unit) == Some(&true) {
                     let clock_port_mapping = PortMappingVHDL {
-                        wire_name: "clk".to_string(),
                         port: SliceVHDL {
                             name: "clk".to_string(),
                             start: None,
@@ -549,20 +951,80 @@ impl TryFrom<&ChipHDL> for VhdlEntity {
                     c.port_mappings.push(clock_port_mapping);
                 }
             }
+            Ok(())
+        };
 
+        let mut statements = Vec::new();
+        for c in &mut vhdl_components {
+            finish_component(c)?;
             statements.push(Statement::Component(c.clone()));
         }
 
+        // Loops are only left in generic chips.
+        if !generics.is_empty() {
+            for part in &chip_hdl.parts {
+                if let Part::Loop(loop_hdl) = part {
+                    let mut body = Vec::new();
+                    for component in &loop_hdl.body {
+                        let mut c = VhdlComponent::from(component);
+                        finish_component(&mut c)?;
+                        body.push(Statement::Component(c));
+                    }
+                    let (start, end, ascending) =
+                        loop_hdl.synthesis_bounds(&chip_hdl.constant_values());
+                    statements.push(Statement::Generate(GenerateVHDL {
+                        iterator: loop_hdl.iterator.value.clone(),
+                        start,
+                        end,
+                        step: loop_hdl.step.clone(),
+                        ascending,
+                        statements: body,
+                    }));
+                }
+            }
+        }
+
         // Synthesize assignments. Assignments were added after components
         // and are handled a little differently because they don't instantiate
         // a chip.
         for assignment in &chip_hdl.parts {
             if let Part::AssignmentHDL(assignment) = assignment {
-                let assignment = AssignmentVHDL::from(assignment);
+                let releases = chip_hdl.ports.iter().any(|p| {
+                    p.direction == PortDirection::InOut && p.name.value == assignment.left.name
+                }) && matches!(assignment.right.as_slice(), [r] if r.name == "none");
+                let mut assignment = AssignmentVHDL::from(assignment);
+                if releases {
+                    assignment.right = SignalRhs::Released;
+                }
                 statements.push(Statement::Assignment(assignment));
             }
         }
 
+        // Bidirectional port bits that nothing drives must be released, or
+        // the chip's driver would keep the bus from being resolved.
+        for bus in released {
+            let range = bus.range.unwrap();
+            statements.push(Statement::Assignment(AssignmentVHDL {
+                left: SliceVHDL {
+                    name: bus.name,
+                    start: Some(GenericWidth::Terminal(Terminal::Num(range.start))),
+                    end: Some(GenericWidth::Terminal(Terminal::Num(range.end - 1))),
+                },
+                right: SignalRhs::Released,
+                extension: Extension::None,
+            }));
+        }
+
+        for part in &chip_hdl.parts {
+            if let Part::Assert(assert) = part {
+                statements.push(Statement::Assert(assert_vhdl(
+                    assert,
+                    chip_hdl,
+                    &inferred_widths,
+                )?));
+            }
+        }
+
         Ok(VhdlEntity {
             name: chip_hdl.name.clone(),
             generics,
@@ -570,21 +1032,242 @@ impl TryFrom<&ChipHDL> for VhdlEntity {
             signals,
             statements,
             optimization_info: Some(Rc::clone(&sequential_pass_info)),
-            chip,
+            hdl: chip_hdl.clone(),
+            target,
+        })
+    }
+}
+
+// A concurrent assertion for an `ASSERT` statement. The signal must have a
+// width that does not depend on generics, so that the value can be written
+// as a bit string.
+fn assert_vhdl(
+    assert: &AssertHDL,
+    chip_hdl: &ChipHDL,
+    inferred_widths: &HashMap<String, GenericWidth>,
+) -> Result<AssertVHDL, Box<dyn Error>> {
+    let name = &assert.signal.value;
+    let assert_error = |msg: String| {
+        Box::new(N2VError {
+            msg,
+            kind: ErrorKind::ParseIdentError(chip_hdl.provider.clone(), assert.signal.clone()),
         })
+    };
+    let width = chip_hdl
+        .ports
+        .iter()
+        .find(|p| &p.name.value == name)
+        .map(|p| &p.width)
+        .or_else(|| inferred_widths.get(name))
+        .ok_or_else(|| {
+            assert_error(format!(
+                "Chip {} has no signal named {} to assert.",
+                chip_hdl.name, name
+            ))
+        })?;
+    let width = match eval_expr(width, &constant_widths(chip_hdl))? {
+        GenericWidth::Terminal(Terminal::Num(width)) => width,
+        width => {
+            return Err(assert_error(format!(
+                "Cannot assert the value of {} in VHDL, because its width {} depends on \
+                 generics.",
+                name, width
+            )))
+        }
+    };
+    // Passes such as output mirroring may have renamed the signal, so the
+    // message refers to the line of the statement instead.
+    let report_msg = match assert.signal.line {
+        Some(line) => format!("Assertion on line {} of {} failed", line, chip_hdl.name),
+        None => format!("Assertion on {} in {} failed", name, chip_hdl.name),
+    };
+    let mut values = assert.expected(width).map_err(assert_error)?;
+    // LiteralVHDL is least significant bit first.
+    values.reverse();
+    Ok(AssertVHDL {
+        signal_name: name.clone(),
+        signal_value: LiteralVHDL { values },
+        report_msg,
+    })
+}
+
+// A numeric constant whose width depends on a generic is converted with
+// to_unsigned. Other constants are written as bit strings.
+fn uses_to_unsigned(c: &VhdlComponent) -> bool {
+    c.port_mappings.iter().any(|m| match &m.wire {
+        SignalRhs::Slice(wire) => {
+            numeric_literal(&wire.name).is_some()
+                && !matches!(wire.end, Some(GenericWidth::Terminal(Terminal::Num(_))))
+        }
+        _ => false,
+    })
+}
+
+/// Gives each numeric constant mapped by a component the width of the port
+/// or port slice it is mapped to, so that it can be written as a vector.
+fn size_constants(
+    c: &mut VhdlComponent,
+    provider: &Rc<dyn HdlProvider>,
+) -> Result<(), Box<dyn Error>> {
+    for m in &mut c.port_mappings {
+        let SignalRhs::Slice(wire) = &mut m.wire else {
+            continue;
+        };
+        if numeric_literal(&wire.name).is_none() {
+            continue;
+        }
+        let one = GenericWidth::Terminal(Terminal::Num(1));
+        let width = match (&m.port.start, &m.port.end) {
+            (Some(start), Some(end)) => &(end - start) + &one,
+            _ => {
+                let unit = get_hdl(&c.unit, provider)?;
+                let mut variables = constant_widths(&unit);
+                variables.extend(
+                    c.generic_decls
+                        .iter()
+                        .cloned()
+                        .zip(c.generic_params.clone()),
+                );
+                let port = unit.get_port(&m.port.name)?;
+                eval_expr(&port.width, &variables)?
+            }
+        };
+        wire.start = Some(GenericWidth::Terminal(Terminal::Num(0)));
+        wire.end = Some(eval_expr(&(&width - &one), &HashMap::new())?);
+    }
+    Ok(())
+}
+
+/// Word width and address width of a `BUILTIN RAM` chip. The chip must have
+/// the ports of the Hack RAM chips, in[W], load, address[A] and out[W],
+/// with any word width W and address width A.
+pub fn ram_widths(hdl: &ChipHDL) -> Result<(GenericWidth, GenericWidth), Box<dyn Error>> {
+    let port = |name: &str, direction: PortDirection| {
+        hdl.ports
+            .iter()
+            .find(|p| p.name.value == name && p.direction == direction)
+    };
+    let ports = (
+        port("in", PortDirection::In),
+        port("load", PortDirection::In),
+        port("address", PortDirection::In),
+        port("out", PortDirection::Out),
+    );
+    match ports {
+        (Some(input), Some(load), Some(address), Some(output))
+            // Compare as text since variables carry their source location.
+            if hdl.ports.len() == 4
+                && input.width.to_string() == output.width.to_string()
+                && load.width == GenericWidth::Terminal(Terminal::Num(1)) =>
+        {
+            // The memory is indexed by a VHDL integer, which has 32 bits.
+            if let GenericWidth::Terminal(Terminal::Num(a)) = address.width {
+                if a > 31 {
+                    return Err(Box::new(N2VError {
+                        msg: format!(
+                            "The address of builtin RAM {} is {} bits wide, but it can be at \
+                             most 31 bits wide.",
+                            hdl.name, a
+                        ),
+                        kind: ErrorKind::Other,
+                    }));
+                }
+            }
+            Ok((input.width.clone(), address.width.clone()))
+        }
+        _ => Err(Box::new(N2VError {
+            msg: format!(
+                "Chip {} is a builtin RAM, so its ports must be in[W], load, address[A] \
+                 and out[W].",
+                hdl.name
+            ),
+            kind: ErrorKind::Other,
+        })),
     }
 }
 
-fn group_port_mappings(component: &Component) -> HashMap<String, Vec<&PortMappingHDL>> {
-    // port_mappings is a HashMap where each key is the port name, and each
-    // value is a vector of all the PortMappingHDL instances where that port
-    // is mapped.
-    let mut grouped_port_mappings: HashMap<String, Vec<&PortMappingHDL>> = HashMap::new();
+/// Synthesizes a `BUILTIN RAM` chip as a memory array instead of a DFF for
+/// every bit.
+fn ram_entity(hdl: &ChipHDL, target: VhdlTarget) -> Result<VhdlEntity, Box<dyn Error>> {
+    let (word_width, address_width) = ram_widths(hdl)?;
+    let mut ports: Vec<VhdlPort> = hdl.ports.iter().map(|p| vhdl_port(p, hdl)).collect();
+    ports.push(VhdlPort {
+        name: "clk".to_string(),
+        width: GenericWidth::Terminal(Terminal::Num(1)),
+        direction: PortDirection::In,
+    });
+    Ok(VhdlEntity {
+        name: hdl.name.clone(),
+        generics: hdl.generic_decls.iter().map(|g| g.value.clone()).collect(),
+        ports,
+        signals: Vec::new(),
+        statements: vec![Statement::Memory(MemoryVHDL {
+            word_width,
+            address_width,
+        })],
+        optimization_info: None,
+        hdl: hdl.clone(),
+        target,
+    })
+}
+
+/// Components of a generic chip outside of loops, and the components to
+/// infer signal widths from. Loop bodies are included in the second list
+/// with the iterator at its largest value, which gives the widest slice each
+/// signal needs when the slice grows with the iterator.
+pub fn generic_components(chip_hdl: &ChipHDL) -> (Vec<Component>, Vec<Component>) {
+    let mut components = Vec::new();
+    let mut width_components = Vec::new();
+    for part in &chip_hdl.parts {
+        match part {
+            Part::Component(c) => {
+                components.push(c.clone());
+                width_components.push(c.clone());
+            }
+            Part::Loop(loop_hdl) => {
+                let highest = loop_hdl.highest(&chip_hdl.constant_values());
+                let last = HashMap::from([(loop_hdl.iterator.value.clone(), highest)]);
+                let at_last = |bus: &BusHDL| BusHDL {
+                    name: bus.name.clone(),
+                    start: bus
+                        .start
+                        .as_ref()
+                        .map(|x| eval_expr(x, &last).unwrap_or(x.clone())),
+                    end: bus
+                        .end
+                        .as_ref()
+                        .map(|x| eval_expr(x, &last).unwrap_or(x.clone())),
+                };
+                for c in &loop_hdl.body {
+                    let mut c = c.clone();
+                    for m in &mut c.mappings {
+                        m.wire = at_last(&m.wire);
+                        m.port = at_last(&m.port);
+                    }
+                    width_components.push(c);
+                }
+            }
+            Part::AssignmentHDL(_) | Part::Assert(_) => {}
+        }
+    }
+    (components, width_components)
+}
+
+pub fn group_port_mappings(component: &Component) -> Vec<(String, Vec<&PortMappingHDL>)> {
+    // Each entry is a port name and a vector of all the PortMappingHDL
+    // instances where that port is mapped. Ports are kept in the order they
+    // first appear in the HDL so that synthesis output is deterministic.
+    let mut grouped_port_mappings: Vec<(String, Vec<&PortMappingHDL>)> = Vec::new();
     for port_mapping in &component.mappings {
-        grouped_port_mappings
-            .entry(port_mapping.port.name.clone())
-            .or_default()
-            .push(port_mapping);
+        match grouped_port_mappings
+            .iter_mut()
+            .find(|(name, _)| name == &port_mapping.port.name)
+        {
+            Some((_, mappings)) => mappings.push(port_mapping),
+            None => {
+                grouped_port_mappings.push((port_mapping.port.name.clone(), vec![port_mapping]))
+            }
+        }
     }
     grouped_port_mappings
 }
@@ -621,7 +1304,6 @@ impl From<&Component> for VhdlComponent {
                     };
 
                     vhdl_port_mappings.push(PortMappingVHDL {
-                        wire_name: intermediate_signal_name,
                         port: SliceVHDL::from(&mapping.port),
                         wire: SignalRhs::Slice(wire),
                     });
@@ -632,6 +1314,7 @@ impl From<&Component> for VhdlComponent {
         VhdlComponent {
             unit: component.name.value.clone(),
             generic_params: component.generic_params.clone(),
+            generic_decls: Vec::new(),
             port_mappings: vhdl_port_mappings,
         }
     }
@@ -655,13 +1338,29 @@ impl From<&SignalRhs> for BusHDL {
                 start: slice.start.clone(),
                 end: slice.end.clone(),
             },
-            SignalRhs::Literal(_l) => {
+            SignalRhs::Literal(_)
+            | SignalRhs::Concat(_)
+            | SignalRhs::Nand(_, _)
+            | SignalRhs::Released => {
                 panic!("Not yet implemented.");
             }
         }
     }
 }
 
+// An entity's ports cannot refer to constants declared in its
+// architecture, so the values of the chip's constants are filled in. A
+// width that can't be evaluated is left as it is.
+fn vhdl_port(port: &GenericPort, hdl: &ChipHDL) -> VhdlPort {
+    let mut res = VhdlPort::from(port);
+    if !hdl.constants.is_empty() {
+        if let Ok(width) = eval_expr(&port.width, &constant_widths(hdl)) {
+            res.width = width;
+        }
+    }
+    res
+}
+
 impl From<&GenericPort> for VhdlPort {
     fn from(port: &GenericPort) -> Self {
         VhdlPort {
@@ -677,7 +1376,6 @@ impl From<&PortMappingHDL> for PortMappingVHDL {
         let wire = SignalRhs::Slice(SliceVHDL::from(&pm.wire));
 
         PortMappingVHDL {
-            wire_name: pm.wire.name.clone(),
             port: SliceVHDL::from(&pm.port),
             wire,
         }
@@ -686,10 +1384,362 @@ impl From<&PortMappingHDL> for PortMappingVHDL {
 
 impl From<&AssignmentHDL> for AssignmentVHDL {
     fn from(assignment: &AssignmentHDL) -> Self {
+        let right = match assignment.right.as_slice() {
+            [right] => SignalRhs::Slice(SliceVHDL::from(right)),
+            segments => SignalRhs::Concat(segments.iter().map(SliceVHDL::from).collect()),
+        };
         AssignmentVHDL {
             left: SliceVHDL::from(&assignment.left),
-            right: SignalRhs::Slice(SliceVHDL::from(&assignment.right)),
+            right,
+            extension: assignment.extension,
+        }
+    }
+}
+
+/// Synthesizes a chip and every chip inside it as a single entity. The
+/// design is elaborated down to NAND gates and DFFs, which are written as
+/// statements instead of component instances. Each net of the flattened
+/// design is a bit of the `net_n2v` signal, unless it is an input port bit.
+/// The time spent building each chip is recorded in `profile`, if set.
+pub fn flatten_entity(
+    hdl: &ChipHDL,
+    profile: Option<Rc<RefCell<Profile>>>,
+) -> Result<VhdlEntity, Box<dyn Error>> {
+    let mut chip = Chip::new_with_profile(
+        hdl,
+        ptr::null_mut(),
+        &hdl.provider,
+        false,
+        &Vec::new(),
+        profile,
+    )?;
+    let (mut netlist, port_nets) = Netlist::from_chip(&mut chip)?;
+
+    let bit = |name: &str, i: usize| SliceVHDL {
+        name: String::from(name),
+        start: Some(GenericWidth::Terminal(Terminal::Num(i))),
+        end: Some(GenericWidth::Terminal(Terminal::Num(i))),
+    };
+
+    // Nets joined to an input port are named after the port bit.
+    let mut names: HashMap<usize, SliceVHDL> = HashMap::new();
+    for port in hdl
+        .ports
+        .iter()
+        .filter(|p| p.direction == PortDirection::In)
+    {
+        for (i, &net) in port_nets[&port.name.value].iter().enumerate() {
+            let root = netlist.find(net);
+            names
+                .entry(root)
+                .or_insert_with(|| bit(&port.name.value, i));
+        }
+    }
+    let mut net_count = 0;
+    let mut name = |netlist: &mut Netlist, net: usize| {
+        let root = netlist.find(net);
+        names
+            .entry(root)
+            .or_insert_with(|| {
+                net_count += 1;
+                bit("net_n2v", net_count - 1)
+            })
+            .clone()
+    };
+
+    let mut statements = Vec::new();
+    let assignment = |left, right| AssignmentVHDL {
+        left,
+        right,
+        extension: Extension::None,
+    };
+    for (net, value) in netlist.literals.clone() {
+        if let Some(value) = value {
+            let right = SignalRhs::Literal(LiteralVHDL {
+                values: vec![value],
+            });
+            statements.push(Statement::Assignment(assignment(
+                name(&mut netlist, net),
+                right,
+            )));
+        }
+    }
+    for (a, b, out) in netlist.nands.clone() {
+        let right = SignalRhs::Nand(name(&mut netlist, a), name(&mut netlist, b));
+        statements.push(Statement::Assignment(assignment(
+            name(&mut netlist, out),
+            right,
+        )));
+    }
+    for (d, q) in netlist.dffs.clone() {
+        let right = SignalRhs::Slice(name(&mut netlist, d));
+        statements.push(Statement::Register(assignment(
+            name(&mut netlist, q),
+            right,
+        )));
+    }
+    for port in hdl
+        .ports
+        .iter()
+        .filter(|p| p.direction == PortDirection::Out)
+    {
+        for (i, &net) in port_nets[&port.name.value].iter().enumerate() {
+            let right = SignalRhs::Slice(name(&mut netlist, net));
+            statements.push(Statement::Assignment(assignment(
+                bit(&port.name.value, i),
+                right,
+            )));
+        }
+    }
+
+    let mut ports: Vec<VhdlPort> = hdl.ports.iter().map(|p| vhdl_port(p, hdl)).collect();
+    if !netlist.dffs.is_empty() {
+        ports.push(VhdlPort {
+            name: "clk".to_string(),
+            width: GenericWidth::Terminal(Terminal::Num(1)),
+            direction: PortDirection::In,
+        });
+    }
+    let mut signals = Vec::new();
+    if net_count > 0 {
+        signals.push(Signal {
+            name: String::from("net_n2v"),
+            width: GenericWidth::Terminal(Terminal::Num(net_count)),
+        });
+    }
+
+    // Without parts no component declarations are written.
+    let mut entity_hdl = hdl.clone();
+    entity_hdl.parts.clear();
+    Ok(VhdlEntity {
+        name: hdl.name.clone(),
+        generics: Vec::new(),
+        ports,
+        signals,
+        statements,
+        optimization_info: None,
+        hdl: entity_hdl,
+        // Every width is a number, so the VHDL is the same for both standards.
+        target: VhdlTarget::default(),
+    })
+}
+
+// Nets of each port of a chip by port name.
+pub(crate) type PortNets = HashMap<String, Vec<usize>>;
+
+// The gates of a flattened design. Nets are numbered, and nets that are
+// wired together are joined into one.
+#[derive(Default)]
+pub(crate) struct Netlist {
+    // Union-find forest of the nets.
+    parents: Vec<usize>,
+    // Inputs and output of each NAND gate.
+    pub(crate) nands: Vec<(usize, usize, usize)>,
+    // Input and output of each DFF.
+    pub(crate) dffs: Vec<(usize, usize)>,
+    // Nets driven by the true, false and none literals.
+    pub(crate) literals: Vec<(usize, Option<bool>)>,
+    // Path of each NAND gate and DFF through the parts that contain it,
+    // such as `Mux.1/Not.0/nand.0`. Parts are numbered by chip name.
+    pub(crate) nand_paths: Vec<String>,
+    pub(crate) dff_paths: Vec<String>,
+    // Each net with the port bits it is connected to, such as `a[0]` for a
+    // port of the flattened chip or `Mux.1/sel` for a port of a part,
+    // including the pins of gates.
+    pub(crate) port_bits: Vec<(usize, String)>,
+}
+
+impl Netlist {
+    // Flattens `hdl`. Also returns the nets of each of its ports.
+    pub(crate) fn new(hdl: &ChipHDL) -> Result<(Netlist, PortNets), Box<dyn Error>> {
+        let mut chip = Chip::new(hdl, ptr::null_mut(), &hdl.provider, false, &Vec::new())?;
+        Netlist::from_chip(&mut chip)
+    }
+
+    // Flattens a chip that has already been built, such as one with generic
+    // arguments. Also returns the nets of each of its ports.
+    pub(crate) fn from_chip(chip: &mut Chip) -> Result<(Netlist, PortNets), Box<dyn Error>> {
+        let mut netlist = Netlist::default();
+        let mut port_nets = PortNets::new();
+        for (name, port) in &chip.ports {
+            port_nets.insert(name.clone(), netlist.add_nets(port.width));
+        }
+        netlist.add_chip(chip, &port_nets, "")?;
+        Ok((netlist, port_nets))
+    }
+
+    fn add_nets(&mut self, width: usize) -> Vec<usize> {
+        let start = self.parents.len();
+        self.parents.extend(start..start + width);
+        (start..start + width).collect()
+    }
+
+    pub(crate) fn find(&mut self, mut net: usize) -> usize {
+        while self.parents[net] != net {
+            self.parents[net] = self.parents[self.parents[net]];
+            net = self.parents[net];
+        }
+        net
+    }
+
+    fn join(&mut self, a: usize, b: usize) {
+        let a = self.find(a);
+        let b = self.find(b);
+        self.parents[a] = b;
+    }
+
+    // Nets of a bus of a node, created the first time they are used.
+    fn bus_nets(
+        &mut self,
+        buses: &mut HashMap<(NodeIndex, String), Vec<usize>>,
+        chip: &Chip,
+        node: NodeIndex,
+        bus: &str,
+    ) -> Vec<usize> {
+        if let Some(nets) = buses.get(&(node, String::from(bus))) {
+            return nets.clone();
+        }
+        let width = chip.circuit[node].signal_width(bus).unwrap_or(0);
+        let nets = self.add_nets(width);
+        buses.insert((node, String::from(bus)), nets.clone());
+        nets
+    }
+
+    // Adds the gates of `chip`, whose ports are connected to `port_nets`.
+    // `path` is the path of `chip`, which is empty for the flattened chip.
+    fn add_chip(
+        &mut self,
+        chip: &mut Chip,
+        port_nets: &PortNets,
+        path: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let unsupported = |name: &str, reason: &str| {
+            Box::new(N2VError {
+                msg: format!("Chip {} cannot be flattened because {}.", name, reason),
+                kind: ErrorKind::Other,
+            })
+        };
+        if chip
+            .ports
+            .values()
+            .any(|p| p.direction == PortDirection::InOut)
+        {
+            return Err(unsupported(&chip.name, "it has bidirectional ports"));
+        }
+        if !chip.elaborated {
+            chip.elaborate()?;
+        }
+
+        // The port nodes of a chip carry the nets of its ports.
+        let mut buses: HashMap<(NodeIndex, String), Vec<usize>> = HashMap::new();
+        for &node in chip.input_port_nodes.iter().chain(&chip.output_port_nodes) {
+            let port_name = &chip.circuit[node].name;
+            let nets = port_nets[port_name].clone();
+            for (i, &net) in nets.iter().enumerate() {
+                let bit = match nets.len() {
+                    1 => port_name.clone(),
+                    _ => format!("{}[{}]", port_name, i),
+                };
+                self.port_bits.push((net, join_path(path, &bit)));
+            }
+            buses.insert((node, String::from("in")), nets);
+        }
+
+        let edges: Vec<(NodeIndex, NodeIndex, Wire)> = chip
+            .circuit
+            .edge_references()
+            .map(|e| (e.source(), e.target(), e.weight().clone()))
+            .collect();
+        for (source, target, wire) in edges {
+            let source_nets = self.bus_nets(&mut buses, chip, source, &wire.source.name);
+            let target_nets = self.bus_nets(&mut buses, chip, target, &wire.target.name);
+            let source_range = wire.source.range.clone().unwrap_or(0..1);
+            let target_range = wire.target.range.clone().unwrap_or(0..1);
+            for (s, t) in source_range.zip(target_range) {
+                self.join(source_nets[s], target_nets[t]);
+            }
         }
+
+        let nodes: Vec<NodeIndex> = chip.circuit.node_indices().collect();
+        let mut part_counts: HashMap<String, usize> = HashMap::new();
+        for node in nodes {
+            if chip.input_port_nodes.contains(&node) || chip.output_port_nodes.contains(&node) {
+                continue;
+            }
+            let part = &chip.circuit[node];
+            let part_name = part.name.clone();
+            let count = part_counts.entry(part_name.clone()).or_insert(0);
+            let part_path = join_path(path, &format!("{}.{}", part_name, count));
+            *count += 1;
+
+            // Literal nodes drive "out". Assignment nodes only pass "in" on.
+            if part.ports.is_empty() {
+                let value = match part_name.as_str() {
+                    "true" => Some(true),
+                    "false" => Some(false),
+                    "none" => None,
+                    _ => continue,
+                };
+                for net in self.bus_nets(&mut buses, chip, node, "out") {
+                    self.literals.push((net, value));
+                }
+                continue;
+            }
+
+            let mut nets =
+                |netlist: &mut Netlist, bus: &str| netlist.bus_nets(&mut buses, chip, node, bus);
+            match part_name.to_uppercase().as_str() {
+                "NAND" => {
+                    let gate = (nets(self, "a")[0], nets(self, "b")[0], nets(self, "out")[0]);
+                    for (net, pin) in [(gate.0, "a"), (gate.1, "b"), (gate.2, "out")] {
+                        self.port_bits.push((net, join_path(&part_path, pin)));
+                    }
+                    self.nands.push(gate);
+                    self.nand_paths.push(part_path);
+                    continue;
+                }
+                "DFF" => {
+                    let dff = (nets(self, "in")[0], nets(self, "out")[0]);
+                    for (net, pin) in [(dff.0, "in"), (dff.1, "out")] {
+                        self.port_bits.push((net, join_path(&part_path, pin)));
+                    }
+                    self.dffs.push(dff);
+                    self.dff_paths.push(part_path);
+                    continue;
+                }
+                "BUFFER" => {
+                    for (a, b) in nets(self, "in").into_iter().zip(nets(self, "out")) {
+                        self.join(a, b);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            match &part.hdl {
+                Some(hdl) if hdl.builtin.is_some() => {
+                    return Err(unsupported(&part_name, "it is a builtin chip"));
+                }
+                // Resolver nodes join the drivers of a bidirectional port.
+                None => return Err(unsupported(&part_name, "it is bidirectional")),
+                _ => {}
+            }
+
+            let port_names: Vec<String> = part.ports.keys().cloned().collect();
+            let mut part_nets = HashMap::new();
+            for port in port_names {
+                let port_nets = nets(self, &port);
+                part_nets.insert(port, port_nets);
+            }
+            self.add_chip(&mut chip.circuit[node], &part_nets, &part_path)?;
+        }
+        Ok(())
+    }
+}
+
+fn join_path(path: &str, name: &str) -> String {
+    match path {
+        "" => String::from(name),
+        _ => format!("{}/{}", path, name),
     }
 }
 
@@ -699,8 +1749,87 @@ impl QuartusProject {
             chip_hdl,
             chip_vhdl,
             project_dir,
+            structural_hash: false,
+            verbose: false,
+            flatten: false,
+            target: VhdlTarget::Vhdl93,
+            profile: None,
+        }
+    }
+}
+
+/// VHDL for the Nand primitive.
+const NAND_VHDL: &str = r#"
+library ieee;
+use ieee.std_logic_1164.all;
+entity nand_n2v is
+port (a : in std_logic_vector(0 downto 0);
+b : in std_logic_vector(0 downto 0);
+out_n2v : out std_logic_vector(0 downto 0)
+);
+end entity nand_n2v;
+architecture arch of nand_n2v is
+begin
+out_n2v <= a nand b;
+end architecture arch;
+"#;
+
+/// VHDL for the DFF primitive, which copies its input to its output on the
+/// rising edge of `clk`.
+const DFF_VHDL: &str = r#"
+library ieee;
+use ieee.std_logic_1164.all;
+
+entity DFF_n2v is
+port (in_n2v : in std_logic_vector(0 downto 0);
+out_n2v : out std_logic_vector(0 downto 0);
+clk : in std_logic_vector(0 downto 0)
+);
+end entity DFF_n2v;
+
+architecture arch of DFF_n2v is
+begin
+process(clk) begin
+if rising_edge(clk(0)) then
+out_n2v <= in_n2v;
+end if;
+end process;
+end architecture arch;
+"#;
+
+// Synthesizes a chip that the project's chip depends on.
+fn synthesize_dependency(qp: &QuartusProject, name: &str) -> Result<VhdlEntity, Box<dyn Error>> {
+    let provider = &qp.chip_hdl.provider;
+    let mut hdl = get_hdl(name, provider)?;
+    if qp.structural_hash {
+        (hdl, _) = StructuralHash::new().apply(&hdl, provider)?;
+    }
+
+    // Convert HDL to VHDL (VHDl synthesis).
+    VhdlEntity::synthesize_with_profile(&hdl, qp.target, qp.profile.clone())
+}
+
+/// Writes the VHDL of the whole design to `w` as a single file instead of
+/// a Quartus project: Nand, DFF if the design uses it, and every chip in
+/// the same order as the project, ending with the project's chip. The
+/// project directory is not used.
+pub fn write_single_file(
+    qp: &QuartusProject,
+    w: &mut impl io::Write,
+) -> Result<(), Box<dyn Error>> {
+    if !qp.flatten {
+        let dependencies = dependency_order(&qp.chip_hdl)?;
+        w.write_all(NAND_VHDL.as_bytes())?;
+        if uses_dff(&qp.chip_hdl, &dependencies)? {
+            w.write_all(DFF_VHDL.as_bytes())?;
+        }
+        for name in dependencies {
+            writeln!(w)?;
+            synthesize_dependency(qp, &name)?.write_io(&mut *w)?;
         }
+        writeln!(w)?;
     }
+    qp.chip_vhdl.write_io(&mut *w)
 }
 
 pub fn write_quartus_project(qp: &QuartusProject) -> Result<(), Box<dyn Error>> {
@@ -886,160 +2015,954 @@ pub fn write_quartus_project(qp: &QuartusProject) -> Result<(), Box<dyn Error>>
         "set_global_assignment -name TOP_LEVEL_ENTITY {}",
         keyw(&qp.chip_vhdl.name)
     )?;
+    if qp.target == VhdlTarget::Vhdl2008 {
+        writeln!(
+            tcl,
+            "set_global_assignment -name VHDL_INPUT_VERSION VHDL_2008"
+        )?;
+    }
+
+    // Files are listed leaf-first so each one only uses entities that are
+    // already compiled. Each file is listed once, and every listed file is
+    // written below.
+    let dependencies = match qp.flatten {
+        true => Vec::new(),
+        false => dependency_order(&qp.chip_hdl)?,
+    };
+    let mut files: Vec<String> = Vec::new();
+    if !qp.flatten {
+        files.push(String::from("Nand.vhdl"));
+        if uses_dff(&qp.chip_hdl, &dependencies)? {
+            files.push(String::from("DFF.vhdl"));
+        }
+    }
+    files.extend(dependencies.iter().map(|name| format!("{}.vhdl", name)));
+    files.push(qp.chip_vhdl.name.clone() + ".vhdl");
+    let mut listed = HashSet::new();
+    for file in files.iter().filter(|f| listed.insert(f.as_str())) {
+        writeln!(tcl, "set_global_assignment -name VHDL_FILE {}", file)?;
+    }
 
-    writeln!(tcl, "set_global_assignment -name VHDL_FILE NAND.vhdl")?;
+    if !qp.flatten {
+        let mut file = File::create(qp.project_dir.join("Nand.vhdl"))?;
+        file.write_all(NAND_VHDL.as_bytes())?;
+        let mut file = File::create(qp.project_dir.join("DFF.vhdl"))?;
+        file.write_all(DFF_VHDL.as_bytes())?;
+    }
+
+    tcl.push_str("project_close");
+    let mut file = File::create(qp.project_dir.join("project.tcl"))?;
+    file.write_all(tcl.as_bytes())?;
+
+    for next_chip_name in dependencies {
+        let next_vhdl = synthesize_dependency(qp, &next_chip_name)?;
+        let next_filename = next_chip_name + ".vhdl";
+        if qp.verbose {
+            println!("Writing {}", next_filename);
+        }
+        next_vhdl.write_file(&qp.project_dir.join(&next_filename))?;
+    }
+
+    // Write the already-parsed main chip last, after everything it uses.
     let chip_filename = qp.chip_vhdl.name.clone() + ".vhdl";
-    writeln!(
-        tcl,
-        "set_global_assignment -name VHDL_FILE {}",
-        chip_filename
-    )?;
+    if qp.verbose {
+        println!("Writing {}", chip_filename);
+    }
+    qp.chip_vhdl
+        .write_file(&qp.project_dir.join(&chip_filename))?;
 
-    // Run the sequential pass on chip HDL
-    let mut sequential_pass = SequentialPass::new();
-    let (_, sequential_pass_info_raw) =
-        sequential_pass.apply(&qp.chip_hdl, &qp.chip_hdl.provider)?;
-    let sequential_pass_info = Rc::new(RefCell::new(sequential_pass_info_raw));
+    Ok(())
+}
 
-    if let OptimizationInfo::SequentialFlagMap(sequential_flag_map) =
-        &*sequential_pass_info.borrow()
-    {
-        for name in sequential_flag_map.keys() {
-            writeln!(tcl, "set_global_assignment -name VHDL_FILE {}.vhdl", name)?;
+// Names of the chips used by parts, including those in loops, in order of
+// first use.
+fn component_names(parts: &[Part]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for part in parts {
+        let components = match part {
+            Part::Component(c) => std::slice::from_ref(c),
+            Part::Loop(l) => l.body.as_slice(),
+            Part::AssignmentHDL(_) | Part::Assert(_) => continue,
+        };
+        for c in components {
+            if !names.contains(&c.name.value) {
+                names.push(c.name.value.clone());
+            }
         }
     }
+    names
+}
 
-    let nand_vhdl = r#"
-library ieee;
-use ieee.std_logic_1164.all;
-entity nand_n2v is
-port (a : in std_logic_vector(0 downto 0);
-b : in std_logic_vector(0 downto 0);
-out_n2v : out std_logic_vector(0 downto 0)
-);
-end entity nand_n2v;
-architecture arch of nand_n2v is
-begin
-out_n2v <= a nand b;
-end architecture arch;
-"#;
-    let mut file = File::create(qp.project_dir.join("Nand.vhdl"))?;
-    file.write_all(nand_vhdl.as_bytes())?;
+/// Name given after `BUILTIN` for chips synthesized as a memory array.
+pub const RAM_BUILTIN: &str = "RAM";
 
-    let dff_vhdl = r#"
-library ieee;
-use ieee.std_logic_1164.all;
-LIBRARY altera;
-USE altera.altera_primitives_components.all;
+// Nand and DFF are written by hand instead of synthesized.
+pub fn is_primitive(name: &str) -> bool {
+    matches!(name.to_lowercase().as_str(), "nand" | "dff")
+}
 
-entity DFF_n2v is
-port (in_n2v : in std_logic_vector(0 downto 0);
-out_n2v : out std_logic_vector(0 downto 0);
-clk : in std_logic_vector(0 downto 0)
-);
-end entity DFF_n2v;
+/// Names of every chip that `chip_hdl` depends on, directly or through
+/// other chips, ordered so that each chip comes after the chips it uses.
+/// Nand and DFF are left out. Returns an error naming the chips if they
+/// depend on each other in a cycle.
+pub fn dependency_order(chip_hdl: &ChipHDL) -> Result<Vec<String>, Box<dyn Error>> {
+    let provider = &chip_hdl.provider;
+
+    // Edges point from a chip to the chips that use it.
+    let mut graph: DiGraph<String, ()> = DiGraph::new();
+    let mut nodes: HashMap<String, NodeIndex> = HashMap::new();
+    let top = graph.add_node(chip_hdl.name.clone());
+    nodes.insert(chip_hdl.name.clone(), top);
+
+    let mut worklist = vec![(top, chip_hdl.clone())];
+    while let Some((node, hdl)) = worklist.pop() {
+        for name in component_names(&hdl.parts) {
+            if is_primitive(&name) {
+                continue;
+            }
+            let dependency = match nodes.get(&name) {
+                Some(&n) => n,
+                None => {
+                    let n = graph.add_node(name.clone());
+                    nodes.insert(name.clone(), n);
+                    worklist.push((n, get_hdl(&name, provider)?));
+                    n
+                }
+            };
+            graph.add_edge(dependency, node, ());
+        }
+    }
 
-architecture arch of DFF_n2v is
+    let order = toposort(&graph, None).map_err(|cycle| N2VError {
+        msg: format!(
+            "Recursive chip instantiation: {}",
+            cycle_names(&graph, cycle.node_id()).join(" -> ")
+        ),
+        kind: ErrorKind::Other,
+    })?;
+    Ok(order
+        .into_iter()
+        .filter(|&n| n != top)
+        .map(|n| graph[n].clone())
+        .collect())
+}
 
-COMPONENT DFF
-   PORT (d   : IN STD_LOGIC;
-        clk  : IN STD_LOGIC;
-        clrn : IN STD_LOGIC;
-        prn  : IN STD_LOGIC;
-        q    : OUT STD_LOGIC );
+// The chips in a dependency cycle through `start`, starting and ending with
+// `start`, each one using the next.
+fn cycle_names(graph: &DiGraph<String, ()>, start: NodeIndex) -> Vec<String> {
+    // Search the chips that use `start` until the search comes back to it.
+    let mut previous: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        for user in graph.neighbors(node) {
+            if user == start {
+                let mut names = vec![graph[start].clone()];
+                let mut n = node;
+                while n != start {
+                    names.push(graph[n].clone());
+                    n = previous[&n];
+                }
+                names.push(graph[start].clone());
+                return names;
+            }
+            if let Entry::Vacant(e) = previous.entry(user) {
+                e.insert(node);
+                queue.push_back(user);
+            }
+        }
+    }
+    vec![graph[start].clone()]
+}
 
-END COMPONENT;
+// Whether the chip or any of its dependencies uses a DFF.
+fn uses_dff(chip_hdl: &ChipHDL, dependencies: &[String]) -> Result<bool, Box<dyn Error>> {
+    let uses = |hdl: &ChipHDL| {
+        component_names(&hdl.parts)
+            .iter()
+            .any(|name| name.to_lowercase() == "dff")
+    };
+    if uses(chip_hdl) {
+        return Ok(true);
+    }
+    for name in dependencies {
+        if uses(&get_hdl(name, &chip_hdl.provider)?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
 
-begin
-x0: DFF port map (d => in_n2v(0), clrn => '1', prn => '1', q => out_n2v(0), clk => clk(0));
-end architecture arch;
-"#;
+/// VHDL-2008 reserved words. These cannot be used as entity, port or
+/// signal names.
+pub const VHDL_RESERVED: &[&str] = &[
+    "abs",
+    "access",
+    "after",
+    "alias",
+    "all",
+    "and",
+    "architecture",
+    "array",
+    "assert",
+    "assume",
+    "assume_guarantee",
+    "attribute",
+    "begin",
+    "block",
+    "body",
+    "buffer",
+    "bus",
+    "case",
+    "component",
+    "configuration",
+    "constant",
+    "context",
+    "cover",
+    "default",
+    "disconnect",
+    "downto",
+    "else",
+    "elsif",
+    "end",
+    "entity",
+    "exit",
+    "fairness",
+    "file",
+    "for",
+    "force",
+    "function",
+    "generate",
+    "generic",
+    "group",
+    "guarded",
+    "if",
+    "impure",
+    "in",
+    "inertial",
+    "inout",
+    "is",
+    "label",
+    "library",
+    "linkage",
+    "literal",
+    "loop",
+    "map",
+    "mod",
+    "nand",
+    "new",
+    "next",
+    "nor",
+    "not",
+    "null",
+    "of",
+    "on",
+    "open",
+    "or",
+    "others",
+    "out",
+    "package",
+    "parameter",
+    "port",
+    "postponed",
+    "procedure",
+    "process",
+    "property",
+    "protected",
+    "pure",
+    "range",
+    "record",
+    "register",
+    "reject",
+    "release",
+    "rem",
+    "report",
+    "restrict",
+    "restrict_guarantee",
+    "return",
+    "rol",
+    "ror",
+    "select",
+    "sequence",
+    "severity",
+    "shared",
+    "signal",
+    "sla",
+    "sll",
+    "sra",
+    "srl",
+    "strong",
+    "subtype",
+    "then",
+    "to",
+    "transport",
+    "type",
+    "unaffected",
+    "units",
+    "until",
+    "use",
+    "variable",
+    "vmode",
+    "vprop",
+    "vunit",
+    "wait",
+    "when",
+    "while",
+    "with",
+    "xnor",
+    "xor",
+];
+
+// VHDL keywords that we can't use. VHDL is case insensitive, so every use of
+// a name must go through keyw to get the same escaped name. A quoted HDL name
+// that isn't a VHDL identifier, such as one with a space, is written as an
+// extended identifier like \my port\.
+pub fn keyw(name: &str) -> String {
+    let lower = name.to_lowercase();
+    if lower == "dff" {
+        // Matches the built-in DFF entity.
+        String::from("DFF_n2v")
+    } else if VHDL_RESERVED.contains(&lower.as_str()) {
+        format!("{}_n2v", lower)
+    } else if !is_basic_identifier(name) {
+        format!("\\{}\\", name.replace('\\', "\\\\"))
+    } else {
+        String::from(name)
+    }
+}
 
-    let mut file = File::create(qp.project_dir.join("DFF.vhdl"))?;
-    file.write_all(dff_vhdl.as_bytes())?;
+// A VHDL basic identifier is a letter followed by letters, digits, and single
+// underscores, and doesn't end with an underscore.
+fn is_basic_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.ends_with('_')
+        && !name.contains("__")
+}
 
-    tcl.push_str("project_close");
-    let mut file = File::create(qp.project_dir.join("project.tcl"))?;
-    file.write_all(tcl.as_bytes())?;
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    // Locks down synthesized VHDL. The streamed and Display output must both
+    // match the golden file.
+    #[test]
+    fn test_vhdl_golden_mux4way16() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let hdl_path = tests_dir.join("nand2tetris/solutions/Mux4Way16.hdl");
+        let (hdl, _) = parse_hdl_path(&hdl_path).expect("Parse error");
+        let entity = VhdlEntity::try_from(&hdl).expect("Synthesis error");
+
+        let golden = fs::read_to_string(tests_dir.join("vhdl/Mux4Way16.vhdl"))
+            .expect("Unable to read golden file");
+
+        let mut streamed = String::new();
+        entity.write(&mut streamed).expect("Write error");
+        assert_eq!(streamed, golden);
+        assert_eq!(entity.to_string(), golden);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let out_path = temp_dir.path().join("Mux4Way16.vhdl");
+        entity.write_file(&out_path).expect("Write error");
+        assert_eq!(fs::read_to_string(out_path).unwrap(), golden);
+    }
 
-    // Write the already-parsed main chip.
-    let chip_filename = qp.chip_vhdl.name.clone() + ".vhdl";
-    let mut file = File::create(qp.project_dir.join(&chip_filename))?;
-    file.write_all(format!("{}", qp.chip_vhdl).as_bytes())?;
-
-    // The chip names we have already processed. We only need to
-    // convert each chip type once.
-    let mut done: HashSet<String> = HashSet::new();
-    done.insert(String::from("Nand"));
-    done.insert(String::from("DFF"));
-
-    // Recursively parse and write all dependency components.
-    // Worklist is set of chip names that we need to convert from HDL to VHDL.
-    let mut worklist: Vec<String> = Vec::new();
-
-    // Pushes parts onto the worklist.
-    fn push_parts(parts: &Vec<Part>, worklist: &mut Vec<String>, done: &mut HashSet<String>) {
-        for part in parts {
-            match part {
-                Part::Component(c) => {
-                    if !done.contains(&c.name.value) {
-                        done.insert(c.name.value.clone());
-                        worklist.push(c.name.value.clone());
-                    }
-                }
-                Part::Loop(l) => {
-                    for c in &l.body {
-                        if !done.contains(&c.name.value) {
-                            done.insert(c.name.value.clone());
-                            worklist.push(c.name.value.clone());
-                        }
-                    }
-                }
-                _ => {}
-            }
+    #[test]
+    fn test_extended_assignment_vhdl() {
+        let slice = |name: &str, range: Option<(usize, usize)>| SliceVHDL {
+            name: String::from(name),
+            start: range.map(|r| GenericWidth::Terminal(Terminal::Num(r.0))),
+            end: range.map(|r| GenericWidth::Terminal(Terminal::Num(r.1))),
+        };
+        let zext = AssignmentVHDL {
+            left: slice("wide", None),
+            right: SignalRhs::Slice(slice("small", None)),
+            extension: Extension::Zero,
+        };
+        assert_eq!(
+            zext.vhdl(VhdlTarget::Vhdl93),
+            "wide <= std_logic_vector(resize(unsigned(small), wide'length));"
+        );
+        let sext = AssignmentVHDL {
+            left: slice("wide", Some((0, 15))),
+            right: SignalRhs::Slice(slice("small", Some((0, 7)))),
+            extension: Extension::Sign,
+        };
+        assert_eq!(
+            sext.vhdl(VhdlTarget::Vhdl93),
+            "wide(15 downto 0) <= std_logic_vector(resize(signed(small(7 downto 0)), 15 - 0 + 1));"
+        );
+    }
+
+    #[test]
+    fn test_concat_assignment_vhdl() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("buffer");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("Concat", &provider).expect("Parse error");
+        let assignments: Vec<String> = hdl
+            .parts
+            .iter()
+            .filter_map(|p| match p {
+                Part::AssignmentHDL(a) => Some(AssignmentVHDL::from(a).vhdl(VhdlTarget::Vhdl93)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(assignments[0], "out_n2v <= high & low;");
+        assert_eq!(
+            assignments[1],
+            "nibbles <= low(3 downto 0) & high(7 downto 4);"
+        );
+    }
+
+    #[test]
+    fn test_dependency_cycle() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("bad");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+
+        let hdl = get_hdl("Itself", &provider).expect("Parse error");
+        let err = dependency_order(&hdl).expect_err("Expected recursion error");
+        assert_eq!(
+            err.to_string(),
+            "Recursive chip instantiation: Itself -> Itself\n"
+        );
+
+        // The cycle may be reported starting from either chip.
+        let hdl = get_hdl("Ping", &provider).expect("Parse error");
+        let err = dependency_order(&hdl).expect_err("Expected recursion error");
+        assert!([
+            "Recursive chip instantiation: Ping -> Pong -> Ping\n",
+            "Recursive chip instantiation: Pong -> Ping -> Pong\n",
+        ]
+        .contains(&err.to_string().as_str()));
+    }
+
+    #[test]
+    fn test_inout_port_vhdl() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("inout");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+
+        let hdl = get_hdl("SharedBus", &provider).expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+        assert!(vhdl.contains("bus_n2v : inout std_logic_vector(0 downto 0)"));
+        assert!(vhdl.contains(
+            "cn2v0: BusPort port map(in_n2v => a, en => ena, bus_n2v => w, out_n2v => ra);"
+        ));
+
+        // Bits the chip does not drive or drives with none are released for
+        // others to drive.
+        let hdl = get_hdl("BusTap", &provider).expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+        assert!(vhdl.contains("bus_n2v(1 downto 1) <= in_n2v;"));
+        assert!(vhdl.contains("bus_n2v(2 downto 2) <= (others => 'Z');"));
+        assert!(vhdl.contains("bus_n2v(0 downto 0) <= (others => 'Z');"));
+    }
+
+    // A generic chip is written once with a generic clause and a generate
+    // loop, and users pass their width through a generic map.
+    #[test]
+    fn test_generic_mux_vhdl() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("arm");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+
+        let hdl = get_hdl("MuxGen", &provider).expect("Parse error");
+        let generic = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+        assert!(generic.contains("entity MuxGen is\ngeneric (X : natural);\nport ("));
+        assert!(generic.contains("in0 : in std_logic_vector((X - 1) downto 0);"));
+        assert!(generic.contains("gn2v0: for i in 0 to (X - 1) generate\n"));
+        assert!(generic.contains(
+            "cn2v0: Mux port map(in0 => in0(i downto i), in1 => in1(i downto i), sel => sel, out_n2v => out_n2v(i downto i));"
+        ));
+
+        let hdl = get_hdl("MuxGen4", &provider).expect("Parse error");
+        let user = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+        assert!(user.contains("component MuxGen is\ngeneric (X : natural);\nport ("));
+        assert!(user.contains(
+            "cn2v0: MuxGen generic map(X => 4) port map(in0 => a, in1 => b, sel => sel, out_n2v => out_n2v);"
+        ));
+    }
+
+    // A generic chip synthesized on its own keeps every width in terms of
+    // its generic, including signals that are only assigned.
+    #[test]
+    fn test_generic_top_level_vhdl() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("de1-hdl");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+
+        let hdl = get_hdl("NotTwiceGen", &provider).expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+        assert!(vhdl.contains("entity NotTwiceGen is\ngeneric (W : natural);\nport ("));
+        assert!(vhdl.contains("copy : out std_logic_vector((W - 1) downto 0)"));
+        assert!(vhdl.contains("signal m : std_logic_vector((W - 1) downto 0);"));
+        assert!(vhdl.contains("signal n : std_logic_vector((W - 1) downto 0);"));
+        assert!(vhdl.contains("gn2v1: for i in 0 to (W - 1) generate\n"));
+        assert!(vhdl.contains("m <= n;\ncopy <= m;\n"));
+    }
+
+    // A loop with a step generates its statements for every step-th value.
+    #[test]
+    fn test_generic_step_vhdl() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("step");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+
+        let hdl = get_hdl("StepGen", &provider).expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+        assert!(vhdl.contains(
+            "gn2v0: for i in 0 to (W - 1) generate
+\
+             sn2v: if ((i - 0) mod 2) = 0 generate
+\
+             cn2v0: nand_n2v port map("
+        ));
+        assert!(vhdl.contains("out_n2v => out_n2v(i downto i));\n\nend generate;\nend generate;"));
+
+        // Whether 1 TO W-1 counts up depends on W, so both loops are written.
+        assert!(vhdl.contains(
+            "gn2v1: block begin\n\
+             un2v: if 1 <= (W - 1) generate\n\
+             ln2v: for i in 1 to (W - 1) generate\n\
+             sn2v: if ((i - 1) mod 2) = 0 generate\n"
+        ));
+        assert!(vhdl.contains(
+            "end generate;\n\
+             dn2v: if 1 > (W - 1) generate\n\
+             ln2v: for i in 1 downto (W - 1) generate\n"
+        ));
+        assert!(vhdl.ends_with("end generate;\nend block;\nend arch;\n"));
+    }
+
+    // A loop that ends at 0 counts down, as it does in simulation.
+    #[test]
+    fn test_generic_descending_vhdl() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("step");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+
+        let hdl = get_hdl("RevGen", &provider).expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+        assert!(vhdl.contains(
+            "gn2v0: for i in (W - 1) downto 0 generate\n\
+             cn2v0: nand_n2v port map(a => in_n2v(i downto i), b => in_n2v(i downto i), \
+             out_n2v => out_n2v(((W - 1) - i) downto ((W - 1) - i)));"
+        ));
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let hdl_path = tests_dir.join("nand2tetris/solutions/Mux4Way16.hdl");
+        let (hdl, _) = parse_hdl_path(&hdl_path).expect("Parse error");
+
+        let order = dependency_order(&hdl).expect("Dependency error");
+        let position = |name: &str| order.iter().position(|n| n == name).unwrap();
+        assert_eq!(order.len(), 5);
+        assert!(position("Not") < position("Mux"));
+        assert!(position("And") < position("Mux"));
+        assert!(position("Or") < position("Mux"));
+        assert!(position("Mux") < position("Mux16"));
+
+        // The project lists files in the same order, with the chip last.
+        let temp_dir = tempfile::tempdir().unwrap();
+        let vhdl = VhdlEntity::try_from(&hdl).expect("Synthesis error");
+        let project = QuartusProject::new(hdl, vhdl, temp_dir.path().to_path_buf());
+        write_quartus_project(&project).expect("Project error");
+        let tcl = fs::read_to_string(temp_dir.path().join("project.tcl")).unwrap();
+        let files: Vec<&str> = tcl
+            .lines()
+            .filter_map(|l| l.strip_prefix("set_global_assignment -name VHDL_FILE "))
+            .collect();
+        let mut expected = vec![String::from("Nand.vhdl")];
+        expected.extend(order.iter().map(|n| format!("{}.vhdl", n)));
+        expected.push(String::from("Mux4Way16.vhdl"));
+        assert_eq!(files, expected);
+    }
+
+    // A sequential project lists every file once, and each listed file is
+    // written with the entity the chips use.
+    #[test]
+    fn test_quartus_project_files() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let hdl_path = manifest_dir.join("resources/tests/nand2tetris/solutions/PC.hdl");
+        let (hdl, _) = parse_hdl_path(&hdl_path).expect("Parse error");
+        let temp_dir = tempfile::tempdir().unwrap();
+        let vhdl = VhdlEntity::try_from(&hdl).expect("Synthesis error");
+        let project = QuartusProject::new(hdl, vhdl, temp_dir.path().to_path_buf());
+        write_quartus_project(&project).expect("Project error");
+
+        let tcl = fs::read_to_string(temp_dir.path().join("project.tcl")).unwrap();
+        let files: Vec<&str> = tcl
+            .lines()
+            .filter_map(|l| l.strip_prefix("set_global_assignment -name VHDL_FILE "))
+            .collect();
+        let unique: HashSet<&str> = files.iter().copied().collect();
+        assert_eq!(
+            unique.len(),
+            files.len(),
+            "Duplicate VHDL_FILE in {:?}",
+            files
+        );
+        for file in &files {
+            assert!(
+                temp_dir.path().join(file).exists(),
+                "{} was not written",
+                file
+            );
         }
+        assert_eq!(files[..2], ["Nand.vhdl", "DFF.vhdl"]);
+        assert_eq!(files.last(), Some(&"PC.vhdl"));
+
+        let nand = fs::read_to_string(temp_dir.path().join("Nand.vhdl")).unwrap();
+        assert!(nand.contains("entity nand_n2v is"));
+        let dff = fs::read_to_string(temp_dir.path().join("DFF.vhdl")).unwrap();
+        assert!(dff.contains("entity DFF_n2v is"));
+        assert!(dff.contains("rising_edge"));
+        assert!(!dff.contains("nand"));
     }
 
-    // Seed the worklist with all components of the top-level entity.
-    push_parts(&qp.chip_hdl.parts, &mut worklist, &mut done);
+    // A single file has every entity in project order, and only has the DFF
+    // entity if the design uses it.
+    #[test]
+    fn test_single_file() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let entities = |name: &str| {
+            let hdl_path = tests_dir.join("nand2tetris/solutions").join(name);
+            let (hdl, _) = parse_hdl_path(&hdl_path).expect("Parse error");
+            let vhdl = VhdlEntity::try_from(&hdl).expect("Synthesis error");
+            let project = QuartusProject::new(hdl, vhdl, PathBuf::new());
+            let mut out = Vec::new();
+            write_single_file(&project, &mut out).expect("Write error");
+            String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .filter_map(|l| l.strip_prefix("entity "))
+                .map(|l| l.trim_end_matches(" is").to_string())
+                .collect::<Vec<String>>()
+        };
 
-    let base_path = qp.chip_hdl.path.as_ref().unwrap().parent().unwrap();
+        assert_eq!(
+            entities("Mux.hdl"),
+            vec!["nand_n2v", "not_n2v", "and_n2v", "or_n2v", "Mux"]
+        );
+        let bit = entities("Bit.hdl");
+        assert_eq!(bit[..2], ["nand_n2v", "DFF_n2v"]);
+        assert_eq!(bit.last().unwrap(), "Bit");
+    }
+
+    // Chips named after VHDL keywords get the same escaped name in their
+    // entity and in every component declaration and instantiation.
+    #[test]
+    fn test_keyword_chip_names() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let includes = [tests_dir.join("nand2tetris/solutions")];
+        let synth = |name: &str| {
+            let path = tests_dir.join("keyword").join(format!("{}.hdl", name));
+            let (hdl, _) = parse_hdl_path_with_includes(&path, &includes).expect("Parse error");
+            VhdlEntity::try_from(&hdl)
+                .expect("Synthesis error")
+                .to_string()
+        };
 
-    while !worklist.is_empty() {
-        let next_chip_name = worklist.pop().unwrap();
-        let next_hdl_path = base_path.join(next_chip_name.clone() + ".hdl");
+        assert_eq!(keyw("Process"), "process_n2v");
+        assert_eq!(keyw("Entity"), "entity_n2v");
+        assert_eq!(keyw("Loop"), "loop_n2v");
+        assert_eq!(keyw("Mux"), "Mux");
+
+        for (name, part) in [
+            ("Buffer", "not_n2v"),
+            ("Process", "buffer_n2v"),
+            ("Signal", "process_n2v"),
+        ] {
+            let escaped = keyw(name);
+            let vhdl = synth(name);
+            assert!(vhdl.contains(&format!("entity {} is", escaped)));
+            assert!(vhdl.contains(&format!("end entity {};", escaped)));
+            assert!(vhdl.contains(&format!("architecture arch of {} is", escaped)));
+            assert!(vhdl.contains(&format!("component {} is", part)));
+            assert!(vhdl.contains(&format!("end component {};", part)));
+            assert!(vhdl.contains(&format!(": {} port map(", part)));
+            assert!(!vhdl.contains(&format!("{} ", name)));
+        }
+    }
 
-        let next_source_code = fs::read_to_string(&next_hdl_path)?;
-        let mut next_scanner = Scanner::new(&next_source_code, next_hdl_path.clone());
-        let mut next_parser = Parser::new(&mut next_scanner, qp.chip_hdl.provider.clone());
-        let next_hdl = next_parser.parse()?;
+    // Ports and signals named after reserved words are escaped wherever
+    // they appear.
+    #[test]
+    fn test_keyword_signal_names() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let (hdl, _) = parse_hdl_path_with_includes(
+            &tests_dir.join("keyword/LoopPort.hdl"),
+            &[tests_dir.join("nand2tetris/solutions")],
+        )
+        .expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+
+        assert!(vhdl.contains("loop_n2v : in std_logic_vector(0 downto 0);\n"));
+        assert!(vhdl.contains("entity_n2v : in std_logic_vector(0 downto 0);\n"));
+        assert!(vhdl.contains("signal wait_n2v : std_logic_vector(0 downto 0);\n"));
+        assert!(vhdl
+            .contains("nand_n2v port map(a => loop_n2v, b => entity_n2v, out_n2v => wait_n2v);"));
+        assert!(vhdl.contains("not_n2v port map(in_n2v => wait_n2v, out_n2v => out_n2v);"));
+    }
 
-        // Convert HDL to VHDL (VHDl synthesis).
-        let next_vhdl: VhdlEntity = VhdlEntity::try_from(&next_hdl)?;
+    // Numeric constants are bit strings, or converted with to_unsigned when
+    // the port width is generic.
+    #[test]
+    fn test_numeric_constants() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let literal_dir = manifest_dir.join("resources").join("tests").join("literal");
+        let synthesize = |name: &str| {
+            let (hdl, _) =
+                parse_hdl_path_with_includes(&literal_dir.join(name), &[]).expect("Parse error");
+            VhdlEntity::try_from(&hdl)
+                .expect("Synthesis error")
+                .to_string()
+        };
 
-        let next_filename = next_chip_name + ".vhdl";
-        let mut next_file = File::create(qp.project_dir.join(&next_filename))?;
-        next_file.write_all(format!("{}", next_vhdl).as_bytes())?;
+        let vhdl = synthesize("Const.hdl");
+        assert!(vhdl.contains("in_n2v => \"00000000000000000000000000101010\""));
+        assert!(!vhdl.contains("numeric_std"));
 
-        push_parts(&next_hdl.parts, &mut worklist, &mut done);
+        let vhdl = synthesize("ConstGen.hdl");
+        assert!(vhdl.contains("use ieee.numeric_std.all;\n"));
+        assert!(vhdl.contains("in_n2v => std_logic_vector(to_unsigned(3, W))"));
     }
 
-    Ok(())
-}
+    // Outputs that are read inside the chip are read through a mirror signal.
+    #[test]
+    fn test_read_output_mirror() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let (hdl, _) = parse_hdl_path_with_includes(
+            &tests_dir.join("vhdl/ReadOut.hdl"),
+            &[tests_dir.join("nand2tetris/solutions")],
+        )
+        .expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+
+        assert!(vhdl.contains("signal out_mirror : std_logic_vector(0 downto 0);"));
+        assert!(vhdl.contains("not_n2v port map(in_n2v => a, out_n2v => out_mirror);"));
+        assert!(vhdl.contains("not_n2v port map(in_n2v => out_mirror, out_n2v => x);"));
+        assert!(vhdl.contains("out_n2v <= out_mirror;"));
+    }
 
-// VHDL keywords that we can't use.
-pub fn keyw(name: &str) -> String {
-    match name.to_lowercase().as_str() {
-        "in" => String::from("in_n2v"),
-        "out" => String::from("out_n2v"),
-        "not" => String::from("not_n2v"),
-        "nand" => String::from("nand_n2v"),
-        "and" => String::from("and_n2v"),
-        "or" => String::from("or_n2v"),
-        "xor" => String::from("xor_n2v"),
-        "nor" => String::from("nor_n2v"),
-        "dff" => String::from("DFF_n2v"),
-        "register" => String::from("register_n2v"),
-        _ => String::from(name),
+    // A mirror is numbered rather than take the name of one of the chip's
+    // signals.
+    #[test]
+    fn test_read_output_mirror_name() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let (hdl, _) = parse_hdl_path_with_includes(
+            &tests_dir.join("vhdl/ReadOutMirror.hdl"),
+            &[tests_dir.join("nand2tetris/solutions")],
+        )
+        .expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+
+        assert!(vhdl.contains("not_n2v port map(in_n2v => a, out_n2v => out_mirror);"));
+        assert!(vhdl.contains("not_n2v port map(in_n2v => out_mirror, out_n2v => out_mirror1);"));
+        assert!(vhdl.contains("not_n2v port map(in_n2v => out_mirror1, out_n2v => x);"));
+        assert!(vhdl.contains("out_n2v <= out_mirror1;"));
+    }
+
+    // VHDL-2008 can read out ports, so no mirror signal is needed.
+    #[test]
+    fn test_read_output_vhdl2008() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let (hdl, _) = parse_hdl_path_with_includes(
+            &tests_dir.join("vhdl/ReadOut.hdl"),
+            &[tests_dir.join("nand2tetris/solutions")],
+        )
+        .expect("Parse error");
+        let vhdl = VhdlEntity::synthesize(&hdl, VhdlTarget::Vhdl2008)
+            .expect("Synthesis error")
+            .to_string();
+
+        assert!(!vhdl.contains("out_mirror"));
+        assert!(vhdl.contains("not_n2v port map(in_n2v => a, out_n2v => out_n2v);"));
+        assert!(vhdl.contains("not_n2v port map(in_n2v => out_n2v, out_n2v => x);"));
+    }
+
+    // Quoted names that aren't VHDL identifiers are written as extended
+    // identifiers.
+    #[test]
+    fn test_extended_identifiers() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let (hdl, _) = parse_hdl_path_with_includes(
+            &tests_dir.join("vhdl/Spaces.hdl"),
+            &[tests_dir.join("nand2tetris/solutions")],
+        )
+        .expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+
+        assert!(vhdl.contains("\\my in\\ : in std_logic_vector(0 downto 0);"));
+        assert!(vhdl.contains("\\my out\\ : out std_logic_vector(0 downto 0)"));
+        assert!(vhdl.contains(
+            "nand_n2v port map(a => \\my in\\, b => \\a\\\\b\\, out_n2v => \\my out\\);"
+        ));
+        assert_eq!(keyw("_x"), "\\_x\\");
+        assert_eq!(keyw("x__y"), "\\x__y\\");
+        assert_eq!(keyw("x_y1"), "x_y1");
+    }
+
+    // MAXIMUM is written in lowercase for VHDL-2008.
+    #[test]
+    fn test_maximum_vhdl2008() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let (hdl, _) = parse_hdl_path_with_includes(
+            &tests_dir.join("vhdl/ReadOut.hdl"),
+            &[tests_dir.join("nand2tetris/solutions")],
+        )
+        .expect("Parse error");
+        let max = GenericWidth::Expr(
+            Op::Max,
+            Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("N")))),
+            Box::new(GenericWidth::Terminal(Terminal::Var(Identifier::from("M")))),
+        );
+        for (target, expected) in [
+            (
+                VhdlTarget::Vhdl93,
+                "signal w : std_logic_vector((MAXIMUM(N, M) - 1) downto 0);",
+            ),
+            (
+                VhdlTarget::Vhdl2008,
+                "signal w : std_logic_vector((maximum(N, M) - 1) downto 0);",
+            ),
+        ] {
+            let mut entity = VhdlEntity::synthesize(&hdl, target).expect("Synthesis error");
+            entity.signals.push(Signal {
+                name: String::from("w"),
+                width: max.clone(),
+            });
+            assert!(entity.to_string().contains(expected));
+        }
+    }
+
+    // A flattened chip is one entity of NAND statements and DFF processes,
+    // written to a project without any other VHDL files.
+    #[test]
+    fn test_flatten_bit() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let hdl_path = tests_dir.join("nand2tetris/solutions/Bit.hdl");
+        let (hdl, _) = parse_hdl_path(&hdl_path).expect("Parse error");
+        let entity = flatten_entity(&hdl, None).expect("Synthesis error");
+        let golden = fs::read_to_string(tests_dir.join("vhdl/BitFlat.vhdl"))
+            .expect("Unable to read golden file");
+        assert_eq!(entity.to_string(), golden);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut project = QuartusProject::new(hdl, entity, temp_dir.path().to_path_buf());
+        project.flatten = true;
+        write_quartus_project(&project).expect("Write error");
+        let mut files: Vec<String> = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["Bit.vhdl", "project.tcl"]);
+    }
+
+    #[test]
+    fn test_flatten_inout() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("inout");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("SharedBus", &provider).expect("Parse error");
+        match flatten_entity(&hdl, None) {
+            Ok(_) => panic!("Expected an error for a bidirectional chip"),
+            Err(e) => assert!(e.to_string().contains("cannot be flattened")),
+        }
+    }
+
+    #[test]
+    fn test_builtin_ram() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tests_dir = manifest_dir.join("resources").join("tests");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&tests_dir.join("ram")));
+
+        let hdl = get_hdl("RAM8", &provider).expect("Parse error");
+        let entity = VhdlEntity::try_from(&hdl).expect("Synthesis error");
+        let golden = fs::read_to_string(tests_dir.join("vhdl/RAM8.vhdl"))
+            .expect("Unable to read golden file");
+        assert_eq!(entity.to_string(), golden);
+
+        let hdl = get_hdl("RAMGen", &provider).expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+        assert!(vhdl.contains(
+            "type memory_t is array (0 to 2**A - 1) of std_logic_vector((W - 1) downto 0);"
+        ));
+
+        // Chips that use a RAM pass it their clock.
+        let hdl = get_hdl("Memory", &provider).expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+        assert!(vhdl.contains("clk : in std_logic_vector(0 downto 0)\n);\nend entity Memory;"));
+        assert!(vhdl.contains("address => address, out_n2v => out_n2v, clk => clk);"));
+
+        let hdl = get_hdl("BadRAM", &provider).expect("Parse error");
+        match VhdlEntity::try_from(&hdl) {
+            Ok(_) => panic!("Expected an error for a RAM without an address port"),
+            Err(e) => assert!(e.to_string().contains("is a builtin RAM")),
+        }
+
+        let hdl = get_hdl("HugeRAM", &provider).expect("Parse error");
+        match VhdlEntity::try_from(&hdl) {
+            Ok(_) => panic!("Expected an error for a 64-bit address"),
+            Err(e) => assert!(e.to_string().contains("it can be at most 31 bits wide")),
+        }
+    }
+
+    #[test]
+    fn test_assert_vhdl() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_path = manifest_dir.join("resources").join("tests").join("assert");
+        let provider: Rc<dyn HdlProvider> = Rc::new(FileReader::new(&base_path));
+        let hdl = get_hdl("Ten", &provider).expect("Parse error");
+        let vhdl = VhdlEntity::try_from(&hdl)
+            .expect("Synthesis error")
+            .to_string();
+        assert!(vhdl.contains("assert one = \"1\" report \"Assertion on line 10 of Ten failed\";"));
+        // The output is read through its mirror signal.
+        assert!(vhdl.contains("assert out_mirror = \"1010\" report"));
     }
 }
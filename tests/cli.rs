@@ -0,0 +1,166 @@
+//! Runs the whidl binary the way a user would and checks what it prints.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_whidl"))
+        .current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+        .args(args)
+        .output()
+        .expect("Unable to run whidl")
+}
+
+fn whidl(args: &[&str]) -> String {
+    let output = run(args);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).expect("Output is not UTF-8")
+}
+
+// Buses are printed most significant bit first, like in test scripts.
+#[test]
+fn test_table_bus_order() {
+    let table = whidl(&[
+        "table",
+        "--top-level-file",
+        "resources/tests/multiply/JoinGen.hdl",
+        "--generics",
+        "2",
+    ]);
+    let mut lines = table.lines();
+    assert_eq!(lines.next(), Some("high\tlow\tout"));
+    assert!(lines.any(|line| line == "01\t10\t0110"));
+}
+
+// Synthesis reports the time spent building the chip and its dependencies.
+#[test]
+fn test_synth_vhdl_profile() {
+    let dir = tempfile::tempdir().unwrap();
+    let vhdl = dir.path().join("Mux4Way16.vhd");
+    let output = whidl(&[
+        "synth-vhdl",
+        "resources/tests/nand2tetris/solutions/Mux4Way16.hdl",
+        "--single-file",
+        vhdl.to_str().unwrap(),
+        "--profile",
+    ]);
+    assert!(vhdl.exists(), "No VHDL file written");
+    let mut lines = output.lines().skip_while(|line| *line != "Profile:");
+    assert!(lines.next().is_some());
+    assert!(lines.any(|line| line.starts_with("Mux16 ")));
+}
+
+// Outputs computed from none are unknown, which --strict reports.
+#[test]
+fn test_check_strict_none() {
+    let args = [
+        "check",
+        "--top-level-file",
+        "resources/tests/literal/TieNone.hdl",
+        "-q",
+    ];
+    whidl(&args);
+
+    let output = run(&[&args[..], &["--strict"]].concat());
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("which are computed from none: out, unknown, wide."));
+}
+
+// Output bits are printed as expressions over the inputs, simplified unless --raw is given.
+#[test]
+fn test_symbolic() {
+    let output = whidl(&["symbolic", "resources/tests/nand2tetris/solutions/Mux.hdl"]);
+    assert_eq!(output, "out = ((a & !sel) | (b & sel))\n");
+
+    let output = whidl(&[
+        "symbolic",
+        "resources/tests/nand2tetris/solutions/And.hdl",
+        "--raw",
+    ]);
+    assert_eq!(output, "out = nand(nand(a, b), nand(a, b))\n");
+}
+
+// -o writes a command's output to a file, creating its missing parent directories.
+#[test]
+fn test_output_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nested").join("tables").join("And.txt");
+    let chip = "resources/tests/nand2tetris/solutions/And.hdl";
+    let output = whidl(&["table", "-t", chip, "-o", path.to_str().unwrap()]);
+    assert_eq!(output, "");
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        whidl(&["table", "-t", chip])
+    );
+}
+
+// Parts are also found in the directories listed in WHIDL_PATH.
+#[test]
+fn test_whidl_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let (chips, lib) = (dir.path().join("chips"), dir.path().join("lib"));
+    fs::create_dir(&chips).unwrap();
+    fs::create_dir(&lib).unwrap();
+    let top = chips.join("Top.hdl");
+    fs::write(
+        &top,
+        "CHIP Top { IN a; OUT out; PARTS: Inv(in=a, out=out); }",
+    )
+    .unwrap();
+    let inv = "CHIP Inv { IN in; OUT out; PARTS: Nand(a=in, b=in, out=out); }";
+    fs::write(lib.join("Inv.hdl"), inv).unwrap();
+
+    let args = ["table", "-t", top.to_str().unwrap()];
+    assert!(!run(&args).status.success());
+    let output = Command::new(env!("CARGO_BIN_EXE_whidl"))
+        .env("WHIDL_PATH", &lib)
+        .args(args)
+        .output()
+        .expect("Unable to run whidl");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "a\tout\n0\t1\n1\t0\n"
+    );
+}
+
+// A part's undriven outputs fail check unless --allow-undriven is given. The
+// chip's own undriven outputs are only warnings.
+#[test]
+fn test_check_undriven_part() {
+    let args = [
+        "check",
+        "--top-level-file",
+        "resources/tests/bad/UsesUndriven.hdl",
+        "-q",
+    ];
+    let output = run(&args);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Chip Undriven has output bits with no source: flag, out[1], out[3]."));
+
+    let output = run(&[&args[..], &["--allow-undriven"]].concat());
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("⚠ output 'Undriven.0/out[1]' has no source"));
+
+    let output = run(&[
+        "check",
+        "--top-level-file",
+        "resources/tests/bad/Undriven.hdl",
+        "-q",
+    ]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("⚠ output 'flag' has no source"));
+}